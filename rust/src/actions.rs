@@ -0,0 +1,227 @@
+// src/actions.rs
+// Carrying out planned duplicate actions: a dry run unless armed, and only while the files still match the plan
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rayon::prelude::*;
+
+use crate::paths;
+use crate::script::Action;
+use crate::simulation;
+
+/// Size, modification time and identity of a file when the plan was made
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FileState {
+    size: u64,
+    modified: Option<SystemTime>,
+    /// Device and inode, so a file replaced by another of the same size is told apart
+    identity: Option<(u64, u64)>,
+}
+
+fn file_state(path: &str) -> Option<FileState> {
+    let metadata = fs::metadata(path).ok().filter(|m| m.is_file())?;
+    Some(FileState { size: metadata.len(), modified: metadata.modified().ok(), identity: simulation::identity(&metadata) })
+}
+
+pub struct Step {
+    pub kept: String,
+    pub duplicate: String,
+    // None for a file that was missing
+    kept_state: Option<FileState>,
+    duplicate_state: Option<FileState>,
+}
+
+impl Step {
+    /// Whether both files are still as they were when planned
+    fn unchanged(&self) -> bool {
+        file_state(&self.kept) == self.kept_state && file_state(&self.duplicate) == self.duplicate_state
+    }
+
+    fn already_linked(&self) -> bool {
+        let identity = |state: Option<FileState>| state.and_then(|state| state.identity);
+        identity(self.kept_state).is_some() && identity(self.kept_state) == identity(self.duplicate_state)
+    }
+}
+
+/// Every step of one action, with a hash over the steps and the state of each file
+pub struct Plan {
+    pub action: Action,
+    pub move_to: Option<String>,
+    pub steps: Vec<Step>,
+    /// Hex blake3; any edited, replaced or missing file changes it
+    pub hash: String,
+}
+
+/// Plan `action` over the `(kept, duplicate)` pairs from `script::plan`
+pub fn plan(pairs: Vec<(String, String)>, action: Action, move_to: Option<&str>) -> Plan {
+    let steps: Vec<Step> = pairs
+        .into_par_iter()
+        .map(|(kept, duplicate)| Step {
+            kept_state: file_state(&kept),
+            duplicate_state: file_state(&duplicate),
+            kept,
+            duplicate,
+        })
+        .collect();
+
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(action.name().as_bytes());
+    hasher.update(&[0]);
+    hasher.update(move_to.unwrap_or_default().as_bytes());
+    for step in &steps {
+        for (path, state) in [(&step.kept, step.kept_state), (&step.duplicate, step.duplicate_state)] {
+            hasher.update(&[0]);
+            hasher.update(path.as_bytes());
+            hasher.update(&[0]);
+            if let Some(state) = state {
+                let nanos = state.modified.and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_nanos());
+                hasher.update(&state.size.to_le_bytes());
+                hasher.update(&nanos.to_le_bytes());
+                if let Some((device, inode)) = state.identity {
+                    hasher.update(&device.to_le_bytes());
+                    hasher.update(&inode.to_le_bytes());
+                }
+            }
+        }
+    }
+    Plan { action, move_to: move_to.map(str::to_string), steps, hash: hasher.finalize().to_hex().to_string() }
+}
+
+/// Plan hashes by the tokens issued for them, each usable once
+fn tokens() -> &'static Mutex<HashMap<String, String>> {
+    static TOKENS: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    TOKENS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+static ISSUED: AtomicU64 = AtomicU64::new(0);
+
+/// A one-time token that arms the execution of the plan with `hash`
+pub fn issue_token(hash: &str) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(hash.as_bytes());
+    hasher.update(&ISSUED.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    hasher.update(&std::process::id().to_le_bytes());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    hasher.update(&now.to_le_bytes());
+    let token = hasher.finalize().to_hex()[..32].to_string();
+
+    tokens().lock().unwrap_or_else(|e| e.into_inner()).insert(token.clone(), hash.to_string());
+    token
+}
+
+/// The plan hash `token` was issued for, used up by this call
+pub fn redeem(token: &str) -> Option<String> {
+    tokens().lock().unwrap_or_else(|e| e.into_inner()).remove(token)
+}
+
+/// What became of one step
+pub enum Outcome {
+    /// Dry run: nothing was touched
+    Planned,
+    Done,
+    /// Left alone, with the reason
+    Skipped(&'static str),
+    Failed(String),
+}
+
+impl Outcome {
+    pub fn status(&self) -> &'static str {
+        match self {
+            Outcome::Planned => "planned",
+            Outcome::Done => "done",
+            Outcome::Skipped(_) => "skipped",
+            Outcome::Failed(_) => "failed",
+        }
+    }
+
+    pub fn reason(&self) -> Option<String> {
+        match self {
+            Outcome::Planned | Outcome::Done => None,
+            Outcome::Skipped(reason) => Some(reason.to_string()),
+            Outcome::Failed(error) => Some(error.clone()),
+        }
+    }
+}
+
+/// Carry out every step of `plan`, one at a time
+///
+/// Each step first checks that both files are still as planned and is
+/// skipped otherwise, so a file edited in the meantime is never replaced or
+/// removed. A hard link is made next to the duplicate and renamed over it, so
+/// a failure leaves the duplicate in place; a move never overwrites, not
+/// even a file created at the destination while it runs.
+pub fn execute(plan: &Plan) -> Vec<Outcome> {
+    if let Some(dir) = &plan.move_to {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return plan.steps.iter().map(|_| Outcome::Failed(format!("Failed to create {}: {}", dir, e))).collect();
+        }
+    }
+
+    plan.steps
+        .iter()
+        .map(|step| {
+            if step.kept_state.is_none() {
+                return Outcome::Skipped("kept file missing");
+            }
+            if step.duplicate_state.is_none() {
+                return Outcome::Skipped("duplicate missing");
+            }
+            if !step.unchanged() {
+                return Outcome::Skipped("changed since planned");
+            }
+            if plan.action == Action::Hardlink && step.already_linked() {
+                return Outcome::Skipped("already linked");
+            }
+            let result = match plan.action {
+                Action::Hardlink => replace_with_link(&step.kept, &step.duplicate),
+                Action::Delete => fs::remove_file(&step.duplicate),
+                Action::Move => {
+                    let dir = Path::new(plan.move_to.as_deref().unwrap_or("."));
+                    let target = dir.join(Path::new(&step.duplicate).file_name().unwrap_or_default());
+                    paths::rename_no_replace(Path::new(&step.duplicate), &target)
+                },
+            };
+            match result {
+                Ok(()) => Outcome::Done,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Outcome::Skipped("destination exists"),
+                Err(e) => Outcome::Failed(e.to_string()),
+            }
+        })
+        .collect()
+}
+
+fn replace_with_link(kept: &str, duplicate: &str) -> std::io::Result<()> {
+    let duplicate = Path::new(duplicate);
+    let temp = paths::sibling_temp(duplicate);
+    let _ = fs::remove_file(&temp);
+    fs::hard_link(kept, &temp)?;
+    fs::rename(&temp, duplicate).inspect_err(|_| {
+        let _ = fs::remove_file(&temp); // Clean up
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tiff::fixtures::temp_path;
+
+    #[test]
+    fn links_over_duplicates_with_long_names() {
+        let dir = temp_path("actions_long_names");
+        fs::create_dir_all(&dir).unwrap();
+        let kept = dir.join("kept.jpg");
+        let duplicate = dir.join(format!("{}.jpg", "x".repeat(250)));
+        fs::write(&kept, b"kept").unwrap();
+        fs::write(&duplicate, b"kept").unwrap();
+
+        replace_with_link(kept.to_str().unwrap(), duplicate.to_str().unwrap()).unwrap();
+        assert_eq!(fs::read(&duplicate).unwrap(), b"kept");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+// src/backend_stats.rs
+// Which conversion steps succeed per extension and camera, and what they cost, to reorder the chain per machine
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::camera_profiles;
+use crate::remote;
+use crate::storage::{self, Backend};
+
+/// Outcomes of one step for one extension and camera
+#[derive(Clone, Copy, Default)]
+pub struct StepStats {
+    pub attempts: u64,
+    pub successes: u64,
+    /// Time spent in the step, failed attempts included
+    pub time: Duration,
+    /// Whole conversion time of the files this step produced, earlier steps included
+    pub time_to_success: Duration,
+}
+
+impl StepStats {
+    /// Expected time to a result when the step is tried first: its mean
+    /// cost over its success rate; None when it never succeeded
+    fn expected_cost(&self) -> Option<f64> {
+        (self.successes > 0).then(|| self.time.as_secs_f64() / self.successes as f64)
+    }
+}
+
+/// Conversions of one extension and camera
+#[derive(Clone, Default)]
+pub struct Entry {
+    pub extension: String,
+    /// Make and model from the file header, empty when it could not be read
+    pub camera: String,
+    pub files: u64,
+    /// Files no step could convert
+    pub failed: u64,
+    /// Per step, in the order first tried
+    pub steps: Vec<(Backend, StepStats)>,
+}
+
+fn entries() -> &'static Mutex<HashMap<(String, String), Entry>> {
+    static ENTRIES: OnceLock<Mutex<HashMap<(String, String), Entry>>> = OnceLock::new();
+    ENTRIES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// `Make Model` of a local file, without repeating a make the model already starts with
+pub fn camera(path: &str) -> String {
+    if remote::is_remote(path) {
+        return String::new();
+    }
+    let Some((make, model)) = camera_profiles::read_make_model(path) else {
+        return String::new();
+    };
+    let (make, model) = (make.trim(), model.trim());
+    if model.to_lowercase().starts_with(&make.to_lowercase()) || make.is_empty() {
+        model.to_string()
+    } else {
+        format!("{} {}", make, model).trim().to_string()
+    }
+}
+
+/// Record the steps one conversion of `path` went through, as `(step,
+/// succeeded, time)` in order, and its total time
+pub fn record(path: &str, extension: &str, steps: &[(Backend, bool, Duration)], total: Duration) {
+    if steps.is_empty() {
+        return;
+    }
+    let camera = camera(path);
+    let mut entries = entries().lock().unwrap_or_else(|e| e.into_inner());
+    let entry = entries.entry((extension.to_string(), camera.clone())).or_insert_with(|| Entry {
+        extension: extension.to_string(),
+        camera,
+        ..Default::default()
+    });
+    entry.files += 1;
+    if !steps.iter().any(|(_, succeeded, _)| *succeeded) {
+        entry.failed += 1;
+    }
+    for &(backend, succeeded, time) in steps {
+        let position = match entry.steps.iter().position(|(b, _)| *b == backend) {
+            Some(position) => position,
+            None => {
+                entry.steps.push((backend, StepStats::default()));
+                entry.steps.len() - 1
+            },
+        };
+        let stats = &mut entry.steps[position].1;
+        stats.attempts += 1;
+        stats.time += time;
+        if succeeded {
+            stats.successes += 1;
+            stats.time_to_success += total;
+        }
+    }
+}
+
+/// Collects the steps of one conversion and records them when dropped,
+/// however the conversion ends
+pub struct Recorder {
+    path: String,
+    extension: String,
+    start: Instant,
+    steps: Vec<(Backend, bool, Duration)>,
+}
+
+impl Recorder {
+    pub fn start(path: &str, extension: &str) -> Self {
+        Recorder { path: path.to_string(), extension: extension.to_string(), start: Instant::now(), steps: Vec::new() }
+    }
+
+    pub fn step(&mut self, backend: Backend, succeeded: bool, time: Duration) {
+        self.steps.push((backend, succeeded, time));
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // A restricted chain says nothing about which order works best
+        if storage::restriction() != storage::Restriction::Unrestricted {
+            return;
+        }
+        record(&self.path, &self.extension, &self.steps, self.start.elapsed());
+    }
+}
+
+/// Everything recorded so far, by extension then camera
+pub fn report() -> Vec<Entry> {
+    let mut report: Vec<Entry> = entries().lock().unwrap_or_else(|e| e.into_inner()).values().cloned().collect();
+    report.sort_by(|a, b| (&a.extension, &a.camera).cmp(&(&b.extension, &b.camera)));
+    report
+}
+
+/// Forget everything recorded
+pub fn clear() {
+    entries().lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// Step order per extension that minimizes the expected conversion time,
+/// for extensions with at least `min_files` recorded conversions
+///
+/// Cameras are pooled, since the chain is chosen per extension. Steps that
+/// succeeded come first by time spent per success (trying the cheapest
+/// likely step first is optimal for a chain that stops at the first
+/// success); steps that never succeeded go last in the order they ran.
+pub fn tuned_orders(min_files: u64) -> Vec<(String, Vec<Backend>)> {
+    let mut pooled: HashMap<String, (u64, Vec<(Backend, StepStats)>)> = HashMap::new();
+    for entry in report() {
+        let (files, steps) = pooled.entry(entry.extension).or_default();
+        *files += entry.files;
+        for (backend, stats) in entry.steps {
+            match steps.iter_mut().find(|(b, _)| *b == backend) {
+                Some((_, total)) => {
+                    total.attempts += stats.attempts;
+                    total.successes += stats.successes;
+                    total.time += stats.time;
+                    total.time_to_success += stats.time_to_success;
+                },
+                None => steps.push((backend, stats)),
+            }
+        }
+    }
+
+    let mut orders: Vec<(String, Vec<Backend>)> = pooled
+        .into_iter()
+        .filter(|(_, (files, _))| *files >= min_files.max(1))
+        .map(|(extension, (_, mut steps))| {
+            // A stable sort keeps the order they ran in among equals and for steps that never succeeded
+            steps.sort_by(|(_, a), (_, b)| match (a.expected_cost(), b.expected_cost()) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            });
+            (extension, steps.into_iter().map(|(backend, _)| backend).collect())
+        })
+        .collect();
+    orders.sort_by(|a, b| a.0.cmp(&b.0));
+    orders
+}
@@ -0,0 +1,208 @@
+// src/bktree.rs
+// BK-tree over hashes, for near-duplicate lookups without comparing every pair
+
+use std::collections::HashMap;
+
+use crate::hamming::{self, Packed};
+
+struct Node {
+    hash: Packed,
+    /// Paths with exactly this hash; empty once they are all removed
+    paths: Vec<String>,
+    /// Child node by its distance to this one
+    children: Vec<(u32, usize)>,
+}
+
+/// Paths by hash, searchable by Hamming distance
+///
+/// Every hash in a tree has the same number of bits. A query only visits
+/// subtrees whose distance to a node can be within reach of the query (the
+/// triangle inequality), which for tight radii is a small fraction of the
+/// tree. Removed paths leave their node behind as a signpost until more than
+/// half the nodes are empty, when the tree is rebuilt.
+#[derive(Default)]
+pub struct BkTree {
+    nodes: Vec<Node>,
+    /// Node holding each path
+    locations: HashMap<String, usize>,
+    empty_nodes: usize,
+}
+
+impl BkTree {
+    /// Number of paths in the tree
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    /// Bit length every hash must have, once the first one is added
+    pub fn bits(&self) -> Option<usize> {
+        self.nodes.first().map(|node| node.hash.bits())
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.locations.contains_key(path)
+    }
+
+    /// Add `path` with `hash`, replacing the hash it had; the caller checks the bit length
+    pub fn insert(&mut self, path: String, hash: Packed) {
+        self.remove(&path);
+        let existing = self.nodes.len();
+        let node = self.insert_hash(hash);
+        // An emptied node gets a path again
+        if node < existing && self.nodes[node].paths.is_empty() {
+            self.empty_nodes -= 1;
+        }
+        self.nodes[node].paths.push(path.clone());
+        self.locations.insert(path, node);
+    }
+
+    /// The node for `hash`, created if no node has it yet
+    fn insert_hash(&mut self, hash: Packed) -> usize {
+        if self.nodes.is_empty() {
+            self.nodes.push(Node { hash, paths: Vec::new(), children: Vec::new() });
+            return 0;
+        }
+        let mut current = 0;
+        loop {
+            let distance = hamming::distance(&self.nodes[current].hash, &hash).unwrap_or(u32::MAX);
+            if distance == 0 {
+                return current;
+            }
+            match self.nodes[current].children.iter().find(|(d, _)| *d == distance) {
+                Some(&(_, child)) => current = child,
+                None => {
+                    let node = self.nodes.len();
+                    self.nodes.push(Node { hash, paths: Vec::new(), children: Vec::new() });
+                    self.nodes[current].children.push((distance, node));
+                    return node;
+                },
+            }
+        }
+    }
+
+    /// Remove `path`, returning whether it was in the tree
+    pub fn remove(&mut self, path: &str) -> bool {
+        let Some(node) = self.locations.remove(path) else {
+            return false;
+        };
+        let paths = &mut self.nodes[node].paths;
+        paths.retain(|p| p != path);
+        if paths.is_empty() {
+            self.empty_nodes += 1;
+            if self.empty_nodes * 2 > self.nodes.len() {
+                self.rebuild();
+            }
+        }
+        true
+    }
+
+    /// Build the tree again from the paths still in it, dropping empty nodes
+    fn rebuild(&mut self) {
+        let nodes = std::mem::take(&mut self.nodes);
+        self.locations.clear();
+        self.empty_nodes = 0;
+        for node in nodes.into_iter().filter(|node| !node.paths.is_empty()) {
+            let index = self.insert_hash(node.hash);
+            for path in &node.paths {
+                self.locations.insert(path.clone(), index);
+            }
+            self.nodes[index].paths = node.paths;
+        }
+    }
+
+    /// Every `(path, distance)` within `max_distance` of `hash`, closest first, then by path
+    pub fn query(&self, hash: &Packed, max_distance: u32) -> Vec<(String, u32)> {
+        let mut found = Vec::new();
+        let mut pending = if self.nodes.is_empty() { Vec::new() } else { vec![0] };
+        while let Some(current) = pending.pop() {
+            let node = &self.nodes[current];
+            let Some(distance) = hamming::distance(&node.hash, hash) else {
+                return Vec::new();
+            };
+            if distance <= max_distance {
+                found.extend(node.paths.iter().map(|path| (path.clone(), distance)));
+            }
+            let reach = distance.saturating_sub(max_distance)..=distance.saturating_add(max_distance);
+            pending.extend(node.children.iter().filter(|(d, _)| reach.contains(d)).map(|&(_, child)| child));
+        }
+        found.sort_unstable_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packed(hash: u64) -> Packed {
+        Packed::from_bytes(&hash.to_be_bytes())
+    }
+
+    /// Every path within `max_distance` by comparing against each hash, ordered like `query`
+    fn brute_force(hashes: &HashMap<String, u64>, hash: u64, max_distance: u32) -> Vec<(String, u32)> {
+        let mut found: Vec<(String, u32)> = hashes
+            .iter()
+            .map(|(path, h)| (path.clone(), (h ^ hash).count_ones()))
+            .filter(|&(_, distance)| distance <= max_distance)
+            .collect();
+        found.sort_unstable_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        found
+    }
+
+    fn assert_matches(tree: &BkTree, hashes: &HashMap<String, u64>, queries: &[u64]) {
+        assert_eq!(tree.len(), hashes.len());
+        for &query in queries {
+            for radius in [0, 1, 3, 8, 20, 64] {
+                assert_eq!(tree.query(&packed(query), radius), brute_force(hashes, query, radius), "radius {}", radius);
+            }
+        }
+    }
+
+    #[test]
+    fn queries_agree_with_a_full_scan() {
+        // Clusters of near-identical hashes around a few bases, some sharing a hash
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let bases: Vec<u64> = (0..8).map(|_| next()).collect();
+        let mut tree = BkTree::default();
+        let mut hashes = HashMap::new();
+        for i in 0..200 {
+            let flips = (0..i % 5).fold(0u64, |mask, _| mask | 1 << (next() % 64));
+            let hash = bases[i % bases.len()] ^ flips;
+            tree.insert(format!("/p/{:03}.jpg", i), packed(hash));
+            hashes.insert(format!("/p/{:03}.jpg", i), hash);
+        }
+        let queries: Vec<u64> = bases.iter().copied().chain([next(), bases[0] ^ 0b101]).collect();
+        assert_matches(&tree, &hashes, &queries);
+
+        // Fewer than half removed: their nodes stay behind as signposts
+        for i in (0..200).step_by(3) {
+            let path = format!("/p/{:03}.jpg", i);
+            assert!(tree.remove(&path));
+            hashes.remove(&path);
+        }
+        assert!(!tree.remove("/p/000.jpg"));
+        assert!(!tree.contains("/p/003.jpg") && tree.contains("/p/001.jpg"));
+        assert_matches(&tree, &hashes, &queries);
+
+        // Most of the rest removed, which rebuilds the tree without the empty nodes
+        let nodes = tree.nodes.len();
+        for i in (0..200).filter(|i| i % 3 != 0 && i % 7 != 0) {
+            let path = format!("/p/{:03}.jpg", i);
+            tree.remove(&path);
+            hashes.remove(&path);
+        }
+        assert!(tree.nodes.len() < nodes);
+        assert_matches(&tree, &hashes, &queries);
+
+        // Moving a path to another hash
+        tree.insert("/p/007.jpg".to_string(), packed(bases[1]));
+        hashes.insert("/p/007.jpg".to_string(), bases[1]);
+        assert_matches(&tree, &hashes, &queries);
+    }
+}
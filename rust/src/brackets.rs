@@ -0,0 +1,142 @@
+// src/brackets.rs
+// Grouping of exposure-bracketed (HDR) and focus-stacked sequences from EXIF
+
+use std::collections::{HashMap, HashSet};
+
+use crate::exiftool;
+use crate::naming;
+
+// Tags read per frame, in `parse_frame` order
+const TAGS: &[&str] = &[
+    "Make",
+    "Model",
+    "DateTimeOriginal",
+    "SubSecTimeOriginal",
+    "ExposureMode",
+    "ExposureCompensation",
+    "FocusDistance",
+];
+
+// EXIF ExposureMode value for "Auto bracket"
+const EXPOSURE_MODE_AUTO_BRACKET: &str = "2";
+
+/// What ties the frames of a stack together
+#[derive(Clone, Copy, PartialEq)]
+pub enum StackKind {
+    /// Exposure bracket for HDR merging
+    Exposure,
+    /// Same exposure, stepped focus distance
+    Focus,
+}
+
+impl StackKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StackKind::Exposure => "hdr",
+            StackKind::Focus => "focus",
+        }
+    }
+}
+
+/// Two paths reported as a candidate duplicate
+pub type PathPair = (String, String);
+
+/// A bracketed sequence, frames in capture order
+pub struct Stack {
+    pub kind: StackKind,
+    pub paths: Vec<String>,
+}
+
+/// The EXIF fields bracket detection needs
+struct Frame {
+    path: String,
+    camera: String,
+    time: f64,
+    auto_bracket: bool,
+    exposure_compensation: Option<f64>,
+    focus_distance: Option<f64>,
+}
+
+/// Parse one file's `TAGS`; frames without a capture time are dropped
+fn parse_frame(path: &str, fields: &[String]) -> Option<Frame> {
+    let [make, model, time, subsec, mode, ev, focus] = fields else {
+        return None;
+    };
+
+    let seconds = naming::parse_exif_datetime(exiftool::field(time)?)?;
+    Some(Frame {
+        path: path.to_string(),
+        camera: format!("{} {}", make.trim(), model.trim()),
+        time: seconds + exiftool::subsec_fraction(subsec),
+        auto_bracket: exiftool::field(mode) == Some(EXPOSURE_MODE_AUTO_BRACKET),
+        exposure_compensation: exiftool::field(ev).and_then(|v| v.parse().ok()),
+        focus_distance: exiftool::field(focus).and_then(|v| v.parse().ok()),
+    })
+}
+
+fn distinct_values(values: impl Iterator<Item = Option<f64>>) -> usize {
+    values
+        .flatten()
+        .map(|v| (v * 100.0).round() as i64)
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// Decide whether a run of rapid-fire frames is a bracket; plain bursts are not
+fn classify_run(run: &[Frame]) -> Option<StackKind> {
+    if run.iter().any(|f| f.auto_bracket) || distinct_values(run.iter().map(|f| f.exposure_compensation)) > 1 {
+        return Some(StackKind::Exposure);
+    }
+    (distinct_values(run.iter().map(|f| f.focus_distance)) > 1).then_some(StackKind::Focus)
+}
+
+/// Group `paths` into bracketed sequences
+///
+/// Frames from the same camera taken at most `max_gap` seconds apart form a
+/// run; a run is a stack when it is flagged as auto bracket, its exposure
+/// compensation varies, or its focus distance varies at constant exposure.
+pub fn group(paths: &[String], max_gap: f64) -> Vec<Stack> {
+    let mut frames: Vec<Frame> = paths
+        .iter()
+        .zip(exiftool::read_tags(paths, TAGS))
+        .filter_map(|(path, fields)| parse_frame(path, &fields?))
+        .collect();
+    frames.sort_by(|a, b| a.camera.cmp(&b.camera).then(a.time.total_cmp(&b.time)));
+
+    let mut stacks = Vec::new();
+    let mut start = 0;
+    for end in 1..=frames.len() {
+        let continues = end < frames.len()
+            && frames[end].camera == frames[end - 1].camera
+            && frames[end].time - frames[end - 1].time <= max_gap;
+        if continues {
+            continue;
+        }
+
+        let run = &frames[start..end];
+        if run.len() >= 2 {
+            if let Some(kind) = classify_run(run) {
+                stacks.push(Stack { kind, paths: run.iter().map(|f| f.path.clone()).collect() });
+            }
+        }
+        start = end;
+    }
+
+    stacks
+}
+
+/// Split candidate duplicate pairs into real duplicates and intra-stack pairs
+///
+/// `stacks` holds the frame paths of each stack.
+pub fn partition_pairs(pairs: Vec<PathPair>, stacks: &[Vec<String>]) -> (Vec<PathPair>, Vec<PathPair>) {
+    let stack_of: HashMap<&str, usize> = stacks
+        .iter()
+        .enumerate()
+        .flat_map(|(i, paths)| paths.iter().map(move |p| (p.as_str(), i)))
+        .collect();
+
+    pairs.into_iter().partition(|(a, b)| {
+        let same_stack = matches!((stack_of.get(a.as_str()), stack_of.get(b.as_str())), (Some(x), Some(y)) if x == y);
+        !same_stack
+    })
+}
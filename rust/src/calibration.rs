@@ -0,0 +1,135 @@
+// src/calibration.rs
+// Duplicate score calibration: logistic weights over the hash distances, fitted to labeled pairs
+
+use std::io;
+
+// Gradient descent schedule; the problem is tiny and convex, so a fixed one converges
+const ITERATIONS: usize = 2000;
+const LEARNING_RATE: f64 = 0.5;
+// L2 penalty keeping weights finite when the labels are perfectly separable
+const L2_PENALTY: f64 = 1e-3;
+
+/// Index setting under which the calibration is stored
+pub const SETTING_KEY: &str = "duplicate_calibration";
+
+/// Fitted parameters of the combined scorer
+#[derive(Clone)]
+pub struct Calibration {
+    /// Matching profile the distances were measured with
+    pub profile: String,
+    /// Weights of the average, perceptual and edge hash distances (in bits / 64)
+    pub weights: [f64; 3],
+    pub bias: f64,
+    /// Score at or above which a pair is a duplicate
+    pub threshold: f64,
+}
+
+/// One labeled pair: hash distances and whether it is a duplicate
+pub type Sample = ([u32; 3], bool);
+
+/// How well a calibration separates the pairs it was fitted on
+pub struct FitReport {
+    pub precision: f64,
+    pub recall: f64,
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+fn features(distances: [u32; 3]) -> [f64; 3] {
+    distances.map(|d| d as f64 / 64.0)
+}
+
+impl Calibration {
+    /// Duplicate probability of a pair, 0-1
+    pub fn score(&self, distances: [u32; 3]) -> f64 {
+        let x = features(distances);
+        sigmoid(self.bias + (0..3).map(|i| self.weights[i] * x[i]).sum::<f64>())
+    }
+
+    pub fn is_duplicate(&self, distances: [u32; 3]) -> bool {
+        self.score(distances) >= self.threshold
+    }
+
+    /// Single-line encoding stored as an index setting
+    pub fn encode(&self) -> String {
+        let numbers = [self.weights[0], self.weights[1], self.weights[2], self.bias, self.threshold];
+        std::iter::once(self.profile.clone())
+            .chain(numbers.iter().map(f64::to_string))
+            .collect::<Vec<_>>()
+            .join("\t")
+    }
+
+    pub fn decode(value: &str) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Corrupt calibration: {}", value));
+        let mut fields = value.split('\t');
+        let profile = fields.next().ok_or_else(invalid)?.to_string();
+        let numbers: Vec<f64> = fields.map(|f| f.parse().map_err(|_| invalid())).collect::<io::Result<_>>()?;
+        let [w0, w1, w2, bias, threshold] = <[f64; 5]>::try_from(numbers).map_err(|_| invalid())?;
+        Ok(Calibration { profile, weights: [w0, w1, w2], bias, threshold })
+    }
+}
+
+/// Fit a class-balanced logistic model and the F1-maximizing threshold
+///
+/// Returns None unless both duplicates and non-duplicates are present.
+pub fn fit(profile: &str, samples: &[Sample]) -> Option<(Calibration, FitReport)> {
+    let positives = samples.iter().filter(|(_, dup)| *dup).count();
+    let negatives = samples.len() - positives;
+    if positives == 0 || negatives == 0 {
+        return None;
+    }
+
+    // Libraries hold far more distinct pairs than duplicates; weigh the classes equally
+    let class_weight = |dup: bool| {
+        if dup {
+            0.5 / positives as f64
+        } else {
+            0.5 / negatives as f64
+        }
+    };
+
+    let mut calibration = Calibration { profile: profile.to_string(), weights: [0.0; 3], bias: 0.0, threshold: 0.5 };
+    for _ in 0..ITERATIONS {
+        let mut gradient = [0.0; 3];
+        let mut bias_gradient = 0.0;
+        for &(distances, dup) in samples {
+            let error = (calibration.score(distances) - if dup { 1.0 } else { 0.0 }) * class_weight(dup);
+            let x = features(distances);
+            for (g, xi) in gradient.iter_mut().zip(x) {
+                *g += error * xi;
+            }
+            bias_gradient += error;
+        }
+        for (w, g) in calibration.weights.iter_mut().zip(gradient) {
+            *w -= LEARNING_RATE * (g + L2_PENALTY * *w);
+        }
+        calibration.bias -= LEARNING_RATE * bias_gradient;
+    }
+
+    // Try every training score as the cut-off and keep the best F1
+    let mut scored: Vec<(f64, bool)> = samples.iter().map(|&(d, dup)| (calibration.score(d), dup)).collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut best = (0.0, 0.5, FitReport { precision: 0.0, recall: 0.0 });
+    let mut true_positives = 0;
+    for (i, &(score, dup)) in scored.iter().enumerate() {
+        if dup {
+            true_positives += 1;
+        }
+        // Only cut between distinct scores
+        if scored.get(i + 1).is_some_and(|next| next.0 == score) {
+            continue;
+        }
+        let precision = true_positives as f64 / (i + 1) as f64;
+        let recall = true_positives as f64 / positives as f64;
+        let f1 = if true_positives > 0 { 2.0 * precision * recall / (precision + recall) } else { 0.0 };
+        if f1 > best.0 {
+            best = (f1, score, FitReport { precision, recall });
+        }
+    }
+
+    calibration.threshold = best.1;
+    Some((calibration, best.2))
+}
@@ -0,0 +1,191 @@
+// src/camera_profiles.rs
+// Built-in per-camera tuning keyed by EXIF make/model
+
+use std::fs::File;
+use std::io::Read;
+
+use crate::tiff;
+
+/// Decode behaviour that differs from what the file extension suggests
+#[derive(Clone, Copy, PartialEq)]
+pub enum Quirk {
+    /// Embedded previews are too small for hashing, decode the sensor data instead
+    PreviewTooSmall,
+    /// dcraw mis-decodes these bodies (X-Trans, newer compression), use libraw
+    PreferLibraw,
+    /// rawloader decodes the raw data natively, no external tool needed
+    PreferRawloader,
+}
+
+/// Tuning for one make, or one model family of a make
+pub struct CameraProfile {
+    /// Case-insensitive substring of the EXIF Make
+    pub make: &'static str,
+    /// Case-insensitive prefix of the EXIF Model; empty matches every model
+    pub model_prefix: &'static str,
+    /// Exiftool preview tags in order of preference
+    pub preview_tags: &'static [&'static str],
+    /// Typical size of the preferred preview; anything under half is rejected
+    pub typical_preview_bytes: u64,
+    pub quirks: &'static [Quirk],
+}
+
+impl CameraProfile {
+    pub fn has_quirk(&self, quirk: Quirk) -> bool {
+        self.quirks.contains(&quirk)
+    }
+
+    /// Smallest preview worth keeping for this camera
+    pub fn min_preview_bytes(&self) -> u64 {
+        (self.typical_preview_bytes / 2).max(10_000)
+    }
+}
+
+/// Model-specific entries come before the catch-all entry for their make
+static PROFILES: &[CameraProfile] = &[
+    // Canon: full-size JPEG in IFD0 of CR2, PRVW box in CR3
+    CameraProfile {
+        make: "canon",
+        model_prefix: "",
+        preview_tags: &["-PreviewImage", "-JpgFromRaw"],
+        typical_preview_bytes: 1_500_000,
+        quirks: &[],
+    },
+    // Nikon: JpgFromRaw is full size, PreviewImage only 640px
+    CameraProfile {
+        make: "nikon",
+        model_prefix: "",
+        preview_tags: &["-JpgFromRaw", "-PreviewImage"],
+        typical_preview_bytes: 1_000_000,
+        quirks: &[],
+    },
+    // Sony: 1616x1080 PreviewImage, full-size JpgFromRaw only on some bodies
+    CameraProfile {
+        make: "sony",
+        model_prefix: "",
+        preview_tags: &["-JpgFromRaw", "-PreviewImage"],
+        typical_preview_bytes: 300_000,
+        quirks: &[],
+    },
+    // Fujifilm X-Trans sensors are not Bayer, dcraw output is unusable
+    CameraProfile {
+        make: "fujifilm",
+        model_prefix: "x",
+        preview_tags: &["-PreviewImage", "-JpgFromRaw"],
+        typical_preview_bytes: 800_000,
+        quirks: &[Quirk::PreferLibraw],
+    },
+    CameraProfile {
+        make: "fujifilm",
+        model_prefix: "",
+        preview_tags: &["-PreviewImage", "-JpgFromRaw"],
+        typical_preview_bytes: 800_000,
+        quirks: &[],
+    },
+    // Olympus / OM System: 3200x2400 preview in the CameraSettings maker notes
+    CameraProfile {
+        make: "olympus",
+        model_prefix: "",
+        preview_tags: &["-PreviewImage", "-ThumbnailImage"],
+        typical_preview_bytes: 1_000_000,
+        quirks: &[],
+    },
+    CameraProfile {
+        make: "om digital",
+        model_prefix: "",
+        preview_tags: &["-PreviewImage", "-ThumbnailImage"],
+        typical_preview_bytes: 1_000_000,
+        quirks: &[],
+    },
+    // Panasonic: 1920x1440 JpgFromRaw in IFD0
+    CameraProfile {
+        make: "panasonic",
+        model_prefix: "",
+        preview_tags: &["-JpgFromRaw", "-PreviewImage"],
+        typical_preview_bytes: 400_000,
+        quirks: &[Quirk::PreferRawloader],
+    },
+    CameraProfile {
+        make: "pentax",
+        model_prefix: "",
+        preview_tags: &["-PreviewImage", "-JpgFromRaw"],
+        typical_preview_bytes: 500_000,
+        quirks: &[],
+    },
+    CameraProfile {
+        make: "ricoh",
+        model_prefix: "",
+        preview_tags: &["-PreviewImage", "-JpgFromRaw"],
+        typical_preview_bytes: 500_000,
+        quirks: &[],
+    },
+    // DJI drone DNGs only carry a tiny preview
+    CameraProfile {
+        make: "dji",
+        model_prefix: "",
+        preview_tags: &["-PreviewImage"],
+        typical_preview_bytes: 20_000,
+        quirks: &[Quirk::PreviewTooSmall, Quirk::PreferRawloader],
+    },
+    // Leica DNGs: small preview, clean DNG raw data
+    CameraProfile {
+        make: "leica",
+        model_prefix: "",
+        preview_tags: &["-PreviewImage", "-ThumbnailImage"],
+        typical_preview_bytes: 100_000,
+        quirks: &[Quirk::PreviewTooSmall, Quirk::PreferRawloader],
+    },
+    CameraProfile {
+        make: "samsung",
+        model_prefix: "",
+        preview_tags: &["-PreviewImage", "-ThumbnailImage"],
+        typical_preview_bytes: 300_000,
+        quirks: &[Quirk::PreferRawloader],
+    },
+    // Kodak only stores a small IFD0 thumbnail
+    CameraProfile {
+        make: "kodak",
+        model_prefix: "",
+        preview_tags: &["-ThumbnailImage", "-PreviewImage"],
+        typical_preview_bytes: 10_000,
+        quirks: &[Quirk::PreviewTooSmall, Quirk::PreferRawloader],
+    },
+];
+
+/// Read the EXIF Make and Model of a RAW file (TIFF-based containers and RAF)
+pub fn read_make_model(path: &str) -> Option<(String, String)> {
+    if let Ok(mut file) = tiff::TiffFile::open(path) {
+        let ifds = file.ifds().ok()?;
+        let ifd0 = ifds.first()?;
+        let make = ifd0.find(tiff::TAG_MAKE).and_then(|e| file.value_ascii(e))?;
+        let model = ifd0
+            .find(tiff::TAG_MODEL)
+            .and_then(|e| file.value_ascii(e))
+            .unwrap_or_default();
+        return Some((make, model));
+    }
+
+    // RAF keeps the model name in its own header (32 bytes at offset 28)
+    let mut header = [0u8; 60];
+    File::open(path).ok()?.read_exact(&mut header).ok()?;
+    if !header.starts_with(b"FUJIFILM") {
+        return None;
+    }
+    let model = String::from_utf8_lossy(&header[28..60]);
+    Some(("FUJIFILM".to_string(), model.trim_end_matches('\0').trim().to_string()))
+}
+
+/// Find the most specific profile for a make/model
+pub fn lookup(make: &str, model: &str) -> Option<&'static CameraProfile> {
+    let make = make.to_lowercase();
+    let model = model.to_lowercase();
+    PROFILES
+        .iter()
+        .find(|p| make.contains(p.make) && model.starts_with(p.model_prefix))
+}
+
+/// Look up the profile for a file from its EXIF make/model
+pub fn lookup_for_file(path: &str) -> Option<&'static CameraProfile> {
+    let (make, model) = read_make_model(path)?;
+    lookup(&make, &model)
+}
@@ -0,0 +1,138 @@
+// src/cameras.rs
+// Scan results per camera body, keyed by the EXIF serial number, to trace duplicates back to a card
+
+use std::collections::{BTreeMap, HashSet};
+
+use rayon::prelude::*;
+
+use crate::{orientation, tiff};
+
+/// The body a file was shot with, from its EXIF
+#[derive(Default)]
+pub struct Body {
+    pub serial: Option<String>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    /// `YYYY-MM-DD HH:MM:SS`, from DateTimeOriginal or else DateTime
+    pub captured: Option<String>,
+}
+
+/// `YYYY:MM:DD HH:MM:SS` as cameras write it, with dashes in the date so it
+/// reads as a date and still sorts as text
+fn capture_time(text: &str) -> Option<String> {
+    let date = text.get(..10)?;
+    let time = text.get(11..19)?;
+    let valid = date.bytes().enumerate().all(|(i, b)| if i == 4 || i == 7 { b == b':' } else { b.is_ascii_digit() });
+    (valid && date != "0000:00:00").then(|| format!("{} {}", date.replace(':', "-"), time))
+}
+
+/// Serial number, make, model and capture time of a JPEG or TIFF-based RAW file
+///
+/// The serial is the EXIF BodySerialNumber, or the DNG CameraSerialNumber;
+/// serials only kept in maker notes are not read.
+pub fn read_body(path: &str) -> Body {
+    let mut body = Body::default();
+    let base = orientation::jpeg_exif_offset(path).unwrap_or(0);
+    let Ok(mut file) = tiff::TiffFile::open_at(path, base) else {
+        return body;
+    };
+    let Some(ifd0) = file.ifds().ok().and_then(|ifds| ifds.into_iter().next()) else {
+        return body;
+    };
+    let exif = ifd0.find(tiff::TAG_EXIF_IFD).and_then(|entry| file.sub_ifd(entry).ok());
+
+    let mut ascii = |ifd: &tiff::Ifd, tag| ifd.find(tag).and_then(|entry| file.value_ascii(entry)).filter(|s| !s.is_empty());
+    body.make = ascii(&ifd0, tiff::TAG_MAKE);
+    body.model = ascii(&ifd0, tiff::TAG_MODEL);
+    body.serial = exif.as_ref().and_then(|exif| ascii(exif, tiff::TAG_BODY_SERIAL_NUMBER));
+    body.serial = body.serial.or_else(|| ascii(&ifd0, tiff::TAG_CAMERA_SERIAL_NUMBER));
+    let original = exif.as_ref().and_then(|exif| ascii(exif, tiff::TAG_DATE_TIME_ORIGINAL));
+    body.captured = original.or_else(|| ascii(&ifd0, tiff::TAG_DATE_TIME)).as_deref().and_then(capture_time);
+    body
+}
+
+/// Totals for one camera body
+pub struct CameraStats {
+    /// None for files without a serial number, which are pooled by make and model
+    pub serial: Option<String>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub files: usize,
+    /// Files that are in at least one duplicate group
+    pub duplicate_files: usize,
+    /// Duplicate groups with at least one file from this body
+    pub groups: usize,
+    pub first_capture: Option<String>,
+    pub last_capture: Option<String>,
+}
+
+impl CameraStats {
+    pub fn duplicate_rate(&self) -> f64 {
+        if self.files == 0 {
+            return 0.0;
+        }
+        self.duplicate_files as f64 / self.files as f64
+    }
+}
+
+/// Bodies are told apart by serial; files without one by make and model
+type BodyKey = (Option<String>, Option<String>, Option<String>);
+
+/// Aggregate `files` and the duplicate `groups` among them per camera body,
+/// most duplicate files first
+///
+/// Files only listed in a group count as scanned too.
+pub fn stats(files: &HashSet<String>, groups: &[Vec<String>]) -> Vec<CameraStats> {
+    let mut paths: Vec<&String> = files.iter().chain(groups.iter().flatten()).collect();
+    paths.sort();
+    paths.dedup();
+    let bodies: Vec<Body> = paths.par_iter().map(|path| read_body(path)).collect();
+
+    let mut by_path = std::collections::HashMap::new();
+    let mut cameras: BTreeMap<BodyKey, CameraStats> = BTreeMap::new();
+    for (path, body) in paths.iter().zip(bodies) {
+        let key = match &body.serial {
+            Some(serial) => (Some(serial.clone()), None, None),
+            None => (None, body.make.clone(), body.model.clone()),
+        };
+        let camera = cameras.entry(key.clone()).or_insert_with(|| CameraStats {
+            serial: body.serial.clone(),
+            make: body.make.clone(),
+            model: body.model.clone(),
+            files: 0,
+            duplicate_files: 0,
+            groups: 0,
+            first_capture: None,
+            last_capture: None,
+        });
+        camera.files += 1;
+        if let Some(captured) = body.captured {
+            if camera.first_capture.as_ref().is_none_or(|first| &captured < first) {
+                camera.first_capture = Some(captured.clone());
+            }
+            if camera.last_capture.as_ref().is_none_or(|last| &captured > last) {
+                camera.last_capture = Some(captured);
+            }
+        }
+        by_path.insert(path.as_str(), key);
+    }
+
+    let mut duplicates: HashSet<&str> = HashSet::new();
+    for group in groups {
+        let mut touched = HashSet::new();
+        for path in group {
+            let key = &by_path[path.as_str()];
+            if duplicates.insert(path.as_str()) {
+                cameras.get_mut(key).expect("every path has a body").duplicate_files += 1;
+            }
+            touched.insert(key);
+        }
+        for key in touched {
+            cameras.get_mut(key).expect("every path has a body").groups += 1;
+        }
+    }
+
+    let mut cameras: Vec<CameraStats> = cameras.into_values().collect();
+    cameras.sort_by(|a, b| b.duplicate_files.cmp(&a.duplicate_files).then(b.files.cmp(&a.files)));
+    cameras
+}
@@ -0,0 +1,204 @@
+// src/cfa.rs
+// Bayer color filter array layout: read from the raw metadata, with manual overrides
+
+use std::cell::Cell;
+use std::sync::RwLock;
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+
+// Channel indices as used by rawloader's CFA
+const RED: usize = 0;
+const GREEN: usize = 1;
+const BLUE: usize = 2;
+
+/// Position `(dy, dx)` inside a 2x2 Bayer tile
+pub type TileOffset = (usize, usize);
+
+/// Channel of each position in a 2x2 Bayer tile, `[row][col]`
+#[derive(Clone, Copy, PartialEq)]
+pub struct CfaPattern([[usize; 2]; 2]);
+
+/// Historical assumption for files without usable CFA metadata
+pub const RGGB: CfaPattern = CfaPattern([[RED, GREEN], [GREEN, BLUE]]);
+
+impl CfaPattern {
+    /// Parse a layout name like `RGGB`, `BGGR`, `GRBG` or `GBRG`
+    pub fn parse(name: &str) -> PyResult<Self> {
+        let channels: Vec<usize> = name
+            .to_uppercase()
+            .chars()
+            .filter_map(|c| match c {
+                'R' => Some(RED),
+                'G' => Some(GREEN),
+                'B' => Some(BLUE),
+                _ => None,
+            })
+            .collect();
+
+        let valid = channels.len() == 4
+            && name.len() == 4
+            && channels.iter().filter(|&&c| c == GREEN).count() == 2
+            && channels.contains(&RED)
+            && channels.contains(&BLUE);
+        if !valid {
+            return Err(PyValueError::new_err(format!(
+                "Unsupported CFA pattern '{}', expected a Bayer layout such as 'RGGB', 'BGGR', 'GRBG' or 'GBRG'",
+                name
+            )));
+        }
+        Ok(CfaPattern([[channels[0], channels[1]], [channels[2], channels[3]]]))
+    }
+
+    pub fn name(&self) -> String {
+        self.0
+            .iter()
+            .flatten()
+            .map(|&c| match c {
+                RED => 'R',
+                GREEN => 'G',
+                _ => 'B',
+            })
+            .collect()
+    }
+
+    /// Channel (0 red, 1 green, 2 blue) of the photosite at `(y, x)`
+    pub fn color_at(&self, y: usize, x: usize) -> usize {
+        self.0[y % 2][x % 2]
+    }
+
+    /// Offsets `(dy, dx)` in the 2x2 tile of the red site, both green sites and the blue site
+    pub fn tile_offsets(&self) -> (TileOffset, [TileOffset; 2], TileOffset) {
+        let mut red = (0, 0);
+        let mut blue = (1, 1);
+        let mut greens = Vec::with_capacity(2);
+        for dy in 0..2 {
+            for dx in 0..2 {
+                match self.0[dy][dx] {
+                    RED => red = (dy, dx),
+                    BLUE => blue = (dy, dx),
+                    _ => greens.push((dy, dx)),
+                }
+            }
+        }
+        (red, [greens[0], greens[1]], blue)
+    }
+
+    /// Bilinear demosaicing of a `width` x `height` mosaic, as interleaved RGB
+    ///
+    /// Each photosite keeps its own channel; a missing channel is the mean of
+    /// the nearest sites of that color: the edge neighbors when any of them
+    /// has it (green everywhere, red/blue on green sites), otherwise the
+    /// corner neighbors (blue on red sites and red on blue sites).
+    pub fn demosaic_bilinear(&self, width: usize, height: usize, site: impl Fn(usize, usize) -> f32) -> Vec<f32> {
+        const EDGES: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+        const CORNERS: [(isize, isize); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let own = self.color_at(y, x);
+                let mean = |color: usize, offsets: &[(isize, isize)]| -> Option<f32> {
+                    let (sum, count) = offsets
+                        .iter()
+                        .filter_map(|&(dy, dx)| {
+                            let ny = y.checked_add_signed(dy).filter(|&ny| ny < height)?;
+                            let nx = x.checked_add_signed(dx).filter(|&nx| nx < width)?;
+                            (self.color_at(ny, nx) == color).then(|| site(ny, nx))
+                        })
+                        .fold((0.0, 0u32), |(sum, count), value| (sum + value, count + 1));
+                    (count > 0).then(|| sum / count as f32)
+                };
+                rgb.extend((0..3).map(|color| {
+                    if color == own {
+                        site(y, x)
+                    } else {
+                        mean(color, &EDGES).or_else(|| mean(color, &CORNERS)).unwrap_or(0.0)
+                    }
+                }));
+            }
+        }
+        rgb
+    }
+
+    /// The 2x2 layout rawloader read from the file, if it is a plain Bayer pattern
+    fn from_raw(raw_image: &rawloader::RawImage) -> Option<Self> {
+        let cfa = &raw_image.cfa;
+        if cfa.width != 2 || cfa.height != 2 {
+            return None;
+        }
+        let pattern = CfaPattern([[cfa.color_at(0, 0), cfa.color_at(0, 1)], [cfa.color_at(1, 0), cfa.color_at(1, 1)]]);
+        Self::parse(&pattern.name()).ok().filter(|parsed| *parsed == pattern)
+    }
+}
+
+fn global_override() -> &'static RwLock<Option<CfaPattern>> {
+    static OVERRIDE: RwLock<Option<CfaPattern>> = RwLock::new(None);
+    &OVERRIDE
+}
+
+thread_local! {
+    static CALL_OVERRIDE: Cell<Option<CfaPattern>> = const { Cell::new(None) };
+}
+
+/// Force a layout for every decode (None goes back to reading the metadata)
+pub fn set_global_override(pattern: Option<CfaPattern>) {
+    *global_override().write().unwrap_or_else(|e| e.into_inner()) = pattern;
+}
+
+pub fn global_override_pattern() -> Option<CfaPattern> {
+    *global_override().read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Forces a layout for conversions on this thread until dropped
+pub struct ScopedOverride {
+    previous: Option<CfaPattern>,
+}
+
+impl ScopedOverride {
+    pub fn new(pattern: Option<CfaPattern>) -> Self {
+        let previous = CALL_OVERRIDE.with(|cell| cell.replace(pattern.or(cell.get())));
+        ScopedOverride { previous }
+    }
+}
+
+impl Drop for ScopedOverride {
+    fn drop(&mut self) {
+        CALL_OVERRIDE.with(|cell| cell.set(self.previous));
+    }
+}
+
+/// Layout to demosaic `raw_image` with: per-call override, global override,
+/// the file's own metadata, then RGGB
+pub fn pattern_for(raw_image: &rawloader::RawImage) -> CfaPattern {
+    CALL_OVERRIDE
+        .with(|cell| cell.get())
+        .or_else(global_override_pattern)
+        .or_else(|| CfaPattern::from_raw(raw_image))
+        .unwrap_or(RGGB)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bilinear_uses_the_nearest_sites_of_each_color() {
+        // RGGB: red at even/even, blue at odd/odd
+        let site = |y: usize, x: usize| (y * 4 + x) as f32;
+        let rgb = RGGB.demosaic_bilinear(4, 4, site);
+        let at = |y: usize, x: usize| &rgb[(y * 4 + x) * 3..][..3];
+
+        // Red site (2, 2): green from the 4 edge neighbors, blue from the 4 corners
+        assert_eq!(at(2, 2), [10.0, (6.0 + 14.0 + 9.0 + 11.0) / 4.0, (5.0 + 7.0 + 13.0 + 15.0) / 4.0]);
+        // Green site (1, 2) on a blue row: red above and below, blue left and right
+        assert_eq!(at(1, 2), [(2.0 + 10.0) / 2.0, 6.0, (5.0 + 7.0) / 2.0]);
+        // Corner blue site (3, 3): only one red corner neighbor inside the image
+        assert_eq!(at(3, 3), [10.0, (11.0 + 14.0) / 2.0, 15.0]);
+    }
+
+    #[test]
+    fn flat_mosaics_stay_flat() {
+        let rgb = RGGB.demosaic_bilinear(5, 3, |_, _| 0.5);
+        assert!(rgb.iter().all(|&v| v == 0.5));
+    }
+}
@@ -0,0 +1,170 @@
+// src/checksum.rs
+// BLAKE3 content checksums for integrity auditing
+
+use std::fs::File;
+use std::io::{self, Read};
+
+use memmap2::Mmap;
+use pyo3::exceptions::PyValueError;
+use pyo3::PyResult;
+
+use crate::throttle;
+
+/// Read size per chunk; each chunk is charged to the shared IO throttle
+const CHUNK_BYTES: usize = 1024 * 1024;
+/// Span of a mapped file hashed (and charged to the throttle) at a time;
+/// large enough for BLAKE3 to spread each span over the thread pool
+const MAPPED_CHUNK_BYTES: usize = 16 * 1024 * 1024;
+
+/// Feed a file's contents to `update` in fixed-size chunks
+fn stream(path: &str, mut update: impl FnMut(&[u8])) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0u8; CHUNK_BYTES];
+
+    throttle::acquire(0, 1);
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        throttle::acquire(read as u64, 0);
+        update(&buffer[..read]);
+    }
+}
+
+/// Hex BLAKE3 digest of a file's contents, streamed in fixed-size chunks
+pub fn blake3_file(path: &str) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    stream(path, |chunk| {
+        hasher.update(chunk);
+    })?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Digest used for exact-duplicate detection
+#[derive(Clone, Copy)]
+pub enum Algorithm {
+    Blake3,
+    /// 128-bit XXH3: not cryptographic, but several times faster where the disk keeps up
+    Xxh3,
+}
+
+impl Algorithm {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "blake3" => Ok(Algorithm::Blake3),
+            "xxh3" => Ok(Algorithm::Xxh3),
+            _ => Err(PyValueError::new_err(format!(
+                "Unsupported content hash '{}', expected 'blake3' or 'xxh3'",
+                name
+            ))),
+        }
+    }
+}
+
+/// Hex digest of a file's contents, streamed like `blake3_file` or with
+/// `mapped` read through a memory map
+///
+/// The BLAKE3 digest is the one `blake3_file` returns either way. Mapping
+/// skips the copy into a read buffer, and BLAKE3 hashes each span on several
+/// threads, so one large RAW hashes at memory speed once cached; but a file
+/// truncated by another process while it is mapped crashes the process, so
+/// only map files nothing writes to during the scan.
+pub fn content_hash(path: &str, algorithm: Algorithm, mapped: bool) -> io::Result<String> {
+    if mapped {
+        return mapped_hash(path, algorithm);
+    }
+    match algorithm {
+        Algorithm::Blake3 => blake3_file(path),
+        Algorithm::Xxh3 => {
+            let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+            stream(path, |chunk| hasher.update(chunk))?;
+            Ok(format!("{:032x}", hasher.digest128()))
+        },
+    }
+}
+
+/// `content_hash` through a memory map
+fn mapped_hash(path: &str, algorithm: Algorithm) -> io::Result<String> {
+    let file = File::open(path)?;
+    throttle::acquire(0, 1);
+    let length = file.metadata()?.len();
+    // Zero-length maps are an error on some platforms
+    let map = if length == 0 {
+        None
+    } else {
+        // SAFETY: the map is read-only and dropped before returning; a concurrent
+        // truncation is the caveat documented on `content_hash`
+        Some(unsafe { Mmap::map(&file)? })
+    };
+    let data = map.as_deref().unwrap_or(&[]);
+
+    Ok(match algorithm {
+        Algorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            for span in data.chunks(MAPPED_CHUNK_BYTES) {
+                throttle::acquire(span.len() as u64, 0);
+                hasher.update_rayon(span);
+            }
+            hasher.finalize().to_hex().to_string()
+        },
+        Algorithm::Xxh3 => {
+            let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+            for span in data.chunks(MAPPED_CHUNK_BYTES) {
+                throttle::acquire(span.len() as u64, 0);
+                hasher.update(span);
+            }
+            format!("{:032x}", hasher.digest128())
+        },
+    })
+}
+
+/// Outcome of re-checking one recorded checksum
+pub enum Verification {
+    Ok,
+    /// Contents changed since the checksum was recorded (carries the new digest)
+    Mismatch(String),
+    Missing,
+    Error(String),
+}
+
+impl Verification {
+    pub fn status(&self) -> &'static str {
+        match self {
+            Verification::Ok => "ok",
+            Verification::Mismatch(_) => "mismatch",
+            Verification::Missing => "missing",
+            Verification::Error(_) => "error",
+        }
+    }
+}
+
+/// Re-checksum `path` and compare with the digest recorded at scan time
+pub fn verify(path: &str, expected: &str) -> Verification {
+    match blake3_file(path) {
+        Ok(actual) if actual.eq_ignore_ascii_case(expected) => Verification::Ok,
+        Ok(actual) => Verification::Mismatch(actual),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Verification::Missing,
+        Err(e) => Verification::Error(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streamed_and_mapped_digests_agree() {
+        let path = crate::tiff::fixtures::temp_path("checksum_content.bin");
+        for length in [0, 1000, CHUNK_BYTES + 7] {
+            let data: Vec<u8> = (0..length).map(|i| (i * 31 % 251) as u8).collect();
+            std::fs::write(&path, &data).unwrap();
+            let path = path.to_str().unwrap();
+            assert_eq!(content_hash(path, Algorithm::Blake3, false).unwrap(), blake3::hash(&data).to_hex().to_string());
+            for algorithm in [Algorithm::Blake3, Algorithm::Xxh3] {
+                assert_eq!(content_hash(path, algorithm, false).unwrap(), content_hash(path, algorithm, true).unwrap());
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+// src/compaction.rs
+// Index maintenance: finding the settings nothing will read again so they can be dropped
+
+use std::io;
+
+use rayon::prelude::*;
+
+use crate::image_stats;
+use crate::index::IndexStore;
+use crate::sessions;
+
+/// Settings `compact` found, by kind
+#[derive(Default)]
+pub struct Compaction {
+    /// Quality stats of files that are gone or changed since they were measured
+    pub stale_stats: usize,
+    /// Session chunks emptied by saves of older versions
+    pub emptied_chunks: usize,
+}
+
+/// Drop the settings nothing will read again, or with `dry_run` only count them
+///
+/// Records and duplicate decisions are left alone: a file missing now may
+/// be on an unmounted drive, and decisions follow moved files.
+pub fn compact(store: &mut dyn IndexStore, dry_run: bool) -> io::Result<Compaction> {
+    let settings = store.settings()?;
+    // Checking stats stats their files, which adds up on a network share
+    let stale: Vec<(&str, bool)> = settings
+        .par_iter()
+        .filter_map(|(key, value)| {
+            if sessions::is_emptied_chunk(key, value) {
+                Some((key.as_str(), false))
+            } else {
+                image_stats::is_stale(key, value).then_some((key.as_str(), true))
+            }
+        })
+        .collect();
+
+    let mut compaction = Compaction::default();
+    for (key, is_stats) in stale {
+        if !dry_run {
+            store.remove_setting(key)?;
+        }
+        match is_stats {
+            true => compaction.stale_stats += 1,
+            false => compaction.emptied_chunks += 1,
+        }
+    }
+    if !dry_run {
+        store.flush()?;
+    }
+    Ok(compaction)
+}
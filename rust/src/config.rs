@@ -0,0 +1,463 @@
+// src/config.rs
+// JSON settings file applied on load and re-read on reload, for long-running processes
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::PyResult;
+use serde_json::{Map, Value};
+
+use crate::{deadline, formats, grayscale, memory, process, profiles, script, sidecar, skiplist, storage, throttle, thumbnails, tuning};
+
+/// Settings named in a config file
+///
+/// Keys left out keep their current values, except keys an earlier `load`
+/// applied, which go back to their defaults.
+#[derive(Default)]
+pub struct Config {
+    /// Selected before the other keys, so they can adjust it
+    scan_profile: Option<&'static profiles::ScanProfile>,
+    max_external_processes: Option<usize>,
+    io_limits: Option<(u64, u64)>,
+    memory_budget: Option<u64>,
+    grayscale_mode: Option<grayscale::LumaMode>,
+    /// `Some(None)` removes the per-file deadline
+    file_deadline: Option<Option<Duration>>,
+    storage_policies: Vec<(storage::StorageKind, storage::StoragePolicy)>,
+    /// `Some(None)` turns the shared cache off
+    cache_dir: Option<Option<PathBuf>>,
+    skip_list: Option<Option<(PathBuf, u32)>>,
+    derived_file_rules: Option<Vec<sidecar::Rule>>,
+    /// Extension to handler, None removing the mapping
+    extension_handlers: Vec<(String, Option<String>)>,
+    thumbnail_size: Option<u32>,
+    timeout: Option<Duration>,
+    jpeg_quality: Option<u8>,
+    thresholds: Option<tuning::Thresholds>,
+    watched_directories: Option<Vec<PathBuf>>,
+    duplicate_policy: Option<script::Policy>,
+    /// Every setting named, nested ones as `storage_policies.local` and
+    /// `extension_handlers.cr2`
+    keys: BTreeSet<String>,
+    /// Settings named by the previous file but not this one
+    removed: Vec<String>,
+}
+
+fn invalid(key: &str, expected: &str) -> pyo3::PyErr {
+    PyValueError::new_err(format!("Invalid config: '{}' must be {}", key, expected))
+}
+
+fn as_u64(value: &Value, key: &str) -> PyResult<u64> {
+    value.as_u64().ok_or_else(|| invalid(key, "a non-negative integer"))
+}
+
+/// Optional non-negative integer field of a nested object, 0 when absent
+fn field_u64(object: &Map<String, Value>, key: &str, name: &str) -> PyResult<u64> {
+    object.get(name).map(|v| as_u64(v, &format!("{}.{}", key, name))).unwrap_or(Ok(0))
+}
+
+fn as_object<'a>(value: &'a Value, key: &str) -> PyResult<&'a Map<String, Value>> {
+    value.as_object().ok_or_else(|| invalid(key, "an object"))
+}
+
+fn storage_policy(value: &Value, key: &str) -> PyResult<storage::StoragePolicy> {
+    let object = as_object(value, key)?;
+    let max_processes = field_u64(object, key, "max_processes")? as usize;
+    let Some(backends) = object.get("backends").filter(|v| !v.is_null()) else {
+        return Ok(storage::StoragePolicy { max_processes, ..Default::default() });
+    };
+    let backends = backends
+        .as_array()
+        .filter(|names| !names.is_empty())
+        .ok_or_else(|| invalid(&format!("{}.backends", key), "a non-empty list of backend names"))?
+        .iter()
+        .map(|name| storage::Backend::parse(name.as_str().unwrap_or_default()))
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(storage::StoragePolicy { backends, max_processes })
+}
+
+/// Validate a whole config file before anything is applied
+pub fn parse(text: &str) -> PyResult<Config> {
+    let json: Value =
+        serde_json::from_str(text).map_err(|e| PyValueError::new_err(format!("Invalid config: {}", e)))?;
+    let root = as_object(&json, "config")?;
+    let mut config = Config::default();
+    config.keys.extend(root.keys().filter(|key| !matches!(key.as_str(), "storage_policies" | "extension_handlers")).cloned());
+
+    if let Some(value) = root.get("scan_profile") {
+        let expected = format!("one of {}", profiles::profile_names().join(", "));
+        config.scan_profile =
+            Some(value.as_str().and_then(profiles::lookup).ok_or_else(|| invalid("scan_profile", &expected))?);
+    }
+    if let Some(value) = root.get("max_external_processes") {
+        config.max_external_processes = Some(as_u64(value, "max_external_processes")? as usize);
+    }
+    if let Some(value) = root.get("io_limits") {
+        let object = as_object(value, "io_limits")?;
+        config.io_limits = Some((
+            field_u64(object, "io_limits", "bytes_per_second")?,
+            field_u64(object, "io_limits", "ops_per_second")?,
+        ));
+    }
+    if let Some(value) = root.get("memory_budget") {
+        config.memory_budget = Some(as_u64(value, "memory_budget")?);
+    }
+    if let Some(value) = root.get("grayscale_mode") {
+        let mode = value.as_str().ok_or_else(|| invalid("grayscale_mode", "'bt709', 'bt601', 'green' or 'max'"))?;
+        config.grayscale_mode = Some(grayscale::LumaMode::parse(mode)?);
+    }
+    if let Some(value) = root.get("file_deadline") {
+        config.file_deadline = Some(match value {
+            Value::Null => None,
+            _ => Some(
+                value
+                    .as_f64()
+                    .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                    .filter(|d| !d.is_zero())
+                    .ok_or_else(|| invalid("file_deadline", "a positive number of seconds or null"))?,
+            ),
+        });
+    }
+    if let Some(value) = root.get("storage_policies") {
+        for (name, policy) in as_object(value, "storage_policies")? {
+            let kind = storage::StorageKind::parse(name)?;
+            config.storage_policies.push((kind, storage_policy(policy, &format!("storage_policies.{}", name))?));
+            config.keys.insert(format!("storage_policies.{}", name.to_lowercase()));
+        }
+    }
+    if let Some(value) = root.get("cache_dir") {
+        config.cache_dir = Some(match value {
+            Value::Null => None,
+            Value::String(dir) => Some(PathBuf::from(dir)),
+            _ => return Err(invalid("cache_dir", "a path or null")),
+        });
+    }
+    if let Some(value) = root.get("skip_list") {
+        config.skip_list = Some(match value {
+            Value::Null => None,
+            Value::Object(object) => {
+                let path = object.get("path").and_then(Value::as_str).ok_or_else(|| invalid("skip_list.path", "a path"))?;
+                let max_failures = object.get("max_failures").map(|v| as_u64(v, "skip_list.max_failures")).unwrap_or(Ok(2))?;
+                Some((PathBuf::from(path), max_failures.min(u32::MAX as u64) as u32))
+            },
+            _ => return Err(invalid("skip_list", "an object or null")),
+        });
+    }
+    if let Some(value) = root.get("derived_file_rules") {
+        let rules = value.as_array().ok_or_else(|| invalid("derived_file_rules", "a list of [kind, pattern] pairs"))?;
+        config.derived_file_rules = Some(
+            rules
+                .iter()
+                .map(|rule| match rule.as_array().map(Vec::as_slice) {
+                    Some([Value::String(kind), Value::String(pattern)]) => sidecar::Rule::parse(kind, pattern)
+                        .ok_or_else(|| PyValueError::new_err(format!("Unknown rule kind '{}'", kind))),
+                    _ => Err(invalid("derived_file_rules", "a list of [kind, pattern] pairs")),
+                })
+                .collect::<PyResult<Vec<_>>>()?,
+        );
+    }
+    if let Some(value) = root.get("extension_handlers") {
+        for (extension, handler) in as_object(value, "extension_handlers")? {
+            let handler = match handler {
+                Value::Null => None,
+                Value::String(handler) => Some(handler.as_str()),
+                _ => return Err(invalid(&format!("extension_handlers.{}", extension), "an extension or null")),
+            };
+            let (extension, handler) = formats::validate(extension, handler)?;
+            config.keys.insert(format!("extension_handlers.{}", extension));
+            config.extension_handlers.push((extension, handler));
+        }
+    }
+    if let Some(value) = root.get("thumbnail_size") {
+        config.thumbnail_size = Some(
+            value
+                .as_u64()
+                .filter(|size| (1..=u32::MAX as u64).contains(size))
+                .ok_or_else(|| invalid("thumbnail_size", "a positive integer"))? as u32,
+        );
+    }
+    if let Some(value) = root.get("timeout") {
+        config.timeout = Some(
+            value
+                .as_f64()
+                .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                .filter(|d| !d.is_zero())
+                .ok_or_else(|| invalid("timeout", "a positive number of seconds"))?,
+        );
+    }
+    if let Some(value) = root.get("jpeg_quality") {
+        config.jpeg_quality = Some(
+            value
+                .as_u64()
+                .filter(|quality| (1..=100).contains(quality))
+                .ok_or_else(|| invalid("jpeg_quality", "an integer from 1 to 100"))? as u8,
+        );
+    }
+    if let Some(value) = root.get("thresholds") {
+        let object = as_object(value, "thresholds")?;
+        let field = |name: &str, default: u32| -> PyResult<u32> {
+            let key = format!("thresholds.{}", name);
+            object.get(name).map_or(Ok(default), |v| {
+                as_u64(v, &key)?.try_into().map_err(|_| invalid(&key, "a number of bits"))
+            })
+        };
+        let defaults = tuning::DEFAULT_THRESHOLDS;
+        config.thresholds = Some(tuning::Thresholds {
+            duplicate: field("duplicate_distance", defaults.duplicate)?,
+            similar: field("similar_distance", defaults.similar)?,
+            fine: field("fine_distance", defaults.fine)?,
+        });
+    }
+    if let Some(value) = root.get("watched_directories") {
+        let dirs = value.as_array().ok_or_else(|| invalid("watched_directories", "a list of directories"))?;
+        config.watched_directories = Some(
+            dirs.iter()
+                .map(|dir| {
+                    let dir = dir.as_str().ok_or_else(|| invalid("watched_directories", "a list of directories"))?;
+                    if !std::path::Path::new(dir).is_dir() {
+                        return Err(PyValueError::new_err(format!("Invalid config: watched directory '{}' does not exist", dir)));
+                    }
+                    Ok(PathBuf::from(dir))
+                })
+                .collect::<PyResult<Vec<_>>>()?,
+        );
+    }
+    if let Some(value) = root.get("duplicate_policy") {
+        let object = as_object(value, "duplicate_policy")?;
+        let action = object
+            .get("action")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("duplicate_policy.action", "'hardlink', 'delete' or 'move'"))?;
+        let move_to = match object.get("move_to") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(dir)) => Some(dir.as_str()),
+            Some(_) => return Err(invalid("duplicate_policy.move_to", "a path or null")),
+        };
+        config.duplicate_policy = Some(script::Policy::parse(action, move_to)?);
+    }
+    Ok(config)
+}
+
+fn watched() -> &'static RwLock<Vec<PathBuf>> {
+    static WATCHED: RwLock<Vec<PathBuf>> = RwLock::new(Vec::new());
+    &WATCHED
+}
+
+/// Directories named by `watched_directories` in the loaded config
+pub fn watched_directories() -> Vec<PathBuf> {
+    watched().read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Put a setting the reloaded file no longer names back to its default;
+/// settings a scan profile sets go back to `profile`'s value
+fn reset(key: &str, profile: &'static profiles::ScanProfile) {
+    if let Some(kind) = key.strip_prefix("storage_policies.") {
+        // Keys were lowercased by `parse`
+        let kind = match kind {
+            "local" => storage::StorageKind::Local,
+            "network" => storage::StorageKind::Network,
+            _ => return,
+        };
+        storage::set_policy(kind, profile.storage_policy());
+        process::limits_changed();
+        return;
+    }
+    if let Some(extension) = key.strip_prefix("extension_handlers.") {
+        formats::set(extension.to_string(), None);
+        return;
+    }
+    match key {
+        "max_external_processes" => process::set_max_processes(profile.max_processes),
+        "io_limits" => throttle::set_limits(0, 0),
+        "memory_budget" => memory::set_budget(profile.memory_budget),
+        "grayscale_mode" => grayscale::set_luma_mode(grayscale::LumaMode::Bt709),
+        "file_deadline" => deadline::set_budget(None),
+        "cache_dir" => thumbnails::use_disk_dir(None),
+        "skip_list" => skiplist::install(skiplist::Opened::none()),
+        "derived_file_rules" => sidecar::set_rules(sidecar::default_rules()),
+        "thumbnail_size" => tuning::set_thumbnail_size(tuning::DEFAULT_THUMBNAIL_SIZE),
+        "timeout" => tuning::set_step_timeout(tuning::DEFAULT_STEP_TIMEOUT),
+        "jpeg_quality" => tuning::set_jpeg_quality(tuning::DEFAULT_JPEG_QUALITY),
+        "thresholds" => tuning::set_thresholds(tuning::DEFAULT_THRESHOLDS),
+        "watched_directories" => watched().write().unwrap_or_else(|e| e.into_inner()).clear(),
+        "duplicate_policy" => script::set_policy(None),
+        // `scan_profile` is selected again by `load`; unknown keys set nothing
+        _ => {},
+    }
+}
+
+impl Config {
+    /// Apply the settings, or none of them
+    ///
+    /// Everything that can fail (creating the cache directory, reading the
+    /// skip-list) is done first; only then does any setting change.
+    pub fn apply(mut self) -> PyResult<()> {
+        let skip_list = self.prepare().map_err(PyIOError::new_err)?;
+        self.commit(skip_list);
+        Ok(())
+    }
+
+    /// The fallible part of `apply`; returns the skip-list to install
+    fn prepare(&mut self) -> Result<Option<skiplist::Opened>, String> {
+        if let Some(Some(dir)) = &self.cache_dir {
+            thumbnails::prepare_disk_dir(dir).map_err(|e| format!("Failed to create cache directory: {}", e))?;
+        }
+        self.skip_list
+            .take()
+            .map(|skip_list| {
+                let (file, max_failures) = skip_list.map_or((None, 0), |(file, max)| (Some(file), max));
+                skiplist::open(file, max_failures).map_err(|e| format!("Failed to read skip-list: {}", e))
+            })
+            .transpose()
+    }
+
+    /// Keys the last applied file named but this one does not
+    fn removed_since(&mut self, applied: &BTreeSet<String>) {
+        self.removed = applied.difference(&self.keys).cloned().collect();
+        if self.scan_profile.is_none() && applied.contains("scan_profile") {
+            self.scan_profile = profiles::lookup("default");
+        }
+    }
+
+    fn commit(self, skip_list: Option<skiplist::Opened>) {
+        if let Some(profile) = self.scan_profile {
+            profiles::select(profile);
+        }
+        let profile = profiles::active();
+        for key in &self.removed {
+            reset(key, profile);
+        }
+        if let Some(dir) = self.cache_dir {
+            thumbnails::use_disk_dir(dir);
+        }
+        if let Some(skip_list) = skip_list {
+            skiplist::install(skip_list);
+        }
+        if let Some(limit) = self.max_external_processes {
+            process::set_max_processes(limit);
+        }
+        if let Some((bytes_per_second, ops_per_second)) = self.io_limits {
+            throttle::set_limits(bytes_per_second, ops_per_second);
+        }
+        if let Some(bytes) = self.memory_budget {
+            memory::set_budget(bytes);
+        }
+        if let Some(mode) = self.grayscale_mode {
+            grayscale::set_luma_mode(mode);
+        }
+        if let Some(budget) = self.file_deadline {
+            deadline::set_budget(budget);
+        }
+        for (kind, policy) in self.storage_policies {
+            storage::set_policy(kind, Some(policy));
+            process::limits_changed();
+        }
+        for (extension, handler) in self.extension_handlers {
+            formats::set(extension, handler);
+        }
+        if let Some(rules) = self.derived_file_rules {
+            sidecar::set_rules(rules);
+        }
+        if let Some(size) = self.thumbnail_size {
+            tuning::set_thumbnail_size(size);
+        }
+        if let Some(timeout) = self.timeout {
+            tuning::set_step_timeout(timeout);
+        }
+        if let Some(quality) = self.jpeg_quality {
+            tuning::set_jpeg_quality(quality);
+        }
+        if let Some(thresholds) = self.thresholds {
+            tuning::set_thresholds(thresholds);
+        }
+        if let Some(dirs) = self.watched_directories {
+            *watched().write().unwrap_or_else(|e| e.into_inner()) = dirs;
+        }
+        if let Some(policy) = self.duplicate_policy {
+            script::set_policy(Some(policy));
+        }
+    }
+}
+
+fn source() -> &'static RwLock<Option<PathBuf>> {
+    static SOURCE: RwLock<Option<PathBuf>> = RwLock::new(None);
+    &SOURCE
+}
+
+/// Settings the last loaded file named, reset when a later file leaves them out
+fn applied() -> &'static RwLock<BTreeSet<String>> {
+    static APPLIED: RwLock<BTreeSet<String>> = RwLock::new(BTreeSet::new());
+    &APPLIED
+}
+
+/// Read, validate and apply `path`, remembering it for `reload`; returns the file's text
+pub fn load(path: PathBuf) -> PyResult<String> {
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| PyIOError::new_err(format!("Failed to read config {}: {}", path.display(), e)))?;
+    let mut config = parse(&text)?;
+    // Held while applying, so concurrent reloads apply one file after the other
+    let mut applied = applied().write().unwrap_or_else(|e| e.into_inner());
+    config.removed_since(&applied);
+    let keys = config.keys.clone();
+    config.apply()?;
+    *applied = keys;
+    *source().write().unwrap_or_else(|e| e.into_inner()) = Some(path);
+    Ok(text)
+}
+
+/// Load the last loaded file again
+pub fn reload() -> PyResult<String> {
+    let path = source().read().unwrap_or_else(|e| e.into_inner()).clone();
+    load(path.ok_or_else(|| PyValueError::new_err("No config loaded; call load_config first"))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_preparation_changes_nothing_and_removed_keys_reset() {
+        let watched_dir = crate::tiff::fixtures::temp_path("watched");
+        std::fs::create_dir_all(&watched_dir).unwrap();
+        let thresholds = tuning::Thresholds { duplicate: 7, ..tuning::DEFAULT_THRESHOLDS };
+
+        let mut config = Config {
+            thresholds: Some(thresholds),
+            watched_directories: Some(vec![watched_dir.clone()]),
+            duplicate_policy: Some(script::Policy { action: script::Action::Delete, move_to: None }),
+            keys: ["thresholds", "watched_directories", "duplicate_policy"].map(String::from).into(),
+            ..Config::default()
+        };
+        let skip_list = config.prepare().unwrap();
+        let applied = config.keys.clone();
+        config.commit(skip_list);
+        assert_eq!(tuning::thresholds(), thresholds);
+        assert_eq!(watched_directories(), std::slice::from_ref(&watched_dir));
+        assert!(script::policy().action == script::Action::Delete);
+
+        // A skip-list path that is a directory cannot be read: nothing is applied
+        let mut config = Config {
+            thresholds: Some(tuning::Thresholds { duplicate: 9, ..thresholds }),
+            skip_list: Some(Some((watched_dir.clone(), 2))),
+            keys: ["thresholds", "skip_list"].map(String::from).into(),
+            ..Config::default()
+        };
+        assert!(config.prepare().err().is_some_and(|e| e.starts_with("Failed to read skip-list")));
+        assert_eq!(tuning::thresholds(), thresholds);
+
+        // Keys dropped from the file go back to their defaults
+        let mut config = Config { keys: ["jpeg_quality"].map(String::from).into(), ..Config::default() };
+        config.removed_since(&applied);
+        assert_eq!(config.removed, ["duplicate_policy", "thresholds", "watched_directories"]);
+        let skip_list = config.prepare().unwrap();
+        config.commit(skip_list);
+        assert_eq!(tuning::thresholds(), tuning::DEFAULT_THRESHOLDS);
+        assert!(watched_directories().is_empty());
+        assert!(script::policy().action == script::Action::Hardlink);
+
+        std::fs::remove_dir_all(&watched_dir).unwrap();
+    }
+}
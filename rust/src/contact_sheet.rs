@@ -0,0 +1,155 @@
+// src/contact_sheet.rs
+// Grid montage of a duplicate group with a caption under every tile
+
+use image::{DynamicImage, Rgb, RgbImage, imageops};
+
+// Layout constants
+const PADDING: u32 = 8;
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const TEXT_SCALE: u32 = 2;
+const LINE_HEIGHT: u32 = (GLYPH_HEIGHT + 2) * TEXT_SCALE;
+const CAPTION_LINES: u32 = 2;
+
+const BACKGROUND: Rgb<u8> = Rgb([32, 32, 32]);
+const PLACEHOLDER: Rgb<u8> = Rgb([80, 80, 80]);
+const TEXT_COLOR: Rgb<u8> = Rgb([230, 230, 230]);
+
+/// One cell of the sheet: the thumbnail (None if it failed to decode) and its caption lines
+pub struct Tile {
+    pub image: Option<DynamicImage>,
+    pub caption: Vec<String>,
+}
+
+/// Render tiles into a grid with `columns` cells per row
+pub fn render(tiles: &[Tile], tile_size: u32, columns: u32) -> RgbImage {
+    let columns = columns.max(1).min(tiles.len().max(1) as u32);
+    let rows = (tiles.len() as u32).div_ceil(columns).max(1);
+    let cell_width = tile_size + PADDING;
+    let cell_height = tile_size + CAPTION_LINES * LINE_HEIGHT + PADDING;
+
+    let mut sheet = RgbImage::from_pixel(
+        columns * cell_width + PADDING,
+        rows * cell_height + PADDING,
+        BACKGROUND,
+    );
+
+    for (i, tile) in tiles.iter().enumerate() {
+        let x = PADDING + (i as u32 % columns) * cell_width;
+        let y = PADDING + (i as u32 / columns) * cell_height;
+
+        match &tile.image {
+            Some(img) => {
+                // Fit inside the square cell and center it
+                let thumb = img.thumbnail(tile_size, tile_size).to_rgb8();
+                let offset_x = x + (tile_size - thumb.width()) / 2;
+                let offset_y = y + (tile_size - thumb.height()) / 2;
+                imageops::overlay(&mut sheet, &thumb, offset_x as i64, offset_y as i64);
+            },
+            None => {
+                let placeholder = RgbImage::from_pixel(tile_size, tile_size, PLACEHOLDER);
+                imageops::overlay(&mut sheet, &placeholder, x as i64, y as i64);
+            },
+        }
+
+        let max_chars = (tile_size / ((GLYPH_WIDTH + 1) * TEXT_SCALE)) as usize;
+        for (line, text) in tile.caption.iter().take(CAPTION_LINES as usize).enumerate() {
+            let text_y = y + tile_size + 4 + line as u32 * LINE_HEIGHT;
+            draw_text(&mut sheet, x, text_y, &truncate(text, max_chars));
+        }
+    }
+
+    sheet
+}
+
+/// Shorten text to `max_chars`, keeping the end (where file names differ most)
+fn truncate(text: &str, max_chars: usize) -> String {
+    let count = text.chars().count();
+    if count <= max_chars || max_chars < 4 {
+        return text.chars().take(max_chars).collect();
+    }
+
+    let tail: String = text.chars().skip(count - (max_chars - 2)).collect();
+    format!("..{}", tail)
+}
+
+/// Draw text with the built-in 5x7 bitmap font
+fn draw_text(img: &mut RgbImage, x: u32, y: u32, text: &str) {
+    for (i, ch) in text.chars().enumerate() {
+        let glyph = glyph(ch);
+        let glyph_x = x + i as u32 * (GLYPH_WIDTH + 1) * TEXT_SCALE;
+
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+                for dy in 0..TEXT_SCALE {
+                    for dx in 0..TEXT_SCALE {
+                        let px = glyph_x + col * TEXT_SCALE + dx;
+                        let py = y + row as u32 * TEXT_SCALE + dy;
+                        if px < img.width() && py < img.height() {
+                            img.put_pixel(px, py, TEXT_COLOR);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Rows of a 5x7 glyph, most significant of the low 5 bits on the left
+///
+/// Letters are drawn in upper case; anything unknown becomes '?'.
+fn glyph(ch: char) -> [u8; 7] {
+    match ch.to_ascii_uppercase() {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
@@ -0,0 +1,67 @@
+// src/deadline.rs
+// Total time budget per file, shared by every stage and fallback of its processing
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Budget per file in milliseconds; 0 means no deadline
+static BUDGET_MS: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Set the per-file budget; None turns the deadline off
+pub fn set_budget(budget: Option<Duration>) {
+    let ms = budget.map(|b| (b.as_millis() as u64).max(1)).unwrap_or(0);
+    BUDGET_MS.store(ms, Ordering::Relaxed);
+}
+
+pub fn budget() -> Option<Duration> {
+    match BUDGET_MS.load(Ordering::Relaxed) {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
+    }
+}
+
+/// When the file being processed on this thread must be done, if a budget is set
+pub fn current() -> Option<Instant> {
+    DEADLINE.with(|cell| cell.get())
+}
+
+/// Whether this thread's file has run out of time
+pub fn expired() -> bool {
+    current().is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// Message for work abandoned at the deadline
+pub fn exceeded_message() -> String {
+    format!("Per-file deadline of {} ms exceeded", budget().map(|b| b.as_millis()).unwrap_or(0))
+}
+
+/// Starts the budget of one file on this thread, until dropped
+///
+/// Nested scopes (a batch entry calling the single-file pipeline) keep the
+/// outermost deadline, so the budget covers the file's whole processing.
+pub struct ScopedDeadline {
+    previous: Option<Instant>,
+}
+
+impl ScopedDeadline {
+    pub fn start() -> Self {
+        let previous = current();
+        if previous.is_none() {
+            if let Some(budget) = budget() {
+                DEADLINE.with(|cell| cell.set(Some(Instant::now() + budget)));
+            }
+        }
+        ScopedDeadline { previous }
+    }
+}
+
+impl Drop for ScopedDeadline {
+    fn drop(&mut self) {
+        DEADLINE.with(|cell| cell.set(self.previous));
+    }
+}
@@ -0,0 +1,98 @@
+// src/derivatives.rs
+// RAW files paired with the JPEGs exported or extracted from them, apart from exact duplicates
+
+use crate::matching::{self, Fingerprint, MatchProfile, MatchResult};
+
+/// How an image relates to the RAW file it came from
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Relationship {
+    /// An export: developed, resized or recropped from the RAW file
+    Derivative,
+    /// The RAW file's own embedded preview, saved as a file
+    Preview,
+}
+
+impl Relationship {
+    pub fn name(self) -> &'static str {
+        match self {
+            Relationship::Derivative => "derivative",
+            Relationship::Preview => "preview",
+        }
+    }
+}
+
+/// Fingerprints of one RAW file
+pub struct Original {
+    /// Of the decoded image, under the derivative profile
+    pub fingerprint: Fingerprint,
+    /// Of each embedded preview, under the strict profile
+    pub previews: Vec<Fingerprint>,
+}
+
+/// Fingerprints of one image that may derive from a RAW file
+pub struct Candidate {
+    pub fingerprint: Fingerprint,
+    pub strict: Fingerprint,
+}
+
+/// The RAW file an image derives from
+pub struct Link {
+    pub original: usize,
+    pub relationship: Relationship,
+    pub result: MatchResult,
+}
+
+fn strict() -> &'static MatchProfile {
+    matching::lookup("strict").expect("strict profile is built in")
+}
+
+/// How `candidate` relates to `original`, if at all
+///
+/// A preview is the camera's own rendering, so it is held to the strict
+/// profile; anything else has to match the decode under `profile`.
+fn relate(original: &Original, candidate: &Candidate, profile: &MatchProfile) -> Option<(Relationship, MatchResult)> {
+    let strict = strict();
+    let preview = original
+        .previews
+        .iter()
+        .map(|preview| matching::compare(preview, &candidate.strict, strict))
+        .filter(|result| result.matched)
+        .min_by_key(|result| result.distances.iter().sum::<u32>());
+    if let Some(result) = preview {
+        return Some((Relationship::Preview, result));
+    }
+    let result = matching::compare(&original.fingerprint, &candidate.fingerprint, profile);
+    result.matched.then_some((Relationship::Derivative, result))
+}
+
+/// The original each candidate most likely comes from, if any
+///
+/// A preview beats an export, then more agreeing hashes and smaller
+/// distances win. `None` entries (undecodable files) never link.
+pub fn link(
+    originals: &[Option<Original>],
+    candidates: &[Option<Candidate>],
+    profile: &MatchProfile,
+) -> Vec<Option<Link>> {
+    candidates
+        .iter()
+        .map(|candidate| {
+            let candidate = candidate.as_ref()?;
+            originals
+                .iter()
+                .enumerate()
+                .filter_map(|(index, original)| {
+                    let (relationship, result) = relate(original.as_ref()?, candidate, profile)?;
+                    Some(Link { original: index, relationship, result })
+                })
+                .max_by(|a, b| {
+                    let total = |link: &Link| link.result.distances.iter().sum::<u32>();
+                    a.relationship
+                        .cmp(&b.relationship)
+                        .then(a.result.votes.cmp(&b.result.votes))
+                        .then(total(b).cmp(&total(a)))
+                        .then(b.original.cmp(&a.original))
+                })
+        })
+        .collect()
+}
@@ -0,0 +1,162 @@
+// src/directories.rs
+// Directory-level duplicate detection from per-file hashes
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// One directory whose contents largely appear in another
+pub struct DirectoryOverlap {
+    pub directory: String,
+    pub other: String,
+    /// Distinct hashes of `directory` also present in `other`
+    pub shared: usize,
+    pub files: usize,
+    pub other_files: usize,
+    /// `shared / files`: how much of `directory` is contained in `other`
+    pub containment: f64,
+    /// Both directories hold exactly the same set of hashes
+    pub identical: bool,
+}
+
+/// Directory signatures: the distinct content hashes under each directory
+fn signatures(entries: &[(String, String)], recursive: bool) -> HashMap<String, HashSet<&str>> {
+    let mut signatures: HashMap<String, HashSet<&str>> = HashMap::new();
+    for (path, hash) in entries {
+        let mut dir = Path::new(path).parent();
+        while let Some(d) = dir.filter(|d| !d.as_os_str().is_empty()) {
+            signatures.entry(d.to_string_lossy().into_owned()).or_default().insert(hash.as_str());
+            if !recursive {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+    signatures
+}
+
+fn is_ancestor(ancestor: &str, path: &str) -> bool {
+    Path::new(path).starts_with(ancestor)
+}
+
+/// Find directories at least `min_containment` contained in another directory
+///
+/// `entries` are `(file path, content hash)` pairs; any hash works (BLAKE3,
+/// perceptual) as long as equal content means equal strings. With `recursive`
+/// a directory's signature includes its subdirectories, so `2019 backup/`
+/// matches `Photos/2019/` even when the months are split into subfolders.
+/// Directories with fewer than `min_files` distinct hashes are ignored, and
+/// so are pairs where one directory is inside the other.
+pub fn find_overlaps(
+    entries: &[(String, String)],
+    min_containment: f64,
+    min_files: usize,
+    recursive: bool,
+) -> Vec<DirectoryOverlap> {
+    let signatures = signatures(entries, recursive);
+
+    // A parent holding nothing beyond one subdirectory would repeat that subdirectory's
+    // matches. Only recursive signatures include subdirectories; without them a parent
+    // with the same hashes as a child holds copies of its own, not a pass-through.
+    let pass_through: HashSet<String> = signatures
+        .iter()
+        .filter(|_| recursive)
+        .filter_map(|(dir, hashes)| {
+            let parent = Path::new(dir).parent()?.to_string_lossy().into_owned();
+            (signatures.get(&parent)? == hashes).then_some(parent)
+        })
+        .collect();
+
+    let signatures: Vec<(&String, &HashSet<&str>)> = signatures
+        .iter()
+        .filter(|(dir, hashes)| hashes.len() >= min_files && !pass_through.contains(*dir))
+        .collect();
+
+    // Inverted index so each directory is only compared with directories it shares content with
+    let mut holders: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, (_, hashes)) in signatures.iter().enumerate() {
+        for &hash in hashes.iter() {
+            holders.entry(hash).or_default().push(index);
+        }
+    }
+
+    let mut overlaps = Vec::new();
+    for (index, (dir, hashes)) in signatures.iter().enumerate() {
+        let mut shared: HashMap<usize, usize> = HashMap::new();
+        for hash in hashes.iter() {
+            for &other in &holders[hash] {
+                if other != index {
+                    *shared.entry(other).or_default() += 1;
+                }
+            }
+        }
+
+        for (other, count) in shared {
+            let (other_dir, other_hashes) = signatures[other];
+            let containment = count as f64 / hashes.len() as f64;
+            if containment < min_containment || is_ancestor(dir, other_dir) || is_ancestor(other_dir, dir) {
+                continue;
+            }
+            overlaps.push(DirectoryOverlap {
+                directory: dir.to_string(),
+                other: other_dir.to_string(),
+                shared: count,
+                files: hashes.len(),
+                other_files: other_hashes.len(),
+                containment,
+                identical: count == hashes.len() && count == other_hashes.len(),
+            });
+        }
+    }
+
+    overlaps.sort_by(|a, b| {
+        b.containment
+            .total_cmp(&a.containment)
+            .then(b.shared.cmp(&a.shared))
+            .then_with(|| a.directory.cmp(&b.directory))
+            .then_with(|| a.other.cmp(&b.other))
+    });
+    overlaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(files: &[(&str, &str)]) -> Vec<(String, String)> {
+        files.iter().map(|(path, hash)| (path.to_string(), hash.to_string())).collect()
+    }
+
+    fn pairs(overlaps: &[DirectoryOverlap]) -> Vec<(&str, &str)> {
+        overlaps.iter().map(|o| (o.directory.as_str(), o.other.as_str())).collect()
+    }
+
+    #[test]
+    fn parent_of_single_subdirectory_is_skipped() {
+        let files = entries(&[("/a/only/1.jpg", "x"), ("/a/only/2.jpg", "y"), ("/b/1.jpg", "x"), ("/b/2.jpg", "y")]);
+        let overlaps = find_overlaps(&files, 1.0, 1, true);
+        assert_eq!(pairs(&overlaps), [("/a/only", "/b"), ("/b", "/a/only")]);
+    }
+
+    #[test]
+    fn parent_with_other_content_of_equal_count_is_kept() {
+        // `/a` and `/a/sub` both hold two files, but different ones
+        let files = entries(&[
+            ("/a/1.jpg", "x"),
+            ("/a/2.jpg", "y"),
+            ("/a/sub/3.jpg", "z"),
+            ("/a/sub/4.jpg", "w"),
+            ("/b/1.jpg", "x"),
+            ("/b/2.jpg", "y"),
+        ]);
+        let overlaps = find_overlaps(&files, 1.0, 1, false);
+        assert_eq!(pairs(&overlaps), [("/a", "/b"), ("/b", "/a")]);
+    }
+
+    #[test]
+    fn non_recursive_parent_matching_its_child_is_kept() {
+        let files = entries(&[("/a/1.jpg", "x"), ("/a/sub/1.jpg", "x"), ("/b/1.jpg", "x")]);
+        let overlaps = find_overlaps(&files, 1.0, 1, false);
+        assert!(pairs(&overlaps).contains(&("/a", "/b")));
+        assert!(pairs(&overlaps).contains(&("/a/sub", "/b")));
+    }
+}
@@ -0,0 +1,74 @@
+// src/exif.rs
+// Batch EXIF extraction into columns, one exiftool run per batch of files
+
+use crate::exiftool;
+use crate::naming;
+
+/// How a column's values are parsed
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColumnKind {
+    Text,
+    /// Floating point, NaN when missing
+    Number,
+}
+
+/// Column name, exiftool tag (`#` for the numeric value) and kind
+pub const COLUMNS: &[(&str, &str, ColumnKind)] = &[
+    ("make", "Make", ColumnKind::Text),
+    ("model", "Model", ColumnKind::Text),
+    ("lens_model", "LensModel", ColumnKind::Text),
+    ("capture_time", "DateTimeOriginal", ColumnKind::Number),
+    ("width", "ImageWidth#", ColumnKind::Number),
+    ("height", "ImageHeight#", ColumnKind::Number),
+    ("orientation", "Orientation#", ColumnKind::Number),
+    ("iso", "ISO#", ColumnKind::Number),
+    ("exposure_time", "ExposureTime#", ColumnKind::Number),
+    ("f_number", "FNumber#", ColumnKind::Number),
+    ("focal_length", "FocalLength#", ColumnKind::Number),
+    ("gps_latitude", "GPSLatitude#", ColumnKind::Number),
+    ("gps_longitude", "GPSLongitude#", ColumnKind::Number),
+];
+
+/// Metadata of many files, column by column in input order
+pub struct ExifTable {
+    /// Values per entry of `COLUMNS`; text columns use `None` for missing values
+    pub text: Vec<Vec<Option<String>>>,
+    pub numbers: Vec<Vec<f64>>,
+    /// Whether exiftool returned a row for the file at all
+    pub found: Vec<bool>,
+}
+
+/// Extract the `COLUMNS` fields of `paths` with parallel exiftool batches
+pub fn read_batch(paths: &[String]) -> ExifTable {
+    let tags: Vec<&str> =
+        std::iter::once("SubSecTimeOriginal").chain(COLUMNS.iter().map(|(_, tag, _)| *tag)).collect();
+    let rows = exiftool::read_tags(paths, &tags);
+
+    let mut table = ExifTable {
+        text: vec![Vec::with_capacity(paths.len()); COLUMNS.len()],
+        numbers: vec![Vec::with_capacity(paths.len()); COLUMNS.len()],
+        found: rows.iter().map(Option::is_some).collect(),
+    };
+
+    for row in &rows {
+        // The first field is SubSecTimeOriginal, folded into capture_time
+        let subsec = row.as_ref().map_or(0.0, |r| exiftool::subsec_fraction(&r[0]));
+
+        for (c, (name, _, kind)) in COLUMNS.iter().enumerate() {
+            let value = row.as_ref().and_then(|r| exiftool::field(&r[c + 1]));
+            match kind {
+                ColumnKind::Text => table.text[c].push(value.map(str::to_string)),
+                ColumnKind::Number => {
+                    let number = if *name == "capture_time" {
+                        value.and_then(naming::parse_exif_datetime).map_or(f64::NAN, |seconds| seconds + subsec)
+                    } else {
+                        value.and_then(|v| v.parse::<f64>().ok()).unwrap_or(f64::NAN)
+                    };
+                    table.numbers[c].push(number);
+                },
+            }
+        }
+    }
+
+    table
+}
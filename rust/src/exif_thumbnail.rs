@@ -0,0 +1,213 @@
+// src/exif_thumbnail.rs
+// EXIF thumbnails of JPEG and HEIC files, standing in for a full decode when a small grayscale thumbnail is all that is needed
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use image::DynamicImage;
+
+use crate::formats;
+use crate::orientation;
+use crate::tiff;
+
+// ExifIFD tags holding the size of the main image
+const TAG_PIXEL_X_DIMENSION: u16 = 0xa002;
+const TAG_PIXEL_Y_DIMENSION: u16 = 0xa003;
+
+// Largest HEIF `meta` box read; real ones are a few tens of KB
+const MAX_META_BYTES: u64 = 4 * 1024 * 1024;
+// Top-level HEIF boxes skipped while looking for `meta`
+const MAX_TOP_LEVEL_BOXES: usize = 64;
+
+// Widest aspect ratio difference accepted between thumbnail and image;
+// beyond it the thumbnail is letterboxed or belongs to an older crop
+const MAX_ASPECT_DIFFERENCE: f64 = 0.02;
+
+/// Whether `path` is routed as a JPEG or HEIF file, the formats this fast path serves
+pub fn applies_to(path: &str) -> bool {
+    matches!(formats::extension(path).as_str(), "jpg" | "jpeg" | "heic" | "heif")
+}
+
+/// The EXIF IFD1 thumbnail of a JPEG or HEIF file, decoded, if both its sides reach `min_side`
+///
+/// Also returns the size of the main image. Thumbnails whose aspect ratio
+/// does not match the main image (letterboxed, or left over from an edit)
+/// are refused, as are files whose main image size is unknown.
+pub fn extract(path: &str, min_side: u32) -> Option<(DynamicImage, (u32, u32))> {
+    let jpeg_base = orientation::jpeg_exif_offset(path);
+    let base = jpeg_base.or_else(|| heif_exif_offset(path))?;
+    let mut file = tiff::TiffFile::open_at(path, base).ok()?;
+    let ifds = file.ifds().ok()?;
+
+    // The ExifIFD is not a SubIFD, so `ifds` does not reach it
+    let exif_dimensions = ifds.first().and_then(|ifd0| {
+        let exif = file.sub_ifd(ifd0.find(tiff::TAG_EXIF_IFD)?).ok()?;
+        let width = file.value_u32(exif.find(TAG_PIXEL_X_DIMENSION)?)?;
+        let height = file.value_u32(exif.find(TAG_PIXEL_Y_DIMENSION)?)?;
+        Some((width, height))
+    });
+    // The JPEG frame header is authoritative; HEIF only has the EXIF copy
+    let original = match jpeg_base {
+        Some(_) => image::image_dimensions(path).ok(),
+        None => exif_dimensions,
+    }
+    .filter(|&(width, height)| width > 0 && height > 0)?;
+
+    let ifd1 = ifds.get(1)?;
+    let offset = file.value_u32(ifd1.find(tiff::TAG_JPEG_OFFSET)?)?;
+    let length = file.value_u32(ifd1.find(tiff::TAG_JPEG_LENGTH)?)?;
+    let data = file.read_bytes(offset, length).ok()?;
+    let thumbnail = image::load_from_memory_with_format(&data, image::ImageFormat::Jpeg).ok()?;
+    if thumbnail.width().min(thumbnail.height()) < min_side {
+        return None;
+    }
+
+    let aspect = |(width, height): (u32, u32)| width as f64 / height as f64;
+    let (expected, actual) = (aspect(original), aspect((thumbnail.width(), thumbnail.height())));
+    ((actual - expected).abs() / expected <= MAX_ASPECT_DIFFERENCE).then_some((thumbnail, original))
+}
+
+/// File offset of the TIFF header of the `Exif` item of a HEIF file
+///
+/// Finds the item in the `iinf` box of the top-level `meta`, its location in
+/// `iloc`, and skips the header offset the item starts with.
+fn heif_exif_offset(path: &str) -> Option<u64> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let mut meta = None;
+    for _ in 0..MAX_TOP_LEVEL_BOXES {
+        let (kind, body) = read_box_header(&mut reader)?;
+        if &kind == b"meta" {
+            let length = body.filter(|&length| length <= MAX_META_BYTES)?;
+            let mut data = vec![0u8; length as usize];
+            reader.read_exact(&mut data).ok()?;
+            meta = Some(data);
+            break;
+        }
+        reader.seek_relative(i64::try_from(body?).ok()?).ok()?;
+    }
+    let meta = meta?;
+
+    // `meta` is a full box: version and flags come before the children
+    let children = meta.get(4..)?;
+    let exif_item = child(Boxes(children), b"iinf").and_then(exif_item_id)?;
+    let location = child(Boxes(children), b"iloc").and_then(|iloc| item_location(iloc, exif_item))?;
+
+    reader.seek(SeekFrom::Start(location)).ok()?;
+    let mut header_offset = [0u8; 4];
+    reader.read_exact(&mut header_offset).ok()?;
+    Some(location + 4 + u32::from_be_bytes(header_offset) as u64)
+}
+
+/// Type and body length of the box at the reader's position (None: to the end of the file)
+fn read_box_header(reader: &mut impl Read) -> Option<([u8; 4], Option<u64>)> {
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header).ok()?;
+    let kind = [header[4], header[5], header[6], header[7]];
+    match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+        0 => Some((kind, None)),
+        1 => {
+            let mut large = [0u8; 8];
+            reader.read_exact(&mut large).ok()?;
+            Some((kind, Some(u64::from_be_bytes(large).checked_sub(16)?)))
+        },
+        size => Some((kind, Some((size as u64).checked_sub(8)?))),
+    }
+}
+
+/// Child boxes of an in-memory box body, as (type, body)
+struct Boxes<'a>(&'a [u8]);
+
+impl<'a> Iterator for Boxes<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut cursor = Cursor(self.0);
+        let size = cursor.uint(4)? as usize;
+        let kind = cursor.take(4)?;
+        let (header, size) = match size {
+            0 => (8, self.0.len()),
+            1 => (16, cursor.uint(8)? as usize),
+            size => (8, size),
+        };
+        let body = self.0.get(header..size)?;
+        self.0 = &self.0[size..];
+        Some((kind, body))
+    }
+}
+
+/// Body of the first box of type `kind` among `boxes`
+fn child<'a>(mut boxes: Boxes<'a>, kind: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes.find(|(child, _)| child == kind).map(|(_, body)| body)
+}
+
+/// Big-endian reads from a byte slice
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        let taken = self.0.get(..count)?;
+        self.0 = &self.0[count..];
+        Some(taken)
+    }
+
+    /// Unsigned integer of `bytes` bytes (0 reads nothing and yields 0)
+    fn uint(&mut self, bytes: usize) -> Option<u64> {
+        Some(self.take(bytes)?.iter().fold(0u64, |value, &byte| value << 8 | byte as u64))
+    }
+}
+
+/// ID of the first `Exif` item listed in an `iinf` body
+fn exif_item_id(iinf: &[u8]) -> Option<u64> {
+    let mut cursor = Cursor(iinf);
+    let version = cursor.uint(1)?;
+    cursor.take(3)?;
+    cursor.uint(if version == 0 { 2 } else { 4 })?;
+
+    for (_, infe) in Boxes(cursor.0).filter(|(kind, _)| kind == b"infe") {
+        let mut cursor = Cursor(infe);
+        let version = cursor.uint(1)?;
+        cursor.take(3)?;
+        if version < 2 {
+            continue;
+        }
+        let item_id = cursor.uint(if version == 2 { 2 } else { 4 })?;
+        cursor.take(2)?;
+        if cursor.take(4)? == b"Exif" {
+            return Some(item_id);
+        }
+    }
+    None
+}
+
+/// File offset of item `wanted` from an `iloc` body, if stored in the file itself
+fn item_location(iloc: &[u8], wanted: u64) -> Option<u64> {
+    let mut cursor = Cursor(iloc);
+    let version = cursor.uint(1)?;
+    cursor.take(3)?;
+    let sizes = cursor.uint(1)?;
+    let (offset_size, length_size) = ((sizes >> 4) as usize, (sizes & 0xf) as usize);
+    let sizes = cursor.uint(1)?;
+    let base_offset_size = (sizes >> 4) as usize;
+    let index_size = if version >= 1 { (sizes & 0xf) as usize } else { 0 };
+    let item_count = cursor.uint(if version < 2 { 2 } else { 4 })?;
+
+    for _ in 0..item_count {
+        let item_id = cursor.uint(if version < 2 { 2 } else { 4 })?;
+        let construction_method = if version >= 1 { cursor.uint(2)? & 0xf } else { 0 };
+        cursor.take(2)?;
+        let base_offset = cursor.uint(base_offset_size)?;
+        let extent_count = cursor.uint(2)?;
+        let mut first_extent = None;
+        for _ in 0..extent_count {
+            cursor.uint(index_size)?;
+            let offset = cursor.uint(offset_size)?;
+            cursor.uint(length_size)?;
+            first_extent.get_or_insert(offset);
+        }
+        if item_id == wanted {
+            // Other methods point into `idat` or other items
+            return (construction_method == 0).then(|| base_offset + first_extent.unwrap_or(0));
+        }
+    }
+    None
+}
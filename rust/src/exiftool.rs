@@ -0,0 +1,113 @@
+// src/exiftool.rs
+// Batched `exiftool -T` runs, one row of tag values per input path
+
+use std::collections::HashMap;
+use std::process::Command;
+
+use rayon::prelude::*;
+
+use crate::process::LimitedOutput;
+
+// Files per exiftool invocation; one process per file dominates on large folders
+const BATCH: usize = 100;
+
+/// One `-T` field; None when empty or `-` (what `-f` prints for a missing tag)
+pub fn field(value: &str) -> Option<&str> {
+    let value = value.trim();
+    (!value.is_empty() && value != "-").then_some(value)
+}
+
+/// SubSecTimeOriginal digits as a fraction of a second, 0 when missing
+pub fn subsec_fraction(value: &str) -> f64 {
+    field(value).and_then(|s| format!("0.{}", s).parse::<f64>().ok()).unwrap_or(0.0)
+}
+
+/// Assign each output line to every position whose key is its FilePath
+///
+/// A file given twice (or under two spellings) is read once and its row is
+/// copied to each position, so the result keeps the input's multiplicity.
+fn rows_by_position(keys: &[Option<String>], stdout: &str, tags: usize) -> Vec<Option<Vec<String>>> {
+    let mut positions: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, key) in keys.iter().enumerate() {
+        if let Some(key) = key {
+            positions.entry(key.as_str()).or_default().push(i);
+        }
+    }
+
+    let mut rows = vec![None; keys.len()];
+    for line in stdout.lines() {
+        let mut fields = line.split('\t').map(str::to_string);
+        let Some(path) = fields.next() else {
+            continue;
+        };
+        let row: Vec<String> = fields.collect();
+        if row.len() != tags {
+            continue;
+        }
+        for &i in positions.get(path.as_str()).into_iter().flatten() {
+            rows[i] = Some(row.clone());
+        }
+    }
+    rows
+}
+
+/// Read `tags` of one batch with a single exiftool run
+fn read_chunk(paths: &[String], tags: &[&str]) -> Vec<Option<Vec<String>>> {
+    // FilePath is absolute with symlinks resolved; it maps rows back to positions
+    let keys: Vec<Option<String>> =
+        paths.iter().map(|p| Some(std::fs::canonicalize(p).ok()?.to_string_lossy().into_owned())).collect();
+    let mut unique: Vec<&String> = Vec::with_capacity(paths.len());
+    let mut seen = std::collections::HashSet::new();
+    for (path, key) in paths.iter().zip(&keys) {
+        if key.as_ref().is_some_and(|key| seen.insert(key.as_str())) {
+            unique.push(path);
+        }
+    }
+    if unique.is_empty() {
+        return vec![None; paths.len()];
+    }
+
+    // `-n` prints raw values: numbers as numbers and DateTimeOriginal as
+    // written by the camera, since `-d %s` relies on a strftime Windows builds lack
+    let output = Command::new("exiftool")
+        .args(["-T", "-f", "-n", "-FilePath"])
+        .args(tags.iter().map(|tag| format!("-{}", tag)))
+        .args(unique)
+        .limited_output();
+
+    match output {
+        Ok(output) => rows_by_position(&keys, &String::from_utf8_lossy(&output.stdout), tags.len()),
+        Err(_) => vec![None; paths.len()],
+    }
+}
+
+/// `tags` of every file in `paths` with parallel exiftool batches
+///
+/// One entry per input path, in input order; None where exiftool returned no
+/// row. Fields are raw `-T -n` values, see `field`.
+pub fn read_tags(paths: &[String], tags: &[&str]) -> Vec<Option<Vec<String>>> {
+    paths.par_chunks(BATCH).flat_map_iter(|chunk| read_chunk(chunk, tags)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_are_none() {
+        assert_eq!(field(" 5.6 "), Some("5.6"));
+        assert_eq!(field("-"), None);
+        assert_eq!(field(""), None);
+        assert_eq!(subsec_fraction("25"), 0.25);
+        assert_eq!(subsec_fraction("-"), 0.0);
+    }
+
+    #[test]
+    fn rows_keep_input_order_and_multiplicity() {
+        let keys = vec![Some("/b.jpg".to_string()), None, Some("/a.jpg".to_string()), Some("/b.jpg".to_string())];
+        let stdout = "/a.jpg\tCanon\t100\n/b.jpg\tNikon\t200\n/c.jpg\tSony\t300\n/a.jpg\tshort\n";
+        let rows = rows_by_position(&keys, stdout, 2);
+        let b = Some(vec!["Nikon".to_string(), "200".to_string()]);
+        assert_eq!(rows, [b.clone(), None, Some(vec!["Canon".to_string(), "100".to_string()]), b]);
+    }
+}
@@ -0,0 +1,218 @@
+// src/explain.rs
+// Every signal behind the duplicate decision for one pair, for debugging false positives and negatives
+
+use image::DynamicImage;
+
+use crate::matching::{self, MatchProfile};
+use crate::naming::Metadata;
+
+// SSIM window side and stabilizing constants for 8-bit pixels
+const SSIM_WINDOW: usize = 8;
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+const HISTOGRAM_BINS: usize = 64;
+
+/// Aspect ratios further apart than this are called out as a crop or rotation
+const ASPECT_TOLERANCE: f64 = 0.02;
+
+/// Names of the profile's voting hashes, in `MatchProfile::max_distances` order
+pub const VOTING_HASHES: [&str; 3] = ["average", "perceptual", "edge"];
+
+/// Mean structural similarity of two equally sized square grayscale images
+/// over non-overlapping windows; 1.0 for identical pixels
+pub fn ssim(a: &[u8], b: &[u8], side: usize) -> f64 {
+    let windows = side / SSIM_WINDOW;
+    if windows == 0 || a.len() != side * side || b.len() != a.len() {
+        return 0.0;
+    }
+    let n = (SSIM_WINDOW * SSIM_WINDOW) as f64;
+    let mut total = 0.0;
+    for wy in 0..windows {
+        for wx in 0..windows {
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in wy * SSIM_WINDOW..(wy + 1) * SSIM_WINDOW {
+                for x in wx * SSIM_WINDOW..(wx + 1) * SSIM_WINDOW {
+                    let (pa, pb) = (a[y * side + x] as f64, b[y * side + x] as f64);
+                    sum_a += pa;
+                    sum_b += pb;
+                    sum_aa += pa * pa;
+                    sum_bb += pb * pb;
+                    sum_ab += pa * pb;
+                }
+            }
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covariance = sum_ab / n - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+        }
+    }
+    total / (windows * windows) as f64
+}
+
+fn histogram(pixels: &[u8]) -> [f64; HISTOGRAM_BINS] {
+    let mut bins = [0.0; HISTOGRAM_BINS];
+    for &p in pixels {
+        bins[p as usize * HISTOGRAM_BINS / 256] += 1.0;
+    }
+    let total = pixels.len().max(1) as f64;
+    bins.map(|count| count / total)
+}
+
+/// 1 minus the intersection of the normalized luminance histograms: 0 for the
+/// same tonal distribution, 1 for disjoint ones
+pub fn histogram_distance(a: &[u8], b: &[u8]) -> f64 {
+    let (ha, hb) = (histogram(a), histogram(b));
+    1.0 - ha.iter().zip(&hb).map(|(x, y)| x.min(*y)).sum::<f64>()
+}
+
+/// One metadata field of both files
+pub struct FieldComparison {
+    pub name: &'static str,
+    pub a: Option<String>,
+    pub b: Option<String>,
+}
+
+impl FieldComparison {
+    /// None when either file lacks the field
+    pub fn agrees(&self) -> Option<bool> {
+        Some(self.a.as_ref()? == self.b.as_ref()?)
+    }
+}
+
+pub struct Explanation {
+    pub result: matching::MatchResult,
+    /// Distances between the index hashes of the two thumbnails, `thumbnail_hashes` order
+    pub hash_distances: Vec<(&'static str, Option<u32>)>,
+    pub ssim: f64,
+    pub histogram_distance: f64,
+    pub dimensions: [(u32, u32); 2],
+    /// Smaller aspect ratio over the larger, 1.0 for the same shape
+    pub aspect_ratio: f64,
+    /// Smaller pixel count over the larger
+    pub area_ratio: f64,
+    pub exif: Vec<FieldComparison>,
+    pub reasons: Vec<String>,
+}
+
+fn hamming(a: &str, b: &str) -> Option<u32> {
+    (a.len() == b.len()).then(|| a.bytes().zip(b.bytes()).filter(|(x, y)| x != y).count() as u32)
+}
+
+fn ratio(x: f64, y: f64) -> f64 {
+    if x <= 0.0 || y <= 0.0 {
+        return 0.0;
+    }
+    x.min(y) / x.max(y)
+}
+
+pub fn exif_fields(a: Metadata, b: Metadata, orientations: [u16; 2]) -> Vec<FieldComparison> {
+    let date = |m: &Metadata| m.date.map(|(y, mo, d)| format!("{:04}-{:02}-{:02}", y, mo, d));
+    vec![
+        FieldComparison { name: "make", a: a.make.clone(), b: b.make.clone() },
+        FieldComparison { name: "camera", a: a.camera.clone(), b: b.camera.clone() },
+        FieldComparison { name: "date", a: date(&a), b: date(&b) },
+        FieldComparison {
+            name: "orientation",
+            a: Some(orientations[0].to_string()),
+            b: Some(orientations[1].to_string()),
+        },
+    ]
+}
+
+/// Compute every signal for a decoded pair and explain the profile's verdict
+///
+/// `thumbnails` are the pipeline's square grayscale thumbnails of side `side`;
+/// the verdict itself is `matching::compare` under `profile`, overturned when
+/// `min_ssim` is set and the thumbnails fall short of it.
+pub fn explain(
+    images: [&DynamicImage; 2],
+    thumbnails: [&[u8]; 2],
+    side: usize,
+    exif: Vec<FieldComparison>,
+    profile: &MatchProfile,
+    min_ssim: f64,
+) -> Explanation {
+    let fingerprints = images.map(|img| matching::fingerprint(img, profile));
+    let mut result = matching::compare(&fingerprints[0], &fingerprints[1], profile);
+    let ssim = ssim(thumbnails[0], thumbnails[1], side);
+    let hashes_matched = result.matched;
+    if min_ssim > 0.0 {
+        result.matched &= ssim >= min_ssim;
+    }
+
+    let [hashes_a, hashes_b] = thumbnails.map(|pixels| crate::hashing::thumbnail_hashes(pixels, side));
+    let hash_distances = hashes_a.iter().zip(&hashes_b).map(|((name, a), (_, b))| (*name, hamming(a, b))).collect();
+
+    let dimensions = images.map(|img| (img.width(), img.height()));
+    let [(wa, ha), (wb, hb)] = dimensions.map(|(w, h)| (w as f64, h as f64));
+    let aspect_ratio = ratio(wa / ha.max(1.0), wb / hb.max(1.0));
+    let area_ratio = ratio(wa * ha, wb * hb);
+
+    let mut explanation = Explanation {
+        result,
+        hash_distances,
+        ssim,
+        histogram_distance: histogram_distance(thumbnails[0], thumbnails[1]),
+        dimensions,
+        aspect_ratio,
+        area_ratio,
+        exif,
+        reasons: Vec::new(),
+    };
+    explanation.reasons = reasons(&explanation, profile, hashes_matched, min_ssim);
+    explanation
+}
+
+fn reasons(explanation: &Explanation, profile: &MatchProfile, hashes_matched: bool, min_ssim: f64) -> Vec<String> {
+    let result = &explanation.result;
+    let mut reasons = Vec::new();
+    for (i, name) in VOTING_HASHES.iter().enumerate() {
+        let (distance, max) = (result.distances[i], profile.max_distances[i]);
+        reasons.push(if distance <= max {
+            format!("{} hash distance {} is within {} (agrees)", name, distance, max)
+        } else {
+            format!("{} hash distance {} exceeds {} (disagrees)", name, distance, max)
+        });
+    }
+    reasons.push(format!(
+        "{} of 3 hashes agree, profile '{}' needs {}: {}",
+        result.votes,
+        profile.name,
+        profile.min_votes,
+        if hashes_matched { "duplicate" } else { "different" }
+    ));
+    if hashes_matched && !result.matched {
+        reasons.push(format!(
+            "structural similarity {:.2} is below the scan profile's confirmation threshold {:.2}: different",
+            explanation.ssim, min_ssim
+        ));
+    }
+
+    if explanation.aspect_ratio < 1.0 - ASPECT_TOLERANCE {
+        let [(wa, ha), (wb, hb)] = explanation.dimensions;
+        reasons.push(format!(
+            "aspect ratios differ ({}x{} vs {}x{}): a crop or rotation changes the hashes",
+            wa, ha, wb, hb
+        ));
+    }
+    for field in &explanation.exif {
+        if field.agrees() == Some(false) {
+            reasons.push(format!(
+                "{} differs: {} vs {}",
+                field.name,
+                field.a.as_deref().unwrap_or_default(),
+                field.b.as_deref().unwrap_or_default()
+            ));
+        }
+    }
+    if result.matched && explanation.ssim < 0.5 {
+        reasons.push(format!("low structural similarity ({:.2}) despite the hash match: check for a false positive", explanation.ssim));
+    }
+    if !result.matched && explanation.ssim > 0.9 {
+        reasons.push(format!("high structural similarity ({:.2}) despite the hash mismatch: check for a false negative", explanation.ssim));
+    }
+    reasons
+}
@@ -0,0 +1,89 @@
+// src/exposure.rs
+// Exposure normalization of grayscale thumbnails before hashing
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+
+// Share of pixels clipped at each end by the contrast stretch
+const STRETCH_CLIP: f64 = 0.01;
+
+/// Named preprocessing applied to a uint8 grayscale thumbnail before hashing
+///
+/// Hashes are only comparable when computed with the same profile.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Preprocess {
+    /// Pixels as decoded, matching previously stored hashes
+    None,
+    /// Linear stretch of the 1st-99th percentile range to 0-255
+    Stretch,
+    /// Global histogram equalization; also cancels non-linear tone curves
+    Equalize,
+}
+
+impl Preprocess {
+    /// Parse the profile name passed from Python
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "none" => Ok(Preprocess::None),
+            "stretch" => Ok(Preprocess::Stretch),
+            "equalize" => Ok(Preprocess::Equalize),
+            _ => Err(PyValueError::new_err(format!(
+                "Unsupported preprocessing profile '{}', expected 'none', 'stretch' or 'equalize'",
+                name
+            ))),
+        }
+    }
+
+    /// Normalize `pixels` in place
+    pub fn apply(&self, pixels: &mut [u8]) {
+        match self {
+            Preprocess::None => {},
+            Preprocess::Stretch => stretch(pixels),
+            Preprocess::Equalize => equalize(pixels),
+        }
+    }
+}
+
+fn cumulative_histogram(pixels: &[u8]) -> [usize; 256] {
+    let mut histogram = [0usize; 256];
+    for &p in pixels.iter() {
+        histogram[p as usize] += 1;
+    }
+
+    let mut running = 0;
+    for count in histogram.iter_mut() {
+        running += *count;
+        *count = running;
+    }
+    histogram
+}
+
+/// Spread the histogram over the full range (global histogram equalization)
+pub fn equalize(pixels: &mut [u8]) {
+    let cdf = cumulative_histogram(pixels);
+    let first = cdf.iter().copied().find(|&c| c > 0).unwrap_or(0);
+    let range = (pixels.len() - first).max(1);
+    for p in pixels.iter_mut() {
+        *p = ((cdf[*p as usize] - first) * 255 / range) as u8;
+    }
+}
+
+/// Map the 1st-99th percentile levels linearly onto 0-255
+///
+/// Keeps the shape of the tone curve, so it corrects an underexposed preview
+/// without flattening the texture the hashes rely on.
+pub fn stretch(pixels: &mut [u8]) {
+    let cdf = cumulative_histogram(pixels);
+    let clip = (pixels.len() as f64 * STRETCH_CLIP) as usize;
+    let low = cdf.iter().position(|&c| c > clip).unwrap_or(0);
+    let high = cdf.iter().position(|&c| c >= pixels.len() - clip).unwrap_or(255);
+    if high <= low {
+        return;
+    }
+
+    let scale = 255.0 / (high - low) as f64;
+    for p in pixels.iter_mut() {
+        let level = (*p as usize).clamp(low, high);
+        *p = ((level - low) as f64 * scale).round() as u8;
+    }
+}
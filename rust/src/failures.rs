@@ -0,0 +1,94 @@
+// src/failures.rs
+// Machine-readable reports for files that fail inside a batch
+
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::placeholders;
+
+/// Why a file failed, coarse enough to act on (retry, skip, fix permissions...)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    /// The file does not exist (moved or deleted since it was listed)
+    Missing,
+    PermissionDenied,
+    /// A format or variant no backend understands
+    Unsupported,
+    /// The conversion chain or the per-file deadline ran out of time
+    Timeout,
+    /// A decoder panicked on the file
+    Crashed,
+    /// Bypassed because of earlier crashes or timeouts (see the skip-list)
+    Skipped,
+    /// A zero-byte file or an online-only cloud placeholder, never decoded
+    Placeholder,
+    /// Every backend that was tried failed to decode the file
+    DecodeFailed,
+    /// Reading the file failed for another reason
+    Io,
+    /// Never started: the batch was interrupted with Ctrl-C
+    Interrupted,
+}
+
+impl Category {
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Missing => "missing",
+            Category::PermissionDenied => "permission_denied",
+            Category::Unsupported => "unsupported",
+            Category::Timeout => "timeout",
+            Category::Crashed => "crashed",
+            Category::Skipped => "skipped",
+            Category::Placeholder => "placeholder",
+            Category::DecodeFailed => "decode_failed",
+            Category::Io => "io_error",
+            Category::Interrupted => "interrupted",
+        }
+    }
+}
+
+/// One failed file of a batch
+pub struct FileFailure {
+    pub category: Category,
+    pub message: String,
+    /// Backends tried before giving up, in order
+    pub backends: Vec<&'static str>,
+    pub elapsed: Duration,
+}
+
+/// Classify a failure from the file's state, the error message and what was tried
+///
+/// The filesystem is checked first, so a file deleted mid-scan is `missing`
+/// even if a backend reported something else.
+pub fn categorize(path: &str, message: &str, backends: &[&'static str]) -> Category {
+    // Before opening it: opening an online-only file can start its download
+    if placeholders::detect(path).is_some() {
+        return Category::Placeholder;
+    }
+    if let Err(e) = std::fs::File::open(path) {
+        return match e.kind() {
+            io::ErrorKind::NotFound => Category::Missing,
+            io::ErrorKind::PermissionDenied => Category::PermissionDenied,
+            _ if !Path::new(path).exists() => Category::Missing,
+            _ => Category::Io,
+        };
+    }
+
+    let lower = message.to_lowercase();
+    if lower.contains("timeout") || lower.contains("timed out") || lower.contains("deadline") {
+        Category::Timeout
+    } else if lower.contains("unsupported") || lower.contains("not a ") {
+        Category::Unsupported
+    } else if backends.is_empty() {
+        Category::Io
+    } else {
+        Category::DecodeFailed
+    }
+}
+
+impl FileFailure {
+    pub fn new(path: &str, message: String, backends: Vec<&'static str>, elapsed: Duration) -> Self {
+        FileFailure { category: categorize(path, &message, &backends), message, backends, elapsed }
+    }
+}
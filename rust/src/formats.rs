@@ -0,0 +1,117 @@
+// src/formats.rs
+// File extension routing, remappable at runtime so unusual camera ecosystems work without a new build
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::RwLock;
+
+use image::{DynamicImage, ImageFormat};
+use pyo3::exceptions::PyValueError;
+use pyo3::PyResult;
+
+/// RAW formats the conversion chain accepts as handlers
+const RAW_EXTENSIONS: &[&str] = &[
+    "3fr", "arw", "cr2", "cr3", "crw", "dcr", "dng", "erf", "fff", "iiq", "kdc", "mef", "mos", "mrw", "nef", "nrw",
+    "orf", "pef", "raf", "raw", "rw2", "rwl", "sr2", "srf", "srw", "x3f",
+];
+
+/// Layered formats handled by the streaming decoder
+const STREAMED_EXTENSIONS: &[&str] = &["psd", "psb"];
+
+/// Registered extension (lowercase, without the leading dot, possibly
+/// multi-part like `dng.bak`) to the built-in extension it is handled as
+fn mappings() -> &'static RwLock<BTreeMap<String, String>> {
+    static MAPPINGS: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+    &MAPPINGS
+}
+
+fn normalize(extension: &str) -> String {
+    extension.trim().trim_start_matches('.').to_lowercase()
+}
+
+/// Whether `handler` is an extension the built-in routing knows
+fn is_handler(handler: &str) -> bool {
+    RAW_EXTENSIONS.contains(&handler)
+        || STREAMED_EXTENSIONS.contains(&handler)
+        || ImageFormat::from_extension(handler).is_some()
+}
+
+/// Whether files routed by `extension` go through the RAW conversion chain
+pub fn is_raw(extension: &str) -> bool {
+    RAW_EXTENSIONS.contains(&extension)
+}
+
+/// Normalize and check a mapping before it is registered
+pub fn validate(extension: &str, handler: Option<&str>) -> PyResult<(String, Option<String>)> {
+    let extension = normalize(extension);
+    if extension.is_empty() || extension.contains(['/', '\\']) || extension.ends_with('.') {
+        return Err(PyValueError::new_err(format!("Invalid extension '{}'", extension)));
+    }
+    let handler = handler.map(normalize);
+    if let Some(handler) = handler.as_deref().filter(|h| !is_handler(h)) {
+        return Err(PyValueError::new_err(format!(
+            "Unsupported handler '{}', expected an image extension (jpg, png, tiff...), psd/psb or one of {}",
+            handler,
+            RAW_EXTENSIONS.join(", ")
+        )));
+    }
+    Ok((extension, handler))
+}
+
+/// Handle files ending in `.extension` like files ending in `.handler`; None
+/// removes the mapping. Both come from `validate`.
+pub fn set(extension: String, handler: Option<String>) {
+    let mut mappings = mappings().write().unwrap_or_else(|e| e.into_inner());
+    match handler {
+        Some(handler) => mappings.insert(extension, handler),
+        None => mappings.remove(&extension),
+    };
+}
+
+/// Every registered extension with its handler
+pub fn registered() -> Vec<(String, String)> {
+    let mappings = mappings().read().unwrap_or_else(|e| e.into_inner());
+    mappings.iter().map(|(e, h)| (e.clone(), h.clone())).collect()
+}
+
+/// Handler for `path` when a registered extension matches, the longest one winning
+pub fn remapped(path: &str) -> Option<String> {
+    let mappings = mappings().read().unwrap_or_else(|e| e.into_inner());
+    if mappings.is_empty() {
+        return None;
+    }
+    let name = Path::new(path).file_name()?.to_string_lossy().to_lowercase();
+    mappings
+        .iter()
+        .filter(|(extension, _)| name.len() > extension.len() + 1 && name.ends_with(&format!(".{}", extension)))
+        .max_by_key(|(extension, _)| extension.len())
+        .map(|(_, handler)| handler.clone())
+}
+
+/// Lowercase extension `path` is routed by: its registered handler, or its own extension
+pub fn extension(path: &str) -> String {
+    remapped(path).unwrap_or_else(|| {
+        Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|s| s.to_lowercase())
+            .unwrap_or_default()
+    })
+}
+
+/// Whether a scan picks up `path`: an image, RAW or layered file by its routed extension
+pub fn is_image(path: &str) -> bool {
+    is_handler(&extension(path))
+}
+
+/// Decode `path` with the image crate, honoring remapped extensions; None when
+/// it cannot (RAW and layered formats go through their own pipelines)
+pub fn open_image(path: &str) -> Option<DynamicImage> {
+    let Some(handler) = remapped(path) else {
+        return image::open(path).ok();
+    };
+    let format = ImageFormat::from_extension(&handler)?;
+    let mut reader = image::io::Reader::open(path).ok()?;
+    reader.set_format(format);
+    reader.decode().ok()
+}
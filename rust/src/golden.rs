@@ -0,0 +1,163 @@
+// src/golden.rs
+// Known-good hashes of synthetic images, to check a build hashes like every other build
+
+use std::io::Cursor;
+
+use image::{imageops, DynamicImage, ImageOutputFormat, RgbImage};
+
+use crate::grayscale::{grayscale_thumbnail, GrayscaleBuffer, GrayscaleDtype, LumaMode};
+use crate::{hashing, paths, streaming};
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+
+/// One hash that differs from its golden value
+pub struct Mismatch {
+    pub hash: &'static str,
+    pub expected: String,
+    pub actual: String,
+    /// Differing bits, or None when the lengths differ
+    pub distance: Option<u32>,
+}
+
+/// Outcome of one synthetic image through one pipeline
+pub struct CaseResult {
+    /// `<image>/<pipeline>`, e.g. `rings/stream`
+    pub name: String,
+    /// Set when the pipeline failed before hashing
+    pub error: Option<String>,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.mismatches.is_empty()
+    }
+}
+
+/// Golden hashes per case at the 512px thumbnail size, in
+/// `hashing::thumbnail_hashes` order, as hex
+#[rustfmt::skip]
+const GOLDEN: &[(&str, [&str; 4])] = &[
+    ("gradient/decode", ["000000073fffffff", "000000031fffffff", "0000000000000000000000000007003f01ff1fffffffffffffffffffffffffff", "001a7a7c7e767e00"]),
+    ("gradient/stream", ["000000073fffffff", "000000031fffffff", "0000000000000000000000000007003f01ff1fffffffffffffffffffffffffff", "000000031f7fffff"]),
+    ("checker/decode", ["aa5555aa55aaaa55", "aa0101aa55808055", "cccccccc8000333333338000cccccccc333333330001cccccccc000133333333", "007e7e00007e7e00"]),
+    ("checker/stream", ["aa5555aa55aaaa55", "aa5500aa55aa0055", "cccccccc0000333333330000cccccccc333333330000cccccccc000033333333", "007e7e2a54fe7e00"]),
+    ("rings/decode", ["ffe7e7b9b9e7e7ff", "9846661898e76619", "c7c99bd86424500e93cb7c36a5a583c983c9a5a16424d3ca742625b59a5adbcb", "e7e7c30000c2e7e7"]),
+    ("rings/stream", ["ffe7e7fdf9e7e7ff", "1846049998a443db", "83c113c86424500e93cb6c36a5a183c983c9a5a16c3453ca74246da412489bd9", "e7e7c30000c2e7e7"]),
+    ("blocks/decode", ["ce9d9a9089dca548", "ce9d9b9089dca548", "f0fcf0fcc3f3c3f3c3cfc3cfc300c300c0c3c0c3f3f0f3f0cc33cc3331c033c0", "1b178c391d1c6f4c"]),
+    ("blocks/stream", ["ce9d9b9089dca558", "ce9d9b9089dca548", "f0fcf0fcc3f3c3f3c3cfc3cfc300c300c0c3c0c3f3f0f3f0cc33cc3330c030c0", "1e179a188ddcaf48"]),
+];
+
+/// Deterministic test images, integer math only so they are identical everywhere
+fn synthetic(name: &str) -> RgbImage {
+    let mut state = 0x2545_f491_u32;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+    let blocks: Vec<u8> = (0..64).map(|_| (next() >> 24) as u8).collect();
+
+    RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let (dx, dy) = (x as i32 - WIDTH as i32 / 2, y as i32 - HEIGHT as i32 / 2);
+        let value = match name {
+            "gradient" => [(x * 255 / (WIDTH - 1)) as u8, (y * 255 / (HEIGHT - 1)) as u8, ((x + y) / 3 % 256) as u8],
+            "checker" => {
+                let on = (x / 40 + y / 40) % 2 == 0;
+                if on { [230, 200, 40] } else { [20, 60, 180] }
+            },
+            "rings" => {
+                let band = ((dx * dx + dy * dy) / 700 % 4) as u8;
+                [band * 80, 255 - band * 60, 128]
+            },
+            _ => {
+                let level = blocks[(y * 8 / HEIGHT * 8 + x * 8 / WIDTH) as usize];
+                [level, level / 2 + 64, 255 - level]
+            },
+        };
+        image::Rgb(value)
+    })
+}
+
+const IMAGES: &[&str] = &["gradient", "checker", "rings", "blocks"];
+
+/// Hex spelling of a '0'/'1' hash, four bits per digit
+fn to_hex(bits: &str) -> String {
+    bits.as_bytes()
+        .chunks(4)
+        .map(|nibble| {
+            let value = nibble.iter().fold(0u32, |acc, b| acc << 1 | (*b == b'1') as u32) << (4 - nibble.len());
+            char::from_digit(value, 16).unwrap_or('0')
+        })
+        .collect()
+}
+
+fn hex_distance(a: &str, b: &str) -> Option<u32> {
+    if a.len() != b.len() {
+        return None;
+    }
+    a.chars()
+        .zip(b.chars())
+        .map(|(x, y)| Some((x.to_digit(16)? ^ y.to_digit(16)?).count_ones()))
+        .sum()
+}
+
+/// PNG encode and decode in memory, then the regular thumbnail resize
+fn decode_pipeline(img: &RgbImage, side: u32) -> Result<Vec<u8>, String> {
+    let mut png = Vec::new();
+    DynamicImage::ImageRgb8(img.clone())
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .map_err(|e| e.to_string())?;
+    let decoded = image::load_from_memory(&png).map_err(|e| e.to_string())?;
+    // Golden values are for the default grayscale mode, whatever mode is active
+    match grayscale_thumbnail(&decoded, side, GrayscaleDtype::U8, imageops::FilterType::Triangle, LumaMode::Bt709) {
+        GrayscaleBuffer::U8(pixels) => Ok(pixels),
+        _ => Err("uint8 thumbnail requested".to_string()),
+    }
+}
+
+/// Written as a TIFF file and read back through the streaming decoder
+fn stream_pipeline(img: &RgbImage, side: u32) -> Result<Vec<u8>, String> {
+    let path = paths::temp_file("selftest", "tif");
+    let result = img
+        .save_with_format(&path, image::ImageFormat::Tiff)
+        .map_err(|e| e.to_string())
+        .and_then(|_| {
+            streaming::grayscale_thumbnail_with_dimensions(&path.to_string_lossy(), side as usize, LumaMode::Bt709)
+                .map(|(pixels, _)| pixels)
+                .map_err(|e| e.to_string())
+        });
+    let _ = std::fs::remove_file(&path); // Clean up
+    result
+}
+
+/// Run every synthetic image through every pipeline at thumbnail size `side`
+/// and compare the hashes with the golden values
+pub fn run(side: u32) -> Vec<CaseResult> {
+    let mut results = Vec::new();
+    for image_name in IMAGES {
+        let img = synthetic(image_name);
+        for (pipeline, output) in [("decode", decode_pipeline(&img, side)), ("stream", stream_pipeline(&img, side))] {
+            let name = format!("{}/{}", image_name, pipeline);
+            let golden = GOLDEN.iter().find(|(case, _)| *case == name).map(|(_, hashes)| *hashes);
+            let mut result = CaseResult { name, error: None, mismatches: Vec::new() };
+            match output {
+                Ok(pixels) => {
+                    for (i, (hash, bits)) in hashing::thumbnail_hashes(&pixels, side as usize).into_iter().enumerate() {
+                        let actual = to_hex(&bits);
+                        let expected = golden.map(|g| g[i]).unwrap_or_default();
+                        if actual != expected {
+                            let distance = hex_distance(expected, &actual);
+                            result.mismatches.push(Mismatch { hash, expected: expected.to_string(), actual, distance });
+                        }
+                    }
+                },
+                Err(e) => result.error = Some(e),
+            }
+            results.push(result);
+        }
+    }
+    results
+}
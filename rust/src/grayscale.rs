@@ -0,0 +1,244 @@
+// src/grayscale.rs
+// Grayscale thumbnail generation in the sample types exposed to Python
+
+use std::sync::RwLock;
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use numpy::{Element, PyArray2, PyArray3};
+use ndarray::{Array2, Array3};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Luma, imageops};
+
+/// Output sample type for grayscale thumbnails
+#[derive(Clone, Copy, PartialEq)]
+pub enum GrayscaleDtype {
+    /// 0-255, identical to the historical output used for hashing
+    U8,
+    /// 0-65535, from the 16-bit luma of the decoded image; RAW files are
+    /// decoded at full bit depth for it, 8-bit sources are widened
+    U16,
+    /// 0.0-1.0, resized in floating point so averaging is not re-quantized;
+    /// sourced like `U16`
+    F32,
+}
+
+impl GrayscaleDtype {
+    /// Parse the numpy-style dtype name passed from Python
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "uint8" => Ok(GrayscaleDtype::U8),
+            "uint16" => Ok(GrayscaleDtype::U16),
+            "float32" => Ok(GrayscaleDtype::F32),
+            _ => Err(PyValueError::new_err(format!(
+                "Unsupported dtype '{}', expected 'uint8', 'uint16' or 'float32'",
+                name
+            ))),
+        }
+    }
+}
+
+/// Parse the resize filter used when shrinking to thumbnail size
+///
+/// The filter affects every thumbnail pixel, so hashes are only comparable
+/// when they were computed with the same filter. `triangle` is the default
+/// and matches previously stored hashes; `lanczos3` is the most stable across
+/// source resolutions; `nearest` is fastest but aliases, which makes hashes
+/// sensitive to small crops and scale changes.
+pub fn parse_filter(name: &str) -> PyResult<imageops::FilterType> {
+    match name {
+        "nearest" => Ok(imageops::FilterType::Nearest),
+        "triangle" => Ok(imageops::FilterType::Triangle),
+        "catmullrom" => Ok(imageops::FilterType::CatmullRom),
+        "gaussian" => Ok(imageops::FilterType::Gaussian),
+        "lanczos3" => Ok(imageops::FilterType::Lanczos3),
+        _ => Err(PyValueError::new_err(format!(
+            "Unsupported filter '{}', expected 'nearest', 'triangle', 'catmullrom', 'gaussian' or 'lanczos3'",
+            name
+        ))),
+    }
+}
+
+/// How color is reduced to one channel before resizing and hashing
+///
+/// Like the resize filter, the mode changes every thumbnail pixel, so hashes
+/// are only comparable when computed with the same mode.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LumaMode {
+    /// 0.2126 R + 0.7152 G + 0.0722 B; the default, matching previously stored hashes
+    Bt709,
+    /// 0.299 R + 0.587 G + 0.114 B, as in JPEG and standard-definition video
+    Bt601,
+    /// The green channel alone: half of a Bayer sensor's photosites and the
+    /// least noisy channel, which suits astrophotography
+    Green,
+    /// The brightest channel, for infrared-converted cameras and narrowband
+    /// filters where one channel carries the signal
+    Max,
+}
+
+impl LumaMode {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "bt709" => Ok(LumaMode::Bt709),
+            "bt601" => Ok(LumaMode::Bt601),
+            "green" => Ok(LumaMode::Green),
+            "max" => Ok(LumaMode::Max),
+            _ => Err(PyValueError::new_err(format!(
+                "Unsupported grayscale mode '{}', expected 'bt709', 'bt601', 'green' or 'max'",
+                name
+            ))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LumaMode::Bt709 => "bt709",
+            LumaMode::Bt601 => "bt601",
+            LumaMode::Green => "green",
+            LumaMode::Max => "max",
+        }
+    }
+
+    /// Gray level of normalized RGB samples
+    pub fn luma(self, [r, g, b]: [f64; 3]) -> f64 {
+        let weighted = |weights: [f64; 3]| weights.iter().zip([r, g, b]).map(|(w, c)| w * c).sum();
+        match self {
+            LumaMode::Bt709 => weighted([0.2126, 0.7152, 0.0722]),
+            LumaMode::Bt601 => weighted([0.299, 0.587, 0.114]),
+            LumaMode::Green => g,
+            LumaMode::Max => r.max(g).max(b),
+        }
+    }
+}
+
+static LUMA_MODE: RwLock<LumaMode> = RwLock::new(LumaMode::Bt709);
+
+/// The grayscale mode of the indexing pipeline
+pub fn luma_mode() -> LumaMode {
+    *LUMA_MODE.read().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn set_luma_mode(mode: LumaMode) {
+    *LUMA_MODE.write().unwrap_or_else(|e| e.into_inner()) = mode;
+}
+
+/// `img` reduced to one channel with `luma`
+pub fn to_gray(img: &DynamicImage, luma: LumaMode) -> DynamicImage {
+    if luma == LumaMode::Bt709 {
+        // The image crate's own conversion, so default thumbnails stay bit-identical
+        return img.grayscale();
+    }
+    let rgb = img.to_rgb16();
+    DynamicImage::ImageLuma16(ImageBuffer::from_fn(rgb.width(), rgb.height(), |x, y| {
+        let level = luma.luma(rgb.get_pixel(x, y).0.map(|c| c as f64 / 65535.0));
+        Luma([(level.clamp(0.0, 1.0) * 65535.0).round() as u16])
+    }))
+}
+
+/// Row-major grayscale pixels in the requested sample type
+pub enum GrayscaleBuffer {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    F32(Vec<f32>),
+}
+
+/// Convert a decoded image to a `size` x `size` grayscale thumbnail
+pub fn grayscale_thumbnail(
+    img: &DynamicImage,
+    size: u32,
+    dtype: GrayscaleDtype,
+    filter: imageops::FilterType,
+    luma: LumaMode,
+) -> GrayscaleBuffer {
+    match dtype {
+        GrayscaleDtype::U8 => {
+            // Convert to grayscale and resize to thumbnail size for hashing
+            let resized = to_gray(img, luma).resize_exact(size, size, filter);
+
+            let height = resized.height() as usize;
+            let width = resized.width() as usize;
+            let mut grayscale = vec![0u8; width * height];
+
+            for y in 0..height {
+                for x in 0..width {
+                    let pixel = resized.get_pixel(x as u32, y as u32);
+                    grayscale[y * width + x] = pixel[0]; // Take first channel
+                }
+            }
+
+            GrayscaleBuffer::U8(grayscale)
+        },
+        GrayscaleDtype::U16 => {
+            let gray = if luma == LumaMode::Bt709 { img.to_luma16() } else { to_gray(img, luma).to_luma16() };
+            let resized = imageops::resize(&gray, size, size, filter);
+            GrayscaleBuffer::U16(resized.into_raw())
+        },
+        GrayscaleDtype::F32 => {
+            let gray = if luma == LumaMode::Bt709 { img.to_luma32f() } else { to_gray(img, luma).to_luma32f() };
+            let resized = imageops::resize(&gray, size, size, filter);
+            GrayscaleBuffer::F32(resized.into_raw())
+        },
+    }
+}
+
+impl GrayscaleBuffer {
+    /// Wrap the buffer as a (height, width) numpy array
+    pub fn into_pyarray(self, py: Python<'_>, height: usize, width: usize) -> PyResult<PyObject> {
+        match self {
+            GrayscaleBuffer::U8(data) => array2(py, data, height, width),
+            GrayscaleBuffer::U16(data) => array2(py, data, height, width),
+            GrayscaleBuffer::F32(data) => array2(py, data, height, width),
+        }
+    }
+}
+
+fn array2<T: Element>(py: Python<'_>, data: Vec<T>, height: usize, width: usize) -> PyResult<PyObject> {
+    let array = Array2::from_shape_vec((height, width), data)
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(PyArray2::from_owned_array(py, array).to_object(py))
+}
+
+/// Stack equally sized thumbnails into an (N, side, side) numpy array
+///
+/// `None` entries (failed decodes) become zero-filled slots.
+pub fn stack_thumbnails(
+    py: Python<'_>,
+    buffers: Vec<Option<GrayscaleBuffer>>,
+    side: usize,
+    dtype: GrayscaleDtype,
+) -> PyResult<PyObject> {
+    match dtype {
+        GrayscaleDtype::U8 => stack(py, buffers, side, |b| match b {
+            GrayscaleBuffer::U8(data) => Some(data),
+            _ => None,
+        }),
+        GrayscaleDtype::U16 => stack(py, buffers, side, |b| match b {
+            GrayscaleBuffer::U16(data) => Some(data),
+            _ => None,
+        }),
+        GrayscaleDtype::F32 => stack(py, buffers, side, |b| match b {
+            GrayscaleBuffer::F32(data) => Some(data),
+            _ => None,
+        }),
+    }
+}
+
+fn stack<T, F>(py: Python<'_>, buffers: Vec<Option<GrayscaleBuffer>>, side: usize, unwrap: F) -> PyResult<PyObject>
+where
+    T: Element + Copy + Default,
+    F: Fn(GrayscaleBuffer) -> Option<Vec<T>>,
+{
+    let count = buffers.len();
+    let plane = side * side;
+    let mut data = vec![T::default(); count * plane];
+
+    for (i, buffer) in buffers.into_iter().enumerate() {
+        if let Some(pixels) = buffer.and_then(&unwrap) {
+            data[i * plane..(i + 1) * plane].copy_from_slice(&pixels);
+        }
+    }
+
+    let array = Array3::from_shape_vec((count, side, side), data)
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(PyArray3::from_owned_array(py, array).to_object(py))
+}
@@ -0,0 +1,84 @@
+// src/group_ids.rs
+// Duplicate group ids derived from member content, so the same group keeps its id across scans
+
+use std::collections::{BTreeMap, HashMap};
+
+use rayon::prelude::*;
+
+use crate::{checksum, paths};
+
+// Hex digits of the anchor checksum kept in an id
+const ID_DIGITS: usize = 16;
+
+/// BLAKE3 checksum of a member: the one stored under its identity in
+/// `stored`, else read from the file, or for an unreadable file a hash of its path
+fn member_key(path: &str, stored: &HashMap<&str, &str>) -> String {
+    if let Some(&content_hash) = stored.get(paths::identity(path).as_str()).filter(|hash| !hash.is_empty()) {
+        return content_hash.to_string();
+    }
+    checksum::blake3_file(path).unwrap_or_else(|_| {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"unreadable\0");
+        hasher.update(path.as_bytes());
+        hasher.finalize().to_hex().to_string()
+    })
+}
+
+/// A stable id for each group, in the same order
+///
+/// A group is named after the smallest checksum among its members, so it
+/// keeps its id when files are renamed or moved, when the scan finds them in
+/// another order and when members other than that one join or leave. Groups
+/// sharing their smallest checksum get `-2`, `-3`... in order of their
+/// sorted member checksums. Checksums come from `stored`, the index's
+/// content hashes by identity; only members without one are read, once.
+pub fn stable_ids(groups: &[Vec<String>], stored: &HashMap<&str, &str>) -> Vec<String> {
+    let mut paths: Vec<&str> = groups.iter().flatten().map(String::as_str).collect();
+    paths.sort_unstable();
+    paths.dedup();
+    let keys: HashMap<&str, String> = paths.into_par_iter().map(|path| (path, member_key(path, stored))).collect();
+
+    let members: Vec<Vec<&str>> = groups
+        .iter()
+        .map(|group| {
+            let mut members: Vec<&str> = group.iter().map(|path| keys[path.as_str()].as_str()).collect();
+            members.sort_unstable();
+            members
+        })
+        .collect();
+    let mut by_anchor: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (index, members) in members.iter().enumerate() {
+        by_anchor.entry(members.first().copied().unwrap_or_default()).or_default().push(index);
+    }
+
+    let mut ids = vec![String::new(); groups.len()];
+    for (anchor, mut indices) in by_anchor {
+        indices.sort_by(|&a, &b| members[a].cmp(&members[b]).then(a.cmp(&b)));
+        let base = &anchor[..anchor.len().min(ID_DIGITS)];
+        for (rank, index) in indices.into_iter().enumerate() {
+            ids[index] = match rank {
+                0 => base.to_string(),
+                _ => format!("{}-{}", base, rank + 1),
+            };
+        }
+    }
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_checksums_are_not_reread() {
+        // Neither file exists, so only the stored checksums can name the group
+        let groups = vec![vec!["/gone/b.jpg".to_string(), "/gone/a.jpg".to_string()]];
+        let (a, b) = ("1".repeat(64), "2".repeat(64));
+        let stored = HashMap::from([("/gone/a.jpg", a.as_str()), ("/gone/b.jpg", b.as_str())]);
+        assert_eq!(stable_ids(&groups, &stored), ["1".repeat(ID_DIGITS)]);
+
+        let unstored = stable_ids(&groups, &HashMap::new());
+        assert_ne!(unstored, stable_ids(&groups, &stored));
+        assert_eq!(unstored, stable_ids(&groups, &HashMap::from([("/gone/a.jpg", "")])));
+    }
+}
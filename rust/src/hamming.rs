@@ -0,0 +1,125 @@
+// src/hamming.rs
+// Hamming distances between hashes in any of the formats the hash functions return
+
+use ndarray::Array2;
+use rayon::prelude::*;
+
+/// A hash packed into 64-bit words, first bit most significant
+#[derive(Clone)]
+pub struct Packed {
+    words: Vec<u64>,
+    bits: usize,
+}
+
+impl Packed {
+    /// From a '0'/'1' string; None if it has other characters
+    pub fn from_str(hash: &str) -> Option<Packed> {
+        let words = hash
+            .as_bytes()
+            .chunks(64)
+            .map(|chunk| {
+                chunk.iter().try_fold(0u64, |word, bit| match bit {
+                    b'0' => Some(word << 1),
+                    b'1' => Some(word << 1 | 1),
+                    _ => None,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Packed { words, bits: hash.len() })
+    }
+
+    /// From packed bytes, as `format="bytes"` returns them
+    pub fn from_bytes(hash: &[u8]) -> Packed {
+        let words = hash
+            .chunks(8)
+            .map(|chunk| chunk.iter().fold(0u64, |word, &byte| word << 8 | byte as u64))
+            .collect();
+        Packed { words, bits: hash.len() * 8 }
+    }
+
+    /// From an int's big-endian bytes, as `format="int"` returns hashes,
+    /// zero-extended to `bits`; None when it needs more bits than that
+    pub fn from_int(hash: &[u8], bits: usize) -> Option<Packed> {
+        let hash = &hash[hash.iter().take_while(|&&byte| byte == 0).count()..];
+        let width = bits.div_ceil(8);
+        if hash.len() > width || !bits.is_multiple_of(8) {
+            return None;
+        }
+        let mut padded = vec![0; width - hash.len()];
+        padded.extend_from_slice(hash);
+        Some(Packed::from_bytes(&padded))
+    }
+
+    /// Bits of the narrowest hash an int can be: a multiple of 64, since the
+    /// int form drops a hash's leading zero bits
+    pub fn int_bits(hash: &[u8]) -> usize {
+        let significant = hash.iter().skip_while(|&&byte| byte == 0).count();
+        (significant.div_ceil(8) * 64).max(64)
+    }
+
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+}
+
+/// Number of differing bits; None for hashes of different lengths
+pub fn distance(a: &Packed, b: &Packed) -> Option<u32> {
+    (a.bits == b.bits).then(|| a.words.iter().zip(&b.words).map(|(x, y)| (x ^ y).count_ones()).sum())
+}
+
+/// `distance` between two '0'/'1' strings; None unless both are hashes of
+/// one non-zero length
+pub fn str_distance(a: &str, b: &str) -> Option<u32> {
+    if a.is_empty() {
+        return None;
+    }
+    distance(&Packed::from_str(a)?, &Packed::from_str(b)?)
+}
+
+/// N x N distances between hashes of one length, saturating at 255
+///
+/// Rows are filled in parallel. Only 256-bit hashes can exceed 255, and
+/// only when nearly every bit differs.
+pub fn matrix(hashes: &[Packed]) -> Array2<u8> {
+    let n = hashes.len();
+    let mut distances = vec![0u8; n * n];
+    distances.par_chunks_mut(n.max(1)).zip(hashes).for_each(|(row, a)| {
+        for (cell, b) in row.iter_mut().zip(hashes) {
+            *cell = distance(a, b).unwrap_or(u32::MAX).min(u8::MAX as u32) as u8;
+        }
+    });
+    Array2::from_shape_vec((n, n), distances).expect("n x n distances")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ints_of_any_width_pack_like_their_bytes() {
+        let wide: Vec<u8> = (1..=32).collect();
+        assert_eq!(Packed::int_bits(&wide), 256);
+        let packed = Packed::from_int(&wide, 256).unwrap();
+        assert_eq!(distance(&packed, &Packed::from_bytes(&wide)), Some(0));
+        assert!(Packed::from_int(&wide, 64).is_none());
+
+        // Leading zero bits the int dropped come back when widened to the other hash
+        let mut short = vec![0; 24];
+        short.extend_from_slice(&wide[24..]);
+        assert_eq!(Packed::int_bits(&wide[24..]), 64);
+        let packed = Packed::from_int(&wide[24..], 256).unwrap();
+        assert_eq!(distance(&packed, &Packed::from_bytes(&short)), Some(0));
+        assert_eq!(Packed::from_int(&[0, 0, 1], 64).unwrap().bits(), 64);
+    }
+
+    #[test]
+    fn strings_compare_like_their_packed_bits() {
+        let a = "01".repeat(64);
+        let b = format!("{}{}", "1".repeat(65), "01".repeat(31) + "1");
+        let expected = a.bytes().zip(b.bytes()).filter(|(x, y)| x != y).count() as u32;
+        assert_eq!(str_distance(&a, &b), Some(expected));
+        assert_eq!(str_distance(&a, &a[..64]), None);
+        assert_eq!(str_distance("", ""), None);
+        assert_eq!(str_distance("01x1", "0101"), None);
+    }
+}
@@ -0,0 +1,165 @@
+// src/hash_cache.rs
+// SQLite cache of per-file hashes, keyed by path and checked against size and mtime so rescans only rehash changed files
+
+use std::fs;
+use std::io;
+use std::time::{Duration, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::throttle;
+
+// How long a write waits for another process's transaction
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Size and modification time a cached entry was computed at
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stamp {
+    pub size: u64,
+    /// Nanoseconds since the Unix epoch
+    pub mtime_ns: i64,
+}
+
+/// The current stamp of `path`, or None if it cannot be stat'ed
+pub fn stamp(path: &str) -> Option<Stamp> {
+    throttle::acquire(0, 1);
+    let metadata = fs::metadata(path).ok()?;
+    let mtime_ns = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or(0);
+    Some(Stamp { size: metadata.len(), mtime_ns })
+}
+
+/// Everything cached for one file
+pub struct Entry {
+    pub stamp: Stamp,
+    /// Hex BLAKE3 digest of the contents
+    pub content_hash: String,
+    pub content_type: String,
+    /// '0'/'1' string per hash name
+    pub hashes: Vec<(String, String)>,
+}
+
+pub struct HashCache {
+    conn: Connection,
+}
+
+impl HashCache {
+    pub fn open(location: &str) -> io::Result<Self> {
+        let conn = Connection::open(location).map_err(io::Error::other)?;
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(io::Error::other)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            CREATE TABLE IF NOT EXISTS file_hashes (
+                path TEXT PRIMARY KEY,
+                size INTEGER NOT NULL,
+                mtime_ns INTEGER NOT NULL,
+                content_hash TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                hashes TEXT NOT NULL
+            );",
+        )
+        .map_err(io::Error::other)?;
+        Ok(HashCache { conn })
+    }
+
+    pub fn len(&self) -> io::Result<usize> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM file_hashes", [], |row| row.get::<_, i64>(0))
+            .map(|count| count as usize)
+            .map_err(io::Error::other)
+    }
+
+    /// The entry for `path`, whatever stamp it was computed at
+    pub fn get(&self, path: &str) -> io::Result<Option<Entry>> {
+        self.conn
+            .query_row(
+                "SELECT size, mtime_ns, content_hash, content_type, hashes FROM file_hashes WHERE path = ?1",
+                params![path],
+                |row| {
+                    Ok((
+                        Stamp { size: row.get::<_, i64>(0)? as u64, mtime_ns: row.get(1)? },
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, String>(4)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(io::Error::other)?
+            .map(|(stamp, content_hash, content_type, hashes)| {
+                let hashes: serde_json::Map<String, serde_json::Value> =
+                    serde_json::from_str(&hashes).map_err(io::Error::other)?;
+                let hashes = hashes
+                    .into_iter()
+                    .filter_map(|(name, hash)| Some((name, hash.as_str()?.to_string())))
+                    .collect();
+                Ok(Entry { stamp, content_hash, content_type, hashes })
+            })
+            .transpose()
+    }
+
+    /// The entry for `path` if it was computed at `stamp`, i.e. the file has not changed since
+    pub fn lookup(&self, path: &str, stamp: Stamp) -> io::Result<Option<Entry>> {
+        Ok(self.get(path)?.filter(|entry| entry.stamp == stamp))
+    }
+
+    /// Insert or replace `(path, entry)` pairs in one transaction
+    pub fn store<'a>(&mut self, entries: impl IntoIterator<Item = (&'a str, &'a Entry)>) -> io::Result<()> {
+        let tx = self.conn.transaction().map_err(io::Error::other)?;
+        {
+            let mut statement = tx
+                .prepare(
+                    "INSERT OR REPLACE INTO file_hashes (path, size, mtime_ns, content_hash, content_type, hashes) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )
+                .map_err(io::Error::other)?;
+            for (path, entry) in entries {
+                let hashes: serde_json::Map<String, serde_json::Value> =
+                    entry.hashes.iter().map(|(name, hash)| (name.clone(), hash.clone().into())).collect();
+                statement
+                    .execute(params![
+                        path,
+                        entry.stamp.size as i64,
+                        entry.stamp.mtime_ns,
+                        entry.content_hash,
+                        entry.content_type,
+                        serde_json::Value::Object(hashes).to_string(),
+                    ])
+                    .map_err(io::Error::other)?;
+            }
+        }
+        tx.commit().map_err(io::Error::other)
+    }
+
+    /// Drop entries whose file is gone or has changed, returning how many
+    pub fn prune(&mut self) -> io::Result<usize> {
+        let stale: Vec<String> = {
+            let mut statement =
+                self.conn.prepare("SELECT path, size, mtime_ns FROM file_hashes").map_err(io::Error::other)?;
+            let rows = statement
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, Stamp { size: row.get::<_, i64>(1)? as u64, mtime_ns: row.get(2)? }))
+                })
+                .map_err(io::Error::other)?;
+            rows.filter_map(Result::ok)
+                .filter(|(path, cached)| stamp(path) != Some(*cached))
+                .map(|(path, _)| path)
+                .collect()
+        };
+
+        let tx = self.conn.transaction().map_err(io::Error::other)?;
+        for path in &stale {
+            tx.execute("DELETE FROM file_hashes WHERE path = ?1", params![path]).map_err(io::Error::other)?;
+        }
+        tx.commit().map_err(io::Error::other)?;
+        Ok(stale.len())
+    }
+
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.conn.execute("DELETE FROM file_hashes", []).map(|_| ()).map_err(io::Error::other)
+    }
+}
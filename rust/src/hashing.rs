@@ -0,0 +1,353 @@
+// src/hashing.rs
+// Hash computations shared by the numpy entry points and the decode pipelines
+
+use ndarray::{Array2, ArrayView2};
+use pyo3::exceptions::PyValueError;
+use pyo3::PyResult;
+
+/// How a hash is handed back to Python
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HashFormat {
+    /// '0'/'1' characters, as the index stores them
+    Str,
+    /// An unsigned int whose most significant bit is the hash's first bit
+    Int,
+    /// Packed bits, the first in the top bit of the first byte
+    Bytes,
+}
+
+impl HashFormat {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "str" => Ok(HashFormat::Str),
+            "int" => Ok(HashFormat::Int),
+            "bytes" => Ok(HashFormat::Bytes),
+            _ => Err(PyValueError::new_err(format!(
+                "Unsupported hash format '{}', expected 'str', 'int' or 'bytes'",
+                name
+            ))),
+        }
+    }
+}
+
+/// Pack a '0'/'1' hash eight bits to a byte, first bit most significant;
+/// a final partial byte is padded with zero bits
+pub fn pack_bits(hash: &str) -> Vec<u8> {
+    hash.as_bytes()
+        .chunks(8)
+        .map(|bits| bits.iter().enumerate().fold(0u8, |byte, (i, &bit)| byte | (((bit == b'1') as u8) << (7 - i))))
+        .collect()
+}
+
+/// Average hash of an 8x8 grayscale image as a '0'/'1' string
+pub fn average_hash(arr: ArrayView2<u8>) -> String {
+    // Calculate the average pixel value (optimized)
+    let mut sum = 0u32;
+    for row in arr.rows() {
+        for &pixel in row {
+            sum += pixel as u32;
+        }
+    }
+    let avg = sum / 64;
+
+    // Compute the hash (bit-packed for efficiency)
+    let mut hash = String::with_capacity(64);
+
+    for row in arr.rows() {
+        for &pixel in row {
+            if pixel as u32 >= avg {
+                hash.push('1');
+            } else {
+                hash.push('0');
+            }
+        }
+    }
+
+    hash
+}
+
+/// Region-median perceptual hash of a 32x32 grayscale image as a '0'/'1' string
+pub fn perceptual_hash(arr: ArrayView2<u8>) -> String {
+    region_median_hash(arr, 8)
+}
+
+/// DCT perceptual hash (pHash) of a 32x32 grayscale image as a '0'/'1' string
+///
+/// Takes the 8x8 lowest frequencies of the 2D DCT-II and sets a bit where a
+/// coefficient is above their median. The DC term only measures overall
+/// brightness, so it is left out of the median and its bit is always 0.
+pub fn dct_hash(arr: ArrayView2<u8>) -> String {
+    const SIDE: usize = 32;
+    const LOW: usize = 8;
+    let cosines = Array2::from_shape_fn((LOW, SIDE), |(u, x)| {
+        (std::f64::consts::PI * (2 * x + 1) as f64 * u as f64 / (2 * SIDE) as f64).cos()
+    });
+
+    // Separable: transform the rows, then the columns, keeping low frequencies only
+    let rows = Array2::from_shape_fn((SIDE, LOW), |(y, u)| {
+        (0..SIDE).map(|x| arr[[y, x]] as f64 * cosines[[u, x]]).sum::<f64>()
+    });
+    let coefficients = Array2::from_shape_fn((LOW, LOW), |(v, u)| {
+        (0..SIDE).map(|y| rows[[y, u]] * cosines[[v, y]]).sum::<f64>()
+    });
+
+    let mut ac: Vec<f64> = coefficients.iter().skip(1).copied().collect();
+    ac.sort_by(|a, b| a.total_cmp(b));
+    let median = ac[ac.len() / 2];
+
+    coefficients
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| if i > 0 && c > median { '1' } else { '0' })
+        .collect()
+}
+
+/// Difference hash (dHash) of an 8x9 or 9x8 grayscale image as a '0'/'1' string
+///
+/// Sets a bit where a pixel is brighter than its neighbour before it: along
+/// rows for 8 rows of 9, down columns for 9 rows of 8. Only the direction of
+/// each gradient counts, so brightness and contrast changes leave it alone.
+pub fn difference_hash(arr: ArrayView2<u8>) -> String {
+    let (height, width) = arr.dim();
+    let mut hash = String::with_capacity(64);
+    if width > height {
+        for y in 0..height {
+            for x in 1..width {
+                hash.push(if arr[[y, x]] > arr[[y, x - 1]] { '1' } else { '0' });
+            }
+        }
+    } else {
+        for y in 1..height {
+            for x in 0..width {
+                hash.push(if arr[[y, x]] > arr[[y - 1, x]] { '1' } else { '0' });
+            }
+        }
+    }
+    hash
+}
+
+/// Haar wavelet hash (wHash) of a 64x64 grayscale image as a '0'/'1' string
+///
+/// Decomposes three levels down to the 8x8 approximation band and sets a bit
+/// where a coefficient is above the band's median, as imagehash's `whash`
+/// does. The detail bands, where film grain and sensor noise live, are
+/// dropped. imagehash first removes the image mean, which shifts every
+/// coefficient alike; it is left in here so that, on raw pixel levels, every
+/// coefficient is exact and equal blocks tie exactly.
+pub fn wavelet_hash(arr: ArrayView2<u8>) -> String {
+    const LEVELS: usize = 3;
+    let mut band = arr.mapv(|p| p as f64);
+
+    for _ in 0..LEVELS {
+        let (height, width) = band.dim();
+        // Orthonormal Haar approximation: each 2x2 block summed and halved
+        band = Array2::from_shape_fn((height / 2, width / 2), |(y, x)| {
+            let (y, x) = (2 * y, 2 * x);
+            (band[[y, x]] + band[[y, x + 1]] + band[[y + 1, x]] + band[[y + 1, x + 1]]) / 2.0
+        });
+    }
+
+    let mut sorted: Vec<f64> = band.iter().copied().collect();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let middle = sorted.len() / 2;
+    let median = (sorted[middle - 1] + sorted[middle]) / 2.0;
+    band.iter().map(|&c| if c > median { '1' } else { '0' }).collect()
+}
+
+/// 256-bit region-median hash of a 64x64 grayscale image as a '0'/'1' string
+///
+/// Same construction as the perceptual hash on a 16x16 grid; slower to
+/// compare, but far fewer unrelated images land within a given fraction of
+/// its bits, so it confirms candidates found with the 64-bit hashes.
+pub fn fine_hash(arr: ArrayView2<u8>) -> String {
+    region_median_hash(arr, 16)
+}
+
+/// One bit per cell of a `regions` x `regions` grid: is the cell mean above the median
+fn region_median_hash(arr: ArrayView2<u8>, regions: usize) -> String {
+    let region_height = arr.shape()[0] / regions;
+    let region_width = arr.shape()[1] / regions;
+
+    // Calculate region values (optimized)
+    let mut region_values = vec![0.0; regions * regions];
+
+    for i in 0..regions {
+        for j in 0..regions {
+            let start_y = i * region_height;
+            let end_y = (i + 1) * region_height;
+            let start_x = j * region_width;
+            let end_x = (j + 1) * region_width;
+
+            let mut sum = 0u32;
+            let mut count = 0u32;
+
+            for y in start_y..end_y {
+                for x in start_x..end_x {
+                    sum += arr[[y, x]] as u32;
+                    count += 1;
+                }
+            }
+
+            region_values[i * regions + j] = sum as f32 / count as f32;
+        }
+    }
+
+    // Calculate median (optimized)
+    let mut sorted_values = region_values.clone();
+    sorted_values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median = sorted_values[regions * regions / 2];
+
+    // Create hash (optimized)
+    let mut hash = String::with_capacity(regions * regions);
+    for val in region_values {
+        hash.push(if val > median { '1' } else { '0' });
+    }
+
+    hash
+}
+
+/// Area-average downsample of a square grayscale buffer, like cv2.INTER_AREA
+pub fn area_downsample(pixels: &[u8], side: usize, target: usize) -> Array2<u8> {
+    area_resize(ArrayView2::from_shape((side, side), pixels).expect("square buffer"), target)
+}
+
+/// Area-average resize of any grayscale image to `target` x `target`
+///
+/// Each output pixel is the rounded mean of the source block it covers; a
+/// side shorter than `target` repeats its pixels instead.
+pub fn area_resize(arr: ArrayView2<u8>, target: usize) -> Array2<u8> {
+    area_resize_to(arr, target, target)
+}
+
+/// `area_resize` to `rows` x `cols`, for hashes on a non-square grid
+pub fn area_resize_to(arr: ArrayView2<u8>, rows: usize, cols: usize) -> Array2<u8> {
+    let (height, width) = arr.dim();
+    Array2::from_shape_fn((rows, cols), |(ty, tx)| {
+        let (y0, y1) = (ty * height / rows, ((ty + 1) * height / rows).max(ty * height / rows + 1));
+        let (x0, x1) = (tx * width / cols, ((tx + 1) * width / cols).max(tx * width / cols + 1));
+
+        // u64: one cell of a full-size frame can cover millions of pixels
+        let mut sum = 0u64;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                sum += arr[[y, x]] as u64;
+            }
+        }
+
+        let count = ((y1 - y0) * (x1 - x0)) as u64;
+        ((sum + count / 2) / count) as u8
+    })
+}
+
+/// Kind of image content, used to pick a hash that discriminates well for it
+#[derive(Clone, Copy, PartialEq)]
+pub enum ContentType {
+    /// Natural images: low-frequency structure carries the identity
+    Photo,
+    /// Screenshots, scans, slides: flat backgrounds with sharp text/lines
+    Document,
+}
+
+impl ContentType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContentType::Photo => "photo",
+            ContentType::Document => "document",
+        }
+    }
+}
+
+/// Gradient magnitude (|dx| + |dy|, central differences) at every interior pixel
+fn gradient_magnitudes(arr: ArrayView2<u8>) -> Array2<u16> {
+    let (height, width) = arr.dim();
+    Array2::from_shape_fn((height, width), |(y, x)| {
+        if y == 0 || x == 0 || y + 1 >= height || x + 1 >= width {
+            return 0;
+        }
+        let dx = (arr[[y, x + 1]] as i16 - arr[[y, x - 1]] as i16).unsigned_abs();
+        let dy = (arr[[y + 1, x]] as i16 - arr[[y - 1, x]] as i16).unsigned_abs();
+        dx + dy
+    })
+}
+
+/// Edge-density hash for text and graphics as a '0'/'1' string
+///
+/// Splits the image into an 8x8 grid and sets a bit where the cell holds more
+/// edge energy than the median cell. Low-frequency hashes see every page of
+/// text as "white with grey texture"; edge layout tells them apart.
+pub fn edge_hash(arr: ArrayView2<u8>) -> String {
+    const GRID: usize = 8;
+    let (height, width) = arr.dim();
+    let gradients = gradient_magnitudes(arr);
+
+    let mut cell_values = vec![0u32; GRID * GRID];
+    for i in 0..GRID {
+        for j in 0..GRID {
+            let (y0, y1) = (i * height / GRID, (i + 1) * height / GRID);
+            let (x0, x1) = (j * width / GRID, (j + 1) * width / GRID);
+            cell_values[i * GRID + j] = gradients
+                .slice(ndarray::s![y0..y1, x0..x1])
+                .iter()
+                .map(|&g| g as u32)
+                .sum();
+        }
+    }
+
+    let mut sorted_values = cell_values.clone();
+    sorted_values.sort_unstable();
+    let median = sorted_values[GRID * GRID / 2];
+
+    cell_values
+        .iter()
+        .map(|&v| if v > median { '1' } else { '0' })
+        .collect()
+}
+
+/// Side of the largest grid `all_hashes` reduces a thumbnail to (the edge hash's)
+pub const LARGEST_GRID: usize = 128;
+
+/// Average, perceptual, fine and edge hashes of a `side` x `side` thumbnail,
+/// each computed from its own area-downsampled copy
+pub fn thumbnail_hashes(pixels: &[u8], side: usize) -> [(&'static str, String); 4] {
+    [
+        ("average_hash", average_hash(area_downsample(pixels, side, 8).view())),
+        ("perceptual_hash", perceptual_hash(area_downsample(pixels, side, 32).view())),
+        ("fine_hash", fine_hash(area_downsample(pixels, side, 64).view())),
+        ("edge_hash", edge_hash(area_downsample(pixels, side, LARGEST_GRID).view())),
+    ]
+}
+
+/// `thumbnail_hashes` plus the difference (8 rows of 9), DCT and wavelet
+/// hashes, for callers that want every hash type from one decode
+pub fn all_hashes(pixels: &[u8], side: usize) -> Vec<(&'static str, String)> {
+    let arr = ArrayView2::from_shape((side, side), pixels).expect("square buffer");
+    let mut hashes = thumbnail_hashes(pixels, side).to_vec();
+    hashes.push(("difference_hash", difference_hash(area_resize_to(arr, 8, 9).view())));
+    hashes.push(("dct_hash", dct_hash(area_resize(arr, 32).view())));
+    hashes.push(("wavelet_hash", wavelet_hash(area_resize(arr, 64).view())));
+    hashes
+}
+
+/// Guess whether a grayscale image is a photo or a document/screenshot
+///
+/// Documents are dominated by a few flat levels (paper, UI backgrounds) and
+/// still carry many strong edges; photos spread over the histogram, and flat
+/// photos (sky, studio backdrops) lack the edges.
+pub fn classify_content(arr: ArrayView2<u8>) -> ContentType {
+    let total = arr.len().max(1) as f64;
+
+    let mut histogram = [0u32; 256];
+    for &pixel in arr.iter() {
+        histogram[pixel as usize] += 1;
+    }
+    histogram.sort_unstable_by(|a, b| b.cmp(a));
+    let flat_share = histogram[..4].iter().map(|&c| c as f64).sum::<f64>() / total;
+
+    let strong_edges = gradient_magnitudes(arr).iter().filter(|&&g| g > 96).count() as f64 / total;
+
+    if flat_share > 0.4 && strong_edges > 0.02 {
+        ContentType::Document
+    } else {
+        ContentType::Photo
+    }
+}
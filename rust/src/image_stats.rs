@@ -0,0 +1,136 @@
+// src/image_stats.rs
+// Per-file quality statistics, computed once and kept in the index for the keeper policies
+
+use std::fs;
+use std::io;
+use std::time::UNIX_EPOCH;
+
+use crate::index::IndexStore;
+use crate::paths;
+
+// Thumbnail pixels at or beyond these levels count as clipped shadows or highlights
+const SHADOW_CLIP: u8 = 2;
+const HIGHLIGHT_CLIP: u8 = 253;
+
+/// Statistics of one file, measured on its grayscale hashing thumbnail
+///
+/// Sharpness and noise depend on the thumbnail scale, so they rank copies of
+/// the same picture against each other rather than meaning anything alone.
+#[derive(Clone, Copy)]
+pub struct ImageStats {
+    /// Full image dimensions, None when neither the header nor the decode gave them
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Variance of the Laplacian; higher is sharper, though noise raises it too
+    pub sharpness: f64,
+    /// Mean brightness, 0-1
+    pub exposure: f64,
+    /// Share of pixels clipped to black or white
+    pub clipped: f64,
+    /// Estimated noise standard deviation in 8-bit levels (Immerkær)
+    pub noise: f64,
+}
+
+impl ImageStats {
+    /// Measure a `side` x `side` grayscale thumbnail of an image of `dimensions`
+    pub fn measure(pixels: &[u8], side: usize, dimensions: Option<(u32, u32)>) -> ImageStats {
+        let at = |y: usize, x: usize| pixels[y * side + x] as f64;
+        let count = pixels.len().max(1) as f64;
+        let exposure = pixels.iter().map(|&p| p as f64).sum::<f64>() / count / 255.0;
+        let clipped = pixels.iter().filter(|&&p| p <= SHADOW_CLIP || p >= HIGHLIGHT_CLIP).count() as f64 / count;
+
+        let (mut sum, mut sum_squares, mut noise_sum) = (0.0, 0.0, 0.0);
+        for y in 1..side.saturating_sub(1) {
+            for x in 1..side - 1 {
+                let laplacian = at(y - 1, x) + at(y + 1, x) + at(y, x - 1) + at(y, x + 1) - 4.0 * at(y, x);
+                sum += laplacian;
+                sum_squares += laplacian * laplacian;
+                // Immerkær's mask: the difference of two Laplacians, blind to edges of constant slope
+                let corners = at(y - 1, x - 1) + at(y - 1, x + 1) + at(y + 1, x - 1) + at(y + 1, x + 1);
+                let sides = at(y - 1, x) + at(y + 1, x) + at(y, x - 1) + at(y, x + 1);
+                noise_sum += (corners - 2.0 * sides + 4.0 * at(y, x)).abs();
+            }
+        }
+        let interior = (side.saturating_sub(2) * side.saturating_sub(2)).max(1) as f64;
+        let mean = sum / interior;
+        let sharpness = sum_squares / interior - mean * mean;
+        let noise = noise_sum * (std::f64::consts::PI / 2.0).sqrt() / (6.0 * interior);
+
+        ImageStats {
+            width: dimensions.map(|(width, _)| width),
+            height: dimensions.map(|(_, height)| height),
+            sharpness,
+            exposure,
+            clipped,
+            noise,
+        }
+    }
+}
+
+const KEY_PREFIX: &str = "stats:";
+
+fn setting_key(path: &str) -> String {
+    format!("{}{}", KEY_PREFIX, paths::identity(path))
+}
+
+/// Size and modification time of `path`, taken before measuring it so an
+/// edit made meanwhile invalidates the stored stats
+pub fn freshness(path: &str) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_nanos());
+    Some(format!("{}:{}", metadata.len(), modified))
+}
+
+fn encode(freshness: &str, stats: &ImageStats) -> String {
+    let dimension = |d: Option<u32>| d.map(|d| d.to_string()).unwrap_or_default();
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        freshness,
+        dimension(stats.width),
+        dimension(stats.height),
+        stats.sharpness,
+        stats.exposure,
+        stats.clipped,
+        stats.noise
+    )
+}
+
+fn decode(value: &str) -> Option<(&str, ImageStats)> {
+    let fields: Vec<&str> = value.split('\t').collect();
+    let [freshness, width, height, sharpness, exposure, clipped, noise] = fields[..] else {
+        return None;
+    };
+    let stats = ImageStats {
+        width: width.parse().ok(),
+        height: height.parse().ok(),
+        sharpness: sharpness.parse().ok()?,
+        exposure: exposure.parse().ok()?,
+        clipped: clipped.parse().ok()?,
+        noise: noise.parse().ok()?,
+    };
+    Some((freshness, stats))
+}
+
+/// Stats stored for `path`, or None if never measured or the file changed since
+///
+/// A stored value that does not parse counts as missing, so it is measured again.
+pub fn load(store: &mut dyn IndexStore, path: &str) -> io::Result<Option<ImageStats>> {
+    let Some(value) = store.get_setting(&setting_key(path))? else {
+        return Ok(None);
+    };
+    Ok(decode(&value).filter(|(measured_at, _)| freshness(path).as_deref() == Some(*measured_at)).map(|(_, stats)| stats))
+}
+
+/// Whether a setting holds stats `load` would never return again, because
+/// the file is gone or changed since; false for every other setting
+pub fn is_stale(key: &str, value: &str) -> bool {
+    let Some(path) = key.strip_prefix(KEY_PREFIX) else {
+        return false;
+    };
+    decode(value).is_none_or(|(measured_at, _)| freshness(path).as_deref() != Some(measured_at))
+}
+
+/// Store `stats` for `path`, measured when the file was at `measured_at` (from `freshness`)
+pub fn save(store: &mut dyn IndexStore, path: &str, measured_at: &str, stats: &ImageStats) -> io::Result<()> {
+    store.put_setting(&setting_key(path), &encode(measured_at, stats))
+}
@@ -0,0 +1,250 @@
+// src/importers.rs
+// Duplicate groups and ignore-lists exported by czkawka and dupeGuru, as pair decisions
+
+use std::io;
+
+use serde_json::Value;
+
+/// Decisions recovered from another tool's export
+#[derive(Default)]
+pub struct Imported {
+    /// Duplicate groups found in the export (ignore-lists have none)
+    pub groups: usize,
+    /// `(path_a, path_b, decision)` with decisions as used by the index
+    pub pairs: Vec<(String, String, &'static str)>,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Tools whose exports can be imported
+pub fn tools() -> &'static [&'static str] {
+    &["czkawka", "dupeguru"]
+}
+
+/// Guess the tool from the export's contents: czkawka writes JSON, dupeGuru XML
+pub fn detect_tool(text: &str) -> Option<&'static str> {
+    match text.trim_start().chars().next()? {
+        '[' | '{' => Some("czkawka"),
+        '<' => Some("dupeguru"),
+        _ => None,
+    }
+}
+
+pub fn import(tool: &str, text: &str) -> io::Result<Imported> {
+    match tool {
+        "czkawka" => czkawka(text),
+        "dupeguru" => dupeguru(text),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unsupported tool '{}', expected one of {:?}", tool, tools()),
+        )),
+    }
+}
+
+/// Path of a czkawka file entry (any object with a string `path`)
+fn entry_path(value: &Value) -> Option<&str> {
+    value.get("path")?.as_str()
+}
+
+/// Walk a czkawka JSON export and pair every group member with the group's first
+///
+/// Duplicate files, similar images, videos and music all save groups as arrays
+/// of entries, nested in maps keyed by size, hash or name depending on the
+/// mode. With reference folders a group is `[reference, [entries...]]`, and
+/// the reference is the one to keep.
+fn collect_czkawka(value: &Value, imported: &mut Imported) {
+    match value {
+        Value::Array(items) => {
+            if let [reference, Value::Array(others)] = items.as_slice() {
+                if let Some(reference) = entry_path(reference) {
+                    imported.groups += 1;
+                    for other in others.iter().filter_map(entry_path) {
+                        imported.pairs.push((reference.to_string(), other.to_string(), "keep_a"));
+                    }
+                    return;
+                }
+            }
+
+            let members: Vec<&str> = items.iter().filter_map(entry_path).collect();
+            if let [first, rest @ ..] = members.as_slice() {
+                imported.groups += 1;
+                for other in rest {
+                    imported.pairs.push((first.to_string(), other.to_string(), "duplicate"));
+                }
+            } else {
+                items.iter().for_each(|item| collect_czkawka(item, imported));
+            }
+        },
+        Value::Object(map) if !map.contains_key("path") => map.values().for_each(|v| collect_czkawka(v, imported)),
+        _ => {},
+    }
+}
+
+fn czkawka(text: &str) -> io::Result<Imported> {
+    let json: Value = serde_json::from_str(text).map_err(|e| invalid(format!("Invalid czkawka export: {}", e)))?;
+    let mut imported = Imported::default();
+    collect_czkawka(&json, &mut imported);
+    Ok(imported)
+}
+
+/// One tag of an XML document
+struct Tag {
+    name: String,
+    attributes: Vec<(String, String)>,
+    closing: bool,
+    self_closing: bool,
+}
+
+impl Tag {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+fn unescape_xml(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else { break };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32),
+            _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The element tags of a simple XML document, skipping the declaration and comments
+///
+/// Enough for the flat files dupeGuru writes; text content is ignored.
+fn xml_tags(text: &str) -> io::Result<Vec<Tag>> {
+    let mut tags = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            let end = comment.find("-->").ok_or_else(|| invalid("Unterminated XML comment"))?;
+            rest = &comment[end + 3..];
+            continue;
+        }
+        let end = rest.find('>').ok_or_else(|| invalid("Unterminated XML tag"))?;
+        let body = &rest[..end];
+        rest = &rest[end + 1..];
+        if body.starts_with('?') || body.starts_with('!') {
+            continue;
+        }
+
+        let closing = body.starts_with('/');
+        let self_closing = body.ends_with('/');
+        let body = body.trim_start_matches('/').trim_end_matches('/');
+        let name_end = body.find(char::is_whitespace).unwrap_or(body.len());
+        let mut attributes = Vec::new();
+        let mut attrs = &body[name_end..];
+        while let Some(eq) = attrs.find('=') {
+            let name = attrs[..eq].trim().to_string();
+            let value = attrs[eq + 1..].trim_start();
+            let quote = value.chars().next().filter(|q| *q == '"' || *q == '\'').ok_or_else(|| invalid("Unquoted XML attribute"))?;
+            let close = value[1..].find(quote).ok_or_else(|| invalid("Unterminated XML attribute"))?;
+            attributes.push((name, unescape_xml(&value[1..1 + close])));
+            attrs = &value[close + 2..];
+        }
+        tags.push(Tag { name: body[..name_end].to_string(), attributes, closing, self_closing });
+    }
+    Ok(tags)
+}
+
+/// dupeGuru results (`<results>`, saved as .dupeguru) or ignore list (`<ignore_list>`)
+///
+/// In results, every `<match>` becomes a pair; groups without matches pair
+/// each file with the first. A file marked for action (deletion) loses to an
+/// unmarked one, giving `keep_a`/`keep_b`. Ignore-list entries become
+/// `not_duplicate`.
+fn dupeguru(text: &str) -> io::Result<Imported> {
+    let tags = xml_tags(text)?;
+    let mut imported = Imported::default();
+    match tags.first().map(|t| t.name.as_str()) {
+        Some("results") => {
+            let mut files: Vec<(String, bool)> = Vec::new();
+            let mut matches: Vec<(usize, usize)> = Vec::new();
+            for tag in &tags {
+                match (tag.name.as_str(), tag.closing) {
+                    ("group", false) => {
+                        files.clear();
+                        matches.clear();
+                    },
+                    ("file", false) => {
+                        let path = tag.attribute("path").ok_or_else(|| invalid("dupeGuru file without path"))?;
+                        files.push((path.to_string(), tag.attribute("marked") == Some("y")));
+                    },
+                    ("match", false) => {
+                        let index = |name| tag.attribute(name).and_then(|v| v.parse::<usize>().ok());
+                        if let (Some(first), Some(second)) = (index("first"), index("second")) {
+                            matches.push((first, second));
+                        }
+                    },
+                    ("group", true) => {
+                        if files.len() < 2 {
+                            continue;
+                        }
+                        imported.groups += 1;
+                        if matches.is_empty() {
+                            matches = (1..files.len()).map(|i| (0, i)).collect();
+                        }
+                        for &(a, b) in &matches {
+                            let (Some((path_a, marked_a)), Some((path_b, marked_b))) = (files.get(a), files.get(b)) else {
+                                continue;
+                            };
+                            let decision = match (marked_a, marked_b) {
+                                (false, true) => "keep_a",
+                                (true, false) => "keep_b",
+                                _ => "duplicate",
+                            };
+                            imported.pairs.push((path_a.clone(), path_b.clone(), decision));
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        },
+        Some("ignore_list") => {
+            // <file path="a"><file path="b"/></file> ignores the pair (a, b)
+            let mut outer: Option<String> = None;
+            for tag in tags.iter().filter(|t| t.name == "file") {
+                match (&outer, tag.closing) {
+                    (_, true) => outer = None,
+                    (None, false) if !tag.self_closing => outer = tag.attribute("path").map(str::to_string),
+                    (Some(a), false) => {
+                        if let Some(b) = tag.attribute("path") {
+                            imported.pairs.push((a.clone(), b.to_string(), "not_duplicate"));
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        },
+        _ => return Err(invalid("Not a dupeGuru results or ignore list file")),
+    }
+    Ok(imported)
+}
@@ -0,0 +1,457 @@
+// src/index/flat.rs
+// Flat-file index: one TSV line per record, loaded into memory and rewritten on flush
+// Duplicate decisions and settings live next to it in `<location>.decisions` and
+// `<location>.settings`; flushes from several
+// processes are serialized by `<location>.lock` and merge with what is on disk
+// Every line carries a checksum and every file an end marker, and each flush first
+// writes its changes to `<location>.journal`, so a save cut short by power loss is
+// detected on the next open and repaired from whatever survived
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::{escape, unescape, DuplicateDecision, ImageRecord, IndexStore};
+use crate::locking::{self, FileLock};
+
+type RecordKey = (String, String);
+
+/// First line of a checksummed file; files without it predate checksums and load unchecked
+const HEADER: &str = "#raw_processor flat index\t2";
+/// Last line of a checksummed file, followed by the number of entries
+const FOOTER: &str = "#end\t";
+
+pub struct FlatStore {
+    path: PathBuf,
+    records: HashMap<RecordKey, ImageRecord>,
+    decisions: HashMap<RecordKey, DuplicateDecision>,
+    settings: HashMap<String, String>,
+    /// Keys written or removed since the last flush; everything else is taken from disk
+    changed_records: HashSet<RecordKey>,
+    changed_decisions: HashSet<RecordKey>,
+    changed_settings: HashSet<String>,
+    /// Damage found and salvaged at open
+    recovery: Vec<String>,
+    /// Whether the files still need rewriting clean, even without changes
+    damaged: bool,
+}
+
+fn checksum(payload: &str) -> String {
+    blake3::hash(payload.as_bytes()).to_hex()[..16].to_string()
+}
+
+/// Entries of a file written by `write_lines`, or none if it does not exist yet
+///
+/// Lines failing their checksum, and a missing end marker, are reported in
+/// `problems` instead of failing the load, so the intact entries survive.
+fn read_lines(path: &Path, problems: &mut Vec<String>) -> io::Result<Vec<String>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    // A torn write may leave garbage that is not even UTF-8; the checksums catch it
+    let text = String::from_utf8_lossy(&bytes);
+    let mut lines = text.lines().filter(|l| !l.is_empty());
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+
+    if text.lines().next() != Some(HEADER) {
+        return Ok(lines.map(str::to_string).collect());
+    }
+    lines.next();
+
+    let mut entries = Vec::new();
+    let mut expected = None;
+    for line in lines {
+        let payload = line.rsplit_once('\t').filter(|(payload, sum)| checksum(payload) == *sum).map(|(p, _)| p);
+        match (payload, expected) {
+            (Some(payload), None) => entries.push(payload.to_string()),
+            (None, None) if line.starts_with(FOOTER) => {
+                expected = Some(line[FOOTER.len()..].parse::<usize>().unwrap_or(usize::MAX));
+            },
+            (None, None) => problems.push(format!("{}: dropped a damaged line", name)),
+            (_, Some(_)) => problems.push(format!("{}: ignored data after the end marker", name)),
+        }
+    }
+    match expected {
+        None => problems.push(format!("{}: file was cut short, kept {} intact entries", name, entries.len())),
+        Some(count) if count != entries.len() => {
+            problems.push(format!("{}: expected {} entries, kept {} intact ones", name, count, entries.len()))
+        },
+        Some(_) => {},
+    }
+    Ok(entries)
+}
+
+/// Replace a file atomically: write next to it and rename, so a crash never leaves half a file
+fn write_lines(path: &Path, lines: impl Iterator<Item = String>) -> io::Result<()> {
+    let temp = locking::unique_temp(path);
+
+    let mut file = io::BufWriter::new(fs::File::create(&temp)?);
+    writeln!(file, "{}", HEADER)?;
+    let mut count = 0;
+    for line in lines {
+        writeln!(file, "{}\t{}", line, checksum(&line))?;
+        count += 1;
+    }
+    writeln!(file, "{}{}", FOOTER, count)?;
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&temp, path)?;
+    sync_parent(path)
+}
+
+/// Make a rename or removal in `path`'s directory survive power loss
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => fs::File::open(dir)?.sync_all(),
+        None => fs::File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Decode every entry of a file; entries that pass their checksum (or predate
+/// checksums) but still do not decode are skipped and reported
+fn load_map<K: Hash + Eq, V>(
+    path: &Path,
+    problems: &mut Vec<String>,
+    decode: impl Fn(&str) -> io::Result<(K, V)>,
+) -> io::Result<HashMap<K, V>> {
+    let mut map = HashMap::new();
+    for line in read_lines(path, problems)? {
+        match decode(&line) {
+            Ok((key, value)) => {
+                map.insert(key, value);
+            },
+            Err(e) => salvage(problems, e),
+        }
+    }
+    Ok(map)
+}
+
+fn load_records(path: &Path, problems: &mut Vec<String>) -> io::Result<HashMap<RecordKey, ImageRecord>> {
+    load_map(path, problems, |line| {
+        let record = ImageRecord::decode(line)?;
+        Ok(((record.path.clone(), record.source_prefix.clone()), record))
+    })
+}
+
+fn load_decisions(path: &Path, problems: &mut Vec<String>) -> io::Result<HashMap<RecordKey, DuplicateDecision>> {
+    load_map(path, problems, |line| {
+        let decision = DuplicateDecision::decode(line)?;
+        Ok(((decision.path_a.clone(), decision.path_b.clone()), decision))
+    })
+}
+
+fn load_settings(path: &Path, problems: &mut Vec<String>) -> io::Result<HashMap<String, String>> {
+    load_map(path, problems, decode_setting)
+}
+
+fn decode_setting(line: &str) -> io::Result<(String, String)> {
+    let (key, value) = line
+        .split_once('\t')
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Corrupt setting: {}", line)))?;
+    Ok((unescape(key), unescape(value)))
+}
+
+fn encode_setting(key: &str, value: &str) -> String {
+    format!("{}\t{}", escape(key), escape(value))
+}
+
+/// Report an entry that was skipped because it does not decode
+fn salvage(problems: &mut Vec<String>, error: io::Error) {
+    problems.push(format!("{}, skipped", error));
+}
+
+/// Everything the index keeps, as loaded from disk
+struct Contents {
+    records: HashMap<RecordKey, ImageRecord>,
+    decisions: HashMap<RecordKey, DuplicateDecision>,
+    settings: HashMap<String, String>,
+}
+
+/// One change in the journal; removals name the key they removed
+fn journal_entry<K>(kind: &str, key: &K, value: Option<String>, encode_key: impl Fn(&K) -> String) -> String {
+    match value {
+        Some(value) => format!("{}\t{}", kind, value),
+        None => format!("un{}\t{}", kind, encode_key(key)),
+    }
+}
+
+/// Apply the changes journaled by a flush that may not have finished; returns
+/// the keys they touched so they count as pending changes
+fn replay(entries: &[String], contents: &mut Contents, problems: &mut Vec<String>) -> Changes {
+    let mut changes = Changes::default();
+    for entry in entries {
+        let (kind, rest) = entry.split_once('\t').unwrap_or((entry, ""));
+        let applied = match kind {
+            "record" => ImageRecord::decode(rest).map(|record| {
+                let key = (record.path.clone(), record.source_prefix.clone());
+                contents.records.insert(key.clone(), record);
+                changes.records.insert(key);
+            }),
+            "unrecord" => decode_key(rest).map(|key| {
+                contents.records.remove(&key);
+                changes.records.insert(key);
+            }),
+            "decision" => DuplicateDecision::decode(rest).map(|decision| {
+                let key = (decision.path_a.clone(), decision.path_b.clone());
+                contents.decisions.insert(key.clone(), decision);
+                changes.decisions.insert(key);
+            }),
+            "undecision" => decode_key(rest).map(|key| {
+                contents.decisions.remove(&key);
+                changes.decisions.insert(key);
+            }),
+            "setting" => decode_setting(rest).map(|(key, value)| {
+                contents.settings.insert(key.clone(), value);
+                changes.settings.insert(key);
+            }),
+            "unsetting" => {
+                let key = unescape(rest);
+                contents.settings.remove(&key);
+                changes.settings.insert(key);
+                Ok(())
+            },
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unknown journal entry: {}", entry))),
+        };
+        if let Err(e) = applied {
+            salvage(problems, e);
+        }
+    }
+    changes
+}
+
+fn encode_key((a, b): &RecordKey) -> String {
+    format!("{}\t{}", escape(a), escape(b))
+}
+
+fn decode_key(line: &str) -> io::Result<RecordKey> {
+    line.split_once('\t')
+        .map(|(a, b)| (unescape(a), unescape(b)))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Corrupt journal key: {}", line)))
+}
+
+/// Keys changed by a replayed journal
+#[derive(Default)]
+struct Changes {
+    records: HashSet<RecordKey>,
+    decisions: HashSet<RecordKey>,
+    settings: HashSet<String>,
+}
+
+/// Apply this process's pending changes on top of the on-disk state
+fn merge<K: Hash + Eq + Clone, V: Clone>(disk: &mut HashMap<K, V>, local: &HashMap<K, V>, changed: &HashSet<K>) {
+    for key in changed {
+        match local.get(key) {
+            Some(value) => disk.insert(key.clone(), value.clone()),
+            None => disk.remove(key),
+        };
+    }
+}
+
+impl FlatStore {
+    pub fn open(location: &str) -> io::Result<Self> {
+        let path = PathBuf::from(location);
+        let _lock = FileLock::shared(&locking::sibling(&path, ".lock"))?;
+
+        let mut recovery = Vec::new();
+        let mut contents = Self::load(&path, &mut recovery)?;
+
+        // A journal left behind means the last flush died before finishing its rewrite
+        let journal = read_lines(&Self::journal_path(&path), &mut recovery)?;
+        let changes = replay(&journal, &mut contents, &mut recovery);
+        if !journal.is_empty() {
+            recovery.push(format!("Replayed {} changes from an interrupted save", journal.len()));
+        }
+
+        Ok(FlatStore {
+            records: contents.records,
+            decisions: contents.decisions,
+            settings: contents.settings,
+            path,
+            changed_records: changes.records,
+            changed_decisions: changes.decisions,
+            changed_settings: changes.settings,
+            damaged: !recovery.is_empty(),
+            recovery,
+        })
+    }
+
+    fn load(path: &Path, problems: &mut Vec<String>) -> io::Result<Contents> {
+        Ok(Contents {
+            records: load_records(path, problems)?,
+            decisions: load_decisions(&Self::decisions_path(path), problems)?,
+            settings: load_settings(&Self::settings_path(path), problems)?,
+        })
+    }
+
+    fn decisions_path(path: &Path) -> PathBuf {
+        locking::sibling(path, ".decisions")
+    }
+
+    fn settings_path(path: &Path) -> PathBuf {
+        locking::sibling(path, ".settings")
+    }
+
+    fn journal_path(path: &Path) -> PathBuf {
+        locking::sibling(path, ".journal")
+    }
+
+    /// This process's pending changes as journal entries
+    fn journal(&self) -> impl Iterator<Item = String> + '_ {
+        let records = self.changed_records.iter().map(|key| {
+            journal_entry("record", key, self.records.get(key).map(ImageRecord::encode), encode_key)
+        });
+        let decisions = self.changed_decisions.iter().map(|key| {
+            journal_entry("decision", key, self.decisions.get(key).map(DuplicateDecision::encode), encode_key)
+        });
+        let settings = self.changed_settings.iter().map(|key| {
+            let value = self.settings.get(key).map(|value| encode_setting(key, value));
+            journal_entry("setting", key, value, |key| escape(key))
+        });
+        records.chain(decisions).chain(settings)
+    }
+}
+
+impl IndexStore for FlatStore {
+    fn get(&mut self, path: &str, source_prefix: &str) -> io::Result<Option<ImageRecord>> {
+        Ok(self.records.get(&(path.to_string(), source_prefix.to_string())).cloned())
+    }
+
+    fn put(&mut self, record: &ImageRecord) -> io::Result<()> {
+        let key = (record.path.clone(), record.source_prefix.clone());
+        self.records.insert(key.clone(), record.clone());
+        self.changed_records.insert(key);
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &str, source_prefix: &str) -> io::Result<bool> {
+        let key = (path.to_string(), source_prefix.to_string());
+        let removed = self.records.remove(&key).is_some();
+        if removed {
+            self.changed_records.insert(key);
+        }
+        Ok(removed)
+    }
+
+    fn records(&mut self) -> io::Result<Vec<ImageRecord>> {
+        Ok(self.records.values().cloned().collect())
+    }
+
+    fn put_decision(&mut self, decision: &DuplicateDecision) -> io::Result<()> {
+        let key = (decision.path_a.clone(), decision.path_b.clone());
+        self.decisions.insert(key.clone(), decision.clone());
+        self.changed_decisions.insert(key);
+        Ok(())
+    }
+
+    fn remove_decision(&mut self, path_a: &str, path_b: &str) -> io::Result<bool> {
+        let key = (path_a.to_string(), path_b.to_string());
+        let removed = self.decisions.remove(&key).is_some();
+        if removed {
+            self.changed_decisions.insert(key);
+        }
+        Ok(removed)
+    }
+
+    fn decisions(&mut self) -> io::Result<Vec<DuplicateDecision>> {
+        Ok(self.decisions.values().cloned().collect())
+    }
+
+    fn get_setting(&mut self, key: &str) -> io::Result<Option<String>> {
+        Ok(self.settings.get(key).cloned())
+    }
+
+    fn put_setting(&mut self, key: &str, value: &str) -> io::Result<()> {
+        self.settings.insert(key.to_string(), value.to_string());
+        self.changed_settings.insert(key.to_string());
+        Ok(())
+    }
+
+    fn remove_setting(&mut self, key: &str) -> io::Result<bool> {
+        let removed = self.settings.remove(key).is_some();
+        if removed {
+            self.changed_settings.insert(key.to_string());
+        }
+        Ok(removed)
+    }
+
+    fn settings(&mut self) -> io::Result<Vec<(String, String)>> {
+        Ok(self.settings.iter().map(|(key, value)| (key.clone(), value.clone())).collect())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let unchanged =
+            self.changed_records.is_empty() && self.changed_decisions.is_empty() && self.changed_settings.is_empty();
+        if unchanged && !self.damaged {
+            return Ok(());
+        }
+
+        // Other workers may have flushed since we loaded; merge instead of overwriting them
+        let _lock = FileLock::exclusive(&locking::sibling(&self.path, ".lock"))?;
+        let journal_path = Self::journal_path(&self.path);
+
+        // Damage found now is repaired by this rewrite, like damage found at open
+        let mut problems = Vec::new();
+        let mut disk = Self::load(&self.path, &mut problems)?;
+        // A journal another worker left behind is older than our changes, and
+        // stays in ours until the files carry it
+        let unfinished = read_lines(&journal_path, &mut problems)?;
+        replay(&unfinished, &mut disk, &mut problems);
+
+        write_lines(&journal_path, unfinished.into_iter().chain(self.journal()))?;
+
+        merge(&mut disk.records, &self.records, &self.changed_records);
+        write_lines(&self.path, disk.records.values().map(ImageRecord::encode))?;
+
+        merge(&mut disk.decisions, &self.decisions, &self.changed_decisions);
+        write_lines(&Self::decisions_path(&self.path), disk.decisions.values().map(DuplicateDecision::encode))?;
+
+        merge(&mut disk.settings, &self.settings, &self.changed_settings);
+        write_lines(&Self::settings_path(&self.path), disk.settings.iter().map(|(k, v)| encode_setting(k, v)))?;
+
+        fs::remove_file(&journal_path)?;
+        sync_parent(&journal_path)?;
+
+        self.records = disk.records;
+        self.decisions = disk.decisions;
+        self.settings = disk.settings;
+        self.changed_records.clear();
+        self.changed_decisions.clear();
+        self.changed_settings.clear();
+        self.damaged = false;
+        Ok(())
+    }
+
+    fn vacuum(&mut self) -> io::Result<()> {
+        // Every flush rewrites the files whole, so removed entries hold no space
+        self.flush()
+    }
+
+    fn size_on_disk(&mut self) -> io::Result<u64> {
+        let files = [
+            self.path.clone(),
+            Self::decisions_path(&self.path),
+            Self::settings_path(&self.path),
+            Self::journal_path(&self.path),
+        ];
+        Ok(files.iter().filter_map(|path| fs::metadata(path).ok()).map(|metadata| metadata.len()).sum())
+    }
+
+    fn recovery(&self) -> Vec<String> {
+        self.recovery.clone()
+    }
+}
+
+impl Drop for FlatStore {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
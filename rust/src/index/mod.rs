@@ -0,0 +1,425 @@
+// src/index/mod.rs
+// Persistent image index behind a storage trait, so backends can be swapped per deployment
+
+use std::io;
+use std::sync::{Arc, Mutex, Weak};
+
+mod flat;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod sled_store;
+mod sqlite;
+
+/// One indexed image; mirrors the `images` table of the Python scanner
+#[derive(Clone, Default)]
+pub struct ImageRecord {
+    /// The file's identity (`paths::identity`), the key it is looked up by;
+    /// not necessarily a path that opens the file
+    pub path: String,
+    pub source_prefix: String,
+    pub format: String,
+    pub width: u32,
+    pub height: u32,
+    pub created_at: String,
+    pub modified_at: String,
+    pub size: u64,
+    pub average_hash: String,
+    pub perceptual_hash: String,
+    pub is_raw_format: bool,
+    /// 256-bit hash confirming coarse matches; empty for records indexed before it existed
+    pub fine_hash: String,
+    /// BLAKE3 checksum of the file bytes, identifying it across moves; empty when not computed
+    pub content_hash: String,
+    /// The path as given when indexed, for reaching the file; empty for
+    /// records indexed before it was kept
+    pub location: String,
+}
+
+/// A reviewed verdict on a candidate duplicate pair, e.g. `keep_a` or `not_duplicate`
+#[derive(Clone)]
+pub struct DuplicateDecision {
+    /// Identities of the two files, the key the decision is stored under
+    pub path_a: String,
+    pub path_b: String,
+    pub decision: String,
+    /// The two paths as given, for reaching the files; empty for decisions
+    /// recorded before they were kept
+    pub location_a: String,
+    pub location_b: String,
+}
+
+/// Storage for image records, keyed by (path, source_prefix), duplicate decisions and settings
+pub trait IndexStore: Send {
+    /// Look up one record
+    fn get(&mut self, path: &str, source_prefix: &str) -> io::Result<Option<ImageRecord>>;
+
+    /// Insert or replace a record
+    fn put(&mut self, record: &ImageRecord) -> io::Result<()>;
+
+    /// Delete a record, returning whether it existed
+    fn remove(&mut self, path: &str, source_prefix: &str) -> io::Result<bool>;
+
+    /// Every record, in no particular order
+    fn records(&mut self) -> io::Result<Vec<ImageRecord>>;
+
+    /// Record or replace the decision for a pair
+    fn put_decision(&mut self, decision: &DuplicateDecision) -> io::Result<()>;
+
+    /// Delete the decision on a pair given in canonical order, returning whether it existed
+    fn remove_decision(&mut self, path_a: &str, path_b: &str) -> io::Result<bool>;
+
+    /// Every recorded decision
+    fn decisions(&mut self) -> io::Result<Vec<DuplicateDecision>>;
+
+    /// Look up an index-wide setting, such as the duplicate score calibration
+    fn get_setting(&mut self, key: &str) -> io::Result<Option<String>>;
+
+    /// Insert or replace an index-wide setting
+    fn put_setting(&mut self, key: &str, value: &str) -> io::Result<()>;
+
+    /// Delete a setting, returning whether it existed
+    fn remove_setting(&mut self, key: &str) -> io::Result<bool>;
+
+    /// Every setting as `(key, value)`, in no particular order
+    fn settings(&mut self) -> io::Result<Vec<(String, String)>>;
+
+    /// Make all writes durable
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Flush, then give the space still held by removed entries back
+    fn vacuum(&mut self) -> io::Result<()>;
+
+    /// Bytes the index takes in its files or database tables
+    fn size_on_disk(&mut self) -> io::Result<u64>;
+
+    /// Damage found when the index was opened and what was salvaged; empty
+    /// when it was intact or the backend checks itself (sqlite, sled, postgres)
+    fn recovery(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Names accepted by `open` in this build
+pub fn backends() -> Vec<&'static str> {
+    let mut names = vec!["sqlite", "sled", "flat"];
+    if cfg!(feature = "postgres") {
+        names.push("postgres");
+    }
+    names
+}
+
+/// Open (creating if needed) an index with the named backend at `location`
+///
+/// `sqlite` takes a database file and shares the Python scanner's schema,
+/// `sled` a directory, `flat` a tab-separated text file and `postgres` a
+/// `postgres://` connection URL. All but `sled`, which locks its directory
+/// to one process, can be opened by several worker processes at once.
+pub fn open(backend: &str, location: &str) -> io::Result<Box<dyn IndexStore>> {
+    let store = open_untracked(backend, location)?;
+    let shared = Arc::new(Mutex::new(store));
+    let mut open = open_stores().lock().unwrap_or_else(|e| e.into_inner());
+    open.retain(|store| store.strong_count() > 0);
+    open.push(Arc::downgrade(&shared));
+    Ok(Box::new(Tracked(shared)))
+}
+
+fn open_untracked(backend: &str, location: &str) -> io::Result<Box<dyn IndexStore>> {
+    #[cfg(feature = "postgres")]
+    if backend == "postgres" {
+        return Ok(Box::new(postgres::PostgresStore::connect(location)?));
+    }
+
+    if let Some(dir) = std::path::Path::new(location).parent() {
+        if !dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+
+    match backend {
+        "sqlite" => Ok(Box::new(sqlite::SqliteStore::open(location)?)),
+        "sled" => Ok(Box::new(sled_store::SledStore::open(location)?)),
+        "flat" => Ok(Box::new(flat::FlatStore::open(location)?)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown index backend '{}', expected one of {:?}", backend, backends()),
+        )),
+    }
+}
+
+type Shared = Arc<Mutex<Box<dyn IndexStore>>>;
+type Open = Mutex<Vec<Weak<Mutex<Box<dyn IndexStore>>>>>;
+
+/// Every store returned by `open` that has not been dropped yet
+fn open_stores() -> &'static Open {
+    static OPEN: Open = Mutex::new(Vec::new());
+    &OPEN
+}
+
+/// Flush every index still open, waiting for calls in progress on them;
+/// returns how many were flushed and the errors of the others
+pub fn flush_open() -> (usize, Vec<io::Error>) {
+    let open: Vec<Shared> = {
+        let mut open = open_stores().lock().unwrap_or_else(|e| e.into_inner());
+        open.retain(|store| store.strong_count() > 0);
+        open.iter().filter_map(Weak::upgrade).collect()
+    };
+    let mut flushed = 0;
+    let mut errors = Vec::new();
+    for store in open {
+        match store.lock().unwrap_or_else(|e| e.into_inner()).flush() {
+            Ok(()) => flushed += 1,
+            Err(e) => errors.push(e),
+        }
+    }
+    (flushed, errors)
+}
+
+/// A store `flush_open` can reach while its owner holds it
+struct Tracked(Shared);
+
+impl Tracked {
+    fn store(&self) -> std::sync::MutexGuard<'_, Box<dyn IndexStore>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl IndexStore for Tracked {
+    fn get(&mut self, path: &str, source_prefix: &str) -> io::Result<Option<ImageRecord>> {
+        self.store().get(path, source_prefix)
+    }
+
+    fn put(&mut self, record: &ImageRecord) -> io::Result<()> {
+        self.store().put(record)
+    }
+
+    fn remove(&mut self, path: &str, source_prefix: &str) -> io::Result<bool> {
+        self.store().remove(path, source_prefix)
+    }
+
+    fn records(&mut self) -> io::Result<Vec<ImageRecord>> {
+        self.store().records()
+    }
+
+    fn put_decision(&mut self, decision: &DuplicateDecision) -> io::Result<()> {
+        self.store().put_decision(decision)
+    }
+
+    fn remove_decision(&mut self, path_a: &str, path_b: &str) -> io::Result<bool> {
+        self.store().remove_decision(path_a, path_b)
+    }
+
+    fn decisions(&mut self) -> io::Result<Vec<DuplicateDecision>> {
+        self.store().decisions()
+    }
+
+    fn get_setting(&mut self, key: &str) -> io::Result<Option<String>> {
+        self.store().get_setting(key)
+    }
+
+    fn put_setting(&mut self, key: &str, value: &str) -> io::Result<()> {
+        self.store().put_setting(key, value)
+    }
+
+    fn remove_setting(&mut self, key: &str) -> io::Result<bool> {
+        self.store().remove_setting(key)
+    }
+
+    fn settings(&mut self) -> io::Result<Vec<(String, String)>> {
+        self.store().settings()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.store().flush()
+    }
+
+    fn vacuum(&mut self) -> io::Result<()> {
+        self.store().vacuum()
+    }
+
+    fn size_on_disk(&mut self) -> io::Result<u64> {
+        self.store().size_on_disk()
+    }
+
+    fn recovery(&self) -> Vec<String> {
+        self.store().recovery()
+    }
+}
+
+/// Escape tabs, newlines and backslashes so a field fits in one TSV cell
+pub fn escape(field: &str) -> String {
+    field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+pub fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+impl DuplicateDecision {
+    /// The pair in canonical order, so (a, b) and (b, a) share one decision
+    pub fn normalized(mut self) -> Self {
+        if self.path_b < self.path_a {
+            std::mem::swap(&mut self.path_a, &mut self.path_b);
+            std::mem::swap(&mut self.location_a, &mut self.location_b);
+            self.decision = match self.decision.as_str() {
+                "keep_a" => "keep_b".to_string(),
+                "keep_b" => "keep_a".to_string(),
+                _ => self.decision,
+            };
+        }
+        self
+    }
+
+    /// Paths to reach the two files by, falling back to the identities for
+    /// decisions recorded before the paths were kept
+    pub fn files(&self) -> (&str, &str) {
+        fn or_key<'a>(location: &'a str, key: &'a str) -> &'a str {
+            if location.is_empty() {
+                key
+            } else {
+                location
+            }
+        }
+        (or_key(&self.location_a, &self.path_a), or_key(&self.location_b, &self.path_b))
+    }
+
+    fn encode(&self) -> String {
+        [&self.path_a, &self.path_b, &self.decision, &self.location_a, &self.location_b]
+            .map(|field| escape(field))
+            .join("\t")
+    }
+
+    fn decode(line: &str) -> io::Result<DuplicateDecision> {
+        let mut fields: Vec<String> = line.split('\t').map(unescape).collect();
+        // Decisions written before the paths were kept have 3 fields
+        if fields.len() == 3 {
+            fields.extend([String::new(), String::new()]);
+        }
+        let [path_a, path_b, decision, location_a, location_b] = <[String; 5]>::try_from(fields)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Corrupt decision record: {}", line)))?;
+        Ok(DuplicateDecision { path_a, path_b, decision, location_a, location_b })
+    }
+}
+
+impl ImageRecord {
+    /// Path to reach the file by, falling back to the identity for records
+    /// indexed before the path was kept
+    pub fn file(&self) -> &str {
+        if self.location.is_empty() {
+            &self.path
+        } else {
+            &self.location
+        }
+    }
+
+    /// Single-line text encoding shared by the flat-file and sled backends
+    fn encode(&self) -> String {
+        [
+            escape(&self.path),
+            escape(&self.source_prefix),
+            escape(&self.format),
+            self.width.to_string(),
+            self.height.to_string(),
+            escape(&self.created_at),
+            escape(&self.modified_at),
+            self.size.to_string(),
+            escape(&self.average_hash),
+            escape(&self.perceptual_hash),
+            (self.is_raw_format as u8).to_string(),
+            escape(&self.fine_hash),
+            escape(&self.content_hash),
+            escape(&self.location),
+        ]
+        .join("\t")
+    }
+
+    fn decode(line: &str) -> io::Result<ImageRecord> {
+        let mut fields: Vec<String> = line.split('\t').map(unescape).collect();
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Corrupt index record: {}", line));
+        // Records written before the fine hash, the checksum and the location were added have 11 to 13 fields
+        while (11..14).contains(&fields.len()) {
+            fields.push(String::new());
+        }
+        let [path, source_prefix, format, width, height, created_at, modified_at, size, average_hash, perceptual_hash, is_raw, fine_hash, content_hash, location] =
+            <[String; 14]>::try_from(fields).map_err(|_| invalid())?;
+
+        Ok(ImageRecord {
+            path,
+            source_prefix,
+            format,
+            width: width.parse().map_err(|_| invalid())?,
+            height: height.parse().map_err(|_| invalid())?,
+            created_at,
+            modified_at,
+            size: size.parse().map_err(|_| invalid())?,
+            average_hash,
+            perceptual_hash,
+            is_raw_format: is_raw == "1",
+            fine_hash,
+            content_hash,
+            location,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_round_trip_and_older_lines_decode() {
+        let record = ImageRecord {
+            path: "/photos/a\tb.jpg".to_string(),
+            size: 42,
+            fine_hash: "ab".repeat(32),
+            content_hash: "cd".repeat(32),
+            location: "/photos/a\tb.jpg.".to_string(),
+            ..Default::default()
+        };
+        let decoded = ImageRecord::decode(&record.encode()).unwrap();
+        assert_eq!((decoded.path.as_str(), decoded.size), (record.path.as_str(), 42));
+        assert_eq!((&decoded.fine_hash, &decoded.content_hash), (&record.fine_hash, &record.content_hash));
+        assert_eq!(decoded.file(), "/photos/a\tb.jpg.");
+
+        // Lines written before the location, the checksum and the fine hash lack the trailing fields
+        let line = record.encode();
+        let without_location = &line[..line.rfind('\t').unwrap()];
+        assert_eq!(ImageRecord::decode(without_location).unwrap().file(), record.path);
+        let without_checksum = &without_location[..without_location.rfind('\t').unwrap()];
+        assert!(ImageRecord::decode(without_checksum).unwrap().content_hash.is_empty());
+        let without_fine = &without_checksum[..without_checksum.rfind('\t').unwrap()];
+        assert!(ImageRecord::decode(without_fine).unwrap().fine_hash.is_empty());
+        assert!(ImageRecord::decode("too\tfew").is_err());
+    }
+
+    #[test]
+    fn decisions_keep_their_paths() {
+        let decision = DuplicateDecision {
+            path_a: "/p/b.jpg".to_string(),
+            path_b: "/p/a.jpg".to_string(),
+            decision: "keep_a".to_string(),
+            location_a: "/p/b.jpg.".to_string(),
+            location_b: "/p/A.jpg".to_string(),
+        }
+        .normalized();
+        assert_eq!((decision.decision.as_str(), decision.files()), ("keep_b", ("/p/A.jpg", "/p/b.jpg.")));
+        let decoded = DuplicateDecision::decode(&decision.encode()).unwrap();
+        assert_eq!((decoded.path_a.as_str(), decoded.files()), ("/p/a.jpg", ("/p/A.jpg", "/p/b.jpg.")));
+
+        let legacy = DuplicateDecision::decode("/p/a.jpg\t/p/b.jpg\tnot_duplicate").unwrap();
+        assert_eq!(legacy.files(), ("/p/a.jpg", "/p/b.jpg"));
+    }
+}
@@ -0,0 +1,305 @@
+// src/index/postgres.rs
+// Postgres index shared by several indexer workers (feature `postgres`)
+
+use std::io;
+
+use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use sqlx::Row;
+use tokio::runtime::Runtime;
+
+use super::{DuplicateDecision, ImageRecord, IndexStore};
+
+// Connections per worker; writes are single upserts, so a few are plenty
+const MAX_CONNECTIONS: u32 = 4;
+
+// Advisory lock key serializing schema creation across workers ("imgfind" in ASCII)
+const SCHEMA_LOCK: i64 = 0x0069_6d67_6669_6e64;
+
+const SCHEMA: [&str; 11] = [
+    "CREATE TABLE IF NOT EXISTS images (
+        id BIGSERIAL PRIMARY KEY,
+        path TEXT NOT NULL,
+        source_prefix TEXT NOT NULL,
+        format TEXT NOT NULL,
+        width BIGINT NOT NULL,
+        height BIGINT NOT NULL,
+        created_at TEXT NOT NULL,
+        modified_at TEXT NOT NULL,
+        size BIGINT NOT NULL,
+        average_hash TEXT NOT NULL,
+        perceptual_hash TEXT NOT NULL,
+        is_raw_format BOOLEAN NOT NULL,
+        UNIQUE(path, source_prefix)
+    )",
+    "ALTER TABLE images ADD COLUMN IF NOT EXISTS fine_hash TEXT NOT NULL DEFAULT ''",
+    "ALTER TABLE images ADD COLUMN IF NOT EXISTS content_hash TEXT NOT NULL DEFAULT ''",
+    "ALTER TABLE images ADD COLUMN IF NOT EXISTS location TEXT NOT NULL DEFAULT ''",
+    "CREATE INDEX IF NOT EXISTS idx_average_hash ON images(average_hash)",
+    "CREATE INDEX IF NOT EXISTS idx_perceptual_hash ON images(perceptual_hash)",
+    "CREATE INDEX IF NOT EXISTS idx_source_prefix ON images(source_prefix)",
+    "CREATE TABLE IF NOT EXISTS duplicate_decisions (
+        path_a TEXT NOT NULL,
+        path_b TEXT NOT NULL,
+        decision TEXT NOT NULL,
+        PRIMARY KEY(path_a, path_b)
+    )",
+    "ALTER TABLE duplicate_decisions ADD COLUMN IF NOT EXISTS location_a TEXT NOT NULL DEFAULT ''",
+    "ALTER TABLE duplicate_decisions ADD COLUMN IF NOT EXISTS location_b TEXT NOT NULL DEFAULT ''",
+    "CREATE TABLE IF NOT EXISTS index_settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    )",
+];
+
+const COLUMNS: &str = "path, source_prefix, format, width, height, created_at, modified_at, \
+                       size, average_hash, perceptual_hash, is_raw_format, fine_hash, content_hash, location";
+
+/// sqlx is async-only; each store drives its queries on a private runtime
+pub struct PostgresStore {
+    runtime: Runtime,
+    pool: PgPool,
+}
+
+fn from_row(row: &PgRow) -> Result<ImageRecord, sqlx::Error> {
+    Ok(ImageRecord {
+        path: row.try_get(0)?,
+        source_prefix: row.try_get(1)?,
+        format: row.try_get(2)?,
+        width: row.try_get::<i64, _>(3)? as u32,
+        height: row.try_get::<i64, _>(4)? as u32,
+        created_at: row.try_get(5)?,
+        modified_at: row.try_get(6)?,
+        size: row.try_get::<i64, _>(7)? as u64,
+        average_hash: row.try_get(8)?,
+        perceptual_hash: row.try_get(9)?,
+        is_raw_format: row.try_get(10)?,
+        fine_hash: row.try_get(11)?,
+        content_hash: row.try_get(12)?,
+        location: row.try_get(13)?,
+    })
+}
+
+impl PostgresStore {
+    /// Connect to a `postgres://` URL and create the tables if missing
+    pub fn connect(url: &str) -> io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+
+        let pool = runtime.block_on(async {
+            let pool = PgPoolOptions::new().max_connections(MAX_CONNECTIONS).connect(url).await?;
+            // `IF NOT EXISTS` alone still races on the catalog when two workers
+            // start at once; the lock is released when the transaction ends
+            let mut transaction = pool.begin().await?;
+            sqlx::query("SELECT pg_advisory_xact_lock($1)").bind(SCHEMA_LOCK).execute(&mut *transaction).await?;
+            for statement in SCHEMA {
+                sqlx::query(statement).execute(&mut *transaction).await?;
+            }
+            transaction.commit().await?;
+            Ok::<_, sqlx::Error>(pool)
+        });
+
+        Ok(PostgresStore { pool: pool.map_err(io::Error::other)?, runtime })
+    }
+}
+
+impl IndexStore for PostgresStore {
+    fn get(&mut self, path: &str, source_prefix: &str) -> io::Result<Option<ImageRecord>> {
+        let query = format!("SELECT {} FROM images WHERE path = $1 AND source_prefix = $2", COLUMNS);
+        let row = self
+            .runtime
+            .block_on(sqlx::query(&query).bind(path).bind(source_prefix).fetch_optional(&self.pool))
+            .map_err(io::Error::other)?;
+        row.as_ref().map(from_row).transpose().map_err(io::Error::other)
+    }
+
+    fn put(&mut self, record: &ImageRecord) -> io::Result<()> {
+        let query = format!(
+            "INSERT INTO images ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+             ON CONFLICT (path, source_prefix) DO UPDATE SET
+                format = excluded.format,
+                width = excluded.width,
+                height = excluded.height,
+                modified_at = excluded.modified_at,
+                size = excluded.size,
+                average_hash = excluded.average_hash,
+                perceptual_hash = excluded.perceptual_hash,
+                is_raw_format = excluded.is_raw_format,
+                fine_hash = excluded.fine_hash,
+                content_hash = excluded.content_hash,
+                location = excluded.location",
+            COLUMNS
+        );
+        self.runtime
+            .block_on(
+                sqlx::query(&query)
+                    .bind(&record.path)
+                    .bind(&record.source_prefix)
+                    .bind(&record.format)
+                    .bind(record.width as i64)
+                    .bind(record.height as i64)
+                    .bind(&record.created_at)
+                    .bind(&record.modified_at)
+                    .bind(record.size as i64)
+                    .bind(&record.average_hash)
+                    .bind(&record.perceptual_hash)
+                    .bind(record.is_raw_format)
+                    .bind(&record.fine_hash)
+                    .bind(&record.content_hash)
+                    .bind(&record.location)
+                    .execute(&self.pool),
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &str, source_prefix: &str) -> io::Result<bool> {
+        let result = self
+            .runtime
+            .block_on(
+                sqlx::query("DELETE FROM images WHERE path = $1 AND source_prefix = $2")
+                    .bind(path)
+                    .bind(source_prefix)
+                    .execute(&self.pool),
+            )
+            .map_err(io::Error::other)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    fn records(&mut self) -> io::Result<Vec<ImageRecord>> {
+        let query = format!("SELECT {} FROM images", COLUMNS);
+        let rows = self
+            .runtime
+            .block_on(sqlx::query(&query).fetch_all(&self.pool))
+            .map_err(io::Error::other)?;
+        rows.iter().map(from_row).collect::<Result<_, _>>().map_err(io::Error::other)
+    }
+
+    fn put_decision(&mut self, decision: &DuplicateDecision) -> io::Result<()> {
+        self.runtime
+            .block_on(
+                sqlx::query(
+                    "INSERT INTO duplicate_decisions (path_a, path_b, decision, location_a, location_b)
+                     VALUES ($1, $2, $3, $4, $5)
+                     ON CONFLICT (path_a, path_b) DO UPDATE SET
+                        decision = excluded.decision,
+                        location_a = excluded.location_a,
+                        location_b = excluded.location_b",
+                )
+                .bind(&decision.path_a)
+                .bind(&decision.path_b)
+                .bind(&decision.decision)
+                .bind(&decision.location_a)
+                .bind(&decision.location_b)
+                .execute(&self.pool),
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn remove_decision(&mut self, path_a: &str, path_b: &str) -> io::Result<bool> {
+        let result = self
+            .runtime
+            .block_on(
+                sqlx::query("DELETE FROM duplicate_decisions WHERE path_a = $1 AND path_b = $2")
+                    .bind(path_a)
+                    .bind(path_b)
+                    .execute(&self.pool),
+            )
+            .map_err(io::Error::other)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    fn decisions(&mut self) -> io::Result<Vec<DuplicateDecision>> {
+        let rows = self
+            .runtime
+            .block_on(
+                sqlx::query("SELECT path_a, path_b, decision, location_a, location_b FROM duplicate_decisions")
+                    .fetch_all(&self.pool),
+            )
+            .map_err(io::Error::other)?;
+        rows.iter()
+            .map(|row| {
+                Ok(DuplicateDecision {
+                    path_a: row.try_get(0)?,
+                    path_b: row.try_get(1)?,
+                    decision: row.try_get(2)?,
+                    location_a: row.try_get(3)?,
+                    location_b: row.try_get(4)?,
+                })
+            })
+            .collect::<Result<_, sqlx::Error>>()
+            .map_err(io::Error::other)
+    }
+
+    fn get_setting(&mut self, key: &str) -> io::Result<Option<String>> {
+        let row = self
+            .runtime
+            .block_on(sqlx::query("SELECT value FROM index_settings WHERE key = $1").bind(key).fetch_optional(&self.pool))
+            .map_err(io::Error::other)?;
+        row.map(|row| row.try_get(0)).transpose().map_err(io::Error::other)
+    }
+
+    fn put_setting(&mut self, key: &str, value: &str) -> io::Result<()> {
+        self.runtime
+            .block_on(
+                sqlx::query(
+                    "INSERT INTO index_settings (key, value) VALUES ($1, $2)
+                     ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                )
+                .bind(key)
+                .bind(value)
+                .execute(&self.pool),
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn remove_setting(&mut self, key: &str) -> io::Result<bool> {
+        let result = self
+            .runtime
+            .block_on(sqlx::query("DELETE FROM index_settings WHERE key = $1").bind(key).execute(&self.pool))
+            .map_err(io::Error::other)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    fn settings(&mut self) -> io::Result<Vec<(String, String)>> {
+        let rows = self
+            .runtime
+            .block_on(sqlx::query("SELECT key, value FROM index_settings").fetch_all(&self.pool))
+            .map_err(io::Error::other)?;
+        rows.iter()
+            .map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+            .collect::<Result<Vec<_>, sqlx::Error>>()
+            .map_err(io::Error::other)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Every statement is committed by the server as it runs
+        Ok(())
+    }
+
+    fn vacuum(&mut self) -> io::Result<()> {
+        // Plain VACUUM makes dead rows' space reusable without the exclusive
+        // lock of VACUUM FULL, so other workers keep writing meanwhile
+        self.runtime
+            .block_on(sqlx::query("VACUUM (ANALYZE) images, duplicate_decisions, index_settings").execute(&self.pool))
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn size_on_disk(&mut self) -> io::Result<u64> {
+        let row = self
+            .runtime
+            .block_on(
+                sqlx::query(
+                    "SELECT pg_total_relation_size('images') + pg_total_relation_size('duplicate_decisions')
+                        + pg_total_relation_size('index_settings')",
+                )
+                .fetch_one(&self.pool),
+            )
+            .map_err(io::Error::other)?;
+        Ok(row.try_get::<i64, _>(0).map_err(io::Error::other)? as u64)
+    }
+}
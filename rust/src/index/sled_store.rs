@@ -0,0 +1,163 @@
+// src/index/sled_store.rs
+// Embedded sled key-value index, keyed by source prefix and path
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{DuplicateDecision, ImageRecord, IndexStore};
+use crate::locking;
+
+pub struct SledStore {
+    location: PathBuf,
+    db: sled::Db,
+    decisions: sled::Tree,
+    settings: sled::Tree,
+}
+
+fn key(path: &str, source_prefix: &str) -> Vec<u8> {
+    // NUL cannot appear in either part, so the split is unambiguous
+    [source_prefix.as_bytes(), b"\0", path.as_bytes()].concat()
+}
+
+fn decode_value(value: &[u8]) -> io::Result<ImageRecord> {
+    ImageRecord::decode(&String::from_utf8_lossy(value))
+}
+
+impl SledStore {
+    pub fn open(location: &str) -> io::Result<Self> {
+        let location = PathBuf::from(location);
+        // A vacuum cut short between its two renames left the index under its old name
+        let previous = locking::sibling(&location, ".old");
+        if !location.exists() && previous.exists() {
+            fs::rename(&previous, &location)?;
+        }
+        let (db, decisions, settings) = Self::open_trees(&location)?;
+        Ok(SledStore { location, db, decisions, settings })
+    }
+
+    fn open_trees(location: &Path) -> io::Result<(sled::Db, sled::Tree, sled::Tree)> {
+        let db = sled::open(location).map_err(io::Error::other)?;
+        let decisions = db.open_tree("duplicate_decisions").map_err(io::Error::other)?;
+        let settings = db.open_tree("index_settings").map_err(io::Error::other)?;
+        Ok((db, decisions, settings))
+    }
+
+    /// Open the directory at `location` again after a vacuum swapped it (or failed to)
+    fn reopen(&mut self) -> io::Result<()> {
+        (self.db, self.decisions, self.settings) = Self::open_trees(&self.location)?;
+        Ok(())
+    }
+}
+
+impl IndexStore for SledStore {
+    fn get(&mut self, path: &str, source_prefix: &str) -> io::Result<Option<ImageRecord>> {
+        match self.db.get(key(path, source_prefix)).map_err(io::Error::other)? {
+            Some(value) => Ok(Some(decode_value(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put(&mut self, record: &ImageRecord) -> io::Result<()> {
+        self.db
+            .insert(key(&record.path, &record.source_prefix), record.encode().into_bytes())
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &str, source_prefix: &str) -> io::Result<bool> {
+        Ok(self.db.remove(key(path, source_prefix)).map_err(io::Error::other)?.is_some())
+    }
+
+    fn records(&mut self) -> io::Result<Vec<ImageRecord>> {
+        self.db
+            .iter()
+            .values()
+            .map(|value| decode_value(&value.map_err(io::Error::other)?))
+            .collect()
+    }
+
+    fn put_decision(&mut self, decision: &DuplicateDecision) -> io::Result<()> {
+        self.decisions
+            .insert([decision.path_a.as_bytes(), b"\0", decision.path_b.as_bytes()].concat(), decision.encode().into_bytes())
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn remove_decision(&mut self, path_a: &str, path_b: &str) -> io::Result<bool> {
+        let key = [path_a.as_bytes(), b"\0", path_b.as_bytes()].concat();
+        Ok(self.decisions.remove(key).map_err(io::Error::other)?.is_some())
+    }
+
+    fn decisions(&mut self) -> io::Result<Vec<DuplicateDecision>> {
+        self.decisions
+            .iter()
+            .values()
+            .map(|value| DuplicateDecision::decode(&String::from_utf8_lossy(&value.map_err(io::Error::other)?)))
+            .collect()
+    }
+
+    fn get_setting(&mut self, key: &str) -> io::Result<Option<String>> {
+        let value = self.settings.get(key).map_err(io::Error::other)?;
+        Ok(value.map(|v| String::from_utf8_lossy(&v).into_owned()))
+    }
+
+    fn put_setting(&mut self, key: &str, value: &str) -> io::Result<()> {
+        self.settings.insert(key, value.as_bytes()).map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn remove_setting(&mut self, key: &str) -> io::Result<bool> {
+        Ok(self.settings.remove(key).map_err(io::Error::other)?.is_some())
+    }
+
+    fn settings(&mut self) -> io::Result<Vec<(String, String)>> {
+        self.settings
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry.map_err(io::Error::other)?;
+                Ok((String::from_utf8_lossy(&key).into_owned(), String::from_utf8_lossy(&value).into_owned()))
+            })
+            .collect()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.db.flush().map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    /// sled reclaims the segments of removed entries only gradually, so the
+    /// live entries are copied into a fresh directory that replaces this one
+    fn vacuum(&mut self) -> io::Result<()> {
+        self.flush()?;
+        let fresh = locking::sibling(&self.location, ".compact");
+        let previous = locking::sibling(&self.location, ".old");
+        if fresh.exists() {
+            fs::remove_dir_all(&fresh)?;
+        }
+        {
+            let copy = sled::open(&fresh).map_err(io::Error::other)?;
+            copy.import(self.db.export());
+            copy.flush().map_err(io::Error::other)?;
+        }
+
+        // The directory stays locked until every handle on it is gone
+        let placeholder = sled::Config::new().temporary(true).open().map_err(io::Error::other)?;
+        self.decisions = placeholder.open_tree("duplicate_decisions").map_err(io::Error::other)?;
+        self.settings = placeholder.open_tree("index_settings").map_err(io::Error::other)?;
+        self.db = placeholder;
+
+        if let Err(e) = fs::rename(&self.location, &previous) {
+            let _ = fs::remove_dir_all(&fresh);
+            self.reopen()?;
+            return Err(e);
+        }
+        fs::rename(&fresh, &self.location)?;
+        self.reopen()?;
+        fs::remove_dir_all(&previous)
+    }
+
+    fn size_on_disk(&mut self) -> io::Result<u64> {
+        self.db.size_on_disk().map_err(io::Error::other)
+    }
+}
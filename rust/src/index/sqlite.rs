@@ -0,0 +1,269 @@
+// src/index/sqlite.rs
+// SQLite index using the same `images` table as the Python scanner
+
+use std::io;
+use std::time::Duration;
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use super::{DuplicateDecision, ImageRecord, IndexStore};
+
+const COLUMNS: &str = "path, source_prefix, format, width, height, created_at, modified_at, \
+                       size, average_hash, perceptual_hash, is_raw_format, fine_hash, content_hash, location";
+
+// How long a write waits for another process's transaction
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+fn from_row(row: &Row) -> rusqlite::Result<ImageRecord> {
+    Ok(ImageRecord {
+        path: row.get(0)?,
+        source_prefix: row.get(1)?,
+        format: row.get(2)?,
+        width: row.get(3)?,
+        height: row.get(4)?,
+        created_at: row.get(5)?,
+        modified_at: row.get(6)?,
+        size: row.get::<_, i64>(7)? as u64,
+        average_hash: row.get(8)?,
+        perceptual_hash: row.get(9)?,
+        is_raw_format: row.get::<_, i64>(10)? != 0,
+        fine_hash: row.get(11)?,
+        content_hash: row.get(12)?,
+        location: row.get(13)?,
+    })
+}
+
+impl SqliteStore {
+    pub fn open(location: &str) -> io::Result<Self> {
+        let conn = Connection::open(location).map_err(io::Error::other)?;
+        // WAL lets readers in other processes proceed during writes; writers wait instead of failing
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(io::Error::other)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            CREATE TABLE IF NOT EXISTS images (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                path TEXT NOT NULL,
+                source_prefix TEXT NOT NULL,
+                format TEXT NOT NULL,
+                width INTEGER NOT NULL,
+                height INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                modified_at TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                average_hash TEXT NOT NULL,
+                perceptual_hash TEXT NOT NULL,
+                is_raw_format INTEGER NOT NULL,
+                UNIQUE(path, source_prefix)
+            );
+            CREATE INDEX IF NOT EXISTS idx_path ON images(path);
+            CREATE INDEX IF NOT EXISTS idx_source_prefix ON images(source_prefix);
+            CREATE INDEX IF NOT EXISTS idx_average_hash ON images(average_hash);
+            CREATE INDEX IF NOT EXISTS idx_perceptual_hash ON images(perceptual_hash);
+            CREATE TABLE IF NOT EXISTS duplicate_decisions (
+                path_a TEXT NOT NULL,
+                path_b TEXT NOT NULL,
+                decision TEXT NOT NULL,
+                PRIMARY KEY(path_a, path_b)
+            );
+            CREATE TABLE IF NOT EXISTS index_settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );",
+        )
+        .map_err(io::Error::other)?;
+
+        // Databases created by the Python scanner or older versions lack the fine hash, checksum and locations
+        let added = [
+            ("images", "fine_hash"),
+            ("images", "content_hash"),
+            ("images", "location"),
+            ("duplicate_decisions", "location_a"),
+            ("duplicate_decisions", "location_b"),
+        ];
+        for (table, column) in added {
+            let exists = conn
+                .prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")
+                .and_then(|mut statement| statement.exists([table, column]))
+                .map_err(io::Error::other)?;
+            if !exists {
+                conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} TEXT NOT NULL DEFAULT ''", table, column))
+                    .map_err(io::Error::other)?;
+            }
+        }
+        Ok(SqliteStore { conn })
+    }
+}
+
+impl IndexStore for SqliteStore {
+    fn get(&mut self, path: &str, source_prefix: &str) -> io::Result<Option<ImageRecord>> {
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM images WHERE path = ?1 AND source_prefix = ?2", COLUMNS),
+                params![path, source_prefix],
+                from_row,
+            )
+            .optional()
+            .map_err(io::Error::other)
+    }
+
+    fn put(&mut self, record: &ImageRecord) -> io::Result<()> {
+        self.conn
+            .execute(
+                &format!(
+                    "INSERT INTO images ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+                     ON CONFLICT(path, source_prefix) DO UPDATE SET
+                        format = excluded.format,
+                        width = excluded.width,
+                        height = excluded.height,
+                        modified_at = excluded.modified_at,
+                        size = excluded.size,
+                        average_hash = excluded.average_hash,
+                        perceptual_hash = excluded.perceptual_hash,
+                        is_raw_format = excluded.is_raw_format,
+                        fine_hash = excluded.fine_hash,
+                        content_hash = excluded.content_hash,
+                        location = excluded.location",
+                    COLUMNS
+                ),
+                params![
+                    record.path,
+                    record.source_prefix,
+                    record.format,
+                    record.width,
+                    record.height,
+                    record.created_at,
+                    record.modified_at,
+                    record.size as i64,
+                    record.average_hash,
+                    record.perceptual_hash,
+                    record.is_raw_format as i64,
+                    record.fine_hash,
+                    record.content_hash,
+                    record.location,
+                ],
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &str, source_prefix: &str) -> io::Result<bool> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM images WHERE path = ?1 AND source_prefix = ?2", params![path, source_prefix])
+            .map_err(io::Error::other)?;
+        Ok(deleted > 0)
+    }
+
+    fn records(&mut self) -> io::Result<Vec<ImageRecord>> {
+        let mut statement = self
+            .conn
+            .prepare(&format!("SELECT {} FROM images", COLUMNS))
+            .map_err(io::Error::other)?;
+        let rows = statement.query_map([], from_row).map_err(io::Error::other)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(io::Error::other)
+    }
+
+    fn put_decision(&mut self, decision: &DuplicateDecision) -> io::Result<()> {
+        self.conn
+            .execute(
+                "INSERT INTO duplicate_decisions (path_a, path_b, decision, location_a, location_b)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(path_a, path_b) DO UPDATE SET
+                    decision = excluded.decision,
+                    location_a = excluded.location_a,
+                    location_b = excluded.location_b",
+                params![decision.path_a, decision.path_b, decision.decision, decision.location_a, decision.location_b],
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn remove_decision(&mut self, path_a: &str, path_b: &str) -> io::Result<bool> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM duplicate_decisions WHERE path_a = ?1 AND path_b = ?2", params![path_a, path_b])
+            .map_err(io::Error::other)?;
+        Ok(deleted > 0)
+    }
+
+    fn decisions(&mut self) -> io::Result<Vec<DuplicateDecision>> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT path_a, path_b, decision, location_a, location_b FROM duplicate_decisions")
+            .map_err(io::Error::other)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok(DuplicateDecision {
+                    path_a: row.get(0)?,
+                    path_b: row.get(1)?,
+                    decision: row.get(2)?,
+                    location_a: row.get(3)?,
+                    location_b: row.get(4)?,
+                })
+            })
+            .map_err(io::Error::other)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(io::Error::other)
+    }
+
+    fn get_setting(&mut self, key: &str) -> io::Result<Option<String>> {
+        self.conn
+            .query_row("SELECT value FROM index_settings WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()
+            .map_err(io::Error::other)
+    }
+
+    fn put_setting(&mut self, key: &str, value: &str) -> io::Result<()> {
+        self.conn
+            .execute(
+                "INSERT INTO index_settings (key, value) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                params![key, value],
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn remove_setting(&mut self, key: &str) -> io::Result<bool> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM index_settings WHERE key = ?1", params![key])
+            .map_err(io::Error::other)?;
+        Ok(deleted > 0)
+    }
+
+    fn settings(&mut self) -> io::Result<Vec<(String, String)>> {
+        let mut statement = self.conn.prepare("SELECT key, value FROM index_settings").map_err(io::Error::other)?;
+        let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).map_err(io::Error::other)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(io::Error::other)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Every statement commits on its own outside explicit transactions
+        Ok(())
+    }
+
+    fn vacuum(&mut self) -> io::Result<()> {
+        // Deleted rows only free pages inside the file; VACUUM rebuilds it
+        // without them, and the checkpoint empties the write-ahead log
+        self.conn
+            .execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")
+            .map_err(io::Error::other)
+    }
+
+    fn size_on_disk(&mut self) -> io::Result<u64> {
+        let Some(path) = self.conn.path().filter(|path| !path.is_empty()) else {
+            return Ok(0);
+        };
+        // The write-ahead log holds pages not yet copied into the database file
+        let size = ["", "-wal", "-shm"]
+            .iter()
+            .filter_map(|suffix| std::fs::metadata(format!("{}{}", path, suffix)).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        Ok(size)
+    }
+}
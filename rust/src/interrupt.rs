@@ -0,0 +1,75 @@
+// src/interrupt.rs
+// Ctrl-C during a batch: stop starting new items and hand back what finished instead of losing it
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use pyo3::exceptions::{PyKeyboardInterrupt, PyRuntimeError};
+use pyo3::prelude::*;
+
+use crate::priority;
+
+// How often the waiting caller takes the GIL to run pending signal handlers
+const SIGNAL_POLL: Duration = Duration::from_millis(100);
+
+/// Raised for the batch it is handed to once the user interrupts it
+pub struct Interrupt(AtomicBool);
+
+impl Interrupt {
+    /// Whether items not started yet should be left out
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Run `batch` without the GIL while this thread watches for Ctrl-C
+///
+/// Python runs signal handlers only on the main thread while it holds the
+/// GIL, so a batch released in one `allow_threads` call saw the
+/// KeyboardInterrupt only after it finished, and the exception then threw
+/// its results away. Here the batch runs on a helper thread (in the caller's
+/// priority tier) and the caller wakes every `SIGNAL_POLL` to run pending
+/// handlers. A KeyboardInterrupt raises the flag the batch checks between
+/// items; items already running finish. Returns the batch's result and
+/// whether it was interrupted, which the batch entry points hand back to
+/// Python as an `interrupted` flag rather than raising. Any other exception
+/// a handler raises also stops the batch and is returned once it has wound
+/// down.
+pub fn run<T: Send>(py: Python<'_>, batch: impl FnOnce(&Interrupt) -> T + Send) -> PyResult<(T, bool)> {
+    let interrupt = Interrupt(AtomicBool::new(false));
+    let tier = priority::current();
+    std::thread::scope(|scope| {
+        let (sender, mut receiver) = mpsc::channel();
+        let interrupt = &interrupt;
+        scope.spawn(move || {
+            let _tier = (tier == priority::Tier::Interactive).then(priority::ScopedInteractive::enter);
+            let _ = sender.send(batch(interrupt));
+        });
+
+        let mut raised = None;
+        loop {
+            // The receiver is not Sync, so it travels into the GIL-free closure and back
+            let (received, returned) = py.allow_threads(move || (receiver.recv_timeout(SIGNAL_POLL), receiver));
+            receiver = returned;
+            match received {
+                Ok(result) => {
+                    return match raised {
+                        Some(error) => Err(error),
+                        None => Ok((result, interrupt.is_set())),
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Err(error) = py.check_signals() {
+                        interrupt.0.store(true, Ordering::Relaxed);
+                        if !error.is_instance_of::<PyKeyboardInterrupt>(py) {
+                            raised.get_or_insert(error);
+                        }
+                    }
+                },
+                // The batch panicked; the scope re-raises the panic on the way out
+                Err(RecvTimeoutError::Disconnected) => return Err(PyRuntimeError::new_err("Batch worker panicked")),
+            }
+        }
+    })
+}
@@ -1,143 +1,373 @@
 // src/lib.rs
 use pyo3::prelude::*;
 use pyo3::wrap_pyfunction;
-use pyo3::exceptions::PyIOError;
+use pyo3::types::{PyBytes, PyCFunction, PyDict, PyTuple};
+use pyo3::exceptions::{PyIOError, PyRuntimeWarning, PyValueError};
 use std::path::Path;
 use std::process::Command;
-use numpy::{PyArray2, PyReadonlyArray2};
-use std::io::Write;
-use std::fs::File;
+use numpy::{PyArray1, PyArray2, PyReadonlyArray2, PyReadonlyArray3};
+use rayon::prelude::*;
 use std::time::{Duration, Instant};
 
 // Raw processing libraries
 use rawloader::{decode_file, RawImageData};
-use image::{ImageBuffer, Rgb, DynamicImage, GenericImageView, imageops};
+use image::{ImageBuffer, Rgb, DynamicImage, imageops};
+
+mod actions;
+mod backend_stats;
+mod bktree;
+mod brackets;
+mod calibration;
+mod camera_profiles;
+mod cameras;
+mod cfa;
+mod checksum;
+mod compaction;
+mod config;
+mod contact_sheet;
+mod deadline;
+mod derivatives;
+mod directories;
+mod exif;
+mod exif_thumbnail;
+mod exiftool;
+mod explain;
+mod exposure;
+mod failures;
+mod formats;
+mod golden;
+mod grayscale;
+mod group_ids;
+mod hamming;
+mod hash_cache;
+mod hashing;
+mod image_stats;
+mod importers;
+mod index;
+mod interrupt;
+mod library_fingerprint;
+mod lifecycle;
+mod locking;
+mod matching;
+mod memory;
+mod moves;
+mod naming;
+mod orientation;
+mod output;
+mod paths;
+mod placeholders;
+mod preview_check;
+mod previews;
+mod priority;
+mod process;
+mod profiles;
+mod provenance;
+mod raw_pairs;
+mod remote;
+mod saliency;
+mod salvage;
+mod sampling;
+mod scan_diff;
+mod script;
+mod search;
+mod sensor;
+mod sessions;
+mod sidecar;
+mod simulation;
+mod skiplist;
+mod storage;
+mod streaming;
+mod summaries;
+mod support;
+mod sweep;
+mod thumbnails;
+mod throttle;
+mod tiff;
+mod tuning;
+mod walk;
+mod watermark;
+
+use process::LimitedOutput;
+use grayscale::{GrayscaleBuffer, GrayscaleDtype, grayscale_thumbnail, parse_filter, stack_thumbnails};
 
 // Constants for optimization
-const THUMBNAIL_SIZE: u32 = 512; // Size for thumbnails used in hashing
-const TIMEOUT_SECONDS: u64 = 4; // Timeout for external tools
+const THUMBNAIL_SIZE: u32 = tuning::DEFAULT_THUMBNAIL_SIZE; // Size for thumbnails used in hashing
+
+/// RAW sub-variants that need a different decoder than their base format
+#[derive(PartialEq)]
+enum RawVariant {
+    Standard,
+    /// Sony lossless-compressed ARW (tiled lossless JPEG raw data)
+    SonyLossless,
+    /// Nikon sRAW / small NEF (YCbCr-style data, 3 samples per pixel)
+    NikonSmall,
+    /// Nikon lossless or lossy compressed NEF (Huffman-coded raw data)
+    NikonCompressed,
+}
+
+/// Inspect the raw IFDs to tell compressed sub-variants apart from plain files
+fn detect_raw_variant(path: &str, ext: &str) -> RawVariant {
+    if ext != "arw" && ext != "nef" {
+        return RawVariant::Standard;
+    }
+
+    let mut tiff = match tiff::TiffFile::open(path) {
+        Ok(tiff) => tiff,
+        Err(_) => return RawVariant::Standard,
+    };
+    let ifds = match tiff.ifds() {
+        Ok(ifds) => ifds,
+        Err(_) => return RawVariant::Standard,
+    };
+
+    for ifd in &ifds {
+        let compression = ifd.find(tiff::TAG_COMPRESSION).and_then(|e| tiff.value_u32(e));
+        let samples = ifd.find(tiff::TAG_SAMPLES_PER_PIXEL).and_then(|e| tiff.value_u32(e));
+
+        match ext {
+            // Lossless JPEG (7) only shows up in the raw IFD of lossless ARW files
+            "arw" if compression == Some(7) => return RawVariant::SonyLossless,
+            // Nikon compressed raw IFD: 3 samples per pixel is an sNEF, otherwise
+            // Bayer data in Nikon's Huffman coding, which rawloader fails on
+            "nef" if compression == Some(34713) => {
+                return match samples {
+                    Some(3) => RawVariant::NikonSmall,
+                    _ => RawVariant::NikonCompressed,
+                };
+            },
+            _ => {}
+        }
+    }
+
+    RawVariant::Standard
+}
+
+/// Wait for the shared IO and memory budgets before reading a whole file
+fn begin_file_read(path: &str) -> memory::Reservation {
+    let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    throttle::acquire(size, 1);
+    memory::Reservation::acquire(size, thumbnails::clear)
+}
 
 /// Check if a file is a specific RAW format
 #[pyfunction]
 fn is_specific_raw_format(path: &str, format: &str) -> bool {
-    Path::new(path)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .map_or(false, |ext| ext.to_lowercase() == format.to_lowercase())
+    formats::extension(path) == format.to_lowercase()
 }
 
 /// Special function for RAF files optimized for speed
+///
+/// Steps follow the storage policy of the file; only `camera_profile`,
+/// `embedded_preview` and `format_specific` apply to RAF.
 #[pyfunction]
-fn rust_process_raf_file(path: &str, jpg_path: &str) -> PyResult<bool> {
+fn rust_process_raf_file(py: Python<'_>, path: &str, jpg_path: &str) -> PyResult<bool> {
+    py.allow_threads(|| priority::run(|| process_raf_file(path, jpg_path)))
+}
+
+/// The RAF conversion behind `rust_process_raf_file`
+fn process_raf_file(path: &str, jpg_path: &str) -> PyResult<bool> {
+    let _deadline = deadline::ScopedDeadline::start();
+    let storage = storage::detect(path);
+    let _storage = storage::ScopedStorage::new(storage);
+    
+    // Wait for the shared IO and memory budgets before reading the file
+    let _reservation = begin_file_read(path);
+    
     // Start a timer for performance tracking
     let start = Instant::now();
     
-    // RAF files need special handling - try several approaches in parallel
-    // First, try to extract embedded JPEG preview with exiftool (fastest)
-    let result = extract_preview_with_exiftool(path, jpg_path);
-    if result {
-        return Ok(true);
+    let mut recorder = backend_stats::Recorder::start(path, "raf");
+    let backends = storage::chain(storage, "raf").into_iter().filter(|backend| {
+        matches!(
+            backend,
+            storage::Backend::CameraProfile | storage::Backend::EmbeddedPreview | storage::Backend::FormatSpecific
+        )
+    });
+    for (step, backend) in backends.enumerate() {
+        // Check if timing out
+        if step > 0 && start.elapsed() > tuning::step_timeout() {
+            return Err(PyIOError::new_err("RAF processing timeout"));
+        }
+        lifecycle::check()?;
+        if deadline::expired() {
+            return Err(PyIOError::new_err(deadline::exceeded_message()));
+        }
+        
+        provenance::begin_step(backend.name());
+        let step_start = Instant::now();
+        let converted = match backend {
+            // Known camera models get their tuned path first
+            storage::Backend::CameraProfile => try_camera_profile_processing(path, jpg_path),
+            // Embedded JPEG preview from the RAF header, then with exiftool
+            storage::Backend::EmbeddedPreview => {
+                extract_native_preview(path, jpg_path) || extract_preview_with_exiftool(path, jpg_path)
+            },
+            // dcraw with simplified options, then libraw via dcraw_emu with Fuji options
+            storage::Backend::FormatSpecific => {
+                extract_with_dcraw_simple(path, jpg_path) || extract_with_libraw_fuji(path, jpg_path)
+            },
+            storage::Backend::Libraw | storage::Backend::Rawloader | storage::Backend::Generic => false,
+        };
+        recorder.step(backend, converted, step_start.elapsed());
+        if converted {
+            provenance::finish(backend.name());
+            return Ok(true);
+        }
     }
     
-    // Check if timing out
-    if start.elapsed() > Duration::from_secs(TIMEOUT_SECONDS) {
-        return Err(PyIOError::new_err("RAF processing timeout"));
+    Err(PyIOError::new_err("Failed to process RAF file with any available method"))
+}
+
+/// Exiftool preview tags to try for a RAW format, in order of preference
+fn preview_tags_for_format(ext: &str) -> &'static [&'static str] {
+    match ext {
+        // Olympus keeps the large preview in the CameraSettings maker notes
+        "orf" => &["-PreviewImage", "-ThumbnailImage"],
+        // Panasonic stores a full JPEG directly in IFD0 (tag 0x002e)
+        "rw2" => &["-JpgFromRaw", "-PreviewImage", "-ThumbnailImage"],
+        // Pentax puts the preview in the maker notes, JpgFromRaw on newer bodies
+        "pef" => &["-PreviewImage", "-JpgFromRaw", "-ThumbnailImage"],
+        // Samsung NX writes the preview into the maker notes like Pentax does
+        "srw" => &["-PreviewImage", "-ThumbnailImage"],
+        // Old Kodak bodies only carry a small IFD0 thumbnail, sometimes a KodakIFD preview
+        "dcr" | "kdc" => &["-ThumbnailImage", "-PreviewImage", "-JpgFromRaw"],
+        _ => &[
+            "-PreviewImage",
+            "-JpgFromRaw",
+            "-ThumbnailImage",
+            "-OtherImage",
+            "-EmbeddedImage",
+        ],
     }
+}
+
+/// Extract preview image using exiftool (fastest method)
+fn extract_preview_with_exiftool(path: &str, jpg_path: &str) -> bool {
+    let ext = formats::extension(path);
     
-    // If exiftool failed, try dcraw with simplified options
-    let result = extract_with_dcraw_simple(path, jpg_path);
-    if result {
-        return Ok(true);
+    // More than 10KB is likely a valid image
+    extract_preview_tags(path, jpg_path, preview_tags_for_format(&ext), 10000)
+}
+
+/// Deliver the largest embedded JPEG found by parsing the container, without external tools
+fn extract_native_preview(path: &str, jpg_path: &str) -> bool {
+    if !previews_allowed() {
+        return false;
     }
     
-    // Check if timing out
-    if start.elapsed() > Duration::from_secs(TIMEOUT_SECONDS) {
-        return Err(PyIOError::new_err("RAF processing timeout"));
+    let delivered = previews::best_native_preview(path).is_some_and(|preview| output::deliver_jpeg(jpg_path, preview.data));
+    if delivered {
+        provenance::mark_preview();
     }
-    
-    // Last resort: try using libraw via dcraw_emu with specific options for Fuji
-    let result = extract_with_libraw_fuji(path, jpg_path);
-    if result {
-        return Ok(true);
+    delivered
+}
+
+/// Whether embedded previews may stand in for a decode under the active scan
+/// profile and this thread's restriction
+fn previews_allowed() -> bool {
+    !profiles::active().full_decode && storage::restriction() != storage::Restriction::NoPreviews
+}
+
+/// dcraw/dcraw_emu arguments for the active scan profile: full size with AHD
+/// interpolation (`-q 3`) instead of `-h -q 0` when it asks for full decodes
+fn demosaic_args<'a>(args: &[&'a str]) -> Vec<&'a str> {
+    if !profiles::active().full_decode {
+        return args.to_vec();
     }
-    
-    Err(PyIOError::new_err("Failed to process RAF file with any available method"))
+    let mut full = Vec::with_capacity(args.len());
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "-h" => {},
+            "-q" => {
+                args.next();
+                full.extend(["-q", "3"]);
+            },
+            _ => full.push(arg),
+        }
+    }
+    full
 }
 
-/// Extract preview image using exiftool (fastest method)
-/// Extract preview image using exiftool (fastest method)
-fn extract_preview_with_exiftool(path: &str, jpg_path: &str) -> bool {
+/// Decode a tool's PPM or TIFF output from memory and deliver it as the result
+fn deliver_decoded(jpg_path: &str, stdout: &[u8]) -> bool {
+    // dcraw and dcraw_emu rotate to the camera's orientation unless told `-t 0`
+    provenance::mark_oriented();
+    image::load_from_memory(stdout).is_ok_and(|img| output::deliver_image(jpg_path, img).is_ok())
+}
+
+/// Extract the first of `tags` that yields a preview larger than `min_bytes`
+fn extract_preview_tags(path: &str, jpg_path: &str, tags: &[&str], min_bytes: u64) -> bool {
+    if !previews_allowed() {
+        return false;
+    }
+    
     // Try different preview types in order of preference
-    let preview_tags = [
-        "-PreviewImage",
-        "-JpgFromRaw", 
-        "-ThumbnailImage",
-        "-OtherImage",
-        "-EmbeddedImage"
-    ];
-    
-    for tag in &preview_tags {
+    for tag in tags {
         let exiftool_result = Command::new("exiftool")
-            .args(&["-b", tag, "-w", jpg_path, path])
-            .output();
+            .args(["-b", tag, path])
+            .limited_output();
         
         if let Ok(output) = exiftool_result {
-            if output.status.success() && Path::new(jpg_path).exists() {
-                // Check file size to ensure its a valid image
-                if let Ok(metadata) = std::fs::metadata(jpg_path) {
-                    if metadata.len() > 10000 { // More than 10KB is likely a valid image
-                        return true;
-                    }
-                }
+            // Check the size to ensure its a valid image
+            if output.status.success()
+                && output.stdout.len() as u64 > min_bytes
+                && output::deliver_jpeg(jpg_path, output.stdout)
+            {
+                provenance::mark_preview();
+                return true;
             }
         }
     }
     
     false
 }
+
+/// Per-camera handling from the built-in profile database, tried before the generic chain
+fn try_camera_profile_processing(path: &str, jpg_path: &str) -> bool {
+    let Some(profile) = camera_profiles::lookup_for_file(path) else {
+        return false;
+    };
+    
+    if !profile.has_quirk(camera_profiles::Quirk::PreviewTooSmall)
+        && extract_preview_tags(path, jpg_path, profile.preview_tags, profile.min_preview_bytes())
+    {
+        return true;
+    }
+    
+    if profile.has_quirk(camera_profiles::Quirk::PreferRawloader) && try_rawloader_processing(path, jpg_path) {
+        return true;
+    }
+    
+    profile.has_quirk(camera_profiles::Quirk::PreferLibraw) && try_libraw_processing(path, jpg_path)
+}
+
 /// Extract with dcraw using minimal processing options (faster)
 fn extract_with_dcraw_simple(path: &str, jpg_path: &str) -> bool {
-    // Extract embedded thumbnail (very fast)
-    let dcraw_thumb_result = Command::new("dcraw")
-        .args(&["-e", path])
-        .output();
+    // Extract embedded thumbnail (very fast), to stdout rather than a file named after the source
+    let dcraw_thumb_result = previews_allowed().then(|| {
+        Command::new("dcraw")
+            .args(["-e", "-c", path])
+            .limited_output()
+    });
     
-    if let Ok(output) = dcraw_thumb_result {
-        if output.status.success() {
-            // Check if thumb_*.jpg was created
-            let path_obj = Path::new(path);
-            let filename = path_obj.file_name().unwrap_or_default().to_str().unwrap_or("");
-            let thumb_path = path_obj.with_file_name(format!("thumb_{}", filename)).with_extension("jpg");
-            
-            if thumb_path.exists() {
-                if let Ok(_) = std::fs::copy(thumb_path, jpg_path) {
-                    let _ = std::fs::remove_file(thumb_path); // Clean up
-                    return true;
-                }
-            }
+    if let Some(Ok(output)) = dcraw_thumb_result {
+        if output.status.success() && !output.stdout.is_empty() && output::deliver_jpeg(jpg_path, output.stdout) {
+            provenance::mark_preview();
+            return true;
         }
     }
     
     // If thumbnail extraction failed, try quick conversion
     let dcraw_result = Command::new("dcraw")
-        .args(&["-c", "-h", "-q", "0", path]) // -h = half-size, -q 0 = fast interpolation
-        .output();
+        .args(demosaic_args(&["-c", "-h", "-q", "0", path])) // -h = half-size, -q 0 = fast interpolation
+        .limited_output();
     
     if let Ok(output) = dcraw_result {
         if output.status.success() {
-            // Save output to a temporary PPM file
-            let temp_ppm = format!("{}.ppm", jpg_path);
-            if let Ok(mut file) = File::create(&temp_ppm) {
-                if file.write_all(&output.stdout).is_ok() {
-                    // Convert PPM to JPG
-                    if let Ok(img) = image::open(&temp_ppm) {
-                        if img.save(jpg_path).is_ok() {
-                            let _ = std::fs::remove_file(&temp_ppm); // Clean up
-                            return true;
-                        }
-                    }
-                }
-                let _ = std::fs::remove_file(&temp_ppm); // Clean up on failure
+            // Decode the PPM straight from stdout
+            if deliver_decoded(jpg_path, &output.stdout) {
+                return true;
             }
         }
     }
@@ -145,99 +375,79 @@ fn extract_with_dcraw_simple(path: &str, jpg_path: &str) -> bool {
     false
 }
 
-/// Extract with libraw using Fuji-specific options
+/// Embedded preview written by `dcraw_emu -e`, run in a private temp directory
+fn dcraw_emu_preview(path: &str) -> Option<Vec<u8>> {
+    let dir = paths::TempDir::new(path).ok()?;
+    let ext = formats::extension(path);
+    let ext = if (1..=8).contains(&ext.len()) && ext.bytes().all(|b| b.is_ascii_alphanumeric()) { ext.as_str() } else { "raw" };
+    let source = dir.link(path, &format!("source.{}", ext)).ok()?;
+    let output = Command::new("dcraw_emu").arg("-e").arg(&source).limited_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // The only other file in the directory is the preview (`source.thumb.jpg`)
+    let preview = std::fs::read_dir(dir.path()).ok()?.flatten().map(|entry| entry.path()).find(|p| *p != source)?;
+    std::fs::read(preview).ok()
+}
+
 /// Extract with libraw using Fuji-specific options
 fn extract_with_libraw_fuji(path: &str, jpg_path: &str) -> bool {
-    // First try with dcraw_emu to extract embedded preview (fastest method)
-    let dcraw_emu_result = Command::new("dcraw_emu")
-        .args(&["-e", path]) // Extract embedded preview
-        .output();
-    
-    if let Ok(output) = dcraw_emu_result {
-        if output.status.success() {
-            // Check if thumb_*.jpg was created
-            let path_obj = Path::new(path);
-            let filename = path_obj.file_name().unwrap_or_default().to_str().unwrap_or("");
-            let thumb_path = path_obj.with_file_name(format!("thumb_{}", filename)).with_extension("jpg");
-            
-            if thumb_path.exists() {
-                if let Ok(metadata) = std::fs::metadata(&thumb_path) {
-                    // Make sure the extracted preview is not too small
-                    if metadata.len() > 10000 { // Minimum size check (10KB)
-                        if let Ok(_) = std::fs::copy(thumb_path, jpg_path) {
-                            let _ = std::fs::remove_file(thumb_path); // Clean up
-                            return true;
-                        }
-                    }
-                }
-                let _ = std::fs::remove_file(thumb_path); // Clean up if too small
+    // First try with dcraw_emu to extract embedded preview (fastest method). It
+    // writes the preview next to its input, so it runs on a link to the source
+    // in a private directory and never touches the user's folder.
+    if previews_allowed() {
+        if let Some(data) = dcraw_emu_preview(path) {
+            // Make sure the extracted preview is not too small
+            if data.len() > 10000 && output::deliver_jpeg(jpg_path, data) { // Minimum size check (10KB)
+                provenance::mark_preview();
+                return true;
             }
         }
     }
     
     // Try additional embedded preview extraction with exiftool
-    let exiftool_result = Command::new("exiftool")
-        .args(&["-b", "-JpgFromRaw", "-w", jpg_path, path])
-        .output();
-    
-    if let Ok(output) = exiftool_result {
-        if output.status.success() && Path::new(jpg_path).exists() {
-            if let Ok(metadata) = std::fs::metadata(jpg_path) {
-                if metadata.len() > 10000 { // More than 10KB is likely a valid image
-                    return true;
-                }
-            }
+    let exiftool_result = previews_allowed().then(|| {
+        Command::new("exiftool")
+            .args(["-b", "-JpgFromRaw", path])
+            .limited_output()
+    });
+    
+    if let Some(Ok(output)) = exiftool_result {
+        // More than 10KB is likely a valid image
+        if output.status.success() && output.stdout.len() > 10000 && output::deliver_jpeg(jpg_path, output.stdout) {
+            provenance::mark_preview();
+            return true;
         }
     }
     
     // If preview extraction failed, try fast conversion with -M flag for speed
     let dcraw_emu_fast_result = Command::new("dcraw_emu")
-        .args(&["-c", "-M", "-h", "-q", "0", "-fbdd", "1", "-o", "0", path])
+        .args(demosaic_args(&["-M", "-h", "-q", "0", "-fbdd", "1", "-o", "0", "-Z", "-", path]))
         // -M = use quick interpolation, -h = half-size, -q 0 = fast quality
-        // -fbdd 1 = fixed pattern noise reduction, -o 0 = raw color
-        .output();
+        // -fbdd 1 = fixed pattern noise reduction, -o 0 = raw color, -Z - = PPM to stdout
+        .limited_output();
     
     if let Ok(output) = dcraw_emu_fast_result {
         if output.status.success() {
-            // Save output to a temporary PPM file
-            let temp_ppm = format!("{}.ppm", jpg_path);
-            if let Ok(mut file) = File::create(&temp_ppm) {
-                if file.write_all(&output.stdout).is_ok() {
-                    // Convert PPM to JPG
-                    if let Ok(img) = image::open(&temp_ppm) {
-                        if img.save(jpg_path).is_ok() {
-                            let _ = std::fs::remove_file(&temp_ppm); // Clean up
-                            return true;
-                        }
-                    }
-                }
-                let _ = std::fs::remove_file(&temp_ppm); // Clean up on failure
+            // Decode the PPM straight from stdout
+            if deliver_decoded(jpg_path, &output.stdout) {
+                return true;
             }
         }
     }
     
     // Last resort: Try with specific Fuji X-Trans settings (slower)
     let dcraw_emu_xtrans_result = Command::new("dcraw_emu")
-        .args(&["-M", "-q", "0", "-h", "-f", "-fbdd", "1", path])
+        .args(demosaic_args(&["-M", "-q", "0", "-h", "-f", "-fbdd", "1", "-Z", "-", path]))
         // -M = quick interpolation, -q 0 = fast, -h = half-size
-        // -f = Fuji xtrans mode, -fbdd 1 = fixed pattern noise reduction
-        .output();
+        // -f = Fuji xtrans mode, -fbdd 1 = fixed pattern noise reduction, -Z - = PPM to stdout
+        .limited_output();
     
     if let Ok(output) = dcraw_emu_xtrans_result {
         if output.status.success() {
-            // Save output to a temporary PPM file
-            let temp_ppm = format!("{}.ppm", jpg_path);
-            if let Ok(mut file) = File::create(&temp_ppm) {
-                if file.write_all(&output.stdout).is_ok() {
-                    // Convert PPM to JPG
-                    if let Ok(img) = image::open(&temp_ppm) {
-                        if img.save(jpg_path).is_ok() {
-                            let _ = std::fs::remove_file(&temp_ppm); // Clean up
-                            return true;
-                        }
-                    }
-                }
-                let _ = std::fs::remove_file(&temp_ppm); // Clean up on failure
+            // Decode the PPM straight from stdout
+            if deliver_decoded(jpg_path, &output.stdout) {
+                return true;
             }
         }
     }
@@ -246,101 +456,404 @@ fn extract_with_libraw_fuji(path: &str, jpg_path: &str) -> bool {
 }
 
 /// Convert a RAW image to a processed RGB image with performance optimizations
+///
+/// `cfa_pattern` (e.g. `GRBG`) overrides the Bayer layout read from the file
+/// for native demosaicing, for files with broken metadata. `max_dimension`
+/// caps the long edge of the written JPEG whichever backend produced it, so
+/// previews land at a consistent size.
 #[pyfunction]
-fn rust_convert_raw_to_jpg(path: &str, jpg_path: &str) -> PyResult<bool> {
-    // Check if its a Fuji RAF file - use dedicated function
-    if is_specific_raw_format(path, "raf") {
-        return rust_process_raf_file(path, jpg_path);
+#[pyo3(signature = (path, jpg_path, cfa_pattern = None, max_dimension = None))]
+fn rust_convert_raw_to_jpg(
+    py: Python<'_>,
+    path: &str,
+    jpg_path: &str,
+    cfa_pattern: Option<&str>,
+    max_dimension: Option<u32>,
+) -> PyResult<bool> {
+    if max_dimension == Some(0) {
+        return Err(PyValueError::new_err("max_dimension must be greater than zero"));
     }
+    let cfa_pattern = cfa_pattern.map(cfa::CfaPattern::parse).transpose()?;
     
-    // Start a timer for performance tracking
-    let start = Instant::now();
+    py.allow_threads(|| {
+        priority::run(|| {
+            let _cfa = cfa::ScopedOverride::new(cfa_pattern);
+            let converted = convert_raw_to_jpg(path, jpg_path)?;
+            if let Some(max_dimension) = max_dimension {
+                cap_jpeg_dimension(jpg_path, max_dimension)?;
+            }
+            Ok(converted)
+        })
+    })
+}
+
+/// Per-file output paths and error statuses of a batch conversion
+type ConversionBatch = (Vec<Option<String>>, Vec<Option<String>>);
+
+/// Convert many RAW files to JPEG in parallel, naming outputs from `template`
+///
+/// `template` is a path relative to `out_dir` with `/` between directories
+/// and placeholders `{stem}`, `{name}`, `{src_ext}`, `{ext}` (always `jpg`),
+/// `{width}`/`{height}` of the written JPEG, `{date}` (`YYYY-MM-DD`),
+/// `{year}`, `{month}`, `{day}`, `{make}`, `{camera}` and `{index}` (position
+/// in `paths`), e.g. `{date}/{camera}/{stem}_{width}px.{ext}`. Dates come from
+/// the EXIF DateTime tag, or the file's modification date; missing values
+/// render as `unknown`. Values are sanitized into single, valid path
+/// components on every platform.
+///
+/// `collision` decides what happens when a name is taken, by an existing file
+/// or another file of the batch: `suffix` appends `_1`, `_2`..., `overwrite`
+/// replaces existing files (never another output of the same batch) and `skip`
+/// leaves the file unconverted. Returns the output path per file (None when
+/// it failed or was skipped) and a status per file (None, or the reason).
+#[pyfunction]
+#[pyo3(signature = (paths, out_dir, template = "{stem}.{ext}", collision = "suffix", max_dimension = None))]
+fn rust_convert_raw_to_jpg_batch(
+    py: Python<'_>,
+    paths: Vec<String>,
+    out_dir: &str,
+    template: &str,
+    collision: &str,
+    max_dimension: Option<u32>,
+) -> PyResult<ConversionBatch> {
+    let results = convert_batch(py, &paths, out_dir, template, collision, max_dimension)?;
+    Ok(results
+        .into_iter()
+        .map(|converted| match converted.result {
+            Ok(Some(output)) => (Some(output), None),
+            Ok(None) => (None, Some(SKIPPED_ON_COLLISION.to_string())),
+            Err(message) => (None, Some(message)),
+        })
+        .unzip())
+}
+
+const SKIPPED_ON_COLLISION: &str = "Skipped, output name already taken";
+
+/// One file of a batch conversion
+struct BatchConversion {
+    /// The output path, None when skipped on a name collision, or the error
+    result: Result<Option<String>, String>,
+    source: Option<provenance::Source>,
+    attempted: Vec<&'static str>,
+    elapsed: Duration,
+}
+
+/// Convert `paths` in parallel on the rayon pool, without the GIL
+fn convert_batch(
+    py: Python<'_>,
+    paths: &[String],
+    out_dir: &str,
+    template: &str,
+    collision: &str,
+    max_dimension: Option<u32>,
+) -> PyResult<Vec<BatchConversion>> {
+    if max_dimension == Some(0) {
+        return Err(PyValueError::new_err("max_dimension must be greater than zero"));
+    }
+    let template = naming::Template::parse(template)?;
+    let collision = naming::Collision::parse(collision)?;
+    let out_dir = Path::new(out_dir);
+    let claims = naming::Claims::default();
+    let tier = priority::current();
+    
+    Ok(py.allow_threads(|| {
+        paths
+            .par_iter()
+            .enumerate()
+            .map(|(index, path)| {
+                let _turn = priority::Turn::wait(tier);
+                let start = Instant::now();
+                let _deadline = deadline::ScopedDeadline::start();
+                provenance::clear();
+                let temp_jpg = paths::temp_file(path, "jpg");
+                let temp_str = temp_jpg.to_string_lossy().into_owned();
+                let converted = convert_raw_to_jpg(path, &temp_str).and_then(|converted| {
+                    if converted {
+                        if let Some(max_dimension) = max_dimension {
+                            cap_jpeg_dimension(&temp_str, max_dimension)?;
+                        }
+                    }
+                    Ok(converted)
+                });
+                let result = match converted {
+                    Ok(true) => place_converted(&temp_jpg, path, index, &template, out_dir, &claims, collision),
+                    Ok(false) => Err("No backend could convert the file".to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                let _ = std::fs::remove_file(&temp_jpg); // Clean up
+                BatchConversion {
+                    result,
+                    source: provenance::last(),
+                    attempted: provenance::attempted(),
+                    elapsed: start.elapsed(),
+                }
+            })
+            .collect()
+    }))
+}
+
+/// Convert many RAW files to JPEG in parallel, with a report per file
+///
+/// Runs like `rust_convert_raw_to_jpg_batch` (same `template`, `collision`
+/// and `max_dimension`), spreading the files over all cores with the GIL
+/// released, so external tools run side by side. Returns one dict per path,
+/// in order: `path`, `ok`, `output` (None unless converted), `backend` and
+/// `full_decode` of the step that succeeded, `error` and `category` (as in
+/// `rust_raw_to_grayscale_batch`'s error report, or `skipped` on a name
+/// collision) when it did not, the `backends` tried and `elapsed_ms`.
+#[pyfunction]
+#[pyo3(signature = (paths, out_dir, template = "{stem}.{ext}", collision = "suffix", max_dimension = None))]
+fn rust_convert_raw_batch(
+    py: Python<'_>,
+    paths: Vec<String>,
+    out_dir: &str,
+    template: &str,
+    collision: &str,
+    max_dimension: Option<u32>,
+) -> PyResult<Vec<PyObject>> {
+    let results = convert_batch(py, &paths, out_dir, template, collision, max_dimension)?;
+    paths
+        .iter()
+        .zip(results)
+        .map(|(path, converted)| {
+            let (output, error, category) = match converted.result {
+                Ok(Some(output)) => (Some(output), None, None),
+                Ok(None) => (None, Some(SKIPPED_ON_COLLISION.to_string()), Some(failures::Category::Skipped)),
+                Err(message) => {
+                    let category = failures::categorize(path, &message, &converted.attempted);
+                    (None, Some(message), Some(category))
+                },
+            };
+            let source = converted.source.filter(|_| output.is_some());
+            let report = PyDict::new(py);
+            report.set_item("path", path)?;
+            report.set_item("ok", output.is_some())?;
+            report.set_item("output", output)?;
+            report.set_item("backend", source.map(|s| s.backend))?;
+            report.set_item("full_decode", source.map(|s| s.full_decode))?;
+            report.set_item("error", error)?;
+            report.set_item("category", category.map(failures::Category::name))?;
+            report.set_item("backends", converted.attempted)?;
+            report.set_item("elapsed_ms", converted.elapsed.as_secs_f64() * 1000.0)?;
+            Ok(report.to_object(py))
+        })
+        .collect()
+}
+
+/// Move a converted temp JPEG to its templated name; None when skipped on collision
+fn place_converted(
+    temp_jpg: &Path,
+    source: &str,
+    index: usize,
+    template: &naming::Template,
+    out_dir: &Path,
+    claims: &naming::Claims,
+    collision: naming::Collision,
+) -> Result<Option<String>, String> {
+    let (width, height) = if template.uses("width") || template.uses("height") {
+        image::image_dimensions(temp_jpg).map_err(|e| format!("Failed to read converted image: {}", e))?
+    } else {
+        (0, 0)
+    };
+    let metadata = if ["date", "year", "month", "day", "make", "camera"].iter().any(|f| template.uses(f)) {
+        naming::read_metadata(source)
+    } else {
+        naming::Metadata::default()
+    };
+    let values = naming::Values { source: source.to_string(), index, width, height, metadata };
     
-    // Get file extension to identify the RAW format
-    let ext = Path::new(path)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .map(|s| s.to_lowercase())
-        .unwrap_or_default();
+    let wanted = out_dir.join(template.render(&values));
+    loop {
+        let Some(output) = claims.claim(wanted.clone(), collision) else {
+            return Ok(None);
+        };
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        // A file created at `output` since it was claimed is a collision too, so
+        // only `overwrite` may replace one
+        let placed = match collision {
+            naming::Collision::Overwrite => std::fs::rename(temp_jpg, &output),
+            _ => paths::rename_no_replace(temp_jpg, &output),
+        };
+        // The temp directory may be on another filesystem, where rename fails
+        let placed = match placed {
+            Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => copy_converted(temp_jpg, &output, collision),
+            placed => placed,
+        };
+        match placed {
+            Ok(()) => return Ok(Some(output.to_string_lossy().into_owned())),
+            // Claiming again finds the name taken and skips or picks the next suffix
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("Failed to write {}: {}", output.display(), e)),
+        }
+    }
+}
+
+/// Copy a converted temp JPEG to `output`, which must not exist unless `collision` is `overwrite`
+fn copy_converted(temp_jpg: &Path, output: &Path, collision: naming::Collision) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    match collision {
+        naming::Collision::Overwrite => options.write(true).create(true).truncate(true),
+        _ => options.write(true).create_new(true),
+    };
+    let mut file = options.open(output)?;
+    std::io::copy(&mut std::fs::File::open(temp_jpg)?, &mut file).inspect_err(|_| {
+        let _ = std::fs::remove_file(output); // Clean up
+    })?;
+    Ok(())
+}
+
+/// Shrink the JPEG at `jpg_path` in place so its long edge is at most `max_dimension`
+fn cap_jpeg_dimension(jpg_path: &str, max_dimension: u32) -> PyResult<()> {
+    let img = image::open(jpg_path).map_err(|e| PyIOError::new_err(format!("Failed to open converted image: {}", e)))?;
+    if img.width() <= max_dimension && img.height() <= max_dimension {
+        return Ok(());
+    }
     
-    // For each format type, try the fastest method first
+    tuning::save_jpeg(&img.resize(max_dimension, max_dimension, imageops::FilterType::Triangle), jpg_path)
+        .map_err(|e| PyIOError::new_err(format!("Failed to save resized preview: {}", e)))
+}
+
+/// The RAW conversion chain behind `rust_convert_raw_to_jpg`
+fn convert_raw_to_jpg(path: &str, jpg_path: &str) -> PyResult<bool> {
+    lifecycle::check()?;
+    // The per-file budget covers every step, including fetching remote sources
+    let _deadline = deadline::ScopedDeadline::start();
     
-    // Try extracting embedded preview first (fastest method for all formats)
-    if try_extract_embedded_preview(path, jpg_path) {
-        return Ok(true);
+    // Remote sources are fetched into a local temp copy first
+    if remote::is_remote(path) {
+        return convert_remote_raw_to_jpg(path, jpg_path);
     }
+    placeholders::check(path)?;
     
-    // If timing out, bail early
-    if start.elapsed() > Duration::from_secs(TIMEOUT_SECONDS) {
-        return Err(PyIOError::new_err("RAW processing timeout"));
+    // Check if its a Fuji RAF file - use dedicated function
+    if is_specific_raw_format(path, "raf") {
+        return process_raf_file(path, jpg_path);
     }
     
-    // Try specific optimizations based on format
-    match ext.as_str() {
-        "arw" => {
-            // Sony ARW specific processing
-            if try_sony_arw_processing(path, jpg_path) {
-                return Ok(true);
-            }
-        },
-        "cr2" | "cr3" => {
-            // Canon specific processing
-            if try_canon_cr_processing(path, jpg_path) {
-                return Ok(true);
-            }
-        },
-        "nef" => {
-            // Nikon specific processing
-            if try_nikon_nef_processing(path, jpg_path) {
-                return Ok(true);
-            }
-        },
-        _ => {
-            // Try rawloader for general formats (works well with DNG)
-            if try_rawloader_processing(path, jpg_path) {
-                return Ok(true);
-            }
+    // Steps and process limits depend on whether the file is local or on a network mount
+    let storage = storage::detect(path);
+    let _storage = storage::ScopedStorage::new(storage);
+    
+    // Wait for the shared IO and memory budgets before reading the file
+    let _reservation = begin_file_read(path);
+    
+    // Start a timer for performance tracking
+    let start = Instant::now();
+    
+    // Get file extension (or its registered handler) to identify the RAW format
+    let ext = formats::extension(path);
+    
+    let mut recorder = backend_stats::Recorder::start(path, &ext);
+    for (step, backend) in storage::chain(storage, &ext).into_iter().enumerate() {
+        // If timing out, bail early
+        if step > 0 && start.elapsed() > tuning::step_timeout() {
+            return Err(PyIOError::new_err("RAW processing timeout"));
+        }
+        lifecycle::check()?;
+        if deadline::expired() {
+            return Err(PyIOError::new_err(deadline::exceeded_message()));
+        }
+        
+        provenance::begin_step(backend.name());
+        let step_start = Instant::now();
+        let converted = match backend {
+            // Known camera models get their tuned path before the generic chain
+            storage::Backend::CameraProfile => try_camera_profile_processing(path, jpg_path),
+            // Embedded preview (fastest method for all formats)
+            storage::Backend::EmbeddedPreview => try_extract_embedded_preview(path, jpg_path),
+            // Sub-variants that dcraw and rawloader cannot decode go straight to libraw
+            storage::Backend::Libraw => {
+                detect_raw_variant(path, &ext) != RawVariant::Standard && try_libraw_processing(path, jpg_path)
+            },
+            storage::Backend::FormatSpecific => try_format_specific_processing(path, jpg_path, &ext),
+            // rawloader works well with DNG and needs no external tools
+            storage::Backend::Rawloader => try_rawloader_processing(path, jpg_path),
+            storage::Backend::Generic => try_generic_raw_processing(path, jpg_path),
+        };
+        recorder.step(backend, converted, step_start.elapsed());
+        if converted {
+            provenance::finish(backend.name());
+            return Ok(true);
         }
     }
     
-    // If timing out, bail early
-    if start.elapsed() > Duration::from_secs(TIMEOUT_SECONDS) {
-        return Err(PyIOError::new_err("RAW processing timeout"));
+    Err(PyIOError::new_err(format!("Failed to process RAW file: {}", path)))
+}
+
+/// Tuned dcraw/dcraw_emu settings for the formats that have them
+fn try_format_specific_processing(path: &str, jpg_path: &str, ext: &str) -> bool {
+    match ext {
+        // Sony ARW specific processing
+        "arw" => try_sony_arw_processing(path, jpg_path),
+        // Canon specific processing
+        "cr2" | "cr3" => try_canon_cr_processing(path, jpg_path),
+        // Nikon specific processing
+        "nef" => try_nikon_nef_processing(path, jpg_path),
+        // Olympus specific processing
+        "orf" => try_olympus_orf_processing(path, jpg_path),
+        // Panasonic specific processing
+        "rw2" => try_panasonic_rw2_processing(path, jpg_path),
+        // Pentax specific processing
+        "pef" => try_pentax_pef_processing(path, jpg_path),
+        // Samsung specific processing
+        "srw" => try_samsung_srw_processing(path, jpg_path),
+        // Legacy Kodak processing
+        "dcr" | "kdc" => try_kodak_processing(path, jpg_path),
+        _ => false,
+    }
+}
+
+/// Convert an s3:// or http(s):// source, fetching as little of it as possible
+fn convert_remote_raw_to_jpg(url: &str, jpg_path: &str) -> PyResult<bool> {
+    // Full decodes need the whole file anyway
+    if !previews_allowed() {
+        let full = remote::download(url, None).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        return convert_raw_to_jpg(full.path_str(), jpg_path);
     }
     
-    // Generic fallback processing
-    if try_generic_raw_processing(path, jpg_path) {
+    // A ranged read of the file head usually contains the embedded preview
+    let head = remote::download(url, Some(remote::PREVIEW_RANGE_BYTES))
+        .map_err(|e| PyIOError::new_err(e.to_string()))?;
+    provenance::begin_step(storage::Backend::EmbeddedPreview.name());
+    if try_extract_embedded_preview(head.path_str(), jpg_path) {
+        provenance::finish(storage::Backend::EmbeddedPreview.name());
         return Ok(true);
     }
+    if head.complete {
+        return convert_raw_to_jpg(head.path_str(), jpg_path);
+    }
+    drop(head);
     
-    Err(PyIOError::new_err(format!("Failed to process RAW file: {}", path)))
+    // Fall back to fetching the whole file for a full decode
+    let full = remote::download(url, None).map_err(|e| PyIOError::new_err(e.to_string()))?;
+    convert_raw_to_jpg(full.path_str(), jpg_path)
 }
 
 /// Try to extract embedded preview (fastest method)
 fn try_extract_embedded_preview(path: &str, jpg_path: &str) -> bool {
-    // Try exiftool first (it is usually fastest)
+    // Parse the TIFF IFDs or RAF header directly first: no process to spawn
+    if extract_native_preview(path, jpg_path) {
+        return true;
+    }
+    
+    // Then exiftool, which also reaches previews hidden in maker notes
     if extract_preview_with_exiftool(path, jpg_path) {
         return true;
     }
     
-    // Try dcraw preview extraction
-    let dcraw_thumb_result = Command::new("dcraw")
-        .args(&["-e", path])
-        .output();
+    // Try dcraw preview extraction, to stdout rather than a file named after the source
+    let dcraw_thumb_result = previews_allowed().then(|| {
+        Command::new("dcraw")
+            .args(["-e", "-c", path])
+            .limited_output()
+    });
     
-    if let Ok(output) = dcraw_thumb_result {
-        if output.status.success() {
-            // Check if thumb_*.jpg was created
-            let path_obj = Path::new(path);
-            let filename = path_obj.file_name().unwrap_or_default().to_str().unwrap_or("");
-            let thumb_path = path_obj.with_file_name(format!("thumb_{}", filename)).with_extension("jpg");
-            
-            if thumb_path.exists() {
-                if let Ok(_) = std::fs::copy(thumb_path, jpg_path) {
-                    let _ = std::fs::remove_file(thumb_path); // Clean up
-                    return true;
-                }
-            }
+    if let Some(Ok(output)) = dcraw_thumb_result {
+        if output.status.success() && !output.stdout.is_empty() && output::deliver_jpeg(jpg_path, output.stdout) {
+            provenance::mark_preview();
+            return true;
         }
     }
     
@@ -351,25 +864,15 @@ fn try_extract_embedded_preview(path: &str, jpg_path: &str) -> bool {
 fn try_sony_arw_processing(path: &str, jpg_path: &str) -> bool {
     // Sony ARW works well with custom dcraw settings
     let dcraw_sony_result = Command::new("dcraw")
-        .args(&["-c", "-w", "-h", "-q", "0", "-o", "0", path]) 
+        .args(demosaic_args(&["-c", "-w", "-h", "-q", "0", "-o", "0", path])) 
         // -h = half size, -q 0 = fast quality, -o 0 = raw color
-        .output();
+        .limited_output();
     
     if let Ok(output) = dcraw_sony_result {
         if output.status.success() {
-            // Save output to a temporary PPM file
-            let temp_ppm = format!("{}.ppm", jpg_path);
-            if let Ok(mut file) = File::create(&temp_ppm) {
-                if file.write_all(&output.stdout).is_ok() {
-                    // Convert PPM to JPG
-                    if let Ok(img) = image::open(&temp_ppm) {
-                        if img.save(jpg_path).is_ok() {
-                            let _ = std::fs::remove_file(&temp_ppm); // Clean up
-                            return true;
-                        }
-                    }
-                }
-                let _ = std::fs::remove_file(&temp_ppm); // Clean up on failure
+            // Decode the PPM straight from stdout
+            if deliver_decoded(jpg_path, &output.stdout) {
+                return true;
             }
         }
     }
@@ -381,25 +884,15 @@ fn try_sony_arw_processing(path: &str, jpg_path: &str) -> bool {
 fn try_canon_cr_processing(path: &str, jpg_path: &str) -> bool {
     // Canon works well with these dcraw settings
     let dcraw_canon_result = Command::new("dcraw")
-        .args(&["-c", "-w", "-h", "-q", "0", path]) 
+        .args(demosaic_args(&["-c", "-w", "-h", "-q", "0", path])) 
         // -h = half size (faster), -q 0 = fast quality
-        .output();
+        .limited_output();
     
     if let Ok(output) = dcraw_canon_result {
         if output.status.success() {
-            // Save output to a temporary PPM file
-            let temp_ppm = format!("{}.ppm", jpg_path);
-            if let Ok(mut file) = File::create(&temp_ppm) {
-                if file.write_all(&output.stdout).is_ok() {
-                    // Convert PPM to JPG
-                    if let Ok(img) = image::open(&temp_ppm) {
-                        if img.save(jpg_path).is_ok() {
-                            let _ = std::fs::remove_file(&temp_ppm); // Clean up
-                            return true;
-                        }
-                    }
-                }
-                let _ = std::fs::remove_file(&temp_ppm); // Clean up on failure
+            // Decode the PPM straight from stdout
+            if deliver_decoded(jpg_path, &output.stdout) {
+                return true;
             }
         }
     }
@@ -411,25 +904,135 @@ fn try_canon_cr_processing(path: &str, jpg_path: &str) -> bool {
 fn try_nikon_nef_processing(path: &str, jpg_path: &str) -> bool {
     // Nikon specific settings
     let dcraw_nikon_result = Command::new("dcraw")
-        .args(&["-c", "-w", "-h", "-q", "0", "-o", "1", path]) 
+        .args(demosaic_args(&["-c", "-w", "-h", "-q", "0", "-o", "1", path])) 
         // -h = half size, -q 0 = fast, -o 1 = sRGB (better for Nikon)
-        .output();
+        .limited_output();
     
     if let Ok(output) = dcraw_nikon_result {
         if output.status.success() {
-            // Save output to a temporary PPM file
-            let temp_ppm = format!("{}.ppm", jpg_path);
-            if let Ok(mut file) = File::create(&temp_ppm) {
-                if file.write_all(&output.stdout).is_ok() {
-                    // Convert PPM to JPG
-                    if let Ok(img) = image::open(&temp_ppm) {
-                        if img.save(jpg_path).is_ok() {
-                            let _ = std::fs::remove_file(&temp_ppm); // Clean up
-                            return true;
-                        }
-                    }
-                }
-                let _ = std::fs::remove_file(&temp_ppm); // Clean up on failure
+            // Decode the PPM straight from stdout
+            if deliver_decoded(jpg_path, &output.stdout) {
+                return true;
+            }
+        }
+    }
+    
+    false
+}
+
+/// Olympus ORF specific processing
+fn try_olympus_orf_processing(path: &str, jpg_path: &str) -> bool {
+    // Olympus 12-bit data looks flat in raw color, so convert to sRGB
+    let dcraw_olympus_result = Command::new("dcraw")
+        .args(demosaic_args(&["-c", "-w", "-h", "-q", "0", "-o", "1", path]))
+        // -h = half size, -q 0 = fast, -o 1 = sRGB
+        .limited_output();
+    
+    if let Ok(output) = dcraw_olympus_result {
+        if output.status.success() {
+            // Decode the PPM straight from stdout
+            if deliver_decoded(jpg_path, &output.stdout) {
+                return true;
+            }
+        }
+    }
+    
+    false
+}
+
+/// Panasonic RW2 specific processing
+fn try_panasonic_rw2_processing(path: &str, jpg_path: &str) -> bool {
+    // rawloader decodes the Panasonic bitstream natively, no process spawn needed
+    if try_rawloader_processing(path, jpg_path) {
+        return true;
+    }
+    
+    // Panasonic data already carries lens corrections, plain camera white balance is enough
+    let dcraw_panasonic_result = Command::new("dcraw")
+        .args(demosaic_args(&["-c", "-w", "-h", "-q", "0", path]))
+        // -h = half size, -q 0 = fast quality
+        .limited_output();
+    
+    if let Ok(output) = dcraw_panasonic_result {
+        if output.status.success() {
+            // Decode the PPM straight from stdout
+            if deliver_decoded(jpg_path, &output.stdout) {
+                return true;
+            }
+        }
+    }
+    
+    false
+}
+
+/// Pentax PEF specific processing
+fn try_pentax_pef_processing(path: &str, jpg_path: &str) -> bool {
+    // Pentax stores a usable black level, so skip dcraw auto-brightening
+    let dcraw_pentax_result = Command::new("dcraw")
+        .args(demosaic_args(&["-c", "-w", "-W", "-h", "-q", "0", "-o", "1", path]))
+        // -W = fixed brightness, -h = half size, -q 0 = fast, -o 1 = sRGB
+        .limited_output();
+    
+    if let Ok(output) = dcraw_pentax_result {
+        if output.status.success() {
+            // Decode the PPM straight from stdout
+            if deliver_decoded(jpg_path, &output.stdout) {
+                return true;
+            }
+        }
+    }
+    
+    false
+}
+
+/// Samsung SRW specific processing
+fn try_samsung_srw_processing(path: &str, jpg_path: &str) -> bool {
+    // rawloader understands all three Samsung compression schemes
+    if try_rawloader_processing(path, jpg_path) {
+        return true;
+    }
+    
+    // libraw is more current than dcraw for later NX bodies
+    try_libraw_processing(path, jpg_path)
+}
+
+/// Kodak DCR/KDC specific processing
+fn try_kodak_processing(path: &str, jpg_path: &str) -> bool {
+    // rawloader handles the Kodak 65000 and KDC layouts
+    if try_rawloader_processing(path, jpg_path) {
+        return true;
+    }
+    
+    // Kodak curves are baked in, so keep dcraw from re-brightening the result
+    let dcraw_kodak_result = Command::new("dcraw")
+        .args(demosaic_args(&["-c", "-w", "-W", "-h", "-q", "0", path]))
+        // -W = fixed brightness, -h = half size, -q 0 = fast
+        .limited_output();
+    
+    if let Ok(output) = dcraw_kodak_result {
+        if output.status.success() {
+            // Decode the PPM straight from stdout
+            if deliver_decoded(jpg_path, &output.stdout) {
+                return true;
+            }
+        }
+    }
+    
+    false
+}
+
+/// Decode with libraw (dcraw_emu), which handles newer compressed sub-variants
+fn try_libraw_processing(path: &str, jpg_path: &str) -> bool {
+    let dcraw_emu_result = Command::new("dcraw_emu")
+        .args(demosaic_args(&["-w", "-h", "-q", "0", "-o", "1", "-Z", "-", path]))
+        // -h = half size, -q 0 = fast, -o 1 = sRGB, -Z - = write PPM to stdout
+        .limited_output();
+    
+    if let Ok(output) = dcraw_emu_result {
+        if output.status.success() {
+            // Decode the PPM straight from stdout
+            if deliver_decoded(jpg_path, &output.stdout) {
+                return true;
             }
         }
     }
@@ -442,10 +1045,7 @@ fn try_rawloader_processing(path: &str, jpg_path: &str) -> bool {
     match decode_file(path) {
         Ok(raw_image) => {
             // Process the image based on its data type
-            match process_and_save_image(&raw_image, jpg_path) {
-                Ok(_) => true,
-                Err(_) => false
-            }
+            process_and_save_image(&raw_image, cfa::pattern_for(&raw_image), jpg_path).is_ok()
         },
         Err(_) => false
     }
@@ -455,48 +1055,28 @@ fn try_rawloader_processing(path: &str, jpg_path: &str) -> bool {
 fn try_generic_raw_processing(path: &str, jpg_path: &str) -> bool {
     // Try dcraw with generic options
     let dcraw_result = Command::new("dcraw")
-        .args(&["-c", "-w", "-h", "-q", "0", path]) // Use fast options
-        .output();
+        .args(demosaic_args(&["-c", "-w", "-h", "-q", "0", path])) // Use fast options
+        .limited_output();
     
     if let Ok(output) = dcraw_result {
         if output.status.success() {
-            // Save output to a temporary PPM file
-            let temp_ppm = format!("{}.ppm", jpg_path);
-            if let Ok(mut file) = File::create(&temp_ppm) {
-                if file.write_all(&output.stdout).is_ok() {
-                    // Convert PPM to JPG
-                    if let Ok(img) = image::open(&temp_ppm) {
-                        if img.save(jpg_path).is_ok() {
-                            let _ = std::fs::remove_file(&temp_ppm); // Clean up
-                            return true;
-                        }
-                    }
-                }
-                let _ = std::fs::remove_file(&temp_ppm); // Clean up on failure
+            // Decode the PPM straight from stdout
+            if deliver_decoded(jpg_path, &output.stdout) {
+                return true;
             }
         }
     }
     
     // Last resort: Try dcraw_emu
     let dcraw_emu_result = Command::new("dcraw_emu")
-        .args(&["-T", "-h", "-q", "0", path]) // Use fast options
-        .output();
+        .args(demosaic_args(&["-T", "-h", "-q", "0", "-Z", "-", path])) // Use fast options, TIFF to stdout
+        .limited_output();
     
     if let Ok(output) = dcraw_emu_result {
         if output.status.success() {
-            // Save output to a temporary TIFF file
-            let temp_tiff = format!("{}.tiff", jpg_path);
-            if let Ok(mut file) = File::create(&temp_tiff) {
-                if file.write_all(&output.stdout).is_ok() {
-                    // Convert TIFF to JPG
-                    if let Ok(img) = image::open(&temp_tiff) {
-                        if img.save(jpg_path).is_ok() {
-                            let _ = std::fs::remove_file(&temp_tiff); // Clean up
-                            return true;
-                        }
-                    }
-                }
-                let _ = std::fs::remove_file(&temp_tiff); // Clean up on failure
+            // Decode the TIFF straight from stdout
+            if deliver_decoded(jpg_path, &output.stdout) {
+                return true;
             }
         }
     }
@@ -505,10 +1085,28 @@ fn try_generic_raw_processing(path: &str, jpg_path: &str) -> bool {
 }
 
 /// Process raw image data and save as JPG with improved processing
-fn process_and_save_image(raw_image: &rawloader::RawImage, jpg_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+fn process_and_save_image(
+    raw_image: &rawloader::RawImage,
+    pattern: cfa::CfaPattern,
+    jpg_path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
     let width = raw_image.width;
     let height = raw_image.height;
     
+    // Near the memory budget, skip the full-size buffer and bin straight to half size
+    if memory::under_pressure() && width >= 2 && height >= 2 {
+        let img = DynamicImage::ImageRgb8(binned_rgb(raw_image, pattern));
+        output::deliver_image(jpg_path, img)?;
+        return Ok(());
+    }
+    
+    // Archival runs want every pixel, properly interpolated
+    if profiles::active().full_decode {
+        let img = DynamicImage::ImageRgb8(bilinear_rgb(raw_image, pattern));
+        output::deliver_image(jpg_path, img)?;
+        return Ok(());
+    }
+    
     // Create a new RGB image buffer
     let mut img_buffer = ImageBuffer::<Rgb<u8>, Vec<u8>>::new(width as u32, height as u32);
     
@@ -524,11 +1122,10 @@ fn process_and_save_image(raw_image: &rawloader::RawImage, jpg_path: &str) -> Re
                         // Simple conversion from 16-bit to 8-bit with gamma correction
                         let value = ((data[idx] as f32 / 65535.0).powf(0.45) * 255.0) as u8;
                         
-                        // Simple color estimation based on Bayer pattern (RGGB assumed)
-                        let pattern_idx = (y % 2) * 2 + (x % 2);
-                        let (r, g, b) = match pattern_idx {
+                        // Simple color estimation based on the Bayer pattern
+                        let (r, g, b) = match pattern.color_at(y, x) {
                             0 => (value, value/2, value/2), // R
-                            1 | 2 => (value/2, value, value/2), // G
+                            1 => (value/2, value, value/2), // G
                             _ => (value/2, value/2, value),  // B
                         };
                         
@@ -543,13 +1140,12 @@ fn process_and_save_image(raw_image: &rawloader::RawImage, jpg_path: &str) -> Re
                     let idx = y * width + x;
                     if idx < data.len() {
                         // Convert float to 8-bit with gamma correction
-                        let value = ((data[idx].max(0.0).min(1.0)).powf(0.45) * 255.0) as u8;
+                        let value = ((data[idx].clamp(0.0, 1.0)).powf(0.45) * 255.0) as u8;
                         
                         // Simple color estimation
-                        let pattern_idx = (y % 2) * 2 + (x % 2);
-                        let (r, g, b) = match pattern_idx {
+                        let (r, g, b) = match pattern.color_at(y, x) {
                             0 => (value, value/2, value/2), // R
-                            1 | 2 => (value/2, value, value/2), // G
+                            1 => (value/2, value, value/2), // G
                             _ => (value/2, value/2, value),  // B
                         };
                         
@@ -568,175 +1164,4942 @@ fn process_and_save_image(raw_image: &rawloader::RawImage, jpg_path: &str) -> Re
         img = img.resize(width as u32 / 2, height as u32 / 2, imageops::FilterType::Triangle);
     }
     
-    // Save as JPEG with moderate quality (85%)
-    img.save_with_format(jpg_path, image::ImageFormat::Jpeg)?;
+    // Save as JPEG at the configured quality (85% unless changed)
+    output::deliver_image(jpg_path, img)?;
     
     Ok(())
 }
 
-/// Convert RAW directly to grayscale for hashing (optimized version)
-#[pyfunction]
-fn rust_raw_to_grayscale(py: Python<'_>, path: &str) -> PyResult<Py<PyArray2<u8>>> {
-    // First try to convert to JPG
-    let temp_jpg = format!("{}.temp.jpg", path);
+/// Full-resolution RGB by bilinear demosaicing, see `CfaPattern::demosaic_bilinear`
+///
+/// Sites are scaled to 0-1 with the black and white level of their color
+/// first. Runs on the calling thread: it is called from per-file rayon tasks,
+/// and a nested parallel loop would let work-stealing run another file on
+/// this thread mid-decode, mixing up the thread-local provenance, deadline
+/// and output capture of the two files.
+fn bilinear_rgb(raw_image: &rawloader::RawImage, pattern: cfa::CfaPattern) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let (width, height) = (raw_image.width, raw_image.height);
+    let site = |y: usize, x: usize| -> f32 {
+        let idx = y * width + x;
+        match &raw_image.data {
+            RawImageData::Integer(data) => data.get(idx).map_or(0.0, |&v| {
+                let color = pattern.color_at(y, x);
+                let black = raw_image.blacklevels[color] as f32;
+                let white = raw_image.whitelevels[color] as f32;
+                if white <= black {
+                    return 0.0;
+                }
+                ((v as f32 - black) / (white - black)).clamp(0.0, 1.0)
+            }),
+            RawImageData::Float(data) => data.get(idx).map_or(0.0, |&v| v.clamp(0.0, 1.0)),
+        }
+    };
     
-    let result = if is_specific_raw_format(path, "raf") {
-        rust_process_raf_file(path, &temp_jpg)
-    } else {
-        rust_convert_raw_to_jpg(path, &temp_jpg)
-    };
-    
-    match result {
-        Ok(_) => {
-            // Process the temporary JPG to grayscale
-            match image::open(&temp_jpg) {
-                Ok(img) => {
-                    // Convert to grayscale
-                    let gray_img = img.grayscale();
-                    
-                    // Resize to thumbnail size for hashing
-                    let resized = gray_img.resize_exact(
-                        THUMBNAIL_SIZE, 
-                        THUMBNAIL_SIZE, 
-                        imageops::FilterType::Triangle
-                    );
-                    
-                    // Convert to numpy array
-                    let height = resized.height() as usize;
-                    let width = resized.width() as usize;
-                    let mut grayscale = vec![0u8; width * height];
-                    
-                    for y in 0..height {
-                        for x in 0..width {
-                            let pixel = resized.get_pixel(x as u32, y as u32);
-                            grayscale[y * width + x] = pixel[0]; // Take first channel
-                        }
-                    }
-                    
-                    // Clean up temp file
-                    let _ = std::fs::remove_file(&temp_jpg);
-                    
-                    // Create numpy array
-                    unsafe {
-                        let buffer = numpy::PyArray2::<u8>::new(
-                            py, 
-                            [height, width], 
-                            false
-                        );
-                        
-                        let dataptr = buffer.as_array_mut().as_mut_ptr();
-                        std::ptr::copy_nonoverlapping(
-                            grayscale.as_ptr(), 
-                            dataptr, 
-                            width * height
-                        );
-                        
-                        Ok(buffer.into())
+    let rgb = pattern.demosaic_bilinear(width, height, site);
+    let pixels = rgb.into_iter().map(|value| (value.powf(0.45) * 255.0) as u8).collect();
+    ImageBuffer::from_raw(width as u32, height as u32, pixels).unwrap_or_default()
+}
+
+/// Half-resolution RGB from 2x2 Bayer blocks, built band by band
+///
+/// Only the quarter-size output is allocated, which keeps peak memory low when
+/// the pipeline is close to its budget.
+fn binned_rgb(raw_image: &rawloader::RawImage, pattern: cfa::CfaPattern) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let width = raw_image.width;
+    let out_width = width / 2;
+    let out_height = raw_image.height / 2;
+    
+    let sample = |idx: usize| -> u8 {
+        match &raw_image.data {
+            RawImageData::Integer(data) => ((data[idx] as f32 / 65535.0).powf(0.45) * 255.0) as u8,
+            RawImageData::Float(data) => ((data[idx].clamp(0.0, 1.0)).powf(0.45) * 255.0) as u8,
+        }
+    };
+    let len = match &raw_image.data {
+        RawImageData::Integer(data) => data.len(),
+        RawImageData::Float(data) => data.len(),
+    };
+    
+    let (red, greens, blue) = pattern.tile_offsets();
+    let mut img_buffer = ImageBuffer::<Rgb<u8>, Vec<u8>>::new(out_width as u32, out_height as u32);
+    for y in 0..out_height {
+        let top = 2 * y * width;
+        let bottom = top + width;
+        if bottom + width > len {
+            break;
+        }
+        
+        for x in 0..out_width {
+            let at = |(dy, dx): (usize, usize)| sample(top + dy * width + 2 * x + dx);
+            let r = at(red);
+            let g = ((at(greens[0]) as u16 + at(greens[1]) as u16) / 2) as u8;
+            let b = at(blue);
+            img_buffer.put_pixel(x as u32, y as u32, Rgb([r, g, b]));
+        }
+    }
+    
+    img_buffer
+}
+
+/// Decode a RAW file through the conversion pipeline into an in-memory image
+///
+/// Every step hands its result over in memory, so nothing is written: not
+/// next to the source, which may be a read-only mount, and not to a temp
+/// file another worker on the same source could race for.
+fn decode_raw_image(path: &str) -> PyResult<DynamicImage> {
+    provenance::clear();
+    
+    let capture = output::ScopedCapture::start();
+    if is_specific_raw_format(path, "raf") {
+        process_raf_file(path, output::IN_MEMORY)?;
+    } else {
+        convert_raw_to_jpg(path, output::IN_MEMORY)?;
+    }
+    
+    match capture.take() {
+        Some(decoded) => decoded.map_err(|e| PyIOError::new_err(format!("Failed to open converted image: {}", e))),
+        None => Err(PyIOError::new_err(format!("Failed to process RAW file: {}", path))),
+    }
+}
+
+/// Open any supported image, using the RAW pipeline for formats the image crate cannot read
+fn open_any_image(path: &str) -> PyResult<DynamicImage> {
+    lifecycle::check()?;
+    if !remote::is_remote(path) {
+        placeholders::check(path)?;
+        let _reservation = begin_file_read(path);
+        if let Some(img) = formats::open_image(path) {
+            return Ok(img);
+        }
+        if salvage::enabled() && matches!(formats::extension(path).as_str(), "jpg" | "jpeg") {
+            if let Some(img) = salvage::decode_jpeg(path) {
+                return Ok(img);
+            }
+        }
+    }
+    decode_raw_image(path)
+}
+
+/// Decode a RAW file into a row-major grayscale buffer of `size` x `size` pixels
+fn raw_to_grayscale_buffer(
+    path: &str,
+    size: u32,
+    dtype: GrayscaleDtype,
+    filter: imageops::FilterType,
+    preprocess: exposure::Preprocess,
+    sensor: bool,
+) -> PyResult<GrayscaleBuffer> {
+    raw_to_grayscale_with_info(path, size, dtype, filter, preprocess, sensor).map(|(grayscale, _)| grayscale)
+}
+
+/// `raw_to_grayscale_buffer` plus where its pixels came from
+fn raw_to_grayscale_with_info(
+    path: &str,
+    size: u32,
+    dtype: GrayscaleDtype,
+    filter: imageops::FilterType,
+    preprocess: exposure::Preprocess,
+    sensor: bool,
+) -> PyResult<(GrayscaleBuffer, provenance::DecodeInfo)> {
+    lifecycle::check()?;
+    let _deadline = deadline::ScopedDeadline::start();
+    provenance::clear();
+    if !remote::is_remote(path) {
+        placeholders::check(path)?;
+    }
+    let sensed = if sensor && !remote::is_remote(path) { sensor_thumbnail(path, size, dtype, filter) } else { None };
+    let (mut grayscale, info) = if let Some(sensed) = sensed {
+        sensed
+    } else if let Some((img, original)) = exif_stand_in(path, size) {
+        let info = provenance::DecodeInfo {
+            original: Some(original),
+            decoded: (img.width(), img.height()),
+            source: provenance::Source { backend: "exif_thumbnail", full_decode: false, oriented: false },
+        };
+        (grayscale_thumbnail(&img, size, dtype, filter, grayscale::luma_mode()), info)
+    } else if dtype != GrayscaleDtype::U8 && !remote::is_remote(path) && formats::is_raw(&formats::extension(path)) {
+        high_bit_thumbnail(path, size, dtype, filter)?
+    } else if dtype == GrayscaleDtype::U8 && streaming::is_streamable(path) {
+        provenance::begin_step("stream");
+        let (pixels, dimensions) =
+            streaming::grayscale_thumbnail_with_dimensions(path, size as usize, grayscale::luma_mode())?;
+        let info = provenance::DecodeInfo {
+            original: Some(dimensions),
+            decoded: dimensions,
+            source: provenance::Source { backend: "stream", full_decode: true, oriented: false },
+        };
+        (GrayscaleBuffer::U8(pixels), info)
+    } else {
+        let img = decode_raw_image(path)?;
+        let info = provenance::DecodeInfo {
+            original: provenance::original_dimensions(path),
+            decoded: (img.width(), img.height()),
+            source: provenance::last().unwrap_or(provenance::Source { backend: "unknown", full_decode: true, oriented: false }),
+        };
+        (grayscale_thumbnail(&img, size, dtype, filter, grayscale::luma_mode()), info)
+    };
+    if let GrayscaleBuffer::U8(pixels) = &mut grayscale {
+        preprocess.apply(pixels);
+    }
+    Ok((grayscale, info))
+}
+
+/// The EXIF thumbnail of a local JPEG or HEIC file, when previews may stand
+/// in for decodes and both its sides reach `min_side`, and the main image size
+fn exif_stand_in(path: &str, min_side: u32) -> Option<(DynamicImage, (u32, u32))> {
+    if remote::is_remote(path) || !previews_allowed() || !exif_thumbnail::applies_to(path) {
+        return None;
+    }
+    provenance::begin_step("exif_thumbnail");
+    let _reservation = begin_file_read(path);
+    exif_thumbnail::extract(path, min_side)
+}
+
+/// uint16 / float32 thumbnail of a RAW file from its full bit depth data
+///
+/// The regular chain ends in 8-bit images (previews, JPEG conversions), which
+/// would only be widened; libraw's 16-bit output comes first, then rawloader's
+/// sensor data. Fails rather than widening when neither decodes the file.
+fn high_bit_thumbnail(
+    path: &str,
+    size: u32,
+    dtype: GrayscaleDtype,
+    filter: imageops::FilterType,
+) -> PyResult<(GrayscaleBuffer, provenance::DecodeInfo)> {
+    provenance::begin_step("libraw_16bit");
+    let decoded = {
+        let _reservation = begin_file_read(path);
+        Command::new("dcraw_emu")
+            .args(demosaic_args(&["-6", "-w", "-h", "-q", "0", "-o", "1", "-Z", "-", path]))
+            // -6 = 16-bit output, -h = half size, -q 0 = fast, -o 1 = sRGB, -Z - = PPM to stdout
+            .limited_output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| image::load_from_memory(&output.stdout).ok())
+    };
+    if let Some(img) = decoded {
+        let info = provenance::DecodeInfo {
+            original: provenance::original_dimensions(path),
+            decoded: (img.width(), img.height()),
+            source: provenance::Source { backend: "libraw_16bit", full_decode: true, oriented: true },
+        };
+        return Ok((grayscale_thumbnail(&img, size, dtype, filter, grayscale::luma_mode()), info));
+    }
+    sensor_thumbnail(path, size, dtype, filter).ok_or_else(|| {
+        PyIOError::new_err(format!("No full bit depth decode of {} for uint16/float32 output", path))
+    })
+}
+
+/// Thumbnail of the raw Bayer luminance, None unless rawloader reads `path` as a Bayer RAW
+fn sensor_thumbnail(
+    path: &str,
+    size: u32,
+    dtype: GrayscaleDtype,
+    filter: imageops::FilterType,
+) -> Option<(GrayscaleBuffer, provenance::DecodeInfo)> {
+    provenance::begin_step("sensor");
+    let _reservation = begin_file_read(path);
+    let raw_image = decode_file(path).ok()?;
+    let (grayscale, decoded) = sensor::thumbnail(&raw_image, size, dtype, filter)?;
+    let info = provenance::DecodeInfo {
+        original: Some((raw_image.width as u32, raw_image.height as u32)),
+        decoded,
+        source: provenance::Source { backend: "sensor", full_decode: true, oriented: false },
+    };
+    Some((grayscale, info))
+}
+
+/// Source dimensions and backend of a thumbnail as a dict
+fn decode_info_to_dict(py: Python<'_>, info: &provenance::DecodeInfo) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("width", info.original.map(|(width, _)| width))?;
+    dict.set_item("height", info.original.map(|(_, height)| height))?;
+    dict.set_item("preview_width", info.decoded.0)?;
+    dict.set_item("preview_height", info.decoded.1)?;
+    dict.set_item("preview_source", info.source.backend)?;
+    dict.set_item("full_decode", info.source.full_decode)?;
+    Ok(dict.to_object(py))
+}
+
+/// Parse the preprocessing profile, which is only defined for uint8 thumbnails
+fn parse_preprocess(name: &str, dtype: GrayscaleDtype) -> PyResult<exposure::Preprocess> {
+    let preprocess = exposure::Preprocess::parse(name)?;
+    if preprocess != exposure::Preprocess::None && dtype != GrayscaleDtype::U8 {
+        return Err(PyValueError::new_err("preprocess requires dtype 'uint8'"));
+    }
+    Ok(preprocess)
+}
+
+/// `size` or the configured default thumbnail side, which must not be zero
+fn thumbnail_size(size: Option<u32>) -> PyResult<u32> {
+    match size.unwrap_or_else(tuning::thumbnail_size) {
+        0 => Err(PyValueError::new_err("size must be greater than zero")),
+        size => Ok(size),
+    }
+}
+
+/// Convert RAW directly to grayscale for hashing (optimized version)
+///
+/// `dtype` is one of `uint8` (default), `uint16` or `float32` (normalized 0-1).
+/// For RAW files `uint16` and `float32` come from a 16-bit libraw decode (or
+/// the sensor data), never from an 8-bit preview, and fail when neither is
+/// available; other files keep their own bit depth, so a JPEG only fills
+/// the top 8 bits. `filter` selects the resize filter; hashes are only
+/// comparable between thumbnails made with the same filter (default
+/// `triangle`). `preprocess` normalizes exposure (`none`, `stretch` or
+/// `equalize`) so underexposed previews and corrected exports of the same
+/// shot hash alike.
+///
+/// With `with_info=True` returns `(array, info)`, where `info` holds the
+/// original `width`/`height` (None when the header can't be read), the
+/// `preview_width`/`preview_height` actually thumbnailed, the
+/// `preview_source` backend and whether a `full_decode` happened (False for
+/// embedded JPEG previews), for weighting hash confidence.
+///
+/// `size` is the side of the square thumbnail, by default 512 or the value
+/// set with `configure(thumbnail_size=...)`. For JPEG and HEIC files whose
+/// EXIF thumbnail is at least `size` on both sides (and matches the image's
+/// aspect ratio), that thumbnail is used instead of decoding the full image
+/// (`preview_source` is `exif_thumbnail`), unless the scan profile or
+/// storage restriction rules out previews.
+///
+/// With `sensor=True` Bayer RAW files are thumbnailed from the raw sensor
+/// luminance, averaging each 2x2 tile instead of demosaicing (`preview_source`
+/// is `sensor`). That is faster than a full decode and gives the same
+/// thumbnail whatever tool would have demosaiced the file, but is not
+/// comparable with decoded thumbnails, and the grayscale mode does not apply.
+/// Other files fall back to the regular decode.
+#[pyfunction]
+#[pyo3(signature = (path, dtype = "uint8", filter = "triangle", preprocess = "none", with_info = false, size = None, sensor = false))]
+#[allow(clippy::too_many_arguments)]
+fn rust_raw_to_grayscale(
+    py: Python<'_>,
+    path: &str,
+    dtype: &str,
+    filter: &str,
+    preprocess: &str,
+    with_info: bool,
+    size: Option<u32>,
+    sensor: bool,
+) -> PyResult<PyObject> {
+    let size = thumbnail_size(size)?;
+    let dtype = GrayscaleDtype::parse(dtype)?;
+    let filter = parse_filter(filter)?;
+    let preprocess = parse_preprocess(preprocess, dtype)?;
+    let (grayscale, info) = py.allow_threads(|| {
+        priority::run(|| raw_to_grayscale_with_info(path, size, dtype, filter, preprocess, sensor))
+    })?;
+    let array = grayscale.into_pyarray(py, size as usize, size as usize)?;
+    if !with_info {
+        return Ok(array);
+    }
+    Ok((array, decode_info_to_dict(py, &info)?).to_object(py))
+}
+
+/// Stacked grayscale thumbnails plus a per-file error status
+type GrayscaleBatch = (PyObject, Vec<PyObject>);
+
+/// Convert many RAW files to grayscale in parallel as an (N, size, size) stack
+///
+/// Returns the stack together with one status per path: `None` when the file
+/// decoded, otherwise the error message. Failed slots are left zero-filled.
+/// With `error_report=True` a failure's status is a dict instead, with the
+/// error `category` (`missing`, `permission_denied`, `unsupported`,
+/// `timeout`, `crashed`, `decode_failed`, `io_error`, `skipped` or
+/// `interrupted`), the `message`, the `backends` tried in order and
+/// `elapsed_ms`, so poison files can be triaged without parsing messages.
+///
+/// A decoder panic fails only its own file (`crashed`). With a skip-list set
+/// (see `set_skip_list`), crashes and timeouts are recorded there and files
+/// over the limit are reported as `skipped` without being decoded.
+///
+/// On Ctrl-C (KeyboardInterrupt) the files being decoded finish, the rest
+/// fail as `interrupted`, the skip-list is saved and the partial batch is
+/// returned rather than lost. With `with_interrupted=True` the result is
+/// `(stack, statuses, interrupted)`, so a loop over chunks can tell when to
+/// stop.
+///
+/// `sensor` thumbnails Bayer RAW files without demosaicing, as in
+/// `rust_raw_to_grayscale`.
+#[pyfunction]
+#[pyo3(signature = (paths, size = None, dtype = "uint8", filter = "triangle", preprocess = "none", error_report = false, sensor = false, with_interrupted = false))]
+#[allow(clippy::too_many_arguments)]
+fn rust_raw_to_grayscale_batch(
+    py: Python<'_>,
+    paths: Vec<String>,
+    size: Option<u32>,
+    dtype: &str,
+    filter: &str,
+    preprocess: &str,
+    error_report: bool,
+    sensor: bool,
+    with_interrupted: bool,
+) -> PyResult<PyObject> {
+    let size = thumbnail_size(size)?;
+    let dtype = GrayscaleDtype::parse(dtype)?;
+    let filter = parse_filter(filter)?;
+    let preprocess = parse_preprocess(preprocess, dtype)?;
+    let tier = priority::current();
+    
+    // Decode without holding the GIL so the rayon workers run concurrently
+    let (results, interrupted): (Vec<Result<GrayscaleBuffer, failures::FileFailure>>, bool) = interrupt::run(py, |interrupt| {
+        let results = paths
+            .par_iter()
+            .map(|path| {
+                let _turn = priority::Turn::wait(tier);
+                if interrupt.is_set() {
+                    return Err(failures::FileFailure {
+                        category: failures::Category::Interrupted,
+                        message: "Interrupted before decoding".to_string(),
+                        backends: Vec::new(),
+                        elapsed: Duration::ZERO,
+                    });
+                }
+                if let Some(entry) = skiplist::skipped(path) {
+                    return Err(failures::FileFailure {
+                        category: failures::Category::Skipped,
+                        message: format!("Skipped after {} {} failure(s): {}", entry.failures, entry.category, entry.reason),
+                        backends: Vec::new(),
+                        elapsed: Duration::ZERO,
+                    });
+                }
+                let start = Instant::now();
+                let decoding = skiplist::Decoding::start(path);
+                let decoded = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    raw_to_grayscale_buffer(path, size, dtype, filter, preprocess, sensor)
+                }));
+                drop(decoding);
+                let failure = match decoded {
+                    Ok(Ok(pixels)) => {
+                        skiplist::record_success(path);
+                        return Ok(pixels);
+                    },
+                    Ok(Err(e)) => failures::FileFailure::new(path, e.to_string(), provenance::attempted(), start.elapsed()),
+                    Err(panic) => failures::FileFailure {
+                        category: failures::Category::Crashed,
+                        message: format!("Decoder panicked: {}", panic_message(panic.as_ref())),
+                        backends: provenance::attempted(),
+                        elapsed: start.elapsed(),
+                    },
+                };
+                if skiplist::is_poison(failure.category) {
+                    skiplist::record_failure(path, failure.category, &failure.message);
+                }
+                Err(failure)
+            })
+            .collect();
+        results
+    })?;
+    if interrupted {
+        // Failures are written as they happen; this retries any write that failed
+        skiplist::save().map_err(index_error)?;
+    }
+    
+    let mut buffers = Vec::with_capacity(results.len());
+    let mut statuses = Vec::with_capacity(results.len());
+    
+    for result in results {
+        match result {
+            Ok(pixels) => {
+                buffers.push(Some(pixels));
+                statuses.push(py.None());
+            },
+            Err(failure) if error_report => {
+                buffers.push(None);
+                statuses.push(failure_to_dict(py, &failure)?);
+            },
+            Err(failure) => {
+                buffers.push(None);
+                statuses.push(failure.message.to_object(py));
+            },
+        }
+    }
+    
+    let batch: GrayscaleBatch = (stack_thumbnails(py, buffers, size as usize, dtype)?, statuses);
+    if !with_interrupted {
+        return Ok(batch.to_object(py));
+    }
+    Ok((batch.0, batch.1, interrupted).to_object(py))
+}
+
+/// The message a panic was raised with, when it is a string
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// A failed file's category, message, attempted backends and timing as a dict
+fn failure_to_dict(py: Python<'_>, failure: &failures::FileFailure) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("category", failure.category.name())?;
+    dict.set_item("message", &failure.message)?;
+    dict.set_item("backends", &failure.backends)?;
+    dict.set_item("elapsed_ms", failure.elapsed.as_secs_f64() * 1000.0)?;
+    Ok(dict.to_object(py))
+}
+
+/// Render a contact sheet (grid montage with file names and scores) for a duplicate group
+///
+/// `scores` may be empty or hold one similarity score per path. Tiles come
+/// from the thumbnail cache shared with the review UI, rendered from embedded
+/// previews where the files have them. Files that fail to decode are shown as
+/// gray placeholders so the grid still lines up.
+#[pyfunction]
+#[pyo3(signature = (paths, scores, out_path, tile_size = 256, columns = 4))]
+fn rust_render_contact_sheet(
+    py: Python<'_>,
+    paths: Vec<String>,
+    scores: Vec<f64>,
+    out_path: &str,
+    tile_size: u32,
+    columns: u32,
+) -> PyResult<bool> {
+    if !scores.is_empty() && scores.len() != paths.len() {
+        return Err(PyValueError::new_err("scores must be empty or match the number of paths"));
+    }
+    if tile_size == 0 {
+        return Err(PyValueError::new_err("tile_size must be greater than zero"));
+    }
+    let tile_size = profiles::bound_edge(tile_size);
+    let tier = priority::current();
+    
+    let sheet = py.allow_threads(|| {
+        let tiles: Vec<contact_sheet::Tile> = paths
+            .par_iter()
+            .enumerate()
+            .map(|(i, path)| {
+                let _turn = priority::Turn::wait(tier);
+                // Same renditions as the review UI: cached, or made from an embedded preview
+                let output_format = tuning::jpeg_format();
+                let key = thumbnail_key(path, tile_size, &output_format);
+                let image = cached_thumbnail(&key, path, tile_size, output_format)
+                    .ok()
+                    .and_then(|bytes| image::load_from_memory(&bytes).ok());
+                let name = Path::new(path)
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or(path)
+                    .to_string();
+                
+                let mut caption = vec![name];
+                if let Some(score) = scores.get(i) {
+                    caption.push(format!("score {:.3}", score));
+                }
+                contact_sheet::Tile { image, caption }
+            })
+            .collect();
+        
+        contact_sheet::render(&tiles, tile_size, columns)
+    });
+    
+    sheet
+        .save(out_path)
+        .map_err(|e| PyIOError::new_err(format!("Failed to save contact sheet: {}", e)))?;
+    
+    Ok(true)
+}
+
+/// Return `path` resized to fit `long_edge` pixels, encoded as `jpeg` or `png` bytes
+///
+/// Results are kept in an in-memory cache keyed by path, mtime, size and
+/// rendition, so repeated requests from a review UI skip the decode entirely.
+/// With `set_cache_dir` they are also shared with other worker processes.
+/// The active scan profile may cap `long_edge` (see `set_scan_profile`).
+/// `long_edge` and the JPEG quality default to the values set with `configure`.
+#[pyfunction]
+#[pyo3(signature = (path, long_edge = None, format = "jpeg"))]
+fn get_thumbnail(py: Python<'_>, path: &str, long_edge: Option<u32>, format: &str) -> PyResult<PyObject> {
+    let output_format = parse_thumbnail_format(format)?;
+    let long_edge = long_edge.unwrap_or_else(tuning::thumbnail_size);
+    if long_edge == 0 {
+        return Err(PyValueError::new_err("long_edge must be greater than zero"));
+    }
+    let long_edge = profiles::bound_edge(long_edge);
+    
+    let key = thumbnail_key(path, long_edge, &output_format);
+    let bytes = py.allow_threads(|| priority::run(|| cached_thumbnail(&key, path, long_edge, output_format)))?;
+    Ok(PyBytes::new(py, &bytes).to_object(py))
+}
+
+fn parse_thumbnail_format(format: &str) -> PyResult<image::ImageOutputFormat> {
+    match format {
+        "jpeg" | "jpg" => Ok(tuning::jpeg_format()),
+        "png" => Ok(image::ImageOutputFormat::Png),
+        _ => Err(PyValueError::new_err(format!("Unsupported format '{}', expected 'jpeg' or 'png'", format))),
+    }
+}
+
+/// Cache key for a rendition; JPEGs of different quality are cached apart
+fn thumbnail_key(path: &str, long_edge: u32, output_format: &image::ImageOutputFormat) -> thumbnails::ThumbnailKey {
+    match output_format {
+        image::ImageOutputFormat::Jpeg(quality) => {
+            thumbnails::ThumbnailKey::new(path, long_edge, &format!("jpeg-q{}", quality))
+        },
+        _ => thumbnails::ThumbnailKey::new(path, long_edge, "png"),
+    }
+}
+
+/// Image a `long_edge` thumbnail is shrunk from: an embedded preview (RAW
+/// JpgFromRaw, EXIF thumbnail of JPEG/HEIC) when it is at least that large,
+/// otherwise a full decode
+fn thumbnail_source(path: &str, long_edge: u32) -> PyResult<DynamicImage> {
+    if !remote::is_remote(path) {
+        let preview = if formats::is_raw(&formats::extension(path)) {
+            previews::best_native_preview(path).and_then(|preview| image::load_from_memory(&preview.data).ok())
+        } else if exif_thumbnail::applies_to(path) {
+            exif_thumbnail::extract(path, 0).map(|(img, _)| img)
+        } else {
+            None
+        };
+        if let Some(img) = preview.filter(|img| img.width().max(img.height()) >= long_edge) {
+            return Ok(img);
+        }
+    }
+    open_any_image(path)
+}
+
+/// Thumbnail bytes from the memory cache, the shared directory or a fresh render
+fn cached_thumbnail(
+    key: &thumbnails::ThumbnailKey,
+    path: &str,
+    long_edge: u32,
+    output_format: image::ImageOutputFormat,
+) -> PyResult<Vec<u8>> {
+    if let Some(bytes) = thumbnails::get(key) {
+        return Ok(bytes);
+    }
+    
+    let bytes = thumbnails::load_or_render(key, || -> PyResult<Vec<u8>> {
+        let img = thumbnail_source(path, long_edge)?.thumbnail(long_edge, long_edge);
+        // JPEG has no alpha channel
+        let img = DynamicImage::ImageRgb8(img.to_rgb8());
+        
+        let mut bytes = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut bytes), output_format)
+            .map_err(|e| PyIOError::new_err(format!("Failed to encode thumbnail: {}", e)))?;
+        Ok(bytes)
+    })?;
+    
+    thumbnails::put(key.clone(), bytes.clone());
+    Ok(bytes)
+}
+
+/// Small thumbnails to embed per file in a duplicate report JSON
+///
+/// With `embed="inline"` each entry is a `data:image/jpeg;base64,...` URI, so
+/// a web UI can render the review page without the original volumes. With
+/// `embed="cache"` each entry is the path of the thumbnail in the shared cache
+/// directory (see `set_cache_dir`), relative to `relative_to` (typically the
+/// report's directory) when given. Entries follow `paths`; files that fail to
+/// decode get None.
+#[pyfunction]
+#[pyo3(signature = (paths, embed = "inline", long_edge = 160, relative_to = None))]
+fn rust_report_thumbnails(
+    py: Python<'_>,
+    paths: Vec<String>,
+    embed: &str,
+    long_edge: u32,
+    relative_to: Option<&str>,
+) -> PyResult<Vec<Option<String>>> {
+    use base64::Engine;
+    
+    let inline = match embed {
+        "inline" => true,
+        "cache" => false,
+        _ => return Err(PyValueError::new_err(format!("Unsupported embed mode '{}', expected 'inline' or 'cache'", embed))),
+    };
+    if !inline && thumbnails::disk_dir_path().is_none() {
+        return Err(PyValueError::new_err("embed='cache' requires a cache directory, call set_cache_dir first"));
+    }
+    if long_edge == 0 {
+        return Err(PyValueError::new_err("long_edge must be greater than zero"));
+    }
+    let long_edge = profiles::bound_edge(long_edge);
+    let tier = priority::current();
+    
+    Ok(py.allow_threads(|| {
+        paths
+            .par_iter()
+            .map(|path| {
+                let _turn = priority::Turn::wait(tier);
+                let output_format = tuning::jpeg_format();
+                let key = thumbnail_key(path, long_edge, &output_format);
+                let render = || cached_thumbnail(&key, path, long_edge, output_format.clone());
+                if inline {
+                    let bytes = render().ok()?;
+                    return Some(format!("data:image/jpeg;base64,{}", base64::engine::general_purpose::STANDARD.encode(bytes)));
+                }
+                
+                // A memory cache hit never reaches the directory; make sure the file is there
+                thumbnails::load_or_render(&key, render).ok()?;
+                let cached = thumbnails::disk_path(&key)?;
+                match relative_to {
+                    Some(base) => paths::relative_to(&cached, Path::new(base)).ok(),
+                    None => Some(cached.to_string_lossy().into_owned()),
+                }
+            })
+            .collect()
+    }))
+}
+
+/// A '0'/'1' hash as the Python object `format` asks for
+///
+/// `int` and `bytes` let callers take Hamming distances with
+/// `(a ^ b).bit_count()` instead of comparing characters.
+fn hash_to_py(py: Python<'_>, hash: String, format: hashing::HashFormat) -> PyResult<PyObject> {
+    match format {
+        hashing::HashFormat::Str => Ok(hash.to_object(py)),
+        hashing::HashFormat::Bytes => Ok(PyBytes::new(py, &hashing::pack_bits(&hash)).to_object(py)),
+        hashing::HashFormat::Int if hash.len() <= 64 => Ok(u64::from_str_radix(&hash, 2).unwrap_or(0).to_object(py)),
+        // Wider hashes (the 256-bit fine hash) go through Python's arbitrary-size int
+        hashing::HashFormat::Int => Ok(py.get_type::<pyo3::types::PyLong>().call1((hash, 2))?.to_object(py)),
+    }
+}
+
+// Optimized hash functions
+//
+// Every hash function takes `format`: `str` (the default) returns the
+// '0'/'1' string, `int` an unsigned int with the first bit most significant
+// and `bytes` the bits packed big-endian.
+#[pyfunction]
+#[pyo3(signature = (image, format = "str"))]
+fn rust_compute_average_hash(py: Python<'_>, image: PyReadonlyArray2<u8>, format: &str) -> PyResult<PyObject> {
+    let format = hashing::HashFormat::parse(format)?;
+    let arr = image.as_array();
+    if arr.shape()[0] != 8 || arr.shape()[1] != 8 {
+        return Err(PyIOError::new_err("Image must be 8x8 for average hash"));
+    }
+    
+    hash_to_py(py, py.allow_threads(|| hashing::average_hash(arr)), format)
+}
+
+#[pyfunction]
+#[pyo3(signature = (image, format = "str"))]
+fn rust_compute_perceptual_hash(py: Python<'_>, image: PyReadonlyArray2<u8>, format: &str) -> PyResult<PyObject> {
+    let format = hashing::HashFormat::parse(format)?;
+    let arr = image.as_array();
+    if arr.shape()[0] != 32 || arr.shape()[1] != 32 {
+        return Err(PyIOError::new_err("Image must be 32x32 for perceptual hash"));
+    }
+    
+    hash_to_py(py, py.allow_threads(|| hashing::perceptual_hash(arr)), format)
+}
+
+/// DCT perceptual hash (pHash) of a grayscale image
+///
+/// Unlike `rust_compute_perceptual_hash` (a median of 8x8 block means) this
+/// is a true pHash: the image is area-averaged to 32x32, transformed with a
+/// 2D DCT, and the 8x8 lowest frequencies other than DC are thresholded on
+/// their median: imagehash's `phash` apart from the DC term, which only
+/// measures brightness and whose bit is always 0 here. Any non-empty image
+/// size is accepted; hashes are 64 bits, in `format`, compared by Hamming
+/// distance.
+#[pyfunction]
+#[pyo3(signature = (image, format = "str"))]
+fn rust_compute_dct_hash(py: Python<'_>, image: PyReadonlyArray2<u8>, format: &str) -> PyResult<PyObject> {
+    let format = hashing::HashFormat::parse(format)?;
+    let arr = image.as_array();
+    if arr.is_empty() {
+        return Err(PyValueError::new_err("Image must not be empty"));
+    }
+    
+    hash_to_py(py, py.allow_threads(|| hashing::dct_hash(hashing::area_resize(arr, 32).view())), format)
+}
+
+/// Difference hash (dHash) of a grayscale image
+///
+/// Takes 8 rows of 9 pixels for the horizontal dHash, comparing each pixel
+/// with its left neighbour as imagehash's `dhash` does, or 9 rows of 8 for
+/// the vertical one (`dhash_vertical`). Gradients survive brightness and
+/// contrast changes that move an average hash. Returns 64 bits in
+/// `format`.
+#[pyfunction]
+#[pyo3(signature = (image, format = "str"))]
+fn rust_compute_difference_hash(py: Python<'_>, image: PyReadonlyArray2<u8>, format: &str) -> PyResult<PyObject> {
+    let format = hashing::HashFormat::parse(format)?;
+    let arr = image.as_array();
+    if !matches!(arr.dim(), (8, 9) | (9, 8)) {
+        return Err(PyIOError::new_err("Image must be 8x9 or 9x8 for difference hash"));
+    }
+    
+    hash_to_py(py, py.allow_threads(|| hashing::difference_hash(arr)), format)
+}
+
+/// Haar wavelet hash (wHash) of a 64x64 grayscale image
+///
+/// Three levels of Haar decomposition reduce the image to its 8x8
+/// approximation band, which is thresholded on its median like imagehash's
+/// `whash`. Grain and high-ISO noise end up in the discarded detail bands, so
+/// scanned film and noisy previews match better than with the average hash.
+/// Returns 64 bits in `format`.
+#[pyfunction]
+#[pyo3(signature = (image, format = "str"))]
+fn rust_compute_wavelet_hash(py: Python<'_>, image: PyReadonlyArray2<u8>, format: &str) -> PyResult<PyObject> {
+    let format = hashing::HashFormat::parse(format)?;
+    let arr = image.as_array();
+    if arr.shape()[0] != 64 || arr.shape()[1] != 64 {
+        return Err(PyIOError::new_err("Image must be 64x64 for wavelet hash"));
+    }
+    
+    hash_to_py(py, py.allow_threads(|| hashing::wavelet_hash(arr)), format)
+}
+
+/// A hash in any of the formats the hash functions return
+#[derive(FromPyObject)]
+enum HashValue<'py> {
+    Str(&'py str),
+    Bytes(&'py [u8]),
+    Int(IntHash),
+}
+
+/// A non-negative int of any size, as its big-endian bytes
+struct IntHash(Vec<u8>);
+
+impl<'py> FromPyObject<'py> for IntHash {
+    fn extract(value: &'py PyAny) -> PyResult<Self> {
+        let value = value.downcast::<pyo3::types::PyLong>()?;
+        if value.lt(0)? {
+            return Err(PyValueError::new_err("Hash ints must not be negative"));
+        }
+        let bits: usize = value.call_method0("bit_length")?.extract()?;
+        let bytes: &PyBytes = value.call_method1("to_bytes", (bits.div_ceil(8), "big"))?.downcast()?;
+        Ok(IntHash(bytes.as_bytes().to_vec()))
+    }
+}
+
+impl HashValue<'_> {
+    /// Bit length, unless it is an int, which has none of its own
+    fn bits(&self) -> Option<usize> {
+        match self {
+            HashValue::Str(hash) => Some(hash.len()),
+            HashValue::Bytes(hash) => Some(hash.len() * 8),
+            HashValue::Int(_) => None,
+        }
+    }
+    
+    /// Pack the hash, widening an int to `bits` (by default the narrowest
+    /// multiple of 64 bits it fits)
+    fn pack(&self, bits: Option<usize>) -> PyResult<hamming::Packed> {
+        match self {
+            HashValue::Str(hash) => hamming::Packed::from_str(hash)
+                .ok_or_else(|| PyValueError::new_err(format!("Hash must be made of '0' and '1', got '{}'", hash))),
+            HashValue::Bytes(hash) => Ok(hamming::Packed::from_bytes(hash)),
+            HashValue::Int(IntHash(hash)) => {
+                let bits = bits.unwrap_or_else(|| hamming::Packed::int_bits(hash));
+                hamming::Packed::from_int(hash, bits)
+                    .ok_or_else(|| PyValueError::new_err(format!("Hash int does not fit in {} bits", bits)))
+            },
+        }
+    }
+    
+    /// Pack several hashes at one length: ints take that of the other
+    /// formats, or of the widest int when all are ints
+    fn pack_all(hashes: &[HashValue<'_>]) -> PyResult<Vec<hamming::Packed>> {
+        let bits = hashes.iter().find_map(HashValue::bits).or_else(|| {
+            hashes
+                .iter()
+                .filter_map(|hash| match hash {
+                    HashValue::Int(IntHash(hash)) => Some(hamming::Packed::int_bits(hash)),
+                    _ => None,
+                })
+                .max()
+        });
+        hashes.iter().map(|hash| hash.pack(bits)).collect()
+    }
+}
+
+/// Number of bits that differ between two hashes
+///
+/// Takes hashes in any `format` the hash functions return ('0'/'1' strings,
+/// ints of any size or bytes), even mixed, as long as both have the same
+/// number of bits. An int has lost its leading zero bits, so it is read at
+/// the other hash's length (or the wider int's, when both are ints).
+#[pyfunction]
+fn rust_hamming_distance(a: HashValue<'_>, b: HashValue<'_>) -> PyResult<u32> {
+    let packed = HashValue::pack_all(&[a, b])?;
+    let (a, b) = (&packed[0], &packed[1]);
+    hamming::distance(a, b).ok_or_else(|| {
+        PyValueError::new_err(format!("Hashes have different lengths ({} and {} bits)", a.bits(), b.bits()))
+    })
+}
+
+/// Pairwise Hamming distances as an N x N numpy uint8 matrix
+///
+/// `hashes` are in any `format` the hash functions return, all with the same
+/// number of bits. Rows are computed in parallel with the GIL released;
+/// distances of 256-bit hashes saturate at 255. The matrix takes N^2 bytes,
+/// so 100k hashes need 10 GB.
+#[pyfunction]
+fn rust_hamming_distance_matrix(py: Python<'_>, hashes: Vec<HashValue<'_>>) -> PyResult<PyObject> {
+    let packed = HashValue::pack_all(&hashes)?;
+    if let Some(first) = packed.first() {
+        if let Some(other) = packed.iter().find(|hash| hash.bits() != first.bits()) {
+            return Err(PyValueError::new_err(format!(
+                "Hashes have different lengths ({} and {} bits)",
+                first.bits(),
+                other.bits()
+            )));
+        }
+    }
+    
+    let distances = py.allow_threads(|| hamming::matrix(&packed));
+    Ok(PyArray2::from_owned_array(py, distances).to_object(py))
+}
+
+/// Near-duplicate lookups by hash, backed by a BK-tree
+///
+/// Hashes are in any `format` the hash functions return, all with the same
+/// number of bits as the first one added. A query only compares against the
+/// part of the tree within reach of the radius, so it stays fast on hundreds
+/// of thousands of hashes where comparing every pair does not. Created with
+/// `hash_index()`.
+#[pyclass]
+struct HashIndex {
+    tree: bktree::BkTree,
+}
+
+impl HashIndex {
+    /// Pack `hash`, checking it has the bit length of the indexed hashes
+    fn packed(&self, hash: &HashValue<'_>) -> PyResult<hamming::Packed> {
+        let packed = hash.pack(self.tree.bits())?;
+        match self.tree.bits() {
+            Some(bits) if bits != packed.bits() => Err(PyValueError::new_err(format!(
+                "Hash has {} bits, the index holds {}-bit hashes",
+                packed.bits(),
+                bits
+            ))),
+            _ => Ok(packed),
+        }
+    }
+}
+
+#[pymethods]
+impl HashIndex {
+    fn __len__(&self) -> usize {
+        self.tree.len()
+    }
+    
+    fn __contains__(&self, path: &str) -> bool {
+        self.tree.contains(path)
+    }
+    
+    /// Index `path` under `hash`, replacing the hash it had
+    fn add(&mut self, path: String, hash: HashValue<'_>) -> PyResult<()> {
+        let hash = self.packed(&hash)?;
+        self.tree.insert(path, hash);
+        Ok(())
+    }
+    
+    /// `(path, distance)` of every indexed hash within `max_distance` of
+    /// `hash`, closest first
+    fn query(&self, py: Python<'_>, hash: HashValue<'_>, max_distance: u32) -> PyResult<Vec<(String, u32)>> {
+        let hash = self.packed(&hash)?;
+        Ok(py.allow_threads(|| self.tree.query(&hash, max_distance)))
+    }
+    
+    /// Drop `path`, returning whether it was indexed
+    fn remove(&mut self, path: &str) -> bool {
+        self.tree.remove(path)
+    }
+}
+
+/// An empty `HashIndex`
+#[pyfunction]
+fn hash_index() -> HashIndex {
+    HashIndex { tree: bktree::BkTree::default() }
+}
+
+/// A path to decode or an already decoded uint8 grayscale array
+#[derive(FromPyObject)]
+enum HashInput<'py> {
+    Path(String),
+    Array(PyReadonlyArray2<'py, u8>),
+}
+
+/// Area-average `input` to the `side` x `side` grid a hash function takes
+///
+/// Paths go through the indexing pipeline (grayscale thumbnail, then area
+/// averaging), so the result hashes exactly like the index does; arrays of
+/// any shape are area-averaged directly.
+fn hash_input(py: Python<'_>, input: HashInput<'_>, side: usize) -> PyResult<PyObject> {
+    let grid = match input {
+        HashInput::Path(path) => py.allow_threads(|| {
+            priority::run(|| -> PyResult<_> {
+                let pixels = hashing_thumbnail(&path, side)?;
+                Ok(hashing::area_downsample(&pixels, THUMBNAIL_SIZE as usize, side))
+            })
+        })?,
+        HashInput::Array(image) => {
+            let arr = image.as_array();
+            if arr.is_empty() {
+                return Err(PyValueError::new_err("Image must not be empty"));
+            }
+            py.allow_threads(|| hashing::area_resize(arr, side))
+        },
+    };
+    Ok(PyArray2::from_owned_array(py, grid).to_object(py))
+}
+
+/// The `THUMBNAIL_SIZE` square grayscale thumbnail the index hashes, of any supported image or RAW file
+///
+/// `detail` is the side of the largest grid the caller reduces it to; a
+/// JPEG or HEIC EXIF thumbnail at least that large is used instead of
+/// decoding the whole image.
+fn hashing_thumbnail(path: &str, detail: usize) -> PyResult<Vec<u8>> {
+    let _deadline = deadline::ScopedDeadline::start();
+    if let Some((img, _)) = exif_stand_in(path, detail as u32) {
+        Ok(u8_thumbnail(&img))
+    } else if streaming::is_streamable(path) {
+        Ok(streaming::grayscale_thumbnail(path, THUMBNAIL_SIZE as usize, grayscale::luma_mode())?)
+    } else {
+        Ok(u8_thumbnail(&open_any_image(path)?))
+    }
+}
+
+/// The 8x8 uint8 array `rust_compute_average_hash` expects, from a path or a larger grayscale array
+#[pyfunction]
+fn rust_average_hash_input(py: Python<'_>, source: HashInput<'_>) -> PyResult<PyObject> {
+    hash_input(py, source, 8)
+}
+
+/// The 32x32 uint8 array `rust_compute_perceptual_hash` expects, from a path or a larger grayscale array
+#[pyfunction]
+fn rust_perceptual_hash_input(py: Python<'_>, source: HashInput<'_>) -> PyResult<PyObject> {
+    hash_input(py, source, 32)
+}
+
+/// Locate the subject of a square grayscale image as `(y, x, side)`
+///
+/// `mode` is `saliency` (contrast-based, biased towards the center),
+/// `center` (fixed center weighting) or `none` (whole image).
+#[pyfunction]
+#[pyo3(signature = (image, mode = "saliency"))]
+fn rust_salient_region(py: Python<'_>, image: PyReadonlyArray2<u8>, mode: &str) -> PyResult<(usize, usize, usize)> {
+    let mode = saliency::RoiMode::parse(mode)?;
+    let arr = image.as_array();
+    let side = arr.shape()[0];
+    if side != arr.shape()[1] || side < 32 {
+        return Err(PyIOError::new_err("Image must be square and at least 32x32 for region detection"));
+    }
+    
+    let pixels: Vec<u8> = arr.iter().copied().collect();
+    let roi = py.allow_threads(|| saliency::salient_region(&pixels, side, mode));
+    Ok((roi.y, roi.x, roi.side))
+}
+
+/// Perceptual hash of the salient region of a square grayscale image
+///
+/// Emphasizes the subject over the background, so the same subject cropped
+/// differently or against a slightly different background hashes alike. Only
+/// comparable with ROI hashes computed with the same `mode`.
+#[pyfunction]
+#[pyo3(signature = (image, mode = "saliency", format = "str"))]
+fn rust_compute_roi_hash(py: Python<'_>, image: PyReadonlyArray2<u8>, mode: &str, format: &str) -> PyResult<PyObject> {
+    let mode = saliency::RoiMode::parse(mode)?;
+    let format = hashing::HashFormat::parse(format)?;
+    let arr = image.as_array();
+    let side = arr.shape()[0];
+    if side != arr.shape()[1] || side < 32 {
+        return Err(PyIOError::new_err("Image must be square and at least 32x32 for ROI hash"));
+    }
+    
+    let pixels: Vec<u8> = arr.iter().copied().collect();
+    let hash = py.allow_threads(|| {
+        let roi = saliency::salient_region(&pixels, side, mode);
+        let region = saliency::crop(&pixels, side, roi);
+        hashing::perceptual_hash(hashing::area_downsample(&region, roi.side, 32).view())
+    });
+    hash_to_py(py, hash, format)
+}
+
+/// Normalize the exposure of a grayscale image with a named profile
+///
+/// `profile` is `stretch` (1st-99th percentile contrast stretch), `equalize`
+/// (histogram equalization) or `none`. Apply the same profile to every image
+/// whose hashes will be compared.
+#[pyfunction]
+#[pyo3(signature = (image, profile = "equalize"))]
+fn rust_normalize_exposure(py: Python<'_>, image: PyReadonlyArray2<u8>, profile: &str) -> PyResult<PyObject> {
+    let preprocess = exposure::Preprocess::parse(profile)?;
+    let arr = image.as_array();
+    let (height, width) = arr.dim();
+    
+    let mut pixels: Vec<u8> = arr.iter().copied().collect();
+    py.allow_threads(|| preprocess.apply(&mut pixels));
+    GrayscaleBuffer::U8(pixels).into_pyarray(py, height, width)
+}
+
+#[pyfunction]
+#[pyo3(signature = (image, format = "str"))]
+fn rust_compute_edge_hash(py: Python<'_>, image: PyReadonlyArray2<u8>, format: &str) -> PyResult<PyObject> {
+    let format = hashing::HashFormat::parse(format)?;
+    let arr = image.as_array();
+    if arr.shape()[0] < 16 || arr.shape()[1] < 16 {
+        return Err(PyIOError::new_err("Image must be at least 16x16 for edge hash"));
+    }
+    
+    hash_to_py(py, py.allow_threads(|| hashing::edge_hash(arr)), format)
+}
+
+/// Classify a grayscale image as "photo" or "document" (screenshots, scans, slides)
+#[pyfunction]
+fn rust_classify_content(py: Python<'_>, image: PyReadonlyArray2<u8>) -> PyResult<&'static str> {
+    let arr = image.as_array();
+    Ok(py.allow_threads(|| hashing::classify_content(arr)).name())
+}
+
+/// Hash a grayscale image with the algorithm suited to its content
+///
+/// Photos get the perceptual hash of a 32x32 reduction, documents the edge
+/// hash of a 128x128 reduction. Returns `(content_type, hash)`; only hashes
+/// with the same content type are comparable.
+#[pyfunction]
+#[pyo3(signature = (image, format = "str"))]
+fn rust_compute_adaptive_hash(
+    py: Python<'_>,
+    image: PyReadonlyArray2<u8>,
+    format: &str,
+) -> PyResult<(&'static str, PyObject)> {
+    let format = hashing::HashFormat::parse(format)?;
+    let arr = image.as_array();
+    let side = arr.shape()[0];
+    if side != arr.shape()[1] || side < 32 {
+        return Err(PyIOError::new_err("Image must be square and at least 32x32 for adaptive hash"));
+    }
+    
+    let pixels: Vec<u8> = arr.iter().copied().collect();
+    let (content, hash) = py.allow_threads(|| {
+        let content = hashing::classify_content(arr);
+        let hash = match content {
+            hashing::ContentType::Photo => hashing::perceptual_hash(hashing::area_downsample(&pixels, side, 32).view()),
+            hashing::ContentType::Document => {
+                hashing::edge_hash(hashing::area_downsample(&pixels, side, side.min(128)).view())
+            },
+        };
+        (content, hash)
+    });
+    Ok((content.name(), hash_to_py(py, hash, format)?))
+}
+
+/// Grayscale thumbnail of a huge TIFF/BigTIFF or PSD/PSB scan in bounded memory
+///
+/// Decodes one strip, tile or row at a time and area-averages it into a
+/// `size` x `size` grid, so multi-gigabyte archival scans never have to fit
+/// in memory. `rust_grayscale_and_hashes` and `find_similar` take this path
+/// automatically for PSD/PSB files and TIFFs over 256 MB.
+#[pyfunction]
+#[pyo3(signature = (path, size = None))]
+fn rust_stream_grayscale(py: Python<'_>, path: &str, size: Option<u32>) -> PyResult<PyObject> {
+    let size = thumbnail_size(size)?;
+    let pixels = py
+        .allow_threads(|| streaming::grayscale_thumbnail(path, size as usize, grayscale::luma_mode()))
+        .map_err(|e| PyIOError::new_err(format!("Failed to stream {}: {}", path, e)))?;
+    GrayscaleBuffer::U8(pixels).into_pyarray(py, size as usize, size as usize)
+}
+
+/// Decode once and return the grayscale thumbnail together with all hashes
+///
+/// The hashes are keyed by name (`average_hash`, `perceptual_hash`,
+/// `fine_hash`, `edge_hash`) and are computed from area-averaged 8x8 / 32x32 /
+/// 64x64 / 128x128 reductions of the thumbnail. `content_type` says which one to trust.
+/// `preprocess` is applied to the returned thumbnail before hashing.
+/// `source` describes the decode as in `rust_raw_to_grayscale(with_info=True)`.
+/// `format` (`str`, `int` or `bytes`) applies to every hash. With
+/// `sensor=True` Bayer RAW files are hashed from the raw sensor luminance
+/// without demosaicing, as in `rust_raw_to_grayscale`; those hashes only
+/// compare with other sensor hashes.
+#[pyfunction]
+#[pyo3(signature = (path, filter = "triangle", preprocess = "none", format = "str", sensor = false))]
+fn rust_grayscale_and_hashes(
+    py: Python<'_>,
+    path: &str,
+    filter: &str,
+    preprocess: &str,
+    format: &str,
+    sensor: bool,
+) -> PyResult<(PyObject, PyObject)> {
+    let filter = parse_filter(filter)?;
+    let preprocess = exposure::Preprocess::parse(preprocess)?;
+    let format = hashing::HashFormat::parse(format)?;
+    let side = THUMBNAIL_SIZE as usize;
+    
+    let (pixels, info) = py.allow_threads(|| {
+        priority::run(|| {
+            let _deadline = deadline::ScopedDeadline::start();
+            let decoded = match raw_to_grayscale_with_info(path, THUMBNAIL_SIZE, GrayscaleDtype::U8, filter, preprocess, sensor)? {
+                (GrayscaleBuffer::U8(pixels), info) => (pixels, info),
+                _ => unreachable!("uint8 thumbnail requested"),
+            };
+            if deadline::expired() {
+                return Err(PyIOError::new_err(deadline::exceeded_message()));
+            }
+            Ok(decoded)
+        })
+    })?;
+    
+    let hashes = PyDict::new(py);
+    for (name, hash) in hashing::thumbnail_hashes(&pixels, side) {
+        hashes.set_item(name, hash_to_py(py, hash, format)?)?;
+    }
+    let content = hashing::classify_content(ndarray::ArrayView2::from_shape((side, side), &pixels[..]).expect("square thumbnail"));
+    hashes.set_item("content_type", content.name())?;
+    hashes.set_item("source", decode_info_to_dict(py, &info)?)?;
+    
+    let grayscale = GrayscaleBuffer::U8(pixels).into_pyarray(py, side, side)?;
+    Ok((grayscale, hashes.to_object(py)))
+}
+
+/// Every hash type of an image or RAW file, decoded and hashed entirely in Rust
+///
+/// Returns a dict with `average_hash`, `perceptual_hash`, `fine_hash`,
+/// `edge_hash`, `difference_hash` (horizontal dHash), `dct_hash` (pHash) and
+/// `wavelet_hash` (wHash), all in `format`, computed from the same 512-pixel
+/// grayscale thumbnail the index uses, plus its `content_type`. Equivalent to
+/// decoding with `rust_raw_to_grayscale` and calling each hash function on
+/// its `*_hash_input`, without handing any array to Python. A JPEG or HEIC
+/// EXIF thumbnail of at least 128 pixels a side stands in for the decode, as
+/// it does in the `*_hash_input` functions for the hash's own grid size.
+///
+/// With `salvage=True` a truncated or corrupted JPEG that fails to decode is
+/// hashed from whatever part of it does, with the missing rows painted in
+/// its mean color, and the dict gets `salvaged`: the fraction of rows
+/// recovered, or None when the file decoded intact. Hashes of a damaged copy
+/// then stay within matching distance of the original as long as most of it
+/// survived.
+#[pyfunction]
+#[pyo3(signature = (path, format = "str", salvage = false))]
+fn rust_hash_file(py: Python<'_>, path: &str, format: &str, salvage: bool) -> PyResult<PyObject> {
+    let format = hashing::HashFormat::parse(format)?;
+    let side = THUMBNAIL_SIZE as usize;
+    let (hashes, content, salvaged) = py.allow_threads(|| {
+        priority::run(|| -> PyResult<_> {
+            let _salvage = salvage.then(salvage::ScopedSalvage::enable);
+            let pixels = hashing_thumbnail(path, hashing::LARGEST_GRID)?;
+            let content = hashing::classify_content(ndarray::ArrayView2::from_shape((side, side), &pixels[..]).expect("square thumbnail"));
+            Ok((hashing::all_hashes(&pixels, side), content, salvage::last()))
+        })
+    })?;
+    
+    let dict = PyDict::new(py);
+    for (name, hash) in hashes {
+        dict.set_item(name, hash_to_py(py, hash, format)?)?;
+    }
+    dict.set_item("content_type", content.name())?;
+    if salvage {
+        dict.set_item("salvaged", salvaged)?;
+    }
+    Ok(dict.to_object(py))
+}
+
+/// Hashes of one file from `cached_hashes`
+struct Hashed {
+    entry: hash_cache::Entry,
+    cached: bool,
+    /// Fraction of rows recovered when the file only decoded partially
+    salvaged: Option<f64>,
+}
+
+/// Hashes (and with `checksum` the content checksum) of each of `paths`, and whether they came from `cache`
+///
+/// Files whose size and mtime match their cached entry are not read; the
+/// rest are hashed in parallel and written back to `cache` in one
+/// transaction, except partial decodes from `salvage`. Failed files, and
+/// files not started before `interrupt` was set, get None.
+fn cached_hashes(
+    paths: &[String],
+    cache: Option<&mut hash_cache::HashCache>,
+    checksum: bool,
+    salvage: bool,
+    interrupt: Option<&interrupt::Interrupt>,
+) -> PyResult<Vec<Option<Hashed>>> {
+    let side = THUMBNAIL_SIZE as usize;
+    let tier = priority::current();
+    let (keys, stamps): (Vec<String>, Vec<Option<hash_cache::Stamp>>) = if cache.is_some() {
+        paths.par_iter().map(|path| (paths::identity(path), hash_cache::stamp(path))).unzip()
+    } else {
+        paths.iter().map(|_| (String::new(), Some(hash_cache::Stamp::default()))).unzip()
+    };
+    // Lookups stay on this thread; the connection is not shared with the workers
+    let mut cached: Vec<Option<hash_cache::Entry>> = match &cache {
+        Some(cache) => keys
+            .iter()
+            .zip(&stamps)
+            .map(|(key, stamp)| match stamp {
+                Some(stamp) => cache.lookup(key, *stamp),
+                None => Ok(None),
+            })
+            .collect::<std::io::Result<_>>()
+            .map_err(index_error)?,
+        None => paths.iter().map(|_| None).collect(),
+    };
+    
+    let computed: Vec<Option<(hash_cache::Entry, Option<f64>)>> = paths
+        .par_iter()
+        .zip(&stamps)
+        .zip(&cached)
+        .map(|((path, stamp), cached)| {
+            let stamp = (*stamp)?;
+            if cached.is_some() || interrupt.is_some_and(|interrupt| interrupt.is_set()) {
+                return None;
+            }
+            let _turn = priority::Turn::wait(tier);
+            let _salvage = salvage.then(salvage::ScopedSalvage::enable);
+            let pixels = hashing_thumbnail(path, hashing::LARGEST_GRID).ok()?;
+            let view = ndarray::ArrayView2::from_shape((side, side), &pixels[..]).expect("square thumbnail");
+            let content_type = hashing::classify_content(view).name().to_string();
+            let content_hash = if checksum { checksum::blake3_file(path).ok()? } else { String::new() };
+            let hashes = hashing::all_hashes(&pixels, side).into_iter().map(|(name, hash)| (name.to_string(), hash)).collect();
+            Some((hash_cache::Entry { stamp, content_hash, content_type, hashes }, salvage::last()))
+        })
+        .collect();
+    
+    if let Some(cache) = cache {
+        let fresh = keys.iter().zip(&computed).filter_map(|(key, computed)| match computed {
+            Some((entry, None)) => Some((key.as_str(), entry)),
+            _ => None,
+        });
+        cache.store(fresh).map_err(index_error)?;
+    }
+    Ok(computed
+        .into_iter()
+        .zip(cached.iter_mut())
+        .map(|(computed, cached)| match computed {
+            Some((entry, salvaged)) => Some(Hashed { entry, cached: false, salvaged }),
+            None => cached.take().map(|entry| Hashed { entry, cached: true, salvaged: None }),
+        })
+        .collect())
+}
+
+/// `rust_hash_file` of many files in parallel; failed files get `None`
+///
+/// With a `HashCache`, files whose size and mtime match their cached entry
+/// are answered from it without being read; the rest are hashed and
+/// checksummed and their entries written back in one transaction. Each dict
+/// then also has the file's `content_hash` and whether it was `cached`. The
+/// cache does not track decode settings, so `clear()` it after changing e.g.
+/// the luma mode or the preview restriction. `salvage` is as for
+/// `rust_hash_file`; salvaged files are not cached.
+#[pyfunction]
+#[pyo3(signature = (paths, cache = None, format = "str", salvage = false))]
+fn rust_hash_files(
+    py: Python<'_>,
+    paths: Vec<String>,
+    mut cache: Option<PyRefMut<'_, HashCache>>,
+    format: &str,
+    salvage: bool,
+) -> PyResult<Vec<Option<PyObject>>> {
+    let format = hashing::HashFormat::parse(format)?;
+    let with_cache = cache.is_some();
+    let cache = cache.as_deref_mut().map(|cache| &mut cache.cache);
+    let results = py.allow_threads(|| cached_hashes(&paths, cache, with_cache, salvage, None))?;
+    
+    results
+        .into_iter()
+        .map(|result| {
+            let Some(hashed) = result else {
+                return Ok(None);
+            };
+            let dict = cache_entry_to_dict(py, hashed.entry, format)?;
+            if with_cache {
+                dict.set_item("cached", hashed.cached)?;
+            } else {
+                dict.del_item("content_hash")?;
+            }
+            if salvage {
+                dict.set_item("salvaged", hashed.salvaged)?;
+            }
+            Ok(Some(dict.to_object(py)))
+        })
+        .collect()
+}
+
+fn cache_entry_to_dict(py: Python<'_>, entry: hash_cache::Entry, format: hashing::HashFormat) -> PyResult<&PyDict> {
+    let dict = PyDict::new(py);
+    for (name, hash) in entry.hashes {
+        dict.set_item(name, hash_to_py(py, hash, format)?)?;
+    }
+    dict.set_item("content_type", entry.content_type)?;
+    dict.set_item("content_hash", entry.content_hash)?;
+    Ok(dict)
+}
+
+/// Hashes and content checksums of files, kept in an SQLite file across runs
+///
+/// Entries are keyed by path and only used while the file's size and mtime
+/// match, so a rescan rehashes just what changed. Pass it as `cache` to
+/// `rust_hash_files` or `rust_checksum_files`.
+#[pyclass]
+struct HashCache {
+    cache: hash_cache::HashCache,
+}
+
+#[pymethods]
+impl HashCache {
+    fn __len__(&self) -> PyResult<usize> {
+        self.cache.len().map_err(index_error)
+    }
+    
+    /// The cached hashes of `path` as `rust_hash_files` returns them, or None if missing or out of date
+    #[pyo3(signature = (path, format = "str"))]
+    fn get(&self, py: Python<'_>, path: &str, format: &str) -> PyResult<Option<PyObject>> {
+        let format = hashing::HashFormat::parse(format)?;
+        let Some(stamp) = hash_cache::stamp(path) else {
+            return Ok(None);
+        };
+        match self.cache.lookup(&paths::identity(path), stamp).map_err(index_error)? {
+            Some(entry) => Ok(Some(cache_entry_to_dict(py, entry, format)?.to_object(py))),
+            None => Ok(None),
+        }
+    }
+    
+    /// Drop entries of files that were deleted or changed, returning how many
+    fn prune(&mut self, py: Python<'_>) -> PyResult<usize> {
+        let cache = &mut self.cache;
+        py.allow_threads(|| cache.prune()).map_err(index_error)
+    }
+    
+    /// Drop every entry
+    fn clear(&mut self) -> PyResult<()> {
+        self.cache.clear().map_err(index_error)
+    }
+}
+
+/// Open (creating if needed) the `HashCache` stored at `location`
+#[pyfunction]
+fn open_hash_cache(py: Python<'_>, location: &str) -> PyResult<HashCache> {
+    let cache = py.allow_threads(|| hash_cache::HashCache::open(location)).map_err(index_error)?;
+    Ok(HashCache { cache })
+}
+
+/// Hash names `find_duplicate_groups` accepts and the `all_hashes` entry each selects
+const GROUPING_HASHES: [(&str, &str); 11] = [
+    ("ahash", "average_hash"),
+    ("phash", "dct_hash"),
+    ("dhash", "difference_hash"),
+    ("whash", "wavelet_hash"),
+    ("average", "average_hash"),
+    ("perceptual", "perceptual_hash"),
+    ("fine", "fine_hash"),
+    ("edge", "edge_hash"),
+    ("difference", "difference_hash"),
+    ("dct", "dct_hash"),
+    ("wavelet", "wavelet_hash"),
+];
+
+/// Groups of near-duplicate images among `paths`, as lists of paths
+///
+/// Hashes every file in parallel (through `cache` when given, like
+/// `rust_hash_files`), indexes the hashes in a BK-tree and joins each file
+/// with every other whose hash is within `max_distance` (by default the
+/// configured `thresholds.duplicate_distance`, 5). Groups are
+/// transitive (single linkage) and hold two or more paths in input order;
+/// groups are ordered by their first path's position. `hash_type` is
+/// `phash` (the DCT hash), `ahash`, `dhash`, `whash`, or one of the index's
+/// own `average`, `perceptual`, `fine` or `edge`. Files that fail to decode
+/// are left out. With `collapse_raw_pairs` (the default) a RAW file and the
+/// JPEG the camera wrote beside it (see `rust_group_raw_pairs`) count as one
+/// image, reported by the RAW path: they are never a group of their own, and
+/// either one matching a third file puts the RAW file in its group.
+///
+/// Returns `(groups, interrupted)`. Ctrl-C (KeyboardInterrupt) stops hashing
+/// after the files in progress, keeps those in `cache` and returns the
+/// groups among the files hashed so far with `interrupted` True.
+#[pyfunction]
+#[pyo3(signature = (paths, max_distance = tuning::thresholds().duplicate, hash_type = "phash", cache = None, collapse_raw_pairs = true))]
+fn find_duplicate_groups(
+    py: Python<'_>,
+    paths: Vec<String>,
+    max_distance: u32,
+    hash_type: &str,
+    mut cache: Option<PyRefMut<'_, HashCache>>,
+    collapse_raw_pairs: bool,
+) -> PyResult<(Vec<Vec<String>>, bool)> {
+    let Some(&(_, hash_name)) = GROUPING_HASHES.iter().find(|(name, _)| *name == hash_type) else {
+        return Err(PyValueError::new_err(format!(
+            "Unsupported hash type '{}', expected one of {:?}",
+            hash_type,
+            GROUPING_HASHES.iter().map(|(name, _)| *name).collect::<Vec<_>>()
+        )));
+    };
+    let cache = cache.as_deref_mut().map(|cache| &mut cache.cache);
+    
+    let (groups, interrupted) = interrupt::run(py, |interrupt| -> PyResult<Vec<Vec<usize>>> {
+        let hashed = cached_hashes(&paths, cache, false, false, Some(interrupt))?;
+        let hashes: Vec<Option<hamming::Packed>> = hashed
+            .into_iter()
+            .map(|hashed| {
+                let hashed = hashed?;
+                let (_, hash) = hashed.entry.hashes.iter().find(|(name, _)| name == hash_name)?;
+                hamming::Packed::from_str(hash)
+            })
+            .collect();
+        
+        // Tree keys are positions in `paths`, so repeated paths stay apart
+        let mut tree = bktree::BkTree::default();
+        for (i, hash) in hashes.iter().enumerate() {
+            if let Some(hash) = hash {
+                tree.insert(i.to_string(), hash.clone());
+            }
+        }
+        let edges: Vec<(usize, usize)> = hashes
+            .par_iter()
+            .enumerate()
+            .filter_map(|(i, hash)| Some((i, hash.as_ref()?)))
+            .flat_map_iter(|(i, hash)| {
+                tree.query(hash, max_distance)
+                    .into_iter()
+                    .filter_map(move |(key, _)| key.parse::<usize>().ok().filter(|&j| j > i).map(|j| (i, j)))
+            })
+            .collect();
+        
+        // Siblings' edges move to their RAW file, so a pair never joins itself
+        let mut representative: Vec<usize> = (0..paths.len()).collect();
+        if collapse_raw_pairs {
+            let positions: std::collections::HashMap<&str, Vec<usize>> =
+                paths.iter().enumerate().fold(std::collections::HashMap::new(), |mut positions, (i, path)| {
+                    positions.entry(path.as_str()).or_insert_with(Vec::new).push(i);
+                    positions
+                });
+            for capture in raw_pairs::group(&paths, raw_pairs::MAX_GAP) {
+                let raw = positions[capture.raw.as_str()][0];
+                for member in capture.paths() {
+                    for &i in &positions[member.as_str()] {
+                        representative[i] = raw;
+                    }
+                }
+            }
+        }
+        let edges = edges
+            .into_iter()
+            .map(|(i, j)| (representative[i], representative[j]))
+            .filter(|(i, j)| i != j);
+        Ok(sweep::components(paths.len(), edges))
+    })?;
+    
+    let groups = groups?
+        .into_iter()
+        .map(|group| group.into_iter().map(|i| paths[i].clone()).collect())
+        .collect();
+    Ok((groups, interrupted))
+}
+
+fn match_profile(name: &str) -> PyResult<&'static matching::MatchProfile> {
+    matching::lookup(name).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "Unknown matching profile '{}', expected one of {:?}",
+            name,
+            matching::profile_names()
+        ))
+    })
+}
+
+/// Names of the built-in matching profiles (`strict`, `default`, `edited`, `derivative`, `watermarked`)
+///
+/// `watermarked` cuts the border strips and blacks out the corners of both
+/// images before hashing, so exports stamped with a watermark, logo or date
+/// still match their clean originals; the regions come from the active scan
+/// profile (see `set_watermark_mask`).
+#[pyfunction]
+fn get_matching_profiles() -> Vec<&'static str> {
+    matching::profile_names()
+}
+
+/// The pipeline's uint8 grayscale thumbnail of a decoded image
+fn u8_thumbnail(img: &DynamicImage) -> Vec<u8> {
+    let filter = imageops::FilterType::Triangle;
+    match grayscale_thumbnail(img, THUMBNAIL_SIZE, GrayscaleDtype::U8, filter, grayscale::luma_mode()) {
+        GrayscaleBuffer::U8(pixels) => pixels,
+        _ => unreachable!("uint8 thumbnail requested"),
+    }
+}
+
+/// Compare two images with multi-hash voting under a named matching profile
+///
+/// Returns a dict with `matched`, the number of agreeing hashes in `votes` and
+/// the Hamming `distances` per hash (`average`, `perceptual`, `edge`). When
+/// the active scan profile confirms matches (`archival`), `ssim` holds the
+/// structural similarity of the thumbnails and a hash match below its
+/// threshold is not `matched`; otherwise `ssim` is None.
+#[pyfunction]
+#[pyo3(signature = (path_a, path_b, profile = "default"))]
+fn rust_match_images(py: Python<'_>, path_a: &str, path_b: &str, profile: &str) -> PyResult<PyObject> {
+    let profile = match_profile(profile)?;
+    let min_ssim = profiles::active().min_ssim;
+    let fingerprint = |path: &str| -> PyResult<(matching::Fingerprint, Option<Vec<u8>>)> {
+        let img = open_any_image(path)?;
+        Ok((matching::fingerprint(&img, profile), (min_ssim > 0.0).then(|| u8_thumbnail(&img))))
+    };
+    let (a, b) = py.allow_threads(|| priority::run(|| rayon::join(|| fingerprint(path_a), || fingerprint(path_b))));
+    let ((print_a, thumbnail_a), (print_b, thumbnail_b)) = (a?, b?);
+    let mut result = matching::compare(&print_a, &print_b, profile);
+    
+    let ssim = thumbnail_a
+        .zip(thumbnail_b)
+        .map(|(a, b)| explain::ssim(&a, &b, THUMBNAIL_SIZE as usize));
+    if let Some(ssim) = ssim {
+        result.matched &= ssim >= min_ssim;
+    }
+    
+    let distances = PyDict::new(py);
+    distances.set_item("average", result.distances[0])?;
+    distances.set_item("perceptual", result.distances[1])?;
+    distances.set_item("edge", result.distances[2])?;
+    
+    let report = PyDict::new(py);
+    report.set_item("matched", result.matched)?;
+    report.set_item("votes", result.votes)?;
+    report.set_item("distances", distances)?;
+    report.set_item("ssim", ssim)?;
+    Ok(report.to_object(py))
+}
+
+/// Every signal computed for one pair, with the verdict and the reasons for it
+///
+/// The verdict is exactly `rust_match_images` under `profile`; next to it come
+/// the voting hash distances and their limits, the distances between the
+/// index hashes (`average_hash`, `perceptual_hash`, `fine_hash`, `edge_hash`),
+/// SSIM and luminance histogram distance of the thumbnails, dimensions with
+/// aspect and area ratios, and the EXIF make, camera, date and orientation of
+/// both files with whether they agree. `reasons` spells out why the verdict
+/// came out as it did and flags signals that contradict it.
+#[pyfunction]
+#[pyo3(signature = (path_a, path_b, profile = "default"))]
+fn explain_match(py: Python<'_>, path_a: &str, path_b: &str, profile: &str) -> PyResult<PyObject> {
+    let profile = match_profile(profile)?;
+    let explanation = py.allow_threads(|| priority::run(|| -> PyResult<explain::Explanation> {
+        let (a, b) = rayon::join(|| open_any_image(path_a), || open_any_image(path_b));
+        let (a, b) = (a?, b?);
+        let (thumbnail_a, thumbnail_b) = (u8_thumbnail(&a), u8_thumbnail(&b));
+        let exif = explain::exif_fields(
+            naming::read_exif(path_a),
+            naming::read_exif(path_b),
+            [orientation::read(path_a), orientation::read(path_b)],
+        );
+        let min_ssim = profiles::active().min_ssim;
+        Ok(explain::explain([&a, &b], [&thumbnail_a, &thumbnail_b], THUMBNAIL_SIZE as usize, exif, profile, min_ssim))
+    }))?;
+    
+    let result = &explanation.result;
+    let distances = PyDict::new(py);
+    for (i, name) in explain::VOTING_HASHES.iter().enumerate() {
+        let entry = PyDict::new(py);
+        entry.set_item("distance", result.distances[i])?;
+        entry.set_item("max", profile.max_distances[i])?;
+        entry.set_item("agrees", result.distances[i] <= profile.max_distances[i])?;
+        distances.set_item(name, entry)?;
+    }
+    let hash_distances = PyDict::new(py);
+    for (name, distance) in &explanation.hash_distances {
+        hash_distances.set_item(name, distance)?;
+    }
+    let dimensions = PyDict::new(py);
+    dimensions.set_item("a", explanation.dimensions[0])?;
+    dimensions.set_item("b", explanation.dimensions[1])?;
+    dimensions.set_item("aspect_ratio", explanation.aspect_ratio)?;
+    dimensions.set_item("area_ratio", explanation.area_ratio)?;
+    let exif = PyDict::new(py);
+    for field in &explanation.exif {
+        let entry = PyDict::new(py);
+        entry.set_item("a", &field.a)?;
+        entry.set_item("b", &field.b)?;
+        entry.set_item("agrees", field.agrees())?;
+        exif.set_item(field.name, entry)?;
+    }
+    
+    let report = PyDict::new(py);
+    report.set_item("verdict", if result.matched { "duplicate" } else { "different" })?;
+    report.set_item("matched", result.matched)?;
+    report.set_item("profile", profile.name)?;
+    report.set_item("votes", result.votes)?;
+    report.set_item("min_votes", profile.min_votes)?;
+    report.set_item("distances", distances)?;
+    report.set_item("hash_distances", hash_distances)?;
+    report.set_item("ssim", explanation.ssim)?;
+    report.set_item("histogram_distance", explanation.histogram_distance)?;
+    report.set_item("dimensions", dimensions)?;
+    report.set_item("exif", exif)?;
+    report.set_item("reasons", &explanation.reasons)?;
+    Ok(report.to_object(py))
+}
+
+/// Find which originals (e.g. RAWs) already have a derivative (e.g. exported edit)
+///
+/// Every original is compared against every derivative under `profile`
+/// (default `edited`, tolerant to crop, exposure and color grading). Returns
+/// `(original, derivative, votes)` for each match; undecodable files are skipped.
+#[pyfunction]
+#[pyo3(signature = (originals, derivatives, profile = "edited"))]
+fn rust_find_edited_matches(
+    py: Python<'_>,
+    originals: Vec<String>,
+    derivatives: Vec<String>,
+    profile: &str,
+) -> PyResult<Vec<(String, String, usize)>> {
+    let profile = match_profile(profile)?;
+    
+    Ok(py.allow_threads(|| {
+        let fingerprint_all = |paths: &[String]| -> Vec<Option<matching::Fingerprint>> {
+            paths
+                .par_iter()
+                .map(|path| open_any_image(path).ok().map(|img| matching::fingerprint(&img, profile)))
+                .collect()
+        };
+        let original_prints = fingerprint_all(&originals);
+        let derivative_prints = fingerprint_all(&derivatives);
+        
+        originals
+            .par_iter()
+            .zip(original_prints.par_iter())
+            .flat_map_iter(|(original, a)| {
+                derivatives
+                    .iter()
+                    .zip(derivative_prints.iter())
+                    .filter_map(move |(derivative, b)| {
+                        let result = matching::compare(a.as_ref()?, b.as_ref()?, profile);
+                        result.matched.then(|| (original.clone(), derivative.clone(), result.votes))
+                    })
+            })
+            .collect()
+    }))
+}
+
+/// Group RAW files with the JPEGs (or other images) made from them
+///
+/// Each non-RAW path is paired with the RAW file it most likely comes from:
+/// `preview` when it is the RAW file's embedded preview saved as a file
+/// (matched under `strict` against the previews read from the RAW file), or
+/// `derivative` when it matches the decoded RAW under `profile` (default
+/// `derivative`: loose tolerances plus crops to common export shapes, for
+/// exports developed, resized or recropped elsewhere). These are kept apart
+/// from exact duplicates: both files are worth keeping. Returns one dict per
+/// RAW file with derived images: `original` and `members`, each with `path`,
+/// `relationship`, `votes` and the hash `distances`. Undecodable files are
+/// left out.
+#[pyfunction]
+#[pyo3(signature = (paths, profile = "derivative"))]
+fn rust_group_derivatives(py: Python<'_>, paths: Vec<String>, profile: &str) -> PyResult<Vec<PyObject>> {
+    let profile = match_profile(profile)?;
+    let strict = match_profile("strict")?;
+    let (raws, others): (Vec<String>, Vec<String>) =
+        paths.into_iter().partition(|path| formats::is_raw(&formats::extension(path)));
+    
+    let links = py.allow_threads(|| {
+        let originals: Vec<Option<derivatives::Original>> = raws
+            .par_iter()
+            .map(|path| {
+                let img = open_any_image(path).ok()?;
+                let previews = previews::native_previews(path)
+                    .iter()
+                    .filter_map(|preview| image::load_from_memory(&preview.data).ok())
+                    .map(|preview| matching::fingerprint(&preview, strict))
+                    .collect();
+                Some(derivatives::Original { fingerprint: matching::fingerprint(&img, profile), previews })
+            })
+            .collect();
+        let candidates: Vec<Option<derivatives::Candidate>> = others
+            .par_iter()
+            .map(|path| {
+                let img = open_any_image(path).ok()?;
+                Some(derivatives::Candidate {
+                    fingerprint: matching::fingerprint(&img, profile),
+                    strict: matching::fingerprint(&img, strict),
+                })
+            })
+            .collect();
+        derivatives::link(&originals, &candidates, profile)
+    });
+    
+    let mut members: Vec<Vec<PyObject>> = raws.iter().map(|_| Vec::new()).collect();
+    for (path, link) in others.iter().zip(links) {
+        let Some(link) = link else { continue };
+        let member = PyDict::new(py);
+        member.set_item("path", path)?;
+        member.set_item("relationship", link.relationship.name())?;
+        member.set_item("votes", link.result.votes)?;
+        let distances = PyDict::new(py);
+        distances.set_item("average", link.result.distances[0])?;
+        distances.set_item("perceptual", link.result.distances[1])?;
+        distances.set_item("edge", link.result.distances[2])?;
+        member.set_item("distances", distances)?;
+        members[link.original].push(member.to_object(py));
+    }
+    raws.iter()
+        .zip(members)
+        .filter(|(_, members)| !members.is_empty())
+        .map(|(original, members)| {
+            let group = PyDict::new(py);
+            group.set_item("original", original)?;
+            group.set_item("members", members)?;
+            Ok(group.to_object(py))
+        })
+        .collect()
+}
+
+/// Report the EXIF make/model of a RAW file and the camera profile that applies
+///
+/// Returns None when the make/model cannot be read.
+#[pyfunction]
+fn rust_camera_profile(py: Python<'_>, path: &str) -> PyResult<Option<PyObject>> {
+    let Some((make, model)) = py.allow_threads(|| camera_profiles::read_make_model(path)) else {
+        return Ok(None);
+    };
+    
+    let info = PyDict::new(py);
+    info.set_item("make", &make)?;
+    info.set_item("model", &model)?;
+    if let Some(profile) = camera_profiles::lookup(&make, &model) {
+        info.set_item("preview_tags", profile.preview_tags.to_vec())?;
+        info.set_item("typical_preview_bytes", profile.typical_preview_bytes)?;
+        let quirks: Vec<&str> = profile
+            .quirks
+            .iter()
+            .map(|q| match q {
+                camera_profiles::Quirk::PreviewTooSmall => "preview_too_small",
+                camera_profiles::Quirk::PreferLibraw => "prefer_libraw",
+                camera_profiles::Quirk::PreferRawloader => "prefer_rawloader",
+            })
+            .collect();
+        info.set_item("quirks", quirks)?;
+    }
+    Ok(Some(info.to_object(py)))
+}
+
+/// Report how `path` would be decoded, without decoding it
+///
+/// Returns a dict with the `container` detected from the file's magic bytes,
+/// the `extension` it is routed by (its own or a registered handler), whether
+/// it is `raw`, the camera `make` and `model`, its `dimensions`, the embedded
+/// `previews` (`source`, `bytes`, `width`, `height`), whether a `pure_rust`
+/// decode is possible and by which `native_decoder` (`image`, `streaming` or
+/// `rawloader`), the external `tools` the conversion chain would try (`name`,
+/// `available` on PATH) and `warnings` about files that will be slow or
+/// unsupported. `exiftool=True` also lists previews only exiftool can reach.
+#[pyfunction]
+#[pyo3(signature = (path, exiftool = false))]
+fn probe(py: Python<'_>, path: &str, exiftool: bool) -> PyResult<PyObject> {
+    let report = py
+        .allow_threads(|| support::probe(path, exiftool))
+        .map_err(|e| PyIOError::new_err(format!("Failed to probe {}: {}", path, e)))?;
+    
+    let previews: Vec<PyObject> = report
+        .previews
+        .iter()
+        .map(|preview| {
+            let dict = PyDict::new(py);
+            dict.set_item("source", &preview.source)?;
+            dict.set_item("bytes", preview.bytes)?;
+            dict.set_item("width", preview.dimensions.map(|(w, _)| w))?;
+            dict.set_item("height", preview.dimensions.map(|(_, h)| h))?;
+            Ok(dict.to_object(py))
+        })
+        .collect::<PyResult<_>>()?;
+    let tools: Vec<PyObject> = report
+        .tools
+        .iter()
+        .map(|(name, available)| {
+            let dict = PyDict::new(py);
+            dict.set_item("name", name)?;
+            dict.set_item("available", available)?;
+            Ok(dict.to_object(py))
+        })
+        .collect::<PyResult<_>>()?;
+    
+    let dict = PyDict::new(py);
+    dict.set_item("path", path)?;
+    dict.set_item("container", report.container)?;
+    dict.set_item("extension", &report.extension)?;
+    dict.set_item("raw", report.raw)?;
+    dict.set_item("make", &report.make)?;
+    dict.set_item("model", &report.model)?;
+    dict.set_item("dimensions", report.dimensions)?;
+    dict.set_item("previews", previews)?;
+    dict.set_item("pure_rust", report.native_decoder.is_some())?;
+    dict.set_item("native_decoder", report.native_decoder)?;
+    dict.set_item("tools", tools)?;
+    dict.set_item("warnings", &report.warnings)?;
+    Ok(dict.to_object(py))
+}
+
+/// Dump every embedded preview/thumbnail of `path` into `out_dir`
+///
+/// Returns one dict per distinct preview with the written `path`, the `source`
+/// it came from (e.g. `IFD1:JPEGInterchangeFormat`, `exiftool:PreviewImage`),
+/// its `width`/`height` (0 if undecodable) and size in `bytes`.
+#[pyfunction]
+fn extract_previews(py: Python<'_>, path: &str, out_dir: &str) -> PyResult<Vec<PyObject>> {
+    let found = py.allow_threads(|| previews::all_previews(path));
+    std::fs::create_dir_all(out_dir)
+        .map_err(|e| PyIOError::new_err(format!("Failed to create {}: {}", out_dir, e)))?;
+    
+    let stem = Path::new(path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("preview");
+    
+    found
+        .into_iter()
+        .enumerate()
+        .map(|(i, preview)| {
+            let reader = image::io::Reader::new(std::io::Cursor::new(&preview.data)).with_guessed_format();
+            let format = reader.as_ref().ok().and_then(|r| r.format());
+            let (width, height) = reader
+                .ok()
+                .and_then(|r| r.into_dimensions().ok())
+                .unwrap_or((0, 0));
+            let ext = match format {
+                Some(image::ImageFormat::Tiff) => "tif",
+                _ => "jpg",
+            };
+            
+            let tag: String = preview
+                .source
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect();
+            let out_path = Path::new(out_dir).join(format!("{}_{}_{}.{}", stem, i, tag, ext));
+            std::fs::write(&out_path, &preview.data)
+                .map_err(|e| PyIOError::new_err(format!("Failed to write {}: {}", out_path.display(), e)))?;
+            
+            let info = PyDict::new(py);
+            info.set_item("path", out_path.to_string_lossy().into_owned())?;
+            info.set_item("source", &preview.source)?;
+            info.set_item("width", width)?;
+            info.set_item("height", height)?;
+            info.set_item("bytes", preview.data.len())?;
+            Ok(info.to_object(py))
+        })
+        .collect()
+}
+
+/// BLAKE3 checksum (hex) of a file's contents, for recording alongside its hashes
+#[pyfunction]
+fn rust_checksum_file(py: Python<'_>, path: &str) -> PyResult<String> {
+    py.allow_threads(|| checksum::blake3_file(path))
+        .map_err(|e| PyIOError::new_err(format!("Failed to checksum {}: {}", path, e)))
+}
+
+/// Checksum many files in parallel; failed files get `None`
+///
+/// With a `HashCache`, unchanged files get the checksum cached by
+/// `rust_hash_files` instead of being read again.
+#[pyfunction]
+#[pyo3(signature = (paths, cache = None))]
+fn rust_checksum_files(
+    py: Python<'_>,
+    paths: Vec<String>,
+    mut cache: Option<PyRefMut<'_, HashCache>>,
+) -> PyResult<Vec<Option<String>>> {
+    let tier = priority::current();
+    // Mutable only so the connection can move to the released thread
+    let cache = cache.as_deref_mut().map(|cache| &mut cache.cache);
+    py.allow_threads(|| {
+        let cached: Vec<Option<String>> = match cache {
+            Some(cache) => {
+                let stamps: Vec<_> = paths.par_iter().map(|path| (paths::identity(path), hash_cache::stamp(path))).collect();
+                stamps
+                    .into_iter()
+                    .map(|(key, stamp)| match stamp {
+                        Some(stamp) => Ok(cache.lookup(&key, stamp)?.map(|entry| entry.content_hash)),
+                        None => Ok(None),
+                    })
+                    .collect::<std::io::Result<_>>()
+                    .map_err(index_error)?
+            },
+            None => paths.iter().map(|_| None).collect(),
+        };
+        Ok(paths
+            .par_iter()
+            .zip(cached)
+            .map(|(path, cached)| {
+                cached.or_else(|| {
+                    let _turn = priority::Turn::wait(tier);
+                    checksum::blake3_file(path).ok()
+                })
+            })
+            .collect())
+    })
+}
+
+/// Hex digest of a file's contents, for exact-duplicate detection
+///
+/// `algorithm` is `blake3` (the digest `rust_checksum_file` returns) or
+/// `xxh3` (128-bit, not cryptographic, faster). With `mapped=True` the file
+/// is read through a memory map and BLAKE3 hashes it on several threads,
+/// which is faster for large files, but a file another process truncates
+/// meanwhile crashes the interpreter; only map files nothing writes to.
+#[pyfunction]
+#[pyo3(signature = (path, algorithm = "blake3", mapped = false))]
+fn rust_content_hash(py: Python<'_>, path: &str, algorithm: &str, mapped: bool) -> PyResult<String> {
+    let algorithm = checksum::Algorithm::parse(algorithm)?;
+    py.allow_threads(|| checksum::content_hash(path, algorithm, mapped))
+        .map_err(|e| PyIOError::new_err(format!("Failed to hash {}: {}", path, e)))
+}
+
+/// `rust_content_hash` of many files in parallel; failed files get `None`
+#[pyfunction]
+#[pyo3(signature = (paths, algorithm = "blake3", mapped = false))]
+fn rust_content_hashes(py: Python<'_>, paths: Vec<String>, algorithm: &str, mapped: bool) -> PyResult<Vec<Option<String>>> {
+    let algorithm = checksum::Algorithm::parse(algorithm)?;
+    let tier = priority::current();
+    Ok(py.allow_threads(|| {
+        paths
+            .par_iter()
+            .map(|path| {
+                let _turn = priority::Turn::wait(tier);
+                checksum::content_hash(path, algorithm, mapped).ok()
+            })
+            .collect()
+    }))
+}
+
+/// Groups of byte-identical files among `paths`, to drop before perceptual matching
+///
+/// Only files that share their size with another are read, and those are
+/// hashed with `rust_content_hash` (`mapped` as there). Returns the groups
+/// of two or more paths, each sorted, ordered by their first path;
+/// unreadable files are left out.
+#[pyfunction]
+#[pyo3(signature = (paths, algorithm = "blake3", mapped = false))]
+fn find_exact_duplicates(py: Python<'_>, paths: Vec<String>, algorithm: &str, mapped: bool) -> PyResult<Vec<Vec<String>>> {
+    let algorithm = checksum::Algorithm::parse(algorithm)?;
+    let tier = priority::current();
+    Ok(py.allow_threads(|| {
+        let sizes: Vec<Option<u64>> = paths
+            .par_iter()
+            .map(|path| {
+                throttle::acquire(0, 1);
+                std::fs::metadata(path).ok().filter(|m| m.is_file()).map(|m| m.len())
+            })
+            .collect();
+        let mut by_size: std::collections::HashMap<u64, Vec<&String>> = std::collections::HashMap::new();
+        for (path, size) in paths.iter().zip(sizes) {
+            if let Some(size) = size {
+                by_size.entry(size).or_default().push(path);
+            }
+        }
+        
+        let candidates: Vec<(u64, &String)> = by_size
+            .into_iter()
+            .filter(|(_, paths)| paths.len() > 1)
+            .flat_map(|(size, paths)| paths.into_iter().map(move |path| (size, path)))
+            .collect();
+        let digests: Vec<Option<(u64, String)>> = candidates
+            .par_iter()
+            .map(|&(size, path)| {
+                let _turn = priority::Turn::wait(tier);
+                Some((size, checksum::content_hash(path, algorithm, mapped).ok()?))
+            })
+            .collect();
+        
+        let mut groups: std::collections::HashMap<(u64, String), Vec<String>> = std::collections::HashMap::new();
+        for ((_, path), digest) in candidates.into_iter().zip(digests) {
+            if let Some(key) = digest {
+                groups.entry(key).or_default().push(path.clone());
+            }
+        }
+        let mut groups: Vec<Vec<String>> = groups.into_values().filter(|group| group.len() > 1).collect();
+        groups.iter_mut().for_each(|group| group.sort());
+        groups.sort();
+        groups
+    }))
+}
+
+/// Re-checksum previously recorded files and report silent corruption
+///
+/// Takes `(path, checksum)` pairs as stored at scan time and returns one dict
+/// per entry with `path`, `status` (`ok`, `mismatch`, `missing` or `error`)
+/// and, when available, the `actual` checksum or the `error` message.
+#[pyfunction]
+fn rust_verify_checksums(py: Python<'_>, entries: Vec<(String, String)>) -> PyResult<Vec<PyObject>> {
+    let results: Vec<checksum::Verification> = py.allow_threads(|| {
+        entries
+            .par_iter()
+            .map(|(path, expected)| checksum::verify(path, expected))
+            .collect()
+    });
+    
+    entries
+        .iter()
+        .zip(results)
+        .map(|((path, _), result)| {
+            let report = PyDict::new(py);
+            report.set_item("path", path)?;
+            report.set_item("status", result.status())?;
+            match result {
+                checksum::Verification::Mismatch(actual) => report.set_item("actual", actual)?,
+                checksum::Verification::Error(message) => report.set_item("error", message)?,
+                _ => {},
+            }
+            Ok(report.to_object(py))
+        })
+        .collect()
+}
+
+/// Group bracketed HDR / focus-stack sequences among `paths`
+///
+/// Returns one dict per stack with its `kind` (`hdr` or `focus`) and the
+/// `paths` of its frames in capture order. Frames are grouped per camera when
+/// taken at most `max_gap_seconds` apart; bursts without bracketing are not stacks.
+#[pyfunction]
+#[pyo3(signature = (paths, max_gap_seconds = 2.0))]
+fn rust_group_brackets(py: Python<'_>, paths: Vec<String>, max_gap_seconds: f64) -> PyResult<Vec<PyObject>> {
+    let stacks = py.allow_threads(|| brackets::group(&paths, max_gap_seconds));
+    
+    stacks
+        .iter()
+        .map(|stack| {
+            let entry = PyDict::new(py);
+            entry.set_item("kind", stack.kind.name())?;
+            entry.set_item("paths", &stack.paths)?;
+            Ok(entry.to_object(py))
+        })
+        .collect()
+}
+
+/// Split duplicate candidate pairs into `(duplicates, stack_pairs)`
+///
+/// `stacks` are lists of frame paths as returned by `rust_group_brackets`;
+/// pairs whose two files belong to the same stack are reported separately.
+#[pyfunction]
+fn rust_split_stack_pairs(pairs: Vec<brackets::PathPair>, stacks: Vec<Vec<String>>) -> (Vec<brackets::PathPair>, Vec<brackets::PathPair>) {
+    brackets::partition_pairs(pairs, &stacks)
+}
+
+/// Group RAW files with the JPEGs and XMP sidecars written beside them
+///
+/// Cameras shooting RAW+JPEG write both files under one basename; each such
+/// shot is one logical image. Returns one dict per shot with siblings:
+/// `raw`, `other_raws` (more RAW files with the basename, such as a DNG
+/// conversion), `images` (JPEG/HEIF), `sidecars` (`.xmp`, with or without
+/// the RAW extension) and all `paths`, RAW first. `raw` is the camera's own
+/// format rather than DNG, then the first in path order, whatever order
+/// `paths` come in. Images whose capture time is more than `max_gap_seconds`
+/// from the RAW file's are left out, so reused file numbers do not pair;
+/// files without a capture time pair by name alone.
+#[pyfunction]
+#[pyo3(signature = (paths, max_gap_seconds = raw_pairs::MAX_GAP))]
+fn rust_group_raw_pairs(py: Python<'_>, paths: Vec<String>, max_gap_seconds: f64) -> PyResult<Vec<PyObject>> {
+    let captures = py.allow_threads(|| raw_pairs::group(&paths, max_gap_seconds));
+    
+    captures
+        .iter()
+        .map(|capture| {
+            let entry = PyDict::new(py);
+            entry.set_item("raw", &capture.raw)?;
+            entry.set_item("other_raws", &capture.other_raws)?;
+            entry.set_item("images", &capture.images)?;
+            entry.set_item("sidecars", &capture.sidecars)?;
+            entry.set_item("paths", capture.paths())?;
+            Ok(entry.to_object(py))
+        })
+        .collect()
+}
+
+/// Split duplicate candidate pairs into `(duplicates, raw_pairs)`
+///
+/// `captures` are lists of paths as in the `paths` of `rust_group_raw_pairs`;
+/// a RAW file paired with its own out-of-camera JPEG is reported separately
+/// instead of as a duplicate.
+#[pyfunction]
+fn rust_split_raw_pairs(pairs: Vec<brackets::PathPair>, captures: Vec<Vec<String>>) -> (Vec<brackets::PathPair>, Vec<brackets::PathPair>) {
+    brackets::partition_pairs(pairs, &captures)
+}
+
+/// Read the key EXIF fields of many files as columns
+///
+/// Returns a dict mapping column name to values in `paths` order: `path`,
+/// `found` (whether any metadata could be read) and the text columns as
+/// lists with None for missing values; numeric columns (`capture_time` in
+/// seconds since the epoch, the camera's local time read as UTC;
+/// dimensions, exposure, GPS) are float64 numpy arrays with NaN for missing
+/// values. Files are read in parallel exiftool batches.
+#[pyfunction]
+fn read_exif_batch(py: Python<'_>, paths: Vec<String>) -> PyResult<PyObject> {
+    let table = py.allow_threads(|| exif::read_batch(&paths));
+    
+    let columns = PyDict::new(py);
+    columns.set_item("path", &paths)?;
+    columns.set_item("found", table.found)?;
+    for (c, (name, _, kind)) in exif::COLUMNS.iter().enumerate() {
+        match kind {
+            exif::ColumnKind::Text => columns.set_item(*name, &table.text[c])?,
+            exif::ColumnKind::Number => columns.set_item(*name, PyArray1::from_slice(py, &table.numbers[c]))?,
+        }
+    }
+    Ok(columns.to_object(py))
+}
+
+fn session_from_dict(session: &PyDict) -> PyResult<scan_diff::Session> {
+    let files = match session.get_item("files") {
+        // A list of paths, or a {path: hash} mapping as stored per session
+        Some(files) => files
+            .iter()?
+            .map(|path| path.and_then(|p| p.extract::<String>()))
+            .collect::<PyResult<_>>()?,
+        None => Default::default(),
+    };
+    let groups = match session.get_item("groups") {
+        Some(groups) => groups.extract()?,
+        None => Vec::new(),
+    };
+    Ok(scan_diff::Session { files, groups })
+}
+
+/// Report what changed between two scan sessions
+///
+/// Each session is a dict with `files` (paths, or a mapping keyed by path) and
+/// `groups` (lists of duplicate paths). Returns a dict with `added_files`,
+/// `removed_files`, `new_duplicates` (groups with at least one new duplicate
+/// pair) and `resolved_groups` (old groups with no duplicate pair left).
+#[pyfunction]
+fn diff_scans(py: Python<'_>, old_session: &PyDict, new_session: &PyDict) -> PyResult<PyObject> {
+    let old = session_from_dict(old_session)?;
+    let new = session_from_dict(new_session)?;
+    let diff = py.allow_threads(|| scan_diff::diff(&old, &new));
+    
+    let report = PyDict::new(py);
+    report.set_item("added_files", diff.added_files)?;
+    report.set_item("removed_files", diff.removed_files)?;
+    report.set_item("new_duplicates", diff.new_duplicates)?;
+    report.set_item("resolved_groups", diff.resolved_groups)?;
+    Ok(report.to_object(py))
+}
+
+/// Dry-run a duplicate policy over a saved scan session
+///
+/// `session` is a dict like `diff_scans` takes; its `groups` are the
+/// duplicate groups, merged where they share a file. `policy` is a dict with
+/// `keep` (`largest`, `highest_resolution`, `oldest`, `newest`,
+/// `shortest_path`, or the measured `sharpest`, `least_noisy` and
+/// `best_exposed`) and `action` (`hardlink`, `delete` or `move`). Nothing is
+/// planned or changed: the result lists the files that would be `kept` and
+/// those `removed`, `linked` or `moved` by the action, one dict per group in
+/// `groups` (`kept`, `duplicates`, `reclaimed_bytes`), session files `missing`
+/// from disk, and `total_bytes` / `reclaimed_bytes` over all groups, so
+/// policies can be compared before `export_action_script`.
+///
+/// The measured policies decode each file unless `index` holds its stats
+/// (see `ImageIndex.backfill_stats`); with an `index`, stats measured here
+/// are stored in it for next time, and stored dimensions are reused.
+#[pyfunction]
+#[pyo3(signature = (session, policy, index = None))]
+fn simulate(
+    py: Python<'_>,
+    session: &PyDict,
+    policy: &PyDict,
+    index: Option<PyRefMut<'_, ImageIndex>>,
+) -> PyResult<PyObject> {
+    let groups = session_from_dict(session)?.groups;
+    let keep = match policy.get_item("keep") {
+        Some(keep) => keep.extract::<&str>()?,
+        None => "largest",
+    };
+    let keep = simulation::KeepRule::parse(keep)?;
+    let action = match policy.get_item("action") {
+        Some(action) => action.extract::<&str>()?,
+        None => "hardlink",
+    };
+    let action = script::Action::parse(action)?;
+    
+    let mut stats = std::collections::HashMap::new();
+    if keep.needs_stats() || index.is_some() {
+        let paths: Vec<String> = groups.iter().flatten().cloned().collect();
+        let measured = match index {
+            Some(mut index) => cached_stats(py, index.store.as_mut(), &paths, keep.needs_stats())?,
+            None => py.allow_threads(|| paths.par_iter().map(|path| measure_image_stats(path).ok().map(|(_, s)| s)).collect()),
+        };
+        stats.extend(paths.into_iter().zip(measured).filter_map(|(path, stats)| Some((path, stats?))));
+    }
+    
+    let simulation = py.allow_threads(|| simulation::simulate(&groups, keep, &stats));
+    
+    let mut acted_on = Vec::new();
+    let outcomes = simulation
+        .groups
+        .iter()
+        .map(|group| {
+            acted_on.extend(group.duplicates.iter().cloned());
+            let dict = PyDict::new(py);
+            dict.set_item("kept", &group.kept)?;
+            dict.set_item("duplicates", &group.duplicates)?;
+            dict.set_item("reclaimed_bytes", group.reclaimed_bytes)?;
+            Ok(dict.to_object(py))
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    let kept: Vec<&str> = simulation.groups.iter().map(|group| group.kept.as_str()).collect();
+    
+    let report = PyDict::new(py);
+    report.set_item("action", action.name())?;
+    report.set_item("kept", kept)?;
+    let lists = [("removed", script::Action::Delete), ("linked", script::Action::Hardlink), ("moved", script::Action::Move)];
+    for (name, listed) in lists {
+        report.set_item(name, if action == listed { acted_on.as_slice() } else { &[] })?;
+    }
+    report.set_item("groups", outcomes)?;
+    report.set_item("missing", simulation.missing)?;
+    report.set_item("total_bytes", simulation.total_bytes)?;
+    report.set_item("reclaimed_bytes", simulation.reclaimed_bytes)?;
+    Ok(report.to_object(py))
+}
+
+/// Scan results per camera body, to find whose cards produce the duplicates
+///
+/// `session` is a dict like `diff_scans` takes. Files are attributed to a
+/// body by the EXIF serial number (BodySerialNumber, or CameraSerialNumber
+/// in DNGs); files without one are pooled per make and model with `serial`
+/// None. Returns one dict per body, most duplicate files first, with
+/// `serial`, `make`, `model`, `files`, `duplicate_files` (files in any
+/// group), `duplicate_rate`, `groups` (groups holding one of its files) and
+/// `first_capture` / `last_capture` (`YYYY-MM-DD HH:MM:SS`, None without
+/// capture times).
+#[pyfunction]
+fn camera_stats(py: Python<'_>, session: &PyDict) -> PyResult<Vec<PyObject>> {
+    let session = session_from_dict(session)?;
+    let cameras = py.allow_threads(|| cameras::stats(&session.files, &session.groups));
+    cameras
+        .iter()
+        .map(|camera| {
+            let dict = PyDict::new(py);
+            dict.set_item("serial", &camera.serial)?;
+            dict.set_item("make", &camera.make)?;
+            dict.set_item("model", &camera.model)?;
+            dict.set_item("files", camera.files)?;
+            dict.set_item("duplicate_files", camera.duplicate_files)?;
+            dict.set_item("duplicate_rate", camera.duplicate_rate())?;
+            dict.set_item("groups", camera.groups)?;
+            dict.set_item("first_capture", &camera.first_capture)?;
+            dict.set_item("last_capture", &camera.last_capture)?;
+            Ok(dict.to_object(py))
+        })
+        .collect()
+}
+
+/// A stable id for each duplicate group, in the same order
+///
+/// The id comes from the content of the group's files (the smallest BLAKE3
+/// checksum among its members), not from paths or scan order: renaming or
+/// moving files, re-scanning in another order, or adding and removing other
+/// members keeps a group's id, so annotations attached to it carry over
+/// between sessions. Groups that would share an id get `-2`, `-3`...
+/// suffixes. Every member is read once.
+#[pyfunction]
+fn stable_group_ids(py: Python<'_>, groups: Vec<Vec<String>>) -> Vec<String> {
+    py.allow_threads(|| group_ids::stable_ids(&groups, &std::collections::HashMap::new()))
+}
+
+/// Recursively list the files under `root`, walking and stat'ing in parallel
+///
+/// Returns one dict per file, sorted by path, with `path`, `size`, `mtime`
+/// (seconds since the epoch, None when the filesystem has none) and
+/// `format`, the extension the file is handled as (after
+/// `register_extension` mappings). `extensions` keeps only files with those
+/// extensions (case and leading dot don't matter); by default every image,
+/// RAW and layered file is kept. Files smaller than `min_size` bytes are
+/// left out. Symlinks are skipped unless `follow_symlinks`, which enters each
+/// directory once however many links lead to it. Unreadable directories are
+/// skipped and the walk counts against `set_io_limits`.
+///
+/// Returns `(files, interrupted)`. Ctrl-C (KeyboardInterrupt) stops the walk
+/// and returns the files found so far with `interrupted` True.
+#[pyfunction]
+#[pyo3(signature = (root, extensions = None, min_size = None, follow_symlinks = false))]
+fn scan_directory(
+    py: Python<'_>,
+    root: &str,
+    extensions: Option<Vec<String>>,
+    min_size: Option<u64>,
+    follow_symlinks: bool,
+) -> PyResult<(Vec<PyObject>, bool)> {
+    let filter = walk::Filter {
+        extensions: extensions.map(|extensions| {
+            extensions.iter().map(|e| e.trim().trim_start_matches('.').to_lowercase()).collect()
+        }),
+        min_size: min_size.unwrap_or(0),
+        follow_symlinks,
+    };
+    let (found, interrupted) = interrupt::run(py, |interrupt| walk::scan(Path::new(root), &filter, interrupt))?;
+    
+    let files = found
+        .into_iter()
+        .map(|file| {
+            let dict = PyDict::new(py);
+            dict.set_item("path", file.path)?;
+            dict.set_item("size", file.size)?;
+            let mtime = file.modified.and_then(|m| m.duration_since(std::time::UNIX_EPOCH).ok()).map(|d| d.as_secs_f64());
+            dict.set_item("mtime", mtime)?;
+            dict.set_item("format", file.format)?;
+            Ok(dict.to_object(py))
+        })
+        .collect::<PyResult<_>>()?;
+    Ok((files, interrupted))
+}
+
+/// Write a compact fingerprint of the library under `root` to `out_path`
+///
+/// Walks `root` like `scan_directory` and records each file's path relative
+/// to it, size, content checksum and average and perceptual hashes, gzip'd
+/// into a few tens of bytes per file. Send it to another machine and compare
+/// it there with `compare_library_fingerprints`. With a `HashCache` only
+/// new or changed files are read. Returns a dict with the `files` recorded,
+/// the paths that `failed` to hash, the fingerprint's size in `bytes` and
+/// whether it was `interrupted`.
+///
+/// Ctrl-C (KeyboardInterrupt) stops the scan after the files in progress and
+/// returns with `interrupted` True. Nothing is written then, since a partial
+/// fingerprint would show the rest of the library as missing; `files` counts
+/// those hashed so far, which are kept in the cache so a rerun resumes.
+#[pyfunction]
+#[pyo3(signature = (root, out_path, cache = None, extensions = None, follow_symlinks = false))]
+fn export_library_fingerprint(
+    py: Python<'_>,
+    root: &str,
+    out_path: &str,
+    mut cache: Option<PyRefMut<'_, HashCache>>,
+    extensions: Option<Vec<String>>,
+    follow_symlinks: bool,
+) -> PyResult<PyObject> {
+    let filter = walk::Filter {
+        extensions: extensions.map(|extensions| {
+            extensions.iter().map(|e| e.trim().trim_start_matches('.').to_lowercase()).collect()
+        }),
+        min_size: 0,
+        follow_symlinks,
+    };
+    let cache = cache.as_deref_mut().map(|cache| &mut cache.cache);
+    let root_path = Path::new(root);
+    let ((found, hashed), interrupted) = interrupt::run(py, |interrupt| {
+        let found = walk::scan(root_path, &filter, interrupt);
+        let paths: Vec<String> = found.iter().map(|file| file.path.clone()).collect();
+        let hashed = cached_hashes(&paths, cache, true, false, Some(interrupt));
+        (found, hashed)
+    })?;
+    
+    let mut records = Vec::new();
+    let mut failed = Vec::new();
+    for (file, hashed) in found.into_iter().zip(hashed?) {
+        let relative = match Path::new(&file.path).strip_prefix(root_path) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+            // `root` named the file itself
+            _ => std::path::PathBuf::from(Path::new(&file.path).file_name().unwrap_or_default()),
+        };
+        let relative: Vec<String> = relative.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+        let record = hashed.and_then(|Hashed { entry, .. }| {
+            let hash = |name: &str| entry.hashes.iter().find(|(n, _)| n == name).map(|(_, h)| h.as_str());
+            library_fingerprint::Record::new(
+                relative.join("/"),
+                file.size,
+                &entry.content_hash,
+                hash("average_hash")?,
+                hash("perceptual_hash")?,
+            )
+        });
+        match record {
+            Some(record) => records.push(record),
+            // Files left unhashed by the interrupt did not fail
+            None if !interrupted => failed.push(file.path),
+            None => {},
+        }
+    }
+    
+    let mut bytes = 0;
+    if !interrupted {
+        py.allow_threads(|| library_fingerprint::write(out_path, &records))
+            .map_err(|e| PyIOError::new_err(format!("Failed to write {}: {}", out_path, e)))?;
+        bytes = std::fs::metadata(out_path).map(|m| m.len()).unwrap_or(0);
+    }
+    
+    let dict = PyDict::new(py);
+    dict.set_item("files", records.len())?;
+    dict.set_item("failed", failed)?;
+    dict.set_item("bytes", bytes)?;
+    dict.set_item("interrupted", interrupted)?;
+    Ok(dict.to_object(py))
+}
+
+/// What each of two libraries has that the other lacks, from their fingerprint files
+///
+/// Files with the same contents pair up wherever they are filed. Remaining
+/// files whose perceptual and average hashes are both within `max_distance`
+/// pair up as the same photo in a different encoding (None pairs exact
+/// copies only). Returns a dict with `identical` (`(local, remote)` path
+/// pairs), `similar` (dicts with `local`, `remote` and `distance`),
+/// `only_local` and `only_remote` (relative paths), and the bytes of each
+/// side's unmatched files as `only_local_bytes` / `only_remote_bytes`.
+#[pyfunction]
+#[pyo3(signature = (local, remote, max_distance = 6))]
+fn compare_library_fingerprints(py: Python<'_>, local: &str, remote: &str, max_distance: Option<u32>) -> PyResult<PyObject> {
+    let read = |path: &str| {
+        library_fingerprint::read(path).map_err(|e| PyIOError::new_err(format!("Failed to read fingerprint {}: {}", path, e)))
+    };
+    let (local, remote) = py.allow_threads(|| -> PyResult<_> { Ok((read(local)?, read(remote)?)) })?;
+    let comparison = py.allow_threads(|| library_fingerprint::compare(&local, &remote, max_distance));
+    
+    let identical: Vec<(&str, &str)> = comparison
+        .identical
+        .iter()
+        .map(|&(i, j)| (local[i].path.as_str(), remote[j].path.as_str()))
+        .collect();
+    let similar = comparison
+        .similar
+        .iter()
+        .map(|&(i, j, distance)| {
+            let pair = PyDict::new(py);
+            pair.set_item("local", &local[i].path)?;
+            pair.set_item("remote", &remote[j].path)?;
+            pair.set_item("distance", distance)?;
+            Ok(pair.to_object(py))
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    let only_local: Vec<&str> = comparison.only_local.iter().map(|&i| local[i].path.as_str()).collect();
+    let only_remote: Vec<&str> = comparison.only_remote.iter().map(|&j| remote[j].path.as_str()).collect();
+    
+    let dict = PyDict::new(py);
+    dict.set_item("identical", identical)?;
+    dict.set_item("similar", similar)?;
+    dict.set_item("only_local", only_local)?;
+    dict.set_item("only_remote", only_remote)?;
+    dict.set_item("only_local_bytes", comparison.only_local.iter().map(|&i| local[i].size).sum::<u64>())?;
+    dict.set_item("only_remote_bytes", comparison.only_remote.iter().map(|&j| remote[j].size).sum::<u64>())?;
+    Ok(dict.to_object(py))
+}
+
+/// Estimate duplicates in a large library within a wall-clock budget
+///
+/// Walks `roots` for image files, orders them by `priority` (`size`: largest
+/// first, `recency`: newest first, `directory`: one file per folder in turn)
+/// and hashes as many as fit in `budget_seconds`, in parallel; the walk
+/// itself may use at most half the budget. Files already being hashed when
+/// the budget runs out finish. With `continue_full` the walk and hashing
+/// then go on without a limit, so the result covers the whole library.
+///
+/// Returns a dict with `files_found`, `walk_complete`, `sampled` (files
+/// hashed), `failed` (undecodable or on the skip-list), `complete` (every
+/// file found was hashed), the duplicate `groups` among the sampled files
+/// (perceptual hashes within `max_distance`), `duplicate_files` /
+/// `duplicate_bytes` (all but the largest file of each group),
+/// `estimated_duplicate_files` /
+/// `estimated_duplicate_bytes` (the sample's duplicate share scaled to every
+/// file found that did not fail; twins outside the sample are missed, so it
+/// is a lower bound until `complete`), `total_bytes` and `elapsed_seconds`.
+///
+/// Ctrl-C (KeyboardInterrupt) stops hashing after the files in progress and
+/// returns the result so far with `interrupted` True instead of raising.
+#[pyfunction]
+#[pyo3(signature = (roots, budget_seconds = 60.0, priority = "directory", max_distance = 10, continue_full = false))]
+fn quick_scan(
+    py: Python<'_>,
+    roots: Vec<String>,
+    budget_seconds: f64,
+    priority: &str,
+    max_distance: u32,
+    continue_full: bool,
+) -> PyResult<PyObject> {
+    let priority = sampling::Priority::parse(priority)?;
+    let budget = Duration::try_from_secs_f64(budget_seconds)
+        .map_err(|_| PyValueError::new_err("budget_seconds must be a non-negative number"))?;
+    
+    let start = Instant::now();
+    let tier = priority::current();
+    let ((candidates, walk_complete, hashed), interrupted) = interrupt::run(py, |interrupt| {
+        let (mut found, mut walk_complete) = sampling::discover(&roots, Some(start + budget / 2));
+        if continue_full && !walk_complete && !interrupt.is_set() {
+            found = sampling::discover(&roots, None).0;
+            walk_complete = true;
+        }
+        let candidates = sampling::prioritize(found, priority);
+        
+        // Hash in priority order, one parallel batch at a time, until the budget runs out
+        let batch = rayon::current_num_threads().max(1) * 2;
+        let mut hashed: Vec<Option<search::QueryHashes>> = Vec::with_capacity(candidates.len());
+        for chunk in candidates.chunks(batch) {
+            if interrupt.is_set() || (!continue_full && start.elapsed() >= budget) {
+                break;
+            }
+            hashed.par_extend(chunk.par_iter().map(|candidate| {
+                let _turn = priority::Turn::wait(tier);
+                if skiplist::skipped(&candidate.path).is_some() {
+                    return None;
+                }
+                let _deadline = deadline::ScopedDeadline::start();
+                std::panic::catch_unwind(|| file_hashes(&candidate.path)).ok()?.ok()
+            }));
+        }
+        (candidates, walk_complete, hashed)
+    })?;
+    
+    let sampled: Vec<(&sampling::Candidate, &search::QueryHashes)> = candidates
+        .iter()
+        .zip(&hashed)
+        .filter_map(|(candidate, hashes)| Some((candidate, hashes.as_ref()?)))
+        .collect();
+    let hashes: Vec<&str> = sampled.iter().map(|(_, hashes)| hashes.perceptual.as_str()).collect();
+    let groups = py.allow_threads(|| sweep::groups(&hashes, max_distance));
+    
+    let (mut duplicate_files, mut duplicate_bytes) = (0usize, 0u64);
+    let groups: Vec<Vec<&str>> = groups
+        .iter()
+        .map(|group| {
+            let largest = group.iter().map(|&i| sampled[i].0.size).max().unwrap_or(0);
+            duplicate_files += group.len() - 1;
+            duplicate_bytes += group.iter().map(|&i| sampled[i].0.size).sum::<u64>() - largest;
+            group.iter().map(|&i| sampled[i].0.path.as_str()).collect()
+        })
+        .collect();
+    
+    let total_bytes: u64 = candidates.iter().map(|candidate| candidate.size).sum();
+    let sampled_bytes: u64 = sampled.iter().map(|(candidate, _)| candidate.size).sum();
+    // Scale to the files not known to be undecodable, so a complete scan estimates exactly
+    let failed: Vec<&sampling::Candidate> =
+        candidates.iter().zip(&hashed).filter(|(_, hashes)| hashes.is_none()).map(|(c, _)| c).collect();
+    let population = candidates.len() - failed.len();
+    let population_bytes = total_bytes - failed.iter().map(|candidate| candidate.size).sum::<u64>();
+    let file_share = if sampled.is_empty() { 0.0 } else { duplicate_files as f64 / sampled.len() as f64 };
+    let byte_share = if sampled_bytes == 0 { 0.0 } else { duplicate_bytes as f64 / sampled_bytes as f64 };
+    
+    let report = PyDict::new(py);
+    report.set_item("files_found", candidates.len())?;
+    report.set_item("walk_complete", walk_complete)?;
+    report.set_item("sampled", sampled.len())?;
+    report.set_item("failed", failed.len())?;
+    report.set_item("complete", walk_complete && hashed.len() == candidates.len())?;
+    report.set_item("interrupted", interrupted)?;
+    report.set_item("groups", groups)?;
+    report.set_item("duplicate_files", duplicate_files)?;
+    report.set_item("duplicate_bytes", duplicate_bytes)?;
+    report.set_item("estimated_duplicate_files", (file_share * population as f64).round() as u64)?;
+    report.set_item("estimated_duplicate_bytes", (byte_share * population_bytes as f64).round() as u64)?;
+    report.set_item("total_bytes", total_bytes)?;
+    report.set_item("elapsed_seconds", start.elapsed().as_secs_f64())?;
+    Ok(report.to_object(py))
+}
+
+/// Find directories whose image contents substantially overlap another directory
+///
+/// `entries` are `(path, hash)` pairs from a scan. Returns one dict per
+/// contained directory with `directory`, `other`, `shared`, `files`,
+/// `other_files`, `containment` (share of `directory` found in `other`) and
+/// `identical`, most contained first. Identical directories are reported in
+/// both directions.
+#[pyfunction]
+#[pyo3(signature = (entries, min_containment = 0.9, min_files = 5, recursive = true))]
+fn rust_find_duplicate_directories(
+    py: Python<'_>,
+    entries: Vec<(String, String)>,
+    min_containment: f64,
+    min_files: usize,
+    recursive: bool,
+) -> PyResult<Vec<PyObject>> {
+    let overlaps = py.allow_threads(|| directories::find_overlaps(&entries, min_containment, min_files, recursive));
+    
+    overlaps
+        .iter()
+        .map(|overlap| {
+            let entry = PyDict::new(py);
+            entry.set_item("directory", &overlap.directory)?;
+            entry.set_item("other", &overlap.other)?;
+            entry.set_item("shared", overlap.shared)?;
+            entry.set_item("files", overlap.files)?;
+            entry.set_item("other_files", overlap.other_files)?;
+            entry.set_item("containment", overlap.containment)?;
+            entry.set_item("identical", overlap.identical)?;
+            Ok(entry.to_object(py))
+        })
+        .collect()
+}
+
+/// True if `path` is a derived file (sidecar, catalog preview, NAS thumbnail) to skip when hashing
+#[pyfunction]
+fn is_derived_file(path: &str) -> bool {
+    sidecar::is_derived(path)
+}
+
+/// The original a derived file belongs to, if it can be found on disk
+#[pyfunction]
+fn derived_file_parent(path: &str) -> Option<String> {
+    sidecar::parent_of(path)
+}
+
+/// Keep only the paths that are not derived files, preserving order
+#[pyfunction]
+fn filter_derived_files(paths: Vec<String>) -> Vec<String> {
+    paths.into_iter().filter(|p| !sidecar::is_derived(p)).collect()
+}
+
+/// Add a derived-file rule
+///
+/// `kind` is `extension`, `prefix` (file name prefix), `directory` (exact
+/// directory name) or `directory_suffix`; matching is case-insensitive.
+#[pyfunction]
+fn add_derived_file_rule(kind: &str, pattern: &str) -> PyResult<()> {
+    let rule = sidecar::Rule::parse(kind, pattern)
+        .ok_or_else(|| PyValueError::new_err(format!("Unknown rule kind '{}'", kind)))?;
+    sidecar::add_rule(rule);
+    Ok(())
+}
+
+/// Replace the derived-file rules with `(kind, pattern)` pairs; None restores the defaults
+#[pyfunction]
+#[pyo3(signature = (rules = None))]
+fn set_derived_file_rules(rules: Option<Vec<(String, String)>>) -> PyResult<()> {
+    let rules = match rules {
+        Some(rules) => rules
+            .iter()
+            .map(|(kind, pattern)| {
+                sidecar::Rule::parse(kind, pattern)
+                    .ok_or_else(|| PyValueError::new_err(format!("Unknown rule kind '{}'", kind)))
+            })
+            .collect::<PyResult<Vec<_>>>()?,
+        None => sidecar::default_rules(),
+    };
+    sidecar::set_rules(rules);
+    Ok(())
+}
+
+/// Active derived-file rules as `(kind, pattern)` pairs
+#[pyfunction]
+fn get_derived_file_rules() -> Vec<(String, String)> {
+    sidecar::active_rules()
+        .iter()
+        .map(|rule| {
+            let (kind, pattern) = rule.describe();
+            (kind.to_string(), pattern.to_string())
+        })
+        .collect()
+}
+
+/// Handle files ending in `.extension` as if they ended in `.handler`
+///
+/// `extension` may span several dots (`dng.bak`); the longest registered
+/// match wins. `handler` is any extension the built-in routing knows: an
+/// image format (`jpg`, `png`, `tiff`...), `psd`/`psb`, or a RAW format
+/// (`dng`, `nef`, `raf`...), which then also picks the format-specific
+/// conversion steps. For example `register_extension("insp", "jpg")` reads
+/// Insta360 stills as JPEG. None removes the mapping.
+#[pyfunction]
+#[pyo3(signature = (extension, handler = None))]
+fn register_extension(extension: &str, handler: Option<&str>) -> PyResult<()> {
+    let (extension, handler) = formats::validate(extension, handler)?;
+    formats::set(extension, handler);
+    Ok(())
+}
+
+/// Registered extensions and their handlers as a dict
+#[pyfunction]
+fn get_extension_handlers(py: Python<'_>) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    for (extension, handler) in formats::registered() {
+        dict.set_item(extension, handler)?;
+    }
+    Ok(dict.to_object(py))
+}
+
+/// Limit how many exiftool/dcraw processes may run at once across all threads
+///
+/// This is independent of the decode thread count; 0 restores the default
+/// (one process per CPU).
+#[pyfunction]
+fn set_max_external_processes(limit: usize) {
+    process::set_max_processes(limit);
+}
+
+/// Current limit on concurrently running external tools
+#[pyfunction]
+fn get_max_external_processes() -> usize {
+    process::max_processes()
+}
+
+/// Context manager returned by `interactive()`
+#[pyclass(unsendable)]
+struct InteractiveScope {
+    scope: Option<priority::ScopedInteractive>,
+}
+
+#[pymethods]
+impl InteractiveScope {
+    fn __enter__(mut slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
+        slf.scope = Some(priority::ScopedInteractive::enter());
+        slf
+    }
+
+    fn __exit__(&mut self, _exc_type: Option<&PyAny>, _exc_value: Option<&PyAny>, _traceback: Option<&PyAny>) -> bool {
+        self.scope = None;
+        false
+    }
+}
+
+/// Run this thread's requests ahead of background batch work, as a `with` block
+///
+/// For a GUI thread fetching the file the user just clicked while a scan
+/// runs: `get_thumbnail`, `rust_raw_to_grayscale`, `rust_grayscale_and_hashes`,
+/// the `*_hash_input` functions, `rust_convert_raw_to_jpg`,
+/// `rust_match_images` and `explain_match` called inside the block run on
+/// workers of their own. Until they return, batch items that have not
+/// started yet wait (items already running finish undisturbed), and they
+/// take the next free external tool slot ahead of batch work. Calls from
+/// other threads stay in the background tier and wait for them too.
+#[pyfunction]
+fn interactive() -> InteractiveScope {
+    InteractiveScope { scope: None }
+}
+
+/// Choose the conversion chain and process limit for local or network storage
+///
+/// `storage` is `local` or `network`; files are classified from the mount
+/// they live on (NFS, SMB/CIFS, sshfs... count as network). `backends` is the
+/// ordered chain, from `camera_profile`, `embedded_preview`, `libraw`,
+/// `format_specific`, `rawloader` and `generic`; None restores the full
+/// default chain. `max_processes` caps concurrent exiftool/dcraw runs for that
+/// storage on top of the global limit (0 for no extra cap), e.g. to keep
+/// exiftool round trips over SMB from piling up.
+#[pyfunction]
+#[pyo3(signature = (storage, backends = None, max_processes = 0))]
+fn set_storage_policy(storage: &str, backends: Option<Vec<String>>, max_processes: usize) -> PyResult<()> {
+    let kind = storage::StorageKind::parse(storage)?;
+    let policy = match backends {
+        Some(names) => {
+            let backends = names.iter().map(|name| storage::Backend::parse(name)).collect::<PyResult<Vec<_>>>()?;
+            if backends.is_empty() {
+                return Err(PyValueError::new_err("backends must name at least one backend"));
+            }
+            storage::StoragePolicy { backends, max_processes }
+        },
+        None => storage::StoragePolicy { max_processes, ..Default::default() },
+    };
+    storage::set_policy(kind, Some(policy));
+    process::limits_changed();
+    Ok(())
+}
+
+/// Current policy for `local` or `network` storage as a dict
+#[pyfunction]
+fn get_storage_policy(py: Python<'_>, storage: &str) -> PyResult<PyObject> {
+    let policy = storage::policy(storage::StorageKind::parse(storage)?);
+    let dict = PyDict::new(py);
+    let backends: Vec<&str> = policy.backends.iter().map(|b| b.name()).collect();
+    dict.set_item("backends", backends)?;
+    dict.set_item("max_processes", policy.max_processes)?;
+    Ok(dict.to_object(py))
+}
+
+/// Prefer an order of conversion steps for files with `extension`
+///
+/// The steps named move to the front of the storage policy's chain in this
+/// order; the policy still decides which steps run at all. None drops the
+/// preference. `auto_tune` sets these from the recorded conversions.
+#[pyfunction]
+#[pyo3(signature = (extension, backends = None))]
+fn set_extension_chain(extension: &str, backends: Option<Vec<String>>) -> PyResult<()> {
+    let order = backends
+        .map(|names| names.iter().map(|name| storage::Backend::parse(name)).collect::<PyResult<Vec<_>>>())
+        .transpose()?;
+    storage::set_extension_order(extension.trim_start_matches('.'), order);
+    Ok(())
+}
+
+/// Preferred step order per extension, as set by `set_extension_chain` or `auto_tune`
+#[pyfunction]
+fn extension_chains() -> std::collections::HashMap<String, Vec<&'static str>> {
+    storage::extension_order_list()
+        .into_iter()
+        .map(|(extension, order)| (extension, order.iter().map(|b| b.name()).collect()))
+        .collect()
+}
+
+/// Which conversion steps ran and succeeded for each extension and camera
+///
+/// Every RAW conversion in this process is recorded. Returns dicts with
+/// `extension`, `camera` (make and model, empty when unreadable), `files`,
+/// `failed` (files no step converted) and `steps`, one dict per step in the
+/// order first tried: `backend`, `attempts`, `successes`, `success_rate`,
+/// `mean_ms` per attempt and `mean_ms_to_success`, the whole conversion time
+/// of the files the step produced.
+#[pyfunction]
+fn backend_report(py: Python<'_>) -> PyResult<Vec<PyObject>> {
+    let ms = |time: Duration, count: u64| if count == 0 { None } else { Some(time.as_secs_f64() * 1000.0 / count as f64) };
+    backend_stats::report()
+        .into_iter()
+        .map(|entry| {
+            let steps = entry
+                .steps
+                .iter()
+                .map(|(backend, stats)| {
+                    let dict = PyDict::new(py);
+                    dict.set_item("backend", backend.name())?;
+                    dict.set_item("attempts", stats.attempts)?;
+                    dict.set_item("successes", stats.successes)?;
+                    dict.set_item("success_rate", stats.successes as f64 / stats.attempts.max(1) as f64)?;
+                    dict.set_item("mean_ms", ms(stats.time, stats.attempts))?;
+                    dict.set_item("mean_ms_to_success", ms(stats.time_to_success, stats.successes))?;
+                    Ok(dict.to_object(py))
+                })
+                .collect::<PyResult<Vec<PyObject>>>()?;
+            let dict = PyDict::new(py);
+            dict.set_item("extension", entry.extension)?;
+            dict.set_item("camera", entry.camera)?;
+            dict.set_item("files", entry.files)?;
+            dict.set_item("failed", entry.failed)?;
+            dict.set_item("steps", steps)?;
+            Ok(dict.to_object(py))
+        })
+        .collect()
+}
+
+/// Forget the conversions recorded for `backend_report`
+#[pyfunction]
+fn clear_backend_report() {
+    backend_stats::clear();
+}
+
+/// Reorder the chain per extension from the recorded conversions
+///
+/// For each extension with at least `min_files` conversions (cameras
+/// pooled), steps that produced results come first by time spent per
+/// result, so a slow step that usually fails stops running ahead of a fast
+/// one that works; steps that never succeeded go last. Applied with
+/// `set_extension_chain` unless `dry_run`. Returns the orders by extension.
+/// The recordings reflect this machine's tools and the scan profile they
+/// ran under.
+#[pyfunction]
+#[pyo3(signature = (min_files = 20, dry_run = false))]
+fn auto_tune(min_files: u64, dry_run: bool) -> std::collections::HashMap<String, Vec<&'static str>> {
+    let orders = backend_stats::tuned_orders(min_files);
+    if !dry_run {
+        for (extension, order) in &orders {
+            storage::set_extension_order(extension, Some(order.clone()));
+        }
+    }
+    orders.into_iter().map(|(extension, order)| (extension, order.iter().map(|b| b.name()).collect())).collect()
+}
+
+/// Hash a sample of files from their embedded preview and from a full decode, and compare
+///
+/// Up to `sample_size` of `paths` (evenly spaced through the list; 0 for
+/// all) are thumbnailed twice: once only from the embedded preview, once
+/// from a decode that never uses one. Returns `files`, one dict per sampled
+/// file with its `path`, `camera`, `preview_source` and `full_source` (the
+/// backends used, None when that decode failed), `error` and the
+/// `distances` of each hash (`average_hash`, `perceptual_hash`, `fine_hash`,
+/// `edge_hash`), plus `overall` and per-`cameras` summaries with the `files`,
+/// `failed` count and, per hash, the `mean`, `median`, `p95` and `max`
+/// distance and a `histogram` of distance to file count. Small distances
+/// for a camera mean the `previews_only` scan profile finds the same
+/// duplicates as a full decode would. Run it under the default profile; the
+/// other two rule out one of the decodes.
+#[pyfunction]
+#[pyo3(signature = (paths, sample_size = 50))]
+fn verify_preview_hashes(py: Python<'_>, paths: Vec<String>, sample_size: usize) -> PyResult<PyObject> {
+    let sampled = preview_check::sample(&paths, sample_size);
+    let side = THUMBNAIL_SIZE as usize;
+    let tier = priority::current();
+    
+    let checks: Vec<preview_check::FileCheck> = py.allow_threads(|| {
+        sampled
+            .par_iter()
+            .map(|path| {
+                let _turn = priority::Turn::wait(tier);
+                let thumbnail = |restriction| {
+                    let _restriction = storage::ScopedRestriction::new(restriction);
+                    let filter = imageops::FilterType::Triangle;
+                    let decoded = raw_to_grayscale_with_info(
+                        path,
+                        THUMBNAIL_SIZE,
+                        GrayscaleDtype::U8,
+                        filter,
+                        exposure::Preprocess::None,
+                        false,
+                    );
+                    match decoded {
+                        Ok((GrayscaleBuffer::U8(pixels), info)) => Ok((pixels, info.source.backend)),
+                        Ok(_) => unreachable!("uint8 thumbnail requested"),
+                        Err(e) => Err(e.to_string()),
+                    }
+                };
+                let preview = thumbnail(storage::Restriction::PreviewsOnly);
+                let full = thumbnail(storage::Restriction::NoPreviews);
+                let distances = match (&preview, &full) {
+                    (Ok((a, _)), Ok((b, _))) => preview_check::distances(a, b, side),
+                    _ => Vec::new(),
+                };
+                preview_check::FileCheck {
+                    path: path.clone(),
+                    camera: backend_stats::camera(path),
+                    preview: preview.map(|(_, backend)| backend),
+                    full: full.map(|(_, backend)| backend),
+                    distances,
+                }
+            })
+            .collect()
+    });
+    
+    let summary_to_dict = |summary: &preview_check::Summary| -> PyResult<PyObject> {
+        let dict = PyDict::new(py);
+        dict.set_item("files", summary.files)?;
+        dict.set_item("failed", summary.failed)?;
+        for (name, distribution) in &summary.hashes {
+            let stats = PyDict::new(py);
+            stats.set_item("mean", distribution.mean())?;
+            stats.set_item("median", distribution.percentile(0.5))?;
+            stats.set_item("p95", distribution.percentile(0.95))?;
+            stats.set_item("max", distribution.max())?;
+            stats.set_item("histogram", distribution.histogram().into_iter().collect::<std::collections::HashMap<_, _>>())?;
+            dict.set_item(*name, stats)?;
+        }
+        Ok(dict.to_object(py))
+    };
+    
+    let files = checks
+        .iter()
+        .map(|check| -> PyResult<PyObject> {
+            let dict = PyDict::new(py);
+            dict.set_item("path", &check.path)?;
+            dict.set_item("camera", &check.camera)?;
+            dict.set_item("preview_source", check.preview.as_ref().ok())?;
+            dict.set_item("full_source", check.full.as_ref().ok())?;
+            let error = match (&check.preview, &check.full) {
+                (Err(e), _) => Some(format!("Preview: {}", e)),
+                (_, Err(e)) => Some(format!("Full decode: {}", e)),
+                _ => None,
+            };
+            dict.set_item("error", error)?;
+            let distances = PyDict::new(py);
+            for (name, distance) in &check.distances {
+                distances.set_item(*name, distance)?;
+            }
+            dict.set_item("distances", distances)?;
+            Ok(dict.to_object(py))
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    
+    let (overall, cameras) = preview_check::summarize(&checks);
+    let by_camera = PyDict::new(py);
+    for (camera, summary) in &cameras {
+        by_camera.set_item(camera, summary_to_dict(summary)?)?;
+    }
+    
+    let report = PyDict::new(py);
+    report.set_item("files", files)?;
+    report.set_item("overall", summary_to_dict(&overall)?)?;
+    report.set_item("cameras", by_camera)?;
+    Ok(report.to_object(py))
+}
+
+fn scan_profile(name: &str) -> PyResult<&'static profiles::ScanProfile> {
+    profiles::lookup(name).ok_or_else(|| {
+        PyValueError::new_err(format!("Unknown scan profile '{}', expected one of {:?}", name, profiles::profile_names()))
+    })
+}
+
+/// Select a named scan profile for the rest of the run
+///
+/// A profile replaces the storage policies, memory budget, external process
+/// limit and thumbnail cache size in one go; setters called afterwards still
+/// override single settings. `previews_only` is meant for small boards such
+/// as a Raspberry Pi indexing a NAS: RAW files only ever go through their
+/// embedded preview (never a full decode, so files without one fail), thumbnails
+/// are capped at 512 pixels, the thumbnail cache at 8 MB, decodes at 256 MB and
+/// one external tool runs at a time. `archival` trades time for accuracy:
+/// RAW files are always fully decoded with high-quality interpolation (never
+/// from a preview), hash-type parameters default to the 256-bit `fine` hash,
+/// and `rust_match_images`/`explain_match` confirm hash matches with an SSIM
+/// of at least 0.8. `default` restores the stock settings.
+#[pyfunction]
+#[pyo3(signature = (name = "default"))]
+fn set_scan_profile(name: &str) -> PyResult<()> {
+    profiles::select(scan_profile(name)?);
+    Ok(())
+}
+
+/// The selected scan profile and its settings as a dict
+#[pyfunction]
+fn get_scan_profile(py: Python<'_>) -> PyResult<PyObject> {
+    let profile = profiles::active();
+    let dict = PyDict::new(py);
+    dict.set_item("name", profile.name)?;
+    let backends = profile.backends.map(|backends| backends.iter().map(|b| b.name()).collect::<Vec<_>>());
+    dict.set_item("backends", backends)?;
+    dict.set_item("max_thumbnail_edge", (profile.max_thumbnail_edge > 0).then_some(profile.max_thumbnail_edge))?;
+    dict.set_item("thumbnail_cache_bytes", profile.thumbnail_cache_bytes)?;
+    dict.set_item("memory_budget", profile.memory_budget)?;
+    dict.set_item("max_processes", profile.max_processes)?;
+    dict.set_item("full_decode", profile.full_decode)?;
+    dict.set_item("hash_type", profile.hash_type)?;
+    dict.set_item("min_ssim", (profile.min_ssim > 0.0).then_some(profile.min_ssim))?;
+    let mask = profiles::watermark_mask(profile);
+    let watermark_mask = PyDict::new(py);
+    watermark_mask.set_item("border", mask.border)?;
+    watermark_mask.set_item("corner_width", mask.corner_width)?;
+    watermark_mask.set_item("corner_height", mask.corner_height)?;
+    dict.set_item("watermark_mask", watermark_mask)?;
+    Ok(dict.to_object(py))
+}
+
+/// Set the regions the `watermarked` matching profile leaves out under a scan profile
+///
+/// `border` is the strip cut from every edge and `corner_width` /
+/// `corner_height` the rectangle blacked out in each corner after that, all
+/// as fractions of the image size (the stock mask is 0.03, 0.25 and 0.125).
+/// Applies to `scan_profile`, by default the selected one, and lasts for the
+/// run; `reset=True` restores the profile's stock mask.
+#[pyfunction]
+#[pyo3(signature = (border = 0.03, corner_width = 0.25, corner_height = 0.125, scan_profile = None, reset = false))]
+fn set_watermark_mask(
+    border: f64,
+    corner_width: f64,
+    corner_height: f64,
+    scan_profile: Option<&str>,
+    reset: bool,
+) -> PyResult<()> {
+    let profile = match scan_profile {
+        Some(name) => self::scan_profile(name)?,
+        None => profiles::active(),
+    };
+    let mask = if reset { None } else { Some(watermark::Mask::new(border, corner_width, corner_height)?) };
+    profiles::set_watermark_mask(profile, mask);
+    Ok(())
+}
+
+/// Names of the built-in scan profiles (`default`, `previews_only`, `archival`)
+#[pyfunction]
+fn get_scan_profiles() -> Vec<&'static str> {
+    profiles::profile_names()
+}
+
+/// Whether `path` is on `local` disk or a `network` mount
+#[pyfunction]
+fn rust_detect_storage(path: &str) -> &'static str {
+    storage::detect(path).name()
+}
+
+/// Throttle reads for network storage (bytes and file operations per second)
+///
+/// The limits are shared by every decode in the process; 0 disables a limit.
+/// Python-side directory walkers can draw from the same budget via `io_acquire`.
+#[pyfunction]
+#[pyo3(signature = (bytes_per_second = 0, ops_per_second = 0))]
+fn set_io_limits(bytes_per_second: u64, ops_per_second: u64) {
+    throttle::set_limits(bytes_per_second, ops_per_second);
+}
+
+/// Current IO limits as (bytes per second, operations per second)
+#[pyfunction]
+fn get_io_limits() -> (u64, u64) {
+    throttle::limits()
+}
+
+/// Block until `bytes` and `ops` fit within the shared IO limits
+#[pyfunction]
+#[pyo3(signature = (bytes, ops = 1))]
+fn io_acquire(py: Python<'_>, bytes: u64, ops: u64) {
+    py.allow_threads(|| throttle::acquire(bytes, ops));
+}
+
+/// Set a total memory budget in bytes for in-flight decodes (0 disables it)
+///
+/// Close to the budget, new decodes wait (lowering concurrency), the rawloader
+/// path bins straight to half resolution, and the thumbnail cache is dropped.
+#[pyfunction]
+fn set_memory_budget(bytes: u64) {
+    memory::set_budget(bytes);
+}
+
+/// Current memory budget and estimated bytes in use, as (budget, in_use)
+#[pyfunction]
+fn get_memory_usage() -> (u64, u64) {
+    memory::usage()
+}
+
+fn index_error(e: std::io::Error) -> PyErr {
+    PyIOError::new_err(format!("Index error: {}", e))
+}
+
+/// Run `f` on an index store with the GIL released: stores wait on disk or,
+/// for `postgres`, on the network, which would stall every Python thread
+fn with_store<T: Send>(
+    py: Python<'_>,
+    store: &mut dyn index::IndexStore,
+    f: impl FnOnce(&mut dyn index::IndexStore) -> std::io::Result<T> + Send,
+) -> PyResult<T> {
+    py.allow_threads(|| f(store)).map_err(index_error)
+}
+
+fn record_to_dict(py: Python<'_>, record: &index::ImageRecord) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("path", record.file())?;
+    dict.set_item("source_prefix", &record.source_prefix)?;
+    dict.set_item("format", &record.format)?;
+    dict.set_item("width", record.width)?;
+    dict.set_item("height", record.height)?;
+    dict.set_item("created_at", &record.created_at)?;
+    dict.set_item("modified_at", &record.modified_at)?;
+    dict.set_item("size", record.size)?;
+    dict.set_item("average_hash", &record.average_hash)?;
+    dict.set_item("perceptual_hash", &record.perceptual_hash)?;
+    dict.set_item("is_raw_format", record.is_raw_format)?;
+    dict.set_item("fine_hash", &record.fine_hash)?;
+    dict.set_item("content_hash", &record.content_hash)?;
+    Ok(dict.to_object(py))
+}
+
+/// Build a record from an `ImageInfo.to_dict()`-style dict; only `path` is required
+fn record_from_dict(dict: &PyDict) -> PyResult<index::ImageRecord> {
+    fn field<'a, T: FromPyObject<'a> + Default>(dict: &'a PyDict, key: &str) -> PyResult<T> {
+        match dict.get_item(key) {
+            Some(value) if !value.is_none() => value.extract(),
+            _ => Ok(T::default()),
+        }
+    }
+    
+    let path: String = field(dict, "path")?;
+    if path.is_empty() {
+        return Err(PyValueError::new_err("Index record needs a 'path'"));
+    }
+    
+    Ok(index::ImageRecord {
+        path: paths::identity(&path),
+        source_prefix: paths::normalize(&field::<String>(dict, "source_prefix")?),
+        format: field(dict, "format")?,
+        width: field(dict, "width")?,
+        height: field(dict, "height")?,
+        created_at: field(dict, "created_at")?,
+        modified_at: field(dict, "modified_at")?,
+        size: field(dict, "size")?,
+        average_hash: field(dict, "average_hash")?,
+        perceptual_hash: field(dict, "perceptual_hash")?,
+        is_raw_format: field(dict, "is_raw_format")?,
+        fine_hash: field(dict, "fine_hash")?,
+        content_hash: field(dict, "content_hash")?,
+        location: path,
+    })
+}
+
+/// Canonical spelling of a path, stable across macOS, Linux and Windows
+///
+/// Composes Unicode to NFC, drops trailing dots/spaces from components, uses
+/// `/` for Windows paths and collapses `//` and `.`. Index keys are stored in
+/// this form.
+#[pyfunction]
+fn normalize_path(path: &str) -> String {
+    paths::normalize(path)
+}
+
+/// Identity of a file: its normalized path, spelled as on disk when the
+/// filesystem ignores case, so differently-cased spellings of one file agree
+#[pyfunction]
+fn path_identity(py: Python<'_>, path: &str) -> String {
+    py.allow_threads(|| paths::identity(path))
+}
+
+/// True if the filesystem holding `path` ignores case in file names
+#[pyfunction]
+fn is_case_insensitive_fs(path: &str) -> bool {
+    paths::is_case_insensitive(Path::new(path))
+}
+
+/// Drop paths that are another spelling of an earlier path, keeping order
+#[pyfunction]
+fn unique_paths(py: Python<'_>, paths: Vec<String>) -> Vec<String> {
+    py.allow_threads(|| {
+        let mut seen = std::collections::HashSet::new();
+        paths.into_iter().filter(|p| seen.insert(paths::identity(p))).collect()
+    })
+}
+
+/// Persistent image index with a storage backend chosen at open time
+///
+/// Created with `open_index`. `backend` is `sqlite` (a database file with the scanner's `images` table),
+/// `sled` (a directory) or `flat` (a tab-separated text file). Records are
+/// dicts with the `ImageInfo` fields, keyed by (`path`, `source_prefix`).
+/// Keys compare paths by `path_identity`, but records and decisions hand
+/// back each path as it was given, since the identity may not open the file.
+#[pyclass]
+struct ImageIndex {
+    store: Box<dyn index::IndexStore>,
+    backend: String,
+}
+
+#[pymethods]
+impl ImageIndex {
+    #[getter]
+    fn backend(&self) -> &str {
+        &self.backend
+    }
+    
+    /// The record for `path`, or None
+    #[pyo3(signature = (path, source_prefix = ""))]
+    fn get(&mut self, py: Python<'_>, path: &str, source_prefix: &str) -> PyResult<Option<PyObject>> {
+        let (path, source_prefix) = (paths::identity(path), paths::normalize(source_prefix));
+        match with_store(py, self.store.as_mut(), |store| store.get(&path, &source_prefix))? {
+            Some(record) => Ok(Some(record_to_dict(py, &record)?)),
+            None => Ok(None),
+        }
+    }
+    
+    /// Insert or replace a record
+    ///
+    /// The file is not read. Pass the `content_hash` that `rust_hash_files`
+    /// returns with a cache, which checksums files while hashing them;
+    /// `reconcile_moves` never recognizes a record without one as moved.
+    fn put(&mut self, py: Python<'_>, record: &PyDict) -> PyResult<()> {
+        let record = record_from_dict(record)?;
+        with_store(py, self.store.as_mut(), |store| store.put(&record))
+    }
+    
+    /// Delete a record, returning whether it existed
+    #[pyo3(signature = (path, source_prefix = ""))]
+    fn remove(&mut self, py: Python<'_>, path: &str, source_prefix: &str) -> PyResult<bool> {
+        let (path, source_prefix) = (paths::identity(path), paths::normalize(source_prefix));
+        with_store(py, self.store.as_mut(), |store| store.remove(&path, &source_prefix))
+    }
+    
+    /// `(exists, modified_at)`, like `database.check_image_exists`
+    fn check_image_exists(&mut self, py: Python<'_>, path: &str, source_prefix: &str) -> PyResult<(bool, String)> {
+        let (path, source_prefix) = (paths::identity(path), paths::normalize(source_prefix));
+        Ok(match with_store(py, self.store.as_mut(), |store| store.get(&path, &source_prefix))? {
+            Some(record) => (true, record.modified_at),
+            None => (false, String::new()),
+        })
+    }
+    
+    /// Every record in the index
+    fn records(&mut self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let records = with_store(py, self.store.as_mut(), |store| store.records())?;
+        records.iter().map(|record| record_to_dict(py, record)).collect()
+    }
+    
+    /// Record a reviewed verdict on a pair, e.g. `keep_a`, `keep_b` or `not_duplicate`
+    fn put_decision(&mut self, py: Python<'_>, path_a: &str, path_b: &str, decision: &str) -> PyResult<()> {
+        let decision = index::DuplicateDecision {
+            path_a: paths::identity(path_a),
+            path_b: paths::identity(path_b),
+            decision: decision.to_string(),
+            location_a: path_a.to_string(),
+            location_b: path_b.to_string(),
+        }
+        .normalized();
+        with_store(py, self.store.as_mut(), |store| store.put_decision(&decision))
+    }
+    
+    /// Every recorded decision as `(path_a, path_b, decision)`
+    fn decisions(&mut self, py: Python<'_>) -> PyResult<Vec<(String, String, String)>> {
+        let decisions = with_store(py, self.store.as_mut(), |store| store.decisions())?;
+        Ok(decisions
+            .iter()
+            .map(|d| {
+                let (file_a, file_b) = d.files();
+                (file_a.to_string(), file_b.to_string(), d.decision.clone())
+            })
+            .collect())
+    }
+    
+    /// Find files of a new scan that are indexed files moved or renamed, and
+    /// move their records and decisions to the new paths
+    ///
+    /// `scanned` are record dicts from the new scan. An indexed file counts as
+    /// moved when it no longer exists and exactly one scanned file not yet in
+    /// the index has the same size and content checksum; scanned files
+    /// without a `content_hash` are checksummed here when their size fits.
+    /// Records indexed without a checksum are never taken for moved. Its
+    /// record is replaced by the scanned one, and decisions naming it,
+    /// including ignored pairs, follow it to the new path instead of being
+    /// lost with a deleted record. New records and decisions are written
+    /// before the old ones are removed, so a failure partway leaves stale
+    /// entries rather than orphaned decisions. With `dry_run` nothing
+    /// changes. Returns the `(old_path, new_path)` moves.
+    #[pyo3(signature = (scanned, dry_run = false))]
+    fn reconcile_moves(
+        &mut self,
+        py: Python<'_>,
+        scanned: Vec<&PyDict>,
+        dry_run: bool,
+    ) -> PyResult<Vec<(String, String)>> {
+        let scanned: Vec<index::ImageRecord> = scanned.into_iter().map(record_from_dict).collect::<PyResult<_>>()?;
+        let indexed = with_store(py, self.store.as_mut(), |store| store.records())?;
+        
+        let (vanished, added, moves) = py.allow_threads(|| {
+            let indexed_keys: std::collections::HashSet<(&str, &str)> =
+                indexed.iter().map(|r| (r.path.as_str(), r.source_prefix.as_str())).collect();
+            let scanned_paths: std::collections::HashSet<&str> = scanned.iter().map(|r| r.path.as_str()).collect();
+            // A file missing from a partial scan is not gone; only ones absent from disk are
+            let vanished: Vec<index::ImageRecord> = indexed
+                .iter()
+                .filter(|r| !scanned_paths.contains(r.path.as_str()) && !Path::new(r.file()).exists())
+                .filter(|r| !r.content_hash.is_empty())
+                .cloned()
+                .collect();
+            let sizes: std::collections::HashSet<u64> = vanished.iter().map(|r| r.size).collect();
+            let added: Vec<index::ImageRecord> = scanned
+                .iter()
+                .filter(|r| !indexed_keys.contains(&(r.path.as_str(), r.source_prefix.as_str())))
+                .filter(|r| sizes.contains(&r.size))
+                .cloned()
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .map(|mut record| {
+                    if record.content_hash.is_empty() {
+                        record.content_hash = checksum::blake3_file(record.file()).unwrap_or_default();
                     }
-                },
-                Err(e) => {
-                    let _ = std::fs::remove_file(&temp_jpg); // Clean up
-                    Err(PyIOError::new_err(format!("Failed to open converted image: {}", e)))
+                    record
+                })
+                .collect();
+            let moves = moves::detect(&vanished, &added);
+            (vanished, added, moves)
+        });
+        let moved: Vec<(String, String)> =
+            moves.iter().map(|&(v, a)| (vanished[v].file().to_string(), added[a].file().to_string())).collect();
+        if dry_run || moved.is_empty() {
+            return Ok(moved);
+        }
+        
+        with_store(py, self.store.as_mut(), |store| {
+            for &(_, a) in &moves {
+                store.put(&added[a])?;
+            }
+            let renamed: std::collections::HashMap<&str, &index::ImageRecord> =
+                moves.iter().map(|&(v, a)| (vanished[v].path.as_str(), &added[a])).collect();
+            let mut stale = Vec::new();
+            for decision in store.decisions()? {
+                let record_a = renamed.get(decision.path_a.as_str());
+                let record_b = renamed.get(decision.path_b.as_str());
+                if record_a.is_none() && record_b.is_none() {
+                    continue;
                 }
+                let (file_a, file_b) = decision.files();
+                let moved_decision = index::DuplicateDecision {
+                    path_a: record_a.map_or(&decision.path_a, |r| &r.path).clone(),
+                    path_b: record_b.map_or(&decision.path_b, |r| &r.path).clone(),
+                    decision: decision.decision.clone(),
+                    location_a: record_a.map_or(file_a, |r| r.file()).to_string(),
+                    location_b: record_b.map_or(file_b, |r| r.file()).to_string(),
+                };
+                store.put_decision(&moved_decision.normalized())?;
+                stale.push(decision);
             }
-        },
-        Err(e) => {
-            let _ = std::fs::remove_file(&temp_jpg); // Clean up if it exists
-            Err(e)
-        }
+            // Old keys name vanished paths, which no new key does, so these never undo the puts above
+            for decision in &stale {
+                store.remove_decision(&decision.path_a, &decision.path_b)?;
+            }
+            for &(v, _) in &moves {
+                store.remove(&vanished[v].path, &vanished[v].source_prefix)?;
+            }
+            Ok(())
+        })?;
+        Ok(moved)
+    }
+    
+    /// Fitted duplicate score calibration, or None before `calibrate_duplicates` ran
+    fn calibration(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        load_calibration(py, self.store.as_mut())?
+            .map(|calibration| calibration_to_dict(py, &calibration))
+            .transpose()
+    }
+    
+    /// Record the modification time and file listing (count, sizes and
+    /// modification times) of every directory under `roots`
+    ///
+    /// Call right before scanning `roots`: `summarize_directories` keeps these
+    /// stamps, so anything changed while the scan runs shows up as changed in
+    /// `verify_directories`. Stamps of other roots are kept. Returns the number
+    /// of directories stamped.
+    fn stamp_directories(&mut self, py: Python<'_>, roots: Vec<String>) -> PyResult<usize> {
+        let mut stamps = load_stamps(py, self.store.as_mut())?;
+        let fresh = py.allow_threads(|| {
+            let mut fresh = std::collections::HashMap::new();
+            for root in &roots {
+                summaries::stamp(Path::new(root), &mut fresh);
+            }
+            fresh
+        });
+        let count = fresh.len();
+        stamps.extend(fresh);
+        let encoded = summaries::encode_stamps(&stamps);
+        with_store(py, self.store.as_mut(), |store| store.put_setting(summaries::STAMPS_KEY, &encoded))?;
+        Ok(count)
+    }
+    
+    /// Store a summary of every directory holding indexed files: file count,
+    /// total size and a signature of their content hashes, all covering
+    /// subdirectories too, with the directory's stamp from `stamp_directories`
+    ///
+    /// Run after a scan so `verify_directories` can tell which subtrees the
+    /// next scan may skip; directories not stamped before the scan always
+    /// verify as changed. Returns the number of directories summarized.
+    fn summarize_directories(&mut self, py: Python<'_>) -> PyResult<usize> {
+        let records = with_store(py, self.store.as_mut(), |store| store.records())?;
+        let stamps = load_stamps(py, self.store.as_mut())?;
+        let summaries = py.allow_threads(|| summaries::summarize(&records, &stamps));
+        let encoded = summaries::encode(&summaries);
+        with_store(py, self.store.as_mut(), |store| store.put_setting(summaries::SETTING_KEY, &encoded))?;
+        Ok(summaries.len())
+    }
+    
+    /// The stored summary of `directory` as a dict with `directory`, `files`,
+    /// `total_bytes`, `signature` and `modified_ns`, or None
+    fn directory_summary(&mut self, py: Python<'_>, directory: &str) -> PyResult<Option<PyObject>> {
+        let summaries = load_summaries(py, self.store.as_mut())?;
+        let summary = py.allow_threads(|| summaries::find(&summaries, directory).cloned());
+        summary.map(|summary| summary_to_dict(py, &summary)).transpose()
+    }
+    
+    /// Which parts of `roots` a re-scan must look at, from the stored
+    /// summaries and a stat of each directory and its files, without decoding
+    ///
+    /// Returns a dict with `unchanged` (summaries of the largest subtrees whose
+    /// directories kept their modification times and file listings since they
+    /// were stamped; skip them), `changed` (directories whose entries were
+    /// added, removed, renamed or rewritten; list them again and re-check their
+    /// files) and `unknown` (roots never summarized; scan them in full). A
+    /// subdirectory of a changed directory is judged on its own, and new
+    /// subdirectories are found by listing the changed parent.
+    fn verify_directories(&mut self, py: Python<'_>, roots: Vec<String>) -> PyResult<PyObject> {
+        let summaries = load_summaries(py, self.store.as_mut())?;
+        let verification = py.allow_threads(|| summaries::verify(&summaries, &roots));
+        
+        let unchanged: Vec<PyObject> =
+            verification.unchanged.iter().map(|summary| summary_to_dict(py, summary)).collect::<PyResult<_>>()?;
+        let result = PyDict::new(py);
+        result.set_item("unchanged", unchanged)?;
+        result.set_item("changed", verification.changed)?;
+        result.set_item("unknown", verification.unknown)?;
+        Ok(result.to_object(py))
+    }
+    
+    /// Store the duplicate groups of a scan session under `name`, for paging
+    /// through with `groups` instead of handing a UI one huge list
+    ///
+    /// `session` is a dict like `diff_scans` takes; only its `groups` are
+    /// kept. Sizes come from the index records, or from the files themselves
+    /// when not indexed. Each group gets the `stable_id` that
+    /// `stable_group_ids` gives it, from the records' content hashes where
+    /// stored so only files without one are read, and groups are stored in
+    /// that order with their paths sorted, so saving the same groups found in another order
+    /// stores the same session. Saving under an existing name replaces it.
+    /// Returns the number of groups stored.
+    #[pyo3(signature = (session, name = "latest"))]
+    fn save_session(&mut self, py: Python<'_>, session: &PyDict, name: &str) -> PyResult<usize> {
+        let session_groups = session_from_dict(session)?.groups;
+        let records = with_store(py, self.store.as_mut(), |store| store.records())?;
+        let groups: Vec<sessions::Group> = py.allow_threads(|| {
+            let sizes: std::collections::HashMap<&str, u64> =
+                records.iter().map(|record| (record.path.as_str(), record.size)).collect();
+            let content_hashes: std::collections::HashMap<&str, &str> =
+                records.iter().map(|record| (record.path.as_str(), record.content_hash.as_str())).collect();
+            let stable_ids = group_ids::stable_ids(&session_groups, &content_hashes);
+            let mut groups: Vec<sessions::Group> = session_groups
+                .into_iter()
+                .zip(stable_ids)
+                .map(|(mut paths, stable_id)| {
+                    paths.sort();
+                    let bytes = paths
+                        .iter()
+                        .map(|path| match sizes.get(paths::identity(path).as_str()) {
+                            Some(&size) => size,
+                            None => std::fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+                        })
+                        .sum();
+                    sessions::Group { paths, bytes, stable_id: Some(stable_id) }
+                })
+                .collect();
+            groups.sort_by(|a, b| a.stable_id.cmp(&b.stable_id));
+            groups
+        });
+        with_store(py, self.store.as_mut(), |store| sessions::save(store, name, &groups))?;
+        Ok(groups.len())
+    }
+    
+    /// Number of groups stored by `save_session` under `session`, or None
+    #[pyo3(signature = (session = "latest"))]
+    fn group_count(&mut self, py: Python<'_>, session: &str) -> PyResult<Option<usize>> {
+        with_store(py, self.store.as_mut(), |store| sessions::count(store, session))
+    }
+    
+    /// One page of a stored session's duplicate groups
+    ///
+    /// Returns up to `limit` dicts starting at `offset`, each with the group's
+    /// `id` (its position in the saved session), `stable_id` (None for
+    /// sessions saved by older versions), `paths`, number of `files` and
+    /// total `bytes`. `sort_by` is `files` (most files first), `bytes`
+    /// (largest first) or `path` (by first path). Only the stored chunks
+    /// holding the page are read, so any page of a session with hundreds of
+    /// thousands of groups comes back at once. Past the end or for an
+    /// unknown session the page is empty.
+    #[pyo3(signature = (offset = 0, limit = 100, sort_by = "files", session = "latest"))]
+    fn groups(
+        &mut self,
+        py: Python<'_>,
+        offset: usize,
+        limit: usize,
+        sort_by: &str,
+        session: &str,
+    ) -> PyResult<Vec<PyObject>> {
+        let sort_by = sessions::SortBy::parse(sort_by)?;
+        let page = with_store(py, self.store.as_mut(), |store| sessions::page(store, session, offset, limit, sort_by))?;
+        page.into_iter()
+            .map(|(id, group)| {
+                let dict = PyDict::new(py);
+                dict.set_item("id", id)?;
+                dict.set_item("stable_id", group.stable_id)?;
+                dict.set_item("files", group.paths.len())?;
+                dict.set_item("paths", group.paths)?;
+                dict.set_item("bytes", group.bytes)?;
+                Ok(dict.to_object(py))
+            })
+            .collect()
+    }
+    
+    /// Quality statistics stored for each of `paths`, in the same order
+    ///
+    /// Each entry is a dict with the full `width` and `height`, `sharpness`
+    /// (variance of the Laplacian), `exposure` (mean brightness, 0-1),
+    /// `clipped` (share of black or white pixels) and `noise` (estimated
+    /// standard deviation), all measured on the grayscale hashing thumbnail,
+    /// or None when the file was never measured or changed since. With
+    /// `compute_missing`, those are measured now and stored; undecodable
+    /// files stay None.
+    #[pyo3(signature = (paths, compute_missing = false))]
+    fn image_stats(&mut self, py: Python<'_>, paths: Vec<String>, compute_missing: bool) -> PyResult<Vec<PyObject>> {
+        let stats = cached_stats(py, self.store.as_mut(), &paths, compute_missing)?;
+        stats
+            .iter()
+            .map(|stats| match stats {
+                Some(stats) => stats_to_dict(py, stats),
+                None => Ok(py.None()),
+            })
+            .collect()
+    }
+    
+    /// Measure and store quality statistics for files that lack fresh ones
+    ///
+    /// `paths` defaults to every indexed file. Lets a scan fill in stats
+    /// ahead of time so the `sharpest`, `least_noisy` and `best_exposed`
+    /// policies of `simulate` need not decode anything. Returns the number
+    /// of files measured.
+    #[pyo3(signature = (paths = None))]
+    fn backfill_stats(&mut self, py: Python<'_>, paths: Option<Vec<String>>) -> PyResult<usize> {
+        let paths = match paths {
+            Some(paths) => paths,
+            None => with_store(py, self.store.as_mut(), |store| store.records())?.iter().map(|record| record.file().to_string()).collect(),
+        };
+        let before = cached_stats(py, self.store.as_mut(), &paths, false)?;
+        let after = cached_stats(py, self.store.as_mut(), &paths, true)?;
+        Ok(before.iter().zip(&after).filter(|(before, after)| before.is_none() && after.is_some()).count())
+    }
+    
+    /// Make all writes durable
+    fn flush(&mut self, py: Python<'_>) -> PyResult<()> {
+        with_store(py, self.store.as_mut(), |store| store.flush())
+    }
+    
+    /// Damage found when the index was opened, such as lines lost to an
+    /// interrupted save, and what was salvaged; empty for an intact index
+    fn recovery(&self) -> Vec<String> {
+        self.store.recovery()
+    }
+    
+    /// Drop cached settings nothing will read again: quality stats of files
+    /// that are gone or changed since they were measured, and session chunks
+    /// that older versions emptied instead of removing
+    ///
+    /// Records and decisions are kept, since a missing file may only be on an
+    /// unmounted drive. Returns the counts by kind; with `dry_run` nothing is
+    /// removed. Run `vacuum()` afterwards to give the space back.
+    #[pyo3(signature = (dry_run = false))]
+    fn compact(&mut self, py: Python<'_>, dry_run: bool) -> PyResult<PyObject> {
+        let store = &mut self.store;
+        let compaction = py.allow_threads(|| compaction::compact(store.as_mut(), dry_run)).map_err(index_error)?;
+        let dict = PyDict::new(py);
+        dict.set_item("stale_stats", compaction.stale_stats)?;
+        dict.set_item("emptied_chunks", compaction.emptied_chunks)?;
+        dict.set_item("dry_run", dry_run)?;
+        Ok(dict.to_object(py))
+    }
+    
+    /// Give the space held by removed entries back to the filesystem
+    ///
+    /// `sqlite` rebuilds the database file and empties its write-ahead log,
+    /// `sled` copies the live entries into a fresh directory that replaces
+    /// the old one, `postgres` runs a plain VACUUM (other workers keep
+    /// writing) and `flat` only flushes, as its files never hold removed
+    /// entries. Returns `(bytes_before, bytes_after)`.
+    fn vacuum(&mut self, py: Python<'_>) -> PyResult<(u64, u64)> {
+        let store = &mut self.store;
+        py.allow_threads(|| {
+            let before = store.size_on_disk()?;
+            store.vacuum()?;
+            Ok((before, store.size_on_disk()?))
+        })
+        .map_err(index_error)
+    }
+    
+    /// Bytes on disk and the number of records, decisions and settings, to
+    /// tell when a `compact()` and `vacuum()` are due
+    fn size_report(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let (bytes, records, decisions, settings) = with_store(py, self.store.as_mut(), |store| {
+            Ok((store.size_on_disk()?, store.records()?.len(), store.decisions()?.len(), store.settings()?.len()))
+        })?;
+        let dict = PyDict::new(py);
+        dict.set_item("bytes", bytes)?;
+        dict.set_item("records", records)?;
+        dict.set_item("decisions", decisions)?;
+        dict.set_item("settings", settings)?;
+        Ok(dict.to_object(py))
     }
 }
 
-// Optimized hash functions
+/// Measure one file for the keeper policies, with the freshness it was measured at
+///
+/// Decodes through the hashing pipeline, so a file costs what indexing it does.
+fn measure_image_stats(path: &str) -> PyResult<(String, image_stats::ImageStats)> {
+    let measured_at =
+        image_stats::freshness(path).ok_or_else(|| PyIOError::new_err(format!("Failed to read {}", path)))?;
+    let _deadline = deadline::ScopedDeadline::start();
+    let (pixels, dimensions) = if streaming::is_streamable(path) {
+        streaming::grayscale_thumbnail_with_dimensions(path, THUMBNAIL_SIZE as usize, grayscale::luma_mode())?
+    } else {
+        let img = open_any_image(path)?;
+        (u8_thumbnail(&img), (img.width(), img.height()))
+    };
+    let dimensions = provenance::original_dimensions(path).unwrap_or(dimensions);
+    Ok((measured_at, image_stats::ImageStats::measure(&pixels, THUMBNAIL_SIZE as usize, Some(dimensions))))
+}
+
+/// Stats of `paths` stored in the index; with `measure_missing`, files
+/// never measured or changed since are measured in parallel and stored
+///
+/// Files that cannot be decoded stay None.
+fn cached_stats(
+    py: Python<'_>,
+    store: &mut dyn index::IndexStore,
+    paths: &[String],
+    measure_missing: bool,
+) -> PyResult<Vec<Option<image_stats::ImageStats>>> {
+    let mut stats = with_store(py, store, |store| {
+        paths.iter().map(|path| image_stats::load(store, path)).collect::<std::io::Result<Vec<_>>>()
+    })?;
+    if !measure_missing {
+        return Ok(stats);
+    }
+    
+    let missing: Vec<usize> = (0..paths.len()).filter(|&i| stats[i].is_none()).collect();
+    let tier = priority::current();
+    let measured: Vec<Option<(String, image_stats::ImageStats)>> = py.allow_threads(|| {
+        missing
+            .par_iter()
+            .map(|&i| {
+                let _turn = priority::Turn::wait(tier);
+                measure_image_stats(&paths[i]).ok()
+            })
+            .collect()
+    });
+    let measured: Vec<(usize, (String, image_stats::ImageStats))> =
+        missing.into_iter().zip(measured).filter_map(|(i, measured)| Some((i, measured?))).collect();
+    with_store(py, store, |store| {
+        measured.iter().try_for_each(|(i, (measured_at, stats))| image_stats::save(store, &paths[*i], measured_at, stats))
+    })?;
+    for (i, (_, measured)) in measured {
+        stats[i] = Some(measured);
+    }
+    Ok(stats)
+}
+
+fn stats_to_dict(py: Python<'_>, stats: &image_stats::ImageStats) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("width", stats.width)?;
+    dict.set_item("height", stats.height)?;
+    dict.set_item("sharpness", stats.sharpness)?;
+    dict.set_item("exposure", stats.exposure)?;
+    dict.set_item("clipped", stats.clipped)?;
+    dict.set_item("noise", stats.noise)?;
+    Ok(dict.to_object(py))
+}
+
+fn load_summaries(
+    py: Python<'_>,
+    store: &mut dyn index::IndexStore,
+) -> PyResult<std::collections::HashMap<String, summaries::DirectorySummary>> {
+    match with_store(py, store, |store| store.get_setting(summaries::SETTING_KEY))? {
+        Some(value) => summaries::decode(&value).map_err(index_error),
+        None => Ok(std::collections::HashMap::new()),
+    }
+}
+
+fn load_stamps(
+    py: Python<'_>,
+    store: &mut dyn index::IndexStore,
+) -> PyResult<std::collections::HashMap<String, summaries::Stamp>> {
+    match with_store(py, store, |store| store.get_setting(summaries::STAMPS_KEY))? {
+        Some(value) => summaries::decode_stamps(&value).map_err(index_error),
+        None => Ok(std::collections::HashMap::new()),
+    }
+}
+
+fn summary_to_dict(py: Python<'_>, summary: &summaries::DirectorySummary) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("directory", &summary.directory)?;
+    dict.set_item("files", summary.files)?;
+    dict.set_item("total_bytes", summary.total_bytes)?;
+    dict.set_item("signature", &summary.signature)?;
+    dict.set_item("modified_ns", summary.modified)?;
+    Ok(dict.to_object(py))
+}
+
+fn load_calibration(py: Python<'_>, store: &mut dyn index::IndexStore) -> PyResult<Option<calibration::Calibration>> {
+    match with_store(py, store, |store| store.get_setting(calibration::SETTING_KEY))? {
+        Some(value) => Ok(Some(calibration::Calibration::decode(&value).map_err(index_error)?)),
+        None => Ok(None),
+    }
+}
+
+fn calibration_to_dict(py: Python<'_>, calibration: &calibration::Calibration) -> PyResult<PyObject> {
+    let weights = PyDict::new(py);
+    weights.set_item("average", calibration.weights[0])?;
+    weights.set_item("perceptual", calibration.weights[1])?;
+    weights.set_item("edge", calibration.weights[2])?;
+    
+    let dict = PyDict::new(py);
+    dict.set_item("profile", &calibration.profile)?;
+    dict.set_item("weights", weights)?;
+    dict.set_item("bias", calibration.bias)?;
+    dict.set_item("threshold", calibration.threshold)?;
+    Ok(dict.to_object(py))
+}
+
+/// Fit the duplicate scorer to labeled pairs and store the result in the index
+///
+/// `labeled_pairs` holds `(path_a, path_b, is_duplicate)`; when omitted, the
+/// reviewed decisions in the index are used (`not_duplicate` is negative,
+/// any other decision positive). Distances are measured under `profile`.
+/// Returns the calibration with its `precision` and `recall` on the training
+/// pairs, the number of `samples` used and how many pairs were `skipped`
+/// because a file could not be decoded.
 #[pyfunction]
-fn rust_compute_average_hash(_py: Python<'_>, image: PyReadonlyArray2<u8>) -> PyResult<String> {
-    let arr = image.as_array();
-    if arr.shape()[0] != 8 || arr.shape()[1] != 8 {
-        return Err(PyIOError::new_err("Image must be 8x8 for average hash"));
+#[pyo3(signature = (index, labeled_pairs = None, profile = "default"))]
+fn calibrate_duplicates(
+    py: Python<'_>,
+    mut index: PyRefMut<ImageIndex>,
+    labeled_pairs: Option<Vec<(String, String, bool)>>,
+    profile: &str,
+) -> PyResult<PyObject> {
+    let match_profile = match_profile(profile)?;
+    let labeled_pairs = match labeled_pairs {
+        Some(pairs) => pairs,
+        None => index
+            .store
+            .decisions()
+            .map_err(index_error)?
+            .into_iter()
+            .map(|d| {
+                let (file_a, file_b) = d.files();
+                (file_a.to_string(), file_b.to_string(), d.decision != "not_duplicate")
+            })
+            .collect(),
+    };
+    
+    let samples: Vec<calibration::Sample> = py.allow_threads(|| {
+        let mut paths: Vec<&String> = labeled_pairs.iter().flat_map(|(a, b, _)| [a, b]).collect();
+        paths.sort();
+        paths.dedup();
+        let prints: std::collections::HashMap<&String, matching::Fingerprint> = paths
+            .into_par_iter()
+            .filter_map(|path| Some((path, matching::fingerprint(&open_any_image(path).ok()?, match_profile))))
+            .collect();
+        
+        labeled_pairs
+            .iter()
+            .filter_map(|(a, b, duplicate)| {
+                let result = matching::compare(prints.get(a)?, prints.get(b)?, match_profile);
+                Some((result.distances, *duplicate))
+            })
+            .collect()
+    });
+    
+    let Some((calibration, fit)) = calibration::fit(profile, &samples) else {
+        return Err(PyValueError::new_err("Calibration needs decodable pairs labeled both duplicate and not duplicate"));
+    };
+    index
+        .store
+        .put_setting(calibration::SETTING_KEY, &calibration.encode())
+        .map_err(index_error)?;
+    
+    let report = calibration_to_dict(py, &calibration)?;
+    let dict: &PyDict = report.downcast(py)?;
+    dict.set_item("precision", fit.precision)?;
+    dict.set_item("recall", fit.recall)?;
+    dict.set_item("samples", samples.len())?;
+    dict.set_item("skipped", labeled_pairs.len() - samples.len())?;
+    Ok(report)
+}
+
+/// Score a candidate pair with the calibration stored in `index`
+///
+/// Returns `(score, is_duplicate)` where `score` is the calibrated duplicate
+/// probability (0-1). Raises ValueError if the index was never calibrated.
+#[pyfunction]
+fn score_pair(py: Python<'_>, path_a: &str, path_b: &str, mut index: PyRefMut<ImageIndex>) -> PyResult<(f64, bool)> {
+    let Some(calibration) = load_calibration(py, index.store.as_mut())? else {
+        return Err(PyValueError::new_err("Index has no calibration, run calibrate_duplicates first"));
+    };
+    let profile = match_profile(&calibration.profile)?;
+    let (a, b) = py.allow_threads(|| {
+        rayon::join(
+            || open_any_image(path_a).map(|img| matching::fingerprint(&img, profile)),
+            || open_any_image(path_b).map(|img| matching::fingerprint(&img, profile)),
+        )
+    });
+    
+    let distances = matching::compare(&a?, &b?, profile).distances;
+    Ok((calibration.score(distances), calibration.is_duplicate(distances)))
+}
+
+/// Check whether two files differ only by EXIF orientation
+///
+/// Perceptual hashes are compared on the stored pixels (as indexed) and after
+/// rotating both images to their display orientation. `verdict` is `match`
+/// when both comparisons are within `max_distance` bits,
+/// `same_image_different_orientation` when only one of them is (a mismatch
+/// explained purely by rotation metadata), and `different` otherwise.
+#[pyfunction]
+#[pyo3(signature = (path_a, path_b, max_distance = 10))]
+fn orientation_equivalence(py: Python<'_>, path_a: &str, path_b: &str, max_distance: u32) -> PyResult<PyObject> {
+    let stored_and_displayed = |path: &str| -> PyResult<(u16, search::QueryHashes, search::QueryHashes)> {
+        provenance::clear();
+        let img = open_any_image(path)?;
+        let orientation = orientation::read(path);
+        let stored = image_hashes(&img);
+        let displayed = image_hashes(&orientation::to_display(img, orientation, provenance::last()));
+        Ok((orientation, stored, displayed))
+    };
+    let (a, b) = py.allow_threads(|| rayon::join(|| stored_and_displayed(path_a), || stored_and_displayed(path_b)));
+    let ((orientation_a, stored_a, displayed_a), (orientation_b, stored_b, displayed_b)) = (a?, b?);
+    
+    let distance = hamming::str_distance(&stored_a.perceptual, &stored_b.perceptual).unwrap_or(u32::MAX);
+    let normalized_distance = hamming::str_distance(&displayed_a.perceptual, &displayed_b.perceptual).unwrap_or(u32::MAX);
+    let verdict = match (distance <= max_distance, normalized_distance <= max_distance) {
+        (true, true) => "match",
+        (false, false) => "different",
+        _ => "same_image_different_orientation",
+    };
+    
+    let dict = PyDict::new(py);
+    dict.set_item("orientation_a", orientation_a)?;
+    dict.set_item("orientation_b", orientation_b)?;
+    dict.set_item("distance", distance)?;
+    dict.set_item("normalized_distance", normalized_distance)?;
+    dict.set_item("explained_by_orientation", verdict == "same_image_different_orientation")?;
+    dict.set_item("verdict", verdict)?;
+    Ok(dict.to_object(py))
+}
+
+/// Open (creating if needed) an `ImageIndex` at `location` with the named backend
+///
+/// A `flat` index damaged by an interrupted save opens with whatever records
+/// survived intact, warns with a RuntimeWarning and is rewritten clean on the
+/// next flush; `ImageIndex.recovery()` lists what was found.
+#[pyfunction]
+#[pyo3(signature = (location, backend = "sqlite"))]
+fn open_index(py: Python<'_>, location: &str, backend: &str) -> PyResult<ImageIndex> {
+    if !index::backends().contains(&backend) {
+        return Err(PyValueError::new_err(format!(
+            "Unknown index backend '{}', expected one of {:?}",
+            backend,
+            index::backends()
+        )));
+    }
+    
+    let location = location.to_string();
+    let store = py.allow_threads(|| index::open(backend, &location)).map_err(index_error)?;
+    let recovery = store.recovery();
+    if !recovery.is_empty() {
+        let message = format!("Index at {} was damaged and partly recovered: {}", location, recovery.join("; "));
+        PyErr::warn(py, py.get_type::<PyRuntimeWarning>(), &message, 1)?;
+    }
+    Ok(ImageIndex { store, backend: backend.to_string() })
+}
+
+/// Average, perceptual and fine hash of a decoded image, as stored in the index
+fn image_hashes(img: &DynamicImage) -> search::QueryHashes {
+    let filter = imageops::FilterType::Triangle;
+    match grayscale_thumbnail(img, THUMBNAIL_SIZE, GrayscaleDtype::U8, filter, grayscale::luma_mode()) {
+        GrayscaleBuffer::U8(pixels) => thumbnail_hashes(&pixels),
+        _ => unreachable!("uint8 thumbnail requested"),
+    }
+}
+
+/// `image_hashes` of a file, streaming huge TIFF/PSB scans instead of decoding them whole
+fn file_hashes(path: &str) -> PyResult<search::QueryHashes> {
+    if streaming::is_streamable(path) {
+        let pixels = streaming::grayscale_thumbnail(path, THUMBNAIL_SIZE as usize, grayscale::luma_mode())?;
+        return Ok(thumbnail_hashes(&pixels));
+    }
+    open_any_image(path).map(|img| image_hashes(&img))
+}
+
+/// Hashes of a `THUMBNAIL_SIZE` square grayscale thumbnail
+fn thumbnail_hashes(pixels: &[u8]) -> search::QueryHashes {
+    let side = THUMBNAIL_SIZE as usize;
+    search::QueryHashes {
+        average: hashing::average_hash(hashing::area_downsample(pixels, side, 8).view()),
+        perceptual: hashing::perceptual_hash(hashing::area_downsample(pixels, side, 32).view()),
+        fine: hashing::fine_hash(hashing::area_downsample(pixels, side, 64).view()),
+    }
+}
+
+/// Wrap an HxWx3 uint8 frame (e.g. from OpenCV) as an image, swapping BGR to RGB if needed
+fn frame_to_image(frame: &PyReadonlyArray3<u8>, channel_order: &str) -> PyResult<DynamicImage> {
+    let arr = frame.as_array();
+    let (height, width, channels) = arr.dim();
+    if channels != 3 || height == 0 || width == 0 {
+        return Err(PyValueError::new_err("Frame must be a non-empty HxWx3 uint8 array"));
     }
+    let swap = match channel_order {
+        "bgr" => true,
+        "rgb" => false,
+        _ => return Err(PyValueError::new_err(format!("Unsupported channel order '{}', expected 'bgr' or 'rgb'", channel_order))),
+    };
     
-    // Calculate the average pixel value (optimized)
-    let mut sum = 0u32;
-    for row in arr.rows() {
-        for &pixel in row {
-            sum += pixel as u32;
+    let mut pixels = Vec::with_capacity(height * width * 3);
+    for pixel in arr.rows() {
+        if swap {
+            pixels.extend([pixel[2], pixel[1], pixel[0]]);
+        } else {
+            pixels.extend(pixel.iter().copied());
         }
     }
-    let avg = sum / 64;
     
-    // Compute the hash (bit-packed for efficiency)
-    let mut hash = String::with_capacity(64);
+    ImageBuffer::<Rgb<u8>, _>::from_raw(width as u32, height as u32, pixels)
+        .map(DynamicImage::ImageRgb8)
+        .ok_or_else(|| PyValueError::new_err("Frame dimensions do not match its data"))
+}
+
+/// Average and perceptual hash of an in-memory HxWx3 frame, comparable with the index
+///
+/// Grayscale conversion and resizing happen in Rust, so live-captured frames
+/// can be matched without temporary files. `channel_order` defaults to `bgr`
+/// as produced by OpenCV. Returns `(average_hash, perceptual_hash)` in
+/// `format` (`str`, `int` or `bytes`).
+#[pyfunction]
+#[pyo3(signature = (frame, channel_order = "bgr", format = "str"))]
+fn rust_frame_hashes(
+    py: Python<'_>,
+    frame: PyReadonlyArray3<u8>,
+    channel_order: &str,
+    format: &str,
+) -> PyResult<(PyObject, PyObject)> {
+    let format = hashing::HashFormat::parse(format)?;
+    let img = frame_to_image(&frame, channel_order)?;
+    let hashes = py.allow_threads(|| image_hashes(&img));
+    Ok((hash_to_py(py, hashes.average, format)?, hash_to_py(py, hashes.perceptual, format)?))
+}
+
+/// Find the indexed images most similar to an arbitrary query image
+///
+/// The query may be any supported format, RAW included. Candidates within
+/// `max_distance` bits of the 64-bit perceptual hash are confirmed with the
+/// 256-bit fine hash, which must differ in at most `fine_max_distance` bits
+/// (None skips the confirmation). Both default to the configured
+/// `thresholds`, 10 and 40 bits. Records indexed without a `fine_hash` are
+/// decoded to get one. Returns up to `k` record dicts with added `distance`,
+/// `average_distance` and `fine_distance`, closest first.
+#[pyfunction]
+#[pyo3(signature = (query_path, index, k = 10, max_distance = tuning::thresholds().similar, fine_max_distance = tuning::thresholds().fine))]
+fn find_similar(
+    py: Python<'_>,
+    query_path: &str,
+    mut index: PyRefMut<'_, ImageIndex>,
+    k: usize,
+    max_distance: u32,
+    fine_max_distance: Option<u32>,
+) -> PyResult<Vec<PyObject>> {
+    let query = py.allow_threads(|| file_hashes(query_path))?;
+    search_index(py, &mut index, &query, k, max_distance, fine_max_distance)
+}
+
+/// `find_similar` for an in-memory HxWx3 frame instead of a file
+#[pyfunction]
+#[pyo3(signature = (frame, index, k = 10, max_distance = tuning::thresholds().similar, channel_order = "bgr", fine_max_distance = tuning::thresholds().fine))]
+fn find_similar_frame(
+    py: Python<'_>,
+    frame: PyReadonlyArray3<u8>,
+    mut index: PyRefMut<'_, ImageIndex>,
+    k: usize,
+    max_distance: u32,
+    channel_order: &str,
+    fine_max_distance: Option<u32>,
+) -> PyResult<Vec<PyObject>> {
+    let img = frame_to_image(&frame, channel_order)?;
+    let query = py.allow_threads(|| image_hashes(&img));
+    search_index(py, &mut index, &query, k, max_distance, fine_max_distance)
+}
+
+fn search_index(
+    py: Python<'_>,
+    index: &mut ImageIndex,
+    query: &search::QueryHashes,
+    k: usize,
+    max_distance: u32,
+    fine_max_distance: Option<u32>,
+) -> PyResult<Vec<PyObject>> {
+    let records = with_store(py, index.store.as_mut(), |store| store.records())?;
+    let matches = py.allow_threads(|| match fine_max_distance {
+        Some(max_fine_distance) => search::nearest_confirmed(records, query, k, max_distance, max_fine_distance, |record| {
+            file_hashes(record.file()).ok().map(|hashes| hashes.fine)
+        }),
+        None => search::nearest(records, &query.average, &query.perceptual, k, max_distance),
+    });
+    
+    matches
+        .iter()
+        .map(|m| {
+            let entry = record_to_dict(py, &m.record)?;
+            let dict: &PyDict = entry.downcast(py)?;
+            dict.set_item("distance", m.distance)?;
+            dict.set_item("average_distance", m.average_distance)?;
+            dict.set_item("fine_distance", m.fine_distance)?;
+            Ok(entry)
+        })
+        .collect()
+}
+
+/// Accessor for the stored hash named by `hash_type`
+fn stored_hash(hash_type: &str) -> PyResult<fn(&index::ImageRecord) -> &str> {
+    fn perceptual(record: &index::ImageRecord) -> &str {
+        &record.perceptual_hash
+    }
+    fn average(record: &index::ImageRecord) -> &str {
+        &record.average_hash
+    }
+    fn fine(record: &index::ImageRecord) -> &str {
+        &record.fine_hash
+    }
     
-    for row in arr.rows() {
-        for &pixel in row {
-            if pixel as u32 >= avg {
-                hash.push('1');
-            } else {
-                hash.push('0');
+    match hash_type {
+        "perceptual" => Ok(perceptual),
+        "average" => Ok(average),
+        "fine" => Ok(fine),
+        _ => Err(PyValueError::new_err(format!(
+            "Unsupported hash type '{}', expected 'perceptual', 'average' or 'fine'",
+            hash_type
+        ))),
+    }
+}
+
+/// Pick the medoid of each duplicate group as its representative
+///
+/// For every group of paths, the stored hash named by `hash_type`
+/// (`perceptual`, `average` or `fine`; None for the scan profile's) is read
+/// from `index` and the image with the minimum total Hamming distance to the
+/// rest of its group is chosen.
+/// Returns one dict per group with `representative` (None when no member has
+/// the hash), its `total_distance` and `mean_distance` to the others, and the
+/// paths `missing` from the index or lacking the hash.
+#[pyfunction]
+#[pyo3(signature = (index, groups, hash_type = None, source_prefix = ""))]
+fn group_representatives(
+    py: Python<'_>,
+    mut index: PyRefMut<'_, ImageIndex>,
+    groups: Vec<Vec<String>>,
+    hash_type: Option<&str>,
+    source_prefix: &str,
+) -> PyResult<Vec<PyObject>> {
+    let hash_of = stored_hash(hash_type.unwrap_or(profiles::active().hash_type))?;
+    let source_prefix = paths::normalize(source_prefix);
+    
+    let mut results = Vec::with_capacity(groups.len());
+    for group in &groups {
+        let mut members = Vec::with_capacity(group.len());
+        let mut missing = Vec::new();
+        for path in group {
+            // Keys are stored under the file's identity, as `ImageIndex.put` does
+            let key = paths::identity(path);
+            let hash = with_store(py, index.store.as_mut(), |store| store.get(&key, &source_prefix))?
+                .map(|record| hash_of(&record).to_string());
+            match hash {
+                Some(hash) if !hash.is_empty() => members.push((path.as_str(), hash)),
+                _ => missing.push(path.as_str()),
             }
         }
+        
+        let hashes: Vec<&str> = members.iter().map(|(_, hash)| hash.as_str()).collect();
+        let medoid = py.allow_threads(|| search::medoid(&hashes));
+        
+        let dict = PyDict::new(py);
+        dict.set_item("representative", medoid.map(|(i, _)| members[i].0))?;
+        dict.set_item("total_distance", medoid.map(|(_, total)| total))?;
+        let others = members.len().saturating_sub(1).max(1) as f64;
+        dict.set_item("mean_distance", medoid.map(|(_, total)| total as f64 / others))?;
+        dict.set_item("missing", missing)?;
+        results.push(dict.to_object(py));
     }
+    Ok(results)
+}
+
+/// How duplicate groups change across a range of Hamming thresholds
+///
+/// Every record in `index` is grouped by its `hash_type` hash (`perceptual`,
+/// `average` or `fine`; None for the scan profile's), linking images within
+/// the threshold transitively. Returns one dict per threshold (ascending,
+/// default 0-20) with the number of matching `pairs`, `groups`,
+/// `grouped_images`, `largest_group` and `mean_group_size`, so a threshold
+/// can be picked where groups stop growing into unrelated chains.
+#[pyfunction]
+#[pyo3(signature = (index, thresholds = None, hash_type = None))]
+fn threshold_sweep(
+    py: Python<'_>,
+    mut index: PyRefMut<'_, ImageIndex>,
+    thresholds: Option<Vec<u32>>,
+    hash_type: Option<&str>,
+) -> PyResult<Vec<PyObject>> {
+    let hash_of = stored_hash(hash_type.unwrap_or(profiles::active().hash_type))?;
+    let thresholds = thresholds.unwrap_or_else(|| (0..=20).collect());
+    let records = with_store(py, index.store.as_mut(), |store| store.records())?;
+    
+    let points = py.allow_threads(|| {
+        let hashes: Vec<&str> = records.iter().map(hash_of).collect();
+        sweep::threshold_sweep(&hashes, &thresholds)
+    });
     
-    Ok(hash)
+    points
+        .iter()
+        .map(|point| {
+            let dict = PyDict::new(py);
+            dict.set_item("threshold", point.threshold)?;
+            dict.set_item("pairs", point.pairs)?;
+            dict.set_item("groups", point.groups)?;
+            dict.set_item("grouped_images", point.grouped_images)?;
+            dict.set_item("largest_group", point.largest_group)?;
+            let mean = if point.groups == 0 { 0.0 } else { point.grouped_images as f64 / point.groups as f64 };
+            dict.set_item("mean_group_size", mean)?;
+            Ok(dict.to_object(py))
+        })
+        .collect()
 }
 
+/// Import duplicate groups and ignore-lists exported by czkawka or dupeGuru
+///
+/// `tool` is `czkawka` (JSON results of any mode) or `dupeguru` (a saved
+/// `.dupeguru` results file or `ignore_list.xml`); None guesses from the file.
+/// Groups become pair decisions in `index`: `duplicate`, or `keep_a`/`keep_b`
+/// where the export says which file to keep, and ignore-list entries become
+/// `not_duplicate`. Pairs that already have a decision are left alone. Their
+/// hashes use other algorithms, so no image records are written. Returns a
+/// dict with the `tool`, `groups`, `pairs` found, `imported` and `skipped`.
 #[pyfunction]
-fn rust_compute_perceptual_hash(_py: Python<'_>, image: PyReadonlyArray2<u8>) -> PyResult<String> {
-    let arr = image.as_array();
-    if arr.shape()[0] != 32 || arr.shape()[1] != 32 {
-        return Err(PyIOError::new_err("Image must be 32x32 for perceptual hash"));
+#[pyo3(signature = (index, path, tool = None))]
+fn import_duplicates(
+    py: Python<'_>,
+    mut index: PyRefMut<'_, ImageIndex>,
+    path: &str,
+    tool: Option<&str>,
+) -> PyResult<PyObject> {
+    let text = std::fs::read_to_string(path).map_err(|e| PyIOError::new_err(format!("Failed to read {}: {}", path, e)))?;
+    let tool = match tool {
+        Some(tool) => tool.to_lowercase(),
+        None => importers::detect_tool(&text)
+            .ok_or_else(|| PyValueError::new_err(format!("Cannot tell which tool exported {}", path)))?
+            .to_string(),
+    };
+    let imported = importers::import(&tool, &text).map_err(|e| match e.kind() {
+        std::io::ErrorKind::InvalidInput => PyValueError::new_err(e.to_string()),
+        _ => PyIOError::new_err(format!("Failed to import {}: {}", path, e)),
+    })?;
+    
+    let mut decided: std::collections::HashSet<(String, String)> =
+        with_store(py, index.store.as_mut(), |store| store.decisions())?
+            .into_iter()
+            .map(|d| (d.path_a, d.path_b))
+            .collect();
+    let mut new_decisions = Vec::new();
+    for (path_a, path_b, decision) in &imported.pairs {
+        let decision = index::DuplicateDecision {
+            path_a: paths::identity(path_a),
+            path_b: paths::identity(path_b),
+            decision: decision.to_string(),
+            location_a: path_a.to_string(),
+            location_b: path_b.to_string(),
+        }
+        .normalized();
+        if decision.path_a == decision.path_b || !decided.insert((decision.path_a.clone(), decision.path_b.clone())) {
+            continue;
+        }
+        new_decisions.push(decision);
     }
+    let imported_pairs = new_decisions.len();
+    with_store(py, index.store.as_mut(), |store| {
+        new_decisions.iter().try_for_each(|decision| store.put_decision(decision))?;
+        store.flush()
+    })?;
     
-    const REGIONS: usize = 8;
-    let region_height = arr.shape()[0] / REGIONS;
-    let region_width = arr.shape()[1] / REGIONS;
+    let dict = PyDict::new(py);
+    dict.set_item("tool", tool)?;
+    dict.set_item("groups", imported.groups)?;
+    dict.set_item("pairs", imported.pairs.len())?;
+    dict.set_item("imported", imported_pairs)?;
+    dict.set_item("skipped", imported.pairs.len() - imported_pairs)?;
+    Ok(dict.to_object(py))
+}
+
+/// Write the planned duplicate actions as a reviewable script instead of running them
+///
+/// Every `keep_a`/`keep_b` decision in `index` turns its other file into a
+/// step: `hardlink` replaces it with a hard link to the kept file, `delete`
+/// removes it and `move` moves it into `move_to`; without an `action` the
+/// config's `duplicate_policy` applies (hard links unless set). Chains of
+/// decisions resolve to the file kept in the end. `shell` is `sh` or `powershell` (default by
+/// platform); paths are quoted for that shell and every step first checks the
+/// kept file still exists. Returns the number of planned steps. To carry
+/// the steps out in-process instead, see `plan_actions` and `execute_actions`.
+#[pyfunction]
+#[pyo3(signature = (index, output_path, action = None, shell = None, move_to = None))]
+fn export_action_script(
+    py: Python<'_>,
+    mut index: PyRefMut<'_, ImageIndex>,
+    output_path: &str,
+    action: Option<&str>,
+    shell: Option<&str>,
+    move_to: Option<&str>,
+) -> PyResult<usize> {
+    let policy = script::Policy::resolve(action, move_to)?;
+    let shell = script::Shell::parse(shell)?;
     
-    // Calculate region values (optimized)
-    let mut region_values = vec![0.0; REGIONS * REGIONS];
+    let decisions = with_store(py, index.store.as_mut(), |store| store.decisions())?;
+    let plan = script::plan(&decisions);
+    std::fs::write(output_path, script::render(&plan, policy.action, shell, policy.move_to.as_deref()))
+        .map_err(|e| PyIOError::new_err(format!("Failed to write {}: {}", output_path, e)))?;
+    Ok(plan.len())
+}
+
+fn planned_actions(py: Python<'_>, index: &mut ImageIndex, action: Option<&str>, move_to: Option<&str>) -> PyResult<actions::Plan> {
+    let policy = script::Policy::resolve(action, move_to)?;
+    let decisions = with_store(py, index.store.as_mut(), |store| store.decisions())?;
+    Ok(actions::plan(script::plan(&decisions), policy.action, policy.move_to.as_deref()))
+}
+
+/// Plan the duplicate actions without touching any file
+///
+/// Takes the same `action` and `move_to` as `export_action_script` and
+/// returns `{action, hash, token, steps}`, `steps` being `{kept, duplicate}`
+/// dicts. `hash` covers every step and the size, mtime and identity of each
+/// file; `token` arms one `execute_actions` call for this plan, which is
+/// refused once any of those files changed.
+#[pyfunction]
+#[pyo3(signature = (index, action = None, move_to = None))]
+fn plan_actions(
+    py: Python<'_>,
+    mut index: PyRefMut<'_, ImageIndex>,
+    action: Option<&str>,
+    move_to: Option<&str>,
+) -> PyResult<PyObject> {
+    let plan = planned_actions(py, &mut index, action, move_to)?;
     
-    for i in 0..REGIONS {
-        for j in 0..REGIONS {
-            let start_y = i * region_height;
-            let end_y = (i + 1) * region_height;
-            let start_x = j * region_width;
-            let end_x = (j + 1) * region_width;
-            
-            let mut sum = 0u32;
-            let mut count = 0u32;
-            
-            for y in start_y..end_y {
-                for x in start_x..end_x {
-                    sum += arr[[y, x]] as u32;
-                    count += 1;
-                }
-            }
-            
-            region_values[i * REGIONS + j] = sum as f32 / count as f32;
+    let mut steps = Vec::with_capacity(plan.steps.len());
+    for step in &plan.steps {
+        let entry = PyDict::new(py);
+        entry.set_item("kept", &step.kept)?;
+        entry.set_item("duplicate", &step.duplicate)?;
+        steps.push(entry.to_object(py));
+    }
+    let result = PyDict::new(py);
+    result.set_item("action", plan.action.name())?;
+    result.set_item("hash", &plan.hash)?;
+    result.set_item("token", actions::issue_token(&plan.hash))?;
+    result.set_item("steps", steps)?;
+    Ok(result.to_object(py))
+}
+
+/// Carry out the duplicate actions; a dry run unless armed
+///
+/// By default nothing is touched and every step comes back as `planned`.
+/// Files are only linked, deleted or moved with `armed=True`, or with a
+/// `token` from `plan_actions`: a token works once, and is refused when the
+/// plan it was issued for no longer matches the index decisions or the
+/// files on disk. Each step also re-checks its files right before acting and
+/// is skipped if they changed. Returns `{dry_run, hash, steps}`, each step a
+/// `{kept, duplicate, status, reason}` dict with status `planned`, `done`,
+/// `skipped` or `failed`.
+#[pyfunction]
+#[pyo3(signature = (index, action = None, move_to = None, armed = false, token = None))]
+fn execute_actions(
+    py: Python<'_>,
+    mut index: PyRefMut<'_, ImageIndex>,
+    action: Option<&str>,
+    move_to: Option<&str>,
+    armed: bool,
+    token: Option<&str>,
+) -> PyResult<PyObject> {
+    let plan = planned_actions(py, &mut index, action, move_to)?;
+    if let Some(token) = token {
+        let planned = actions::redeem(token)
+            .ok_or_else(|| PyValueError::new_err("Unknown or already used plan token; call plan_actions again"))?;
+        if planned != plan.hash {
+            return Err(PyValueError::new_err(
+                "The plan changed since the token was issued (files or decisions differ); call plan_actions again",
+            ));
+        }
+    }
+    let dry_run = !armed && token.is_none();
+    
+    let outcomes = if dry_run {
+        plan.steps.iter().map(|_| actions::Outcome::Planned).collect()
+    } else {
+        py.allow_threads(|| actions::execute(&plan))
+    };
+    let mut steps = Vec::with_capacity(plan.steps.len());
+    for (step, outcome) in plan.steps.iter().zip(&outcomes) {
+        let entry = PyDict::new(py);
+        entry.set_item("kept", &step.kept)?;
+        entry.set_item("duplicate", &step.duplicate)?;
+        entry.set_item("status", outcome.status())?;
+        entry.set_item("reason", outcome.reason())?;
+        steps.push(entry.to_object(py));
+    }
+    let result = PyDict::new(py);
+    result.set_item("dry_run", dry_run)?;
+    result.set_item("hash", &plan.hash)?;
+    result.set_item("steps", steps)?;
+    Ok(result.to_object(py))
+}
+
+/// Names of the index backends compiled into this build
+#[pyfunction]
+fn get_index_backends() -> Vec<&'static str> {
+    index::backends()
+}
+
+/// Force the Bayer layout used for native demosaicing (`RGGB`, `BGGR`, `GRBG`, `GBRG`)
+///
+/// `None` goes back to reading the layout from each file's metadata.
+#[pyfunction]
+#[pyo3(signature = (pattern = None))]
+fn set_cfa_override(pattern: Option<&str>) -> PyResult<()> {
+    cfa::set_global_override(pattern.map(cfa::CfaPattern::parse).transpose()?);
+    Ok(())
+}
+
+/// Bayer layout of a RAW file as read by rawloader, or None if it has none
+#[pyfunction]
+fn rust_detect_cfa_pattern(py: Python<'_>, path: &str) -> Option<String> {
+    py.allow_threads(|| {
+        let raw_image = decode_file(path).ok()?;
+        (raw_image.cfa.width == 2 && raw_image.cfa.height == 2).then(|| raw_image.cfa.name.clone())
+    })
+}
+
+/// Choose how color is reduced to gray before thumbnails are hashed
+///
+/// `bt709` (the default, matching previously stored hashes) and `bt601` are
+/// the standard luma weights. `green` keeps only the green channel, which is
+/// the least noisy on a Bayer sensor and suits astrophotography; `max` keeps
+/// the brightest channel, for infrared-converted cameras and narrowband
+/// filters where one channel carries the image. Hashes computed under
+/// different modes are not comparable, so re-index after changing it.
+#[pyfunction]
+#[pyo3(signature = (mode = "bt709"))]
+fn set_grayscale_mode(mode: &str) -> PyResult<()> {
+    grayscale::set_luma_mode(grayscale::LumaMode::parse(mode)?);
+    Ok(())
+}
+
+/// The grayscale mode set with `set_grayscale_mode`
+#[pyfunction]
+fn get_grayscale_mode() -> &'static str {
+    grayscale::luma_mode().name()
+}
+
+/// Share encoded thumbnails between worker processes through `path`
+///
+/// Several processes may point at the same directory; access is serialized
+/// with advisory file locks. `None` turns the shared cache off.
+#[pyfunction]
+#[pyo3(signature = (path = None))]
+fn set_cache_dir(path: Option<String>) -> PyResult<()> {
+    thumbnails::set_disk_dir(path.map(std::path::PathBuf::from))
+        .map_err(|e| PyIOError::new_err(format!("Failed to create cache directory: {}", e)))
+}
+
+/// The shared thumbnail cache directory, or None
+#[pyfunction]
+fn get_cache_dir() -> Option<String> {
+    thumbnails::disk_dir_path().map(|p| p.to_string_lossy().into_owned())
+}
+
+/// Keep a persistent skip-list of poison files in `path`
+///
+/// Files that crash a decoder or time out in `rust_raw_to_grayscale_batch`
+/// are recorded with the reason; after `max_failures` such failures they
+/// are skipped on later runs (still reported, as `skipped`). A file that
+/// decodes again is dropped from the list. `None` turns skipping off.
+///
+/// Each failure is written to the list as it happens, and a marker is kept
+/// under `<path stem>.decoding/` while a file decodes, so a decode that kills
+/// the process (segfault, abort, out of memory) is counted on the next call.
+#[pyfunction]
+#[pyo3(signature = (path = None, max_failures = 2))]
+fn set_skip_list(path: Option<String>, max_failures: u32) -> PyResult<()> {
+    skiplist::configure(path.map(std::path::PathBuf::from), max_failures)
+        .map_err(|e| PyIOError::new_err(format!("Failed to read skip-list: {}", e)))
+}
+
+/// Files in the skip-list with `failures`, last `category` and `reason`, and
+/// whether they are `skipped` yet
+#[pyfunction]
+fn get_skip_list(py: Python<'_>) -> PyResult<Vec<PyObject>> {
+    skiplist::entries()
+        .into_iter()
+        .map(|(path, entry)| {
+            let dict = PyDict::new(py);
+            dict.set_item("skipped", skiplist::skipped(&path).is_some())?;
+            dict.set_item("path", path)?;
+            dict.set_item("failures", entry.failures)?;
+            dict.set_item("category", entry.category)?;
+            dict.set_item("reason", entry.reason)?;
+            Ok(dict.to_object(py))
+        })
+        .collect()
+}
+
+/// Give `path` (or every file, when None) another chance; returns how many
+/// entries were removed
+#[pyfunction]
+#[pyo3(signature = (path = None))]
+fn clear_skip_list(path: Option<&str>) -> PyResult<usize> {
+    let removed = skiplist::clear(path);
+    skiplist::save().map_err(|e| PyIOError::new_err(format!("Failed to write skip-list: {}", e)))?;
+    Ok(removed)
+}
+
+/// Call `handler(path, kind)` for placeholder files before giving up on them
+///
+/// Zero-byte files (`kind` "empty") and online-only cloud files ("cloud_only":
+/// OneDrive, Dropbox or iCloud content not on this machine) are caught from
+/// their metadata before any decoder runs, and fail at once as `placeholder`
+/// instead of going through every backend and timeout. A handler can download
+/// the file (e.g. ask the sync client to keep it on the device) and return
+/// True once it is local, and the file is then decoded as usual. Handlers run
+/// on worker threads with the GIL held. None removes the handler.
+#[pyfunction]
+#[pyo3(signature = (handler = None))]
+fn set_placeholder_handler(handler: Option<PyObject>) {
+    placeholders::set_handler(handler);
+}
+
+/// Stop the engine for a clean exit, e.g. from a GUI's quit handler
+///
+/// From now on no decode or external tool starts (calls raise RuntimeError)
+/// and tools still running are killed, so in-flight batches wind down with
+/// errors for their remaining files. Waits up to `timeout` seconds for the
+/// tools to exit, then writes the skip-list, drops the in-memory thumbnail
+/// cache and flushes every index still open. Returns a dict with the
+/// `processes_remaining` at the timeout, the number of `indexes_flushed` and
+/// any `errors`. Calling it again is harmless.
+#[pyfunction]
+#[pyo3(signature = (timeout = 5.0))]
+fn shutdown(py: Python<'_>, timeout: f64) -> PyResult<PyObject> {
+    lifecycle::begin_shutdown();
+    // Threads waiting for a process slot see the shutdown and give up
+    process::limits_changed();
+    
+    let timeout = Duration::try_from_secs_f64(timeout).unwrap_or(Duration::ZERO);
+    let remaining = py.allow_threads(|| {
+        let start = Instant::now();
+        while process::running() > 0 && start.elapsed() < timeout {
+            std::thread::sleep(Duration::from_millis(10));
         }
+        process::running()
+    });
+    
+    let mut errors = Vec::new();
+    if let Err(e) = skiplist::save() {
+        errors.push(format!("Failed to write skip-list: {}", e));
     }
+    thumbnails::clear();
+    
+    // Waits for index calls still running on other threads
+    let (flushed, failed) = py.allow_threads(index::flush_open);
+    errors.extend(failed.into_iter().map(|e| format!("Failed to flush index: {}", e)));
+    
+    let report = PyDict::new(py);
+    report.set_item("processes_remaining", remaining)?;
+    report.set_item("indexes_flushed", flushed)?;
+    report.set_item("errors", errors)?;
+    Ok(report.to_object(py))
+}
+
+/// Apply the settings in the JSON file at `path` and remember it for `reload`
+///
+/// Recognized keys are `scan_profile` (selected before the other keys),
+/// `max_external_processes`, `io_limits` (`bytes_per_second`,
+/// `ops_per_second`), `memory_budget`, `grayscale_mode`, `file_deadline`
+/// (seconds), `storage_policies` (`local`/`network` to `backends` and
+/// `max_processes`), `cache_dir`, `skip_list` (`path`, `max_failures`),
+/// `derived_file_rules`, `extension_handlers` (extension to handler, null
+/// to remove), `thumbnail_size`, `timeout` and `jpeg_quality` as in
+/// `configure`, `thresholds` (`duplicate_distance`, `similar_distance`,
+/// `fine_distance`: the default distances of `find_duplicate_groups` and
+/// `find_similar`), `watched_directories` (existing directories, see
+/// `watched_directories`) and `duplicate_policy` (`action` and `move_to`
+/// used when `plan_actions` and friends get no action). Keys left out keep
+/// their current values, except keys an earlier file set, which go back to
+/// their defaults. The whole file is validated, and the cache directory and
+/// skip-list prepared, before anything changes: a file that fails leaves every
+/// setting as it was. Returns the parsed file as a dict, so other
+/// application keys can be applied by the caller.
+#[pyfunction]
+fn load_config(py: Python<'_>, path: &str) -> PyResult<PyObject> {
+    let text = config::load(std::path::PathBuf::from(path))?;
+    Ok(py.import("json")?.call_method1("loads", (text,))?.to_object(py))
+}
+
+/// Re-read the file given to `load_config` and apply it again
+///
+/// Only settings change: indexes, caches and running batches are untouched,
+/// so a long-running process can be retuned without a restart.
+#[pyfunction]
+fn reload(py: Python<'_>) -> PyResult<PyObject> {
+    let text = config::reload()?;
+    Ok(py.import("json")?.call_method1("loads", (text,))?.to_object(py))
+}
+
+/// Directories named by `watched_directories` in the loaded config, for the
+/// caller's watch loop to pick up after a `reload`
+#[pyfunction]
+fn watched_directories() -> Vec<String> {
+    config::watched_directories().into_iter().map(|dir| dir.to_string_lossy().into_owned()).collect()
+}
+
+/// Install a SIGHUP handler that calls `reload`
+///
+/// Must be called from the main thread. A reload that fails (bad JSON, an
+/// unknown backend...) is reported as a RuntimeWarning and keeps the previous
+/// settings.
+#[pyfunction]
+fn reload_on_sighup(py: Python<'_>) -> PyResult<()> {
+    let signal = py.import("signal")?;
+    let sighup = signal
+        .getattr("SIGHUP")
+        .map_err(|_| PyValueError::new_err("SIGHUP is not available on this platform"))?;
+    let handler = PyCFunction::new_closure(py, None, None, |args: &PyTuple, _kwargs: Option<&PyDict>| -> PyResult<()> {
+        let py = args.py();
+        if let Err(e) = config::reload() {
+            PyErr::warn(py, py.get_type::<PyRuntimeWarning>(), &format!("Config reload failed: {}", e), 1)?;
+        }
+        Ok(())
+    })?;
+    signal.call_method1("signal", (sighup, handler))?;
+    Ok(())
+}
+
+/// Check that this build hashes exactly like the reference builds
+///
+/// Synthetic images embedded in the module go through the in-memory decode
+/// and the streaming TIFF paths at the regular thumbnail size, and every hash
+/// is compared with its known-good value. Run it before trusting an index
+/// shared with other platforms or builds: any mismatch means stored hashes
+/// from elsewhere are not comparable. Returns a dict with `passed` and per
+/// case `cases` (`name`, `passed`, `error`, and `mismatches` with the `hash`,
+/// `expected` and `actual` hex values and their bit `distance`).
+#[pyfunction]
+fn selftest(py: Python<'_>) -> PyResult<PyObject> {
+    let results = py.allow_threads(|| golden::run(THUMBNAIL_SIZE));
+    let cases = results
+        .iter()
+        .map(|result| {
+            let case = PyDict::new(py);
+            case.set_item("name", &result.name)?;
+            case.set_item("passed", result.passed())?;
+            case.set_item("error", &result.error)?;
+            let mismatches = result
+                .mismatches
+                .iter()
+                .map(|mismatch| {
+                    let dict = PyDict::new(py);
+                    dict.set_item("hash", mismatch.hash)?;
+                    dict.set_item("expected", &mismatch.expected)?;
+                    dict.set_item("actual", &mismatch.actual)?;
+                    dict.set_item("distance", mismatch.distance)?;
+                    Ok(dict.to_object(py))
+                })
+                .collect::<PyResult<Vec<_>>>()?;
+            case.set_item("mismatches", mismatches)?;
+            Ok(case.to_object(py))
+        })
+        .collect::<PyResult<Vec<_>>>()?;
     
-    // Calculate median (optimized)
-    let mut sorted_values = region_values.clone();
-    sorted_values.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    let median = sorted_values[REGIONS * REGIONS / 2];
+    let report = PyDict::new(py);
+    report.set_item("passed", results.iter().all(golden::CaseResult::passed))?;
+    report.set_item("cases", cases)?;
+    Ok(report.to_object(py))
+}
+
+/// Give every file a total processing budget of `seconds` (None for no limit)
+///
+/// Unlike the per-step timeout, the budget spans the whole fallback chain:
+/// preview extraction, every decode backend and hashing share it. Waiting for
+/// a process slot counts against it and external tools still running at the
+/// deadline are killed, so the file fails with a `timeout` instead of
+/// holding up an interactive caller. In-process decoding (rawloader, image
+/// crate) cannot be interrupted; the deadline is checked before each step.
+#[pyfunction]
+#[pyo3(signature = (seconds = None))]
+fn set_file_deadline(seconds: Option<f64>) -> PyResult<()> {
+    let budget = seconds
+        .map(|s| {
+            Duration::try_from_secs_f64(s)
+                .ok()
+                .filter(|d| !d.is_zero())
+                .ok_or_else(|| PyValueError::new_err("seconds must be a positive number"))
+        })
+        .transpose()?;
+    deadline::set_budget(budget);
+    Ok(())
+}
+
+/// The per-file budget in seconds, or None
+#[pyfunction]
+fn get_file_deadline() -> Option<f64> {
+    deadline::budget().map(|b| b.as_secs_f64())
+}
+
+/// Change the default thumbnail size, conversion step timeout or JPEG quality
+///
+/// `thumbnail_size` is the default side of `rust_raw_to_grayscale`,
+/// `rust_raw_to_grayscale_batch`, `rust_stream_grayscale` and the long edge of
+/// `get_thumbnail` (512 unless changed); larger previews allow finer matching.
+/// Hashes stored in an index are always computed from 512-pixel thumbnails,
+/// so they stay comparable. `timeout` is the number of seconds after which a
+/// RAW conversion stops falling back to further backends (4 unless changed),
+/// for slow network storage. `jpeg_quality` (1-100, 85 unless changed)
+/// applies to converted JPEGs and to JPEG thumbnails, which are cached per
+/// quality. Embedded previews are written as they are, without re-encoding.
+/// Arguments left out keep their current values; returns the settings now in
+/// effect as a dict.
+#[pyfunction]
+#[pyo3(signature = (thumbnail_size = None, timeout = None, jpeg_quality = None))]
+fn configure(
+    py: Python<'_>,
+    thumbnail_size: Option<u32>,
+    timeout: Option<f64>,
+    jpeg_quality: Option<u8>,
+) -> PyResult<PyObject> {
+    if thumbnail_size == Some(0) {
+        return Err(PyValueError::new_err("thumbnail_size must be greater than zero"));
+    }
+    let timeout = timeout
+        .map(|s| {
+            Duration::try_from_secs_f64(s)
+                .ok()
+                .filter(|d| !d.is_zero())
+                .ok_or_else(|| PyValueError::new_err("timeout must be a positive number of seconds"))
+        })
+        .transpose()?;
+    if jpeg_quality.is_some_and(|q| !(1..=100).contains(&q)) {
+        return Err(PyValueError::new_err("jpeg_quality must be between 1 and 100"));
+    }
     
-    // Create hash (optimized)
-    let mut hash = String::with_capacity(64);
-    for val in region_values {
-        hash.push(if val > median { '1' } else { '0' });
+    if let Some(size) = thumbnail_size {
+        tuning::set_thumbnail_size(size);
+    }
+    if let Some(timeout) = timeout {
+        tuning::set_step_timeout(timeout);
+    }
+    if let Some(quality) = jpeg_quality {
+        tuning::set_jpeg_quality(quality);
     }
     
-    Ok(hash)
+    let settings = PyDict::new(py);
+    settings.set_item("thumbnail_size", tuning::thumbnail_size())?;
+    settings.set_item("timeout", tuning::step_timeout().as_secs_f64())?;
+    settings.set_item("jpeg_quality", tuning::jpeg_quality())?;
+    Ok(settings.to_object(py))
 }
 
 /// A Python module implemented in Rust
 #[pymodule]
 fn raw_processor(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(rust_convert_raw_to_jpg, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_convert_raw_to_jpg_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_convert_raw_batch, m)?)?;
     m.add_function(wrap_pyfunction!(rust_raw_to_grayscale, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_raw_to_grayscale_batch, m)?)?;
     m.add_function(wrap_pyfunction!(rust_compute_average_hash, m)?)?;
     m.add_function(wrap_pyfunction!(rust_compute_perceptual_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_compute_dct_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_compute_difference_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_compute_wavelet_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_hamming_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_hamming_distance_matrix, m)?)?;
+    m.add_function(wrap_pyfunction!(hash_index, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_average_hash_input, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_perceptual_hash_input, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_salient_region, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_compute_roi_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_normalize_exposure, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_compute_edge_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_classify_content, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_compute_adaptive_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_grayscale_and_hashes, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_hash_file, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_hash_files, m)?)?;
+    m.add_function(wrap_pyfunction!(open_hash_cache, m)?)?;
+    m.add_class::<HashCache>()?;
+    m.add_function(wrap_pyfunction!(find_duplicate_groups, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_stream_grayscale, m)?)?;
+    m.add_function(wrap_pyfunction!(get_matching_profiles, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_match_images, m)?)?;
+    m.add_function(wrap_pyfunction!(explain_match, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_find_edited_matches, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_group_derivatives, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_render_contact_sheet, m)?)?;
+    m.add_function(wrap_pyfunction!(get_thumbnail, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_report_thumbnails, m)?)?;
+    m.add_function(wrap_pyfunction!(set_cache_dir, m)?)?;
+    m.add_function(wrap_pyfunction!(set_cfa_override, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_detect_cfa_pattern, m)?)?;
+    m.add_function(wrap_pyfunction!(set_grayscale_mode, m)?)?;
+    m.add_function(wrap_pyfunction!(get_grayscale_mode, m)?)?;
+    m.add_function(wrap_pyfunction!(get_cache_dir, m)?)?;
+    m.add_function(wrap_pyfunction!(set_skip_list, m)?)?;
+    m.add_function(wrap_pyfunction!(get_skip_list, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_skip_list, m)?)?;
+    m.add_function(wrap_pyfunction!(set_placeholder_handler, m)?)?;
+    m.add_function(wrap_pyfunction!(load_config, m)?)?;
+    m.add_function(wrap_pyfunction!(reload, m)?)?;
+    m.add_function(wrap_pyfunction!(watched_directories, m)?)?;
+    m.add_function(wrap_pyfunction!(reload_on_sighup, m)?)?;
+    m.add_function(wrap_pyfunction!(selftest, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_camera_profile, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_previews, m)?)?;
+    m.add_function(wrap_pyfunction!(probe, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_checksum_file, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_checksum_files, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_content_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_content_hashes, m)?)?;
+    m.add_function(wrap_pyfunction!(find_exact_duplicates, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_verify_checksums, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_group_brackets, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_split_stack_pairs, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_group_raw_pairs, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_split_raw_pairs, m)?)?;
+    m.add_function(wrap_pyfunction!(read_exif_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(diff_scans, m)?)?;
+    m.add_function(wrap_pyfunction!(simulate, m)?)?;
+    m.add_function(wrap_pyfunction!(camera_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(stable_group_ids, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_directory, m)?)?;
+    m.add_function(wrap_pyfunction!(export_library_fingerprint, m)?)?;
+    m.add_function(wrap_pyfunction!(compare_library_fingerprints, m)?)?;
+    m.add_function(wrap_pyfunction!(quick_scan, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_find_duplicate_directories, m)?)?;
+    m.add_function(wrap_pyfunction!(is_derived_file, m)?)?;
+    m.add_function(wrap_pyfunction!(derived_file_parent, m)?)?;
+    m.add_function(wrap_pyfunction!(filter_derived_files, m)?)?;
+    m.add_function(wrap_pyfunction!(add_derived_file_rule, m)?)?;
+    m.add_function(wrap_pyfunction!(set_derived_file_rules, m)?)?;
+    m.add_function(wrap_pyfunction!(get_derived_file_rules, m)?)?;
+    m.add_function(wrap_pyfunction!(register_extension, m)?)?;
+    m.add_function(wrap_pyfunction!(get_extension_handlers, m)?)?;
+    m.add_function(wrap_pyfunction!(set_max_external_processes, m)?)?;
+    m.add_function(wrap_pyfunction!(get_max_external_processes, m)?)?;
+    m.add_function(wrap_pyfunction!(interactive, m)?)?;
+    m.add_function(wrap_pyfunction!(set_file_deadline, m)?)?;
+    m.add_function(wrap_pyfunction!(get_file_deadline, m)?)?;
+    m.add_function(wrap_pyfunction!(configure, m)?)?;
+    m.add_function(wrap_pyfunction!(shutdown, m)?)?;
+    m.add_function(wrap_pyfunction!(set_storage_policy, m)?)?;
+    m.add_function(wrap_pyfunction!(get_storage_policy, m)?)?;
+    m.add_function(wrap_pyfunction!(set_extension_chain, m)?)?;
+    m.add_function(wrap_pyfunction!(extension_chains, m)?)?;
+    m.add_function(wrap_pyfunction!(backend_report, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_backend_report, m)?)?;
+    m.add_function(wrap_pyfunction!(auto_tune, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_preview_hashes, m)?)?;
+    m.add_function(wrap_pyfunction!(set_scan_profile, m)?)?;
+    m.add_function(wrap_pyfunction!(get_scan_profile, m)?)?;
+    m.add_function(wrap_pyfunction!(set_watermark_mask, m)?)?;
+    m.add_function(wrap_pyfunction!(get_scan_profiles, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_detect_storage, m)?)?;
+    m.add_function(wrap_pyfunction!(set_io_limits, m)?)?;
+    m.add_function(wrap_pyfunction!(get_io_limits, m)?)?;
+    m.add_function(wrap_pyfunction!(io_acquire, m)?)?;
+    m.add_function(wrap_pyfunction!(set_memory_budget, m)?)?;
+    m.add_function(wrap_pyfunction!(get_memory_usage, m)?)?;
+    m.add_function(wrap_pyfunction!(normalize_path, m)?)?;
+    m.add_function(wrap_pyfunction!(path_identity, m)?)?;
+    m.add_function(wrap_pyfunction!(is_case_insensitive_fs, m)?)?;
+    m.add_function(wrap_pyfunction!(unique_paths, m)?)?;
+    m.add_function(wrap_pyfunction!(open_index, m)?)?;
+    m.add_function(wrap_pyfunction!(calibrate_duplicates, m)?)?;
+    m.add_function(wrap_pyfunction!(score_pair, m)?)?;
+    m.add_function(wrap_pyfunction!(orientation_equivalence, m)?)?;
+    m.add_function(wrap_pyfunction!(get_index_backends, m)?)?;
+    m.add_function(wrap_pyfunction!(group_representatives, m)?)?;
+    m.add_function(wrap_pyfunction!(threshold_sweep, m)?)?;
+    m.add_function(wrap_pyfunction!(import_duplicates, m)?)?;
+    m.add_function(wrap_pyfunction!(export_action_script, m)?)?;
+    m.add_function(wrap_pyfunction!(plan_actions, m)?)?;
+    m.add_function(wrap_pyfunction!(execute_actions, m)?)?;
+    m.add_function(wrap_pyfunction!(find_similar, m)?)?;
+    m.add_function(wrap_pyfunction!(find_similar_frame, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_frame_hashes, m)?)?;
+    m.add_class::<HashIndex>()?;
+    m.add_class::<ImageIndex>()?;
+    m.add_class::<InteractiveScope>()?;
     m.add_function(wrap_pyfunction!(is_specific_raw_format, m)?)?;
     m.add_function(wrap_pyfunction!(rust_process_raf_file, m)?)?;
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tiff::fixtures::write_tiff;
+
+    /// Variant of a file whose IFD0 holds a JPEG thumbnail and IFD1 the raw data
+    fn variant_of(name: &str, ext: &str, raw_ifd: &[(u16, u32)]) -> RawVariant {
+        let thumbnail: &[(u16, u32)] = &[(crate::tiff::TAG_COMPRESSION, 6)];
+        let path = write_tiff(name, &[thumbnail, raw_ifd]);
+        let variant = detect_raw_variant(path.to_str().unwrap(), ext);
+        std::fs::remove_file(path).unwrap();
+        variant
+    }
+
+    #[test]
+    fn sony_lossless_arw_is_routed_to_libraw() {
+        let raw = [(crate::tiff::TAG_COMPRESSION, 7), (crate::tiff::TAG_SAMPLES_PER_PIXEL, 1)];
+        assert!(variant_of("lossless.arw", "arw", &raw) == RawVariant::SonyLossless);
+    }
+
+    #[test]
+    fn sony_compressed_arw_is_standard() {
+        let raw = [(crate::tiff::TAG_COMPRESSION, 32767), (crate::tiff::TAG_SAMPLES_PER_PIXEL, 1)];
+        assert!(variant_of("compressed.arw", "arw", &raw) == RawVariant::Standard);
+    }
+
+    #[test]
+    fn small_nef_is_detected() {
+        let raw = [(crate::tiff::TAG_COMPRESSION, 34713), (crate::tiff::TAG_SAMPLES_PER_PIXEL, 3)];
+        assert!(variant_of("small.nef", "nef", &raw) == RawVariant::NikonSmall);
+    }
+
+    #[test]
+    fn compressed_nef_is_detected() {
+        let raw = [(crate::tiff::TAG_COMPRESSION, 34713), (crate::tiff::TAG_SAMPLES_PER_PIXEL, 1)];
+        assert!(variant_of("compressed.nef", "nef", &raw) == RawVariant::NikonCompressed);
+        // SamplesPerPixel defaults to 1 when missing
+        let raw = [(crate::tiff::TAG_COMPRESSION, 34713)];
+        assert!(variant_of("compressed_default.nef", "nef", &raw) == RawVariant::NikonCompressed);
+    }
+
+    #[test]
+    fn uncompressed_nef_is_standard() {
+        let raw = [(crate::tiff::TAG_COMPRESSION, 1), (crate::tiff::TAG_SAMPLES_PER_PIXEL, 1)];
+        assert!(variant_of("uncompressed.nef", "nef", &raw) == RawVariant::Standard);
+    }
+
+    #[test]
+    fn other_formats_are_not_inspected() {
+        let raw = [(crate::tiff::TAG_COMPRESSION, 34713), (crate::tiff::TAG_SAMPLES_PER_PIXEL, 3)];
+        assert!(variant_of("other.dng", "dng", &raw) == RawVariant::Standard);
+    }
+}
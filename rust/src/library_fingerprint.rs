@@ -0,0 +1,171 @@
+// src/library_fingerprint.rs
+// Compact gzip'd listing of a library's relative paths, sizes and hashes, for comparing libraries across machines
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::bktree::BkTree;
+use crate::hamming::Packed;
+
+const MAGIC: &[u8; 8] = b"IMGFP\x00\x00\x01";
+
+/// Bytes of the BLAKE3 digest kept per file; 128 bits is plenty to tell files apart
+pub const CHECKSUM_BYTES: usize = 16;
+
+/// One file of a library
+pub struct Record {
+    /// Relative to the library root, '/'-separated
+    pub path: String,
+    pub size: u64,
+    /// Leading bytes of the BLAKE3 content digest
+    pub checksum: [u8; CHECKSUM_BYTES],
+    pub average_hash: u64,
+    pub perceptual_hash: u64,
+}
+
+impl Record {
+    /// From a hex BLAKE3 digest and 64-bit '0'/'1' hash strings; None if any is malformed
+    pub fn new(path: String, size: u64, content_hash: &str, average_hash: &str, perceptual_hash: &str) -> Option<Self> {
+        let mut checksum = [0u8; CHECKSUM_BYTES];
+        for (i, byte) in checksum.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(content_hash.get(2 * i..2 * i + 2)?, 16).ok()?;
+        }
+        let bits = |hash: &str| (hash.len() == 64).then(|| u64::from_str_radix(hash, 2).ok()).flatten();
+        Some(Record { path, size, checksum, average_hash: bits(average_hash)?, perceptual_hash: bits(perceptual_hash)? })
+    }
+}
+
+/// Write `records` to `path`
+///
+/// After the magic and record count, each record is its path length (u16)
+/// and UTF-8 path, size (u64), checksum and the two hashes (u64), little
+/// endian, all gzip-compressed. Sorted paths compress well, so a library
+/// costs a few tens of bytes per file.
+pub fn write(path: &str, records: &[Record]) -> io::Result<()> {
+    let mut out = GzEncoder::new(BufWriter::new(File::create(path)?), Compression::best());
+    out.write_all(MAGIC)?;
+    out.write_all(&(records.len() as u64).to_le_bytes())?;
+    for record in records {
+        let name = record.path.as_bytes();
+        let length = u16::try_from(name.len()).map_err(|_| io::Error::other(format!("Path too long: {}", record.path)))?;
+        out.write_all(&length.to_le_bytes())?;
+        out.write_all(name)?;
+        out.write_all(&record.size.to_le_bytes())?;
+        out.write_all(&record.checksum)?;
+        out.write_all(&record.average_hash.to_le_bytes())?;
+        out.write_all(&record.perceptual_hash.to_le_bytes())?;
+    }
+    out.finish()?.flush()
+}
+
+/// Read the records `write` stored at `path`
+pub fn read(path: &str) -> io::Result<Vec<Record>> {
+    let mut input = GzDecoder::new(BufReader::new(File::open(path)?));
+    let mut magic = [0u8; 8];
+    input.read_exact(&mut magic).map_err(|_| invalid("not a library fingerprint"))?;
+    if &magic != MAGIC {
+        return Err(invalid("not a library fingerprint"));
+    }
+
+    let count = read_u64(&mut input)?;
+    // The count is untrusted, so it only bounds the preallocation
+    let mut records = Vec::with_capacity(count.min(1 << 20) as usize);
+    for _ in 0..count {
+        let mut length = [0u8; 2];
+        input.read_exact(&mut length)?;
+        let mut name = vec![0u8; u16::from_le_bytes(length) as usize];
+        input.read_exact(&mut name)?;
+        let path = String::from_utf8(name).map_err(|_| invalid("path is not UTF-8"))?;
+        let size = read_u64(&mut input)?;
+        let mut checksum = [0u8; CHECKSUM_BYTES];
+        input.read_exact(&mut checksum)?;
+        let average_hash = read_u64(&mut input)?;
+        let perceptual_hash = read_u64(&mut input)?;
+        records.push(Record { path, size, checksum, average_hash, perceptual_hash });
+    }
+    Ok(records)
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// How two libraries relate, as indices into their record lists
+#[derive(Default)]
+pub struct Comparison {
+    /// Same contents on both sides (same size and checksum), wherever they are filed
+    pub identical: Vec<(usize, usize)>,
+    /// Different bytes but the same picture (re-encoded, resized, metadata edits), with the perceptual distance
+    pub similar: Vec<(usize, usize, u32)>,
+    pub only_local: Vec<usize>,
+    pub only_remote: Vec<usize>,
+}
+
+/// Match every local record against the remote library
+///
+/// Byte-identical files pair up first, one to one. Each remaining local file
+/// then takes its nearest remaining remote file whose perceptual and average
+/// hashes are both within `max_distance`; None skips this visual stage.
+pub fn compare(local: &[Record], remote: &[Record], max_distance: Option<u32>) -> Comparison {
+    let mut comparison = Comparison::default();
+    let mut by_content: HashMap<(u64, [u8; CHECKSUM_BYTES]), Vec<usize>> = HashMap::new();
+    for (i, record) in remote.iter().enumerate().rev() {
+        by_content.entry((record.size, record.checksum)).or_default().push(i);
+    }
+    let mut remote_matched = vec![false; remote.len()];
+    let mut unmatched = Vec::new();
+    for (i, record) in local.iter().enumerate() {
+        match by_content.get_mut(&(record.size, record.checksum)).and_then(Vec::pop) {
+            Some(j) => {
+                remote_matched[j] = true;
+                comparison.identical.push((i, j));
+            },
+            None => unmatched.push(i),
+        }
+    }
+
+    if let Some(max_distance) = max_distance {
+        // Tree keys are remote indices
+        let mut tree = BkTree::default();
+        for (j, record) in remote.iter().enumerate().filter(|(j, _)| !remote_matched[*j]) {
+            tree.insert(j.to_string(), packed(record.perceptual_hash));
+        }
+        unmatched.retain(|&i| {
+            let record = &local[i];
+            let nearest = tree
+                .query(&packed(record.perceptual_hash), max_distance)
+                .into_iter()
+                .filter_map(|(key, distance)| Some((key.parse::<usize>().ok()?, distance)))
+                .filter(|&(j, _)| (record.average_hash ^ remote[j].average_hash).count_ones() <= max_distance)
+                .min_by_key(|&(j, distance)| (distance, j));
+            match nearest {
+                Some((j, distance)) => {
+                    tree.remove(&j.to_string());
+                    remote_matched[j] = true;
+                    comparison.similar.push((i, j, distance));
+                    false
+                },
+                None => true,
+            }
+        });
+    }
+
+    comparison.only_local = unmatched;
+    comparison.only_remote = (0..remote.len()).filter(|&j| !remote_matched[j]).collect();
+    comparison
+}
+
+fn packed(hash: u64) -> Packed {
+    Packed::from_str(&format!("{:064b}", hash)).expect("binary digits")
+}
@@ -0,0 +1,28 @@
+// src/lifecycle.rs
+// Process-wide shutdown: once begun, no new decode or external tool starts
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::PyResult;
+
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+pub const SHUT_DOWN_MESSAGE: &str = "raw_processor has been shut down";
+
+/// Refuse new work from now on; running tools are killed by their waiters
+pub fn begin_shutdown() {
+    SHUT_DOWN.store(true, Ordering::SeqCst);
+}
+
+pub fn is_shut_down() -> bool {
+    SHUT_DOWN.load(Ordering::Relaxed)
+}
+
+/// Error out of entry points once the engine is shut down
+pub fn check() -> PyResult<()> {
+    if is_shut_down() {
+        return Err(PyRuntimeError::new_err(SHUT_DOWN_MESSAGE));
+    }
+    Ok(())
+}
@@ -0,0 +1,46 @@
+// src/locking.rs
+// Advisory file locks so several worker processes can share caches and indexes
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// An advisory lock on a lock file, released when dropped
+pub struct FileLock {
+    _file: File,
+}
+
+fn open_lock_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).truncate(false).read(true).write(true).open(path)
+}
+
+impl FileLock {
+    /// Block until no other process holds any lock on `path`
+    pub fn exclusive(path: &Path) -> io::Result<Self> {
+        let file = open_lock_file(path)?;
+        file.lock()?;
+        Ok(FileLock { _file: file })
+    }
+
+    /// Block until no other process holds an exclusive lock on `path`
+    pub fn shared(path: &Path) -> io::Result<Self> {
+        let file = open_lock_file(path)?;
+        file.lock_shared()?;
+        Ok(FileLock { _file: file })
+    }
+}
+
+/// `path` with `suffix` appended to its file name, e.g. `index.tsv` -> `index.tsv.lock`
+pub fn sibling(path: &Path, suffix: &str) -> std::path::PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    name.into()
+}
+
+/// Temp file name unique to this process and call, for write-then-rename
+pub fn unique_temp(path: &Path) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    sibling(path, &format!(".{}.{}.tmp", std::process::id(), n))
+}
@@ -0,0 +1,226 @@
+// src/matching.rs
+// Named matching profiles: multi-hash voting with per-profile tolerances
+
+use image::{imageops, DynamicImage};
+
+use crate::exposure;
+use crate::grayscale;
+use crate::hashing;
+use crate::profiles;
+use crate::saliency;
+
+// Side of the grayscale working image each variant is reduced to
+const WORKING_SIZE: u32 = 256;
+
+/// Tolerances and preprocessing for one kind of "same photo" question
+pub struct MatchProfile {
+    pub name: &'static str,
+    /// Maximum Hamming distances for the average, perceptual and edge hashes
+    pub max_distances: [u32; 3],
+    /// How many of the three hashes must agree
+    pub min_votes: usize,
+    /// Histogram-equalize before hashing so exposure and grading shifts cancel out
+    pub equalize: bool,
+    /// Also hash centered crops, so a cropped export still meets its original
+    pub crop_variants: bool,
+    /// Also hash the salient region, so the subject matches against a changed background
+    pub salient_variant: bool,
+    /// Also hash centered crops to common export shapes, so an export cut to
+    /// another aspect ratio still meets its original
+    pub aspect_variants: bool,
+    /// Leave out the active scan profile's watermark mask (borders and corners) before hashing
+    pub mask_watermarks: bool,
+}
+
+// Width / height of the shapes photos are usually exported in
+const EXPORT_ASPECTS: [f32; 9] =
+    [1.0, 4.0 / 5.0, 5.0 / 4.0, 2.0 / 3.0, 3.0 / 2.0, 3.0 / 4.0, 4.0 / 3.0, 9.0 / 16.0, 16.0 / 9.0];
+
+static PROFILES: &[MatchProfile] = &[
+    // Byte-different copies of the same rendering (re-saves, metadata edits)
+    MatchProfile {
+        name: "strict",
+        max_distances: [2, 4, 4],
+        min_votes: 3,
+        equalize: false,
+        crop_variants: false,
+        salient_variant: false,
+        aspect_variants: false,
+        mask_watermarks: false,
+    },
+    MatchProfile {
+        name: "default",
+        max_distances: [6, 10, 12],
+        min_votes: 2,
+        equalize: false,
+        crop_variants: false,
+        salient_variant: false,
+        aspect_variants: false,
+        mask_watermarks: false,
+    },
+    // Same photo, different edit: crop + exposure + color grade
+    MatchProfile {
+        name: "edited",
+        max_distances: [12, 16, 18],
+        min_votes: 2,
+        equalize: true,
+        crop_variants: true,
+        salient_variant: true,
+        aspect_variants: false,
+        mask_watermarks: false,
+    },
+    // A RAW file against JPEGs exported from it: resized, recropped to another
+    // shape and rendered by a different converter than the decode here
+    MatchProfile {
+        name: "derivative",
+        max_distances: [14, 18, 20],
+        min_votes: 2,
+        equalize: true,
+        crop_variants: true,
+        salient_variant: false,
+        aspect_variants: true,
+        mask_watermarks: false,
+    },
+    // Exports stamped with a watermark, logo or date, or framed with a
+    // border, against their clean originals
+    MatchProfile {
+        name: "watermarked",
+        max_distances: [8, 12, 14],
+        min_votes: 2,
+        equalize: false,
+        crop_variants: false,
+        salient_variant: false,
+        aspect_variants: false,
+        mask_watermarks: true,
+    },
+];
+
+/// Find a profile by name
+pub fn lookup(name: &str) -> Option<&'static MatchProfile> {
+    PROFILES.iter().find(|p| p.name == name)
+}
+
+/// Names of all built-in profiles
+pub fn profile_names() -> Vec<&'static str> {
+    PROFILES.iter().map(|p| p.name).collect()
+}
+
+/// Hashes of every variant of one image, as (average, perceptual, edge) bit patterns
+pub struct Fingerprint {
+    variants: Vec<[u64; 3]>,
+}
+
+/// Outcome of comparing two fingerprints under a profile
+pub struct MatchResult {
+    pub matched: bool,
+    pub votes: usize,
+    /// Hamming distances of the best-agreeing variant pair
+    pub distances: [u32; 3],
+}
+
+fn bits(hash: &str) -> u64 {
+    u64::from_str_radix(hash, 2).unwrap_or(0)
+}
+
+fn grayscale_pixels(img: &DynamicImage, profile: &MatchProfile) -> Vec<u8> {
+    let mut pixels = grayscale::to_gray(img, grayscale::luma_mode())
+        .resize_exact(WORKING_SIZE, WORKING_SIZE, imageops::FilterType::Triangle)
+        .to_luma8()
+        .into_raw();
+    if profile.equalize {
+        exposure::equalize(&mut pixels);
+    }
+    pixels
+}
+
+fn hash_pixels(pixels: &[u8], side: usize) -> [u64; 3] {
+    [
+        bits(&hashing::average_hash(hashing::area_downsample(pixels, side, 8).view())),
+        bits(&hashing::perceptual_hash(hashing::area_downsample(pixels, side, 32).view())),
+        bits(&hashing::edge_hash(hashing::area_downsample(pixels, side, 128).view())),
+    ]
+}
+
+fn hash_variant(img: &DynamicImage, profile: &MatchProfile) -> [u64; 3] {
+    hash_pixels(&grayscale_pixels(img, profile), WORKING_SIZE as usize)
+}
+
+/// Centered crop keeping `fraction` of each dimension
+fn center_crop(img: &DynamicImage, fraction: f32) -> DynamicImage {
+    let (width, height) = (img.width(), img.height());
+    let (w, h) = ((width as f32 * fraction) as u32, (height as f32 * fraction) as u32);
+    img.crop_imm((width - w) / 2, (height - h) / 2, w.max(1), h.max(1))
+}
+
+/// Largest centered crop with width / height `aspect`
+fn aspect_crop(img: &DynamicImage, aspect: f32) -> DynamicImage {
+    let (width, height) = (img.width(), img.height());
+    let (w, h) = if width as f32 / height as f32 > aspect {
+        ((height as f32 * aspect) as u32, height)
+    } else {
+        (width, (width as f32 / aspect) as u32)
+    };
+    img.crop_imm((width - w.min(width)) / 2, (height - h.min(height)) / 2, w.max(1), h.max(1))
+}
+
+/// Hash an image under a profile
+pub fn fingerprint(img: &DynamicImage, profile: &MatchProfile) -> Fingerprint {
+    let masked;
+    let img = if profile.mask_watermarks {
+        masked = profiles::watermark_mask(profiles::active()).apply(img);
+        &masked
+    } else {
+        img
+    };
+    let side = WORKING_SIZE as usize;
+    let pixels = grayscale_pixels(img, profile);
+    let mut variants = vec![hash_pixels(&pixels, side)];
+
+    if profile.salient_variant {
+        let roi = saliency::salient_region(&pixels, side, saliency::RoiMode::Saliency);
+        variants.push(hash_pixels(&saliency::crop(&pixels, side, roi), roi.side));
+    }
+
+    if profile.crop_variants {
+        variants.push(hash_variant(&center_crop(img, 0.85), profile));
+        variants.push(hash_variant(&center_crop(img, 0.7), profile));
+
+        // Square and portrait exports cut from a landscape frame
+        let short = img.width().min(img.height());
+        let square = img.crop_imm((img.width() - short) / 2, (img.height() - short) / 2, short, short);
+        variants.push(hash_variant(&square, profile));
+    }
+
+    if profile.aspect_variants {
+        let aspect = img.width() as f32 / img.height().max(1) as f32;
+        for target in EXPORT_ASPECTS {
+            // The square is already there with the crop variants
+            if (target / aspect - 1.0).abs() < 0.02 || (profile.crop_variants && target == 1.0) {
+                continue;
+            }
+            variants.push(hash_variant(&aspect_crop(img, target), profile));
+        }
+    }
+
+    Fingerprint { variants }
+}
+
+/// Compare two fingerprints, taking the best-agreeing pair of variants
+pub fn compare(a: &Fingerprint, b: &Fingerprint, profile: &MatchProfile) -> MatchResult {
+    let mut best = MatchResult { matched: false, votes: 0, distances: [64; 3] };
+
+    for va in &a.variants {
+        for vb in &b.variants {
+            let distances = [0, 1, 2].map(|i| (va[i] ^ vb[i]).count_ones());
+            let votes = (0..3).filter(|&i| distances[i] <= profile.max_distances[i]).count();
+
+            let total: u32 = distances.iter().sum();
+            let best_total: u32 = best.distances.iter().sum();
+            if votes > best.votes || (votes == best.votes && total < best_total) {
+                best = MatchResult { matched: votes >= profile.min_votes, votes, distances };
+            }
+        }
+    }
+
+    best
+}
@@ -0,0 +1,80 @@
+// src/memory.rs
+// Process-wide memory budget for in-flight decodes
+
+use std::sync::{Condvar, Mutex};
+
+/// Decoded working set relative to file size (16-bit raw plane + 8-bit RGB)
+const DECODE_BYTES_PER_FILE_BYTE: u64 = 6;
+
+/// Fraction of the budget above which decoders switch to low-memory paths
+const PRESSURE_RATIO: f64 = 0.5;
+
+/// Fraction of the budget above which caches are dropped
+const SHED_RATIO: f64 = 0.8;
+
+struct State {
+    /// 0 means unlimited
+    budget: u64,
+    in_use: u64,
+}
+
+static STATE: Mutex<State> = Mutex::new(State { budget: 0, in_use: 0 });
+static RELEASED: Condvar = Condvar::new();
+
+/// Set the total memory budget in bytes (0 disables it)
+pub fn set_budget(bytes: u64) {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    state.budget = bytes;
+    RELEASED.notify_all();
+}
+
+/// Current budget and estimated bytes held by in-flight decodes
+pub fn usage() -> (u64, u64) {
+    let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    (state.budget, state.in_use)
+}
+
+/// True when decoders should prefer their low-memory paths
+pub fn under_pressure() -> bool {
+    let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    state.budget > 0 && state.in_use as f64 > state.budget as f64 * PRESSURE_RATIO
+}
+
+/// Estimated working set of a decode, held until dropped
+pub struct Reservation {
+    bytes: u64,
+}
+
+impl Reservation {
+    /// Reserve memory for decoding a file of `file_size` bytes
+    ///
+    /// Blocks while the budget is exhausted, which lowers the effective
+    /// concurrency; one decode is always let through so progress is made.
+    /// `shed_caches` runs when the reservation pushes usage near the limit.
+    pub fn acquire(file_size: u64, shed_caches: impl FnOnce()) -> Self {
+        let bytes = file_size.saturating_mul(DECODE_BYTES_PER_FILE_BYTE);
+        let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+
+        while state.budget > 0 && state.in_use > 0 && state.in_use + bytes > state.budget {
+            state = RELEASED.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+
+        state.in_use += bytes;
+        let shed = state.budget > 0 && state.in_use as f64 > state.budget as f64 * SHED_RATIO;
+        drop(state);
+
+        if shed {
+            shed_caches();
+        }
+
+        Reservation { bytes }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+        state.in_use = state.in_use.saturating_sub(self.bytes);
+        RELEASED.notify_all();
+    }
+}
@@ -0,0 +1,88 @@
+// src/moves.rs
+// Rename and move detection between an index and a new scan, by content rather than path
+
+use std::collections::BTreeMap;
+
+use crate::index::ImageRecord;
+
+/// What identifies a file's content across paths; None when the record has no checksum
+fn content_key(record: &ImageRecord) -> Option<(u64, &str)> {
+    (!record.content_hash.is_empty()).then_some((record.size, record.content_hash.as_str()))
+}
+
+/// Indices of the vanished and added records sharing one content key
+type Bucket = (Vec<usize>, Vec<usize>);
+
+/// Match records of files that disappeared (`old`) with newly scanned ones
+/// (`new`) holding the same content, as `(old index, new index)` pairs
+///
+/// Content is the same when the sizes and content checksums agree; image
+/// hashes are not enough, since a burst of same-size RAWs can share them.
+/// Records without a checksum never match, and neither do identical files
+/// when more than one vanished or appeared, as there is no telling which
+/// went where.
+pub fn detect(old: &[ImageRecord], new: &[ImageRecord]) -> Vec<(usize, usize)> {
+    let mut buckets: BTreeMap<(u64, &str), Bucket> = BTreeMap::new();
+    for (i, record) in old.iter().enumerate() {
+        if let Some(key) = content_key(record) {
+            buckets.entry(key).or_default().0.push(i);
+        }
+    }
+    for (i, record) in new.iter().enumerate() {
+        if let Some(key) = content_key(record) {
+            buckets.entry(key).or_default().1.push(i);
+        }
+    }
+
+    let mut pairs: Vec<(usize, usize)> = buckets
+        .into_values()
+        .filter_map(|(vanished, added)| match (vanished.as_slice(), added.as_slice()) {
+            (&[v], &[a]) => Some((v, a)),
+            _ => None,
+        })
+        .collect();
+    pairs.sort_by(|a, b| old[a.0].path.cmp(&old[b.0].path));
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(path: &str, size: u64, content_hash: &str) -> ImageRecord {
+        ImageRecord {
+            path: path.to_string(),
+            size,
+            average_hash: "ffff0000ffff0000".to_string(),
+            perceptual_hash: "0f0f0f0f0f0f0f0f".to_string(),
+            fine_hash: "ab".repeat(32),
+            content_hash: content_hash.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matches_on_checksum_not_image_hashes() {
+        // Same size and image hashes, as in a burst of uncompressed RAWs
+        let old = vec![record("/a/IMG_1.CR2", 100, "c1"), record("/a/IMG_2.CR2", 100, "c2")];
+        let new = vec![record("/b/IMG_2.CR2", 100, "c2"), record("/b/IMG_1.CR2", 100, "c1")];
+        assert_eq!(detect(&old, &new), vec![(0, 1), (1, 0)]);
+
+        let unrelated = vec![record("/b/IMG_3.CR2", 100, "c3")];
+        assert!(detect(&old, &unrelated).is_empty());
+        let resized = vec![record("/b/IMG_1.CR2", 99, "c1")];
+        assert!(detect(&old, &resized).is_empty());
+    }
+
+    #[test]
+    fn refuses_ambiguous_and_unchecksummed_matches() {
+        let old = vec![record("/a/x.jpg", 10, "same"), record("/a/y.jpg", 10, "same")];
+        let new = vec![record("/b/x.jpg", 10, "same")];
+        assert!(detect(&old, &new).is_empty());
+        assert!(detect(&new, &old).is_empty());
+
+        let old = vec![record("/a/x.jpg", 10, "")];
+        let new = vec![record("/b/x.jpg", 10, "")];
+        assert!(detect(&old, &new).is_empty());
+    }
+}
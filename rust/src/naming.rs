@@ -0,0 +1,314 @@
+// src/naming.rs
+// Output file names for batch conversions from templates like `{date}/{camera}/{stem}.jpg`
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::PyResult;
+
+use crate::{orientation, tiff};
+
+/// Placeholders a template may use
+pub const FIELDS: &[&str] = &[
+    "stem", "name", "src_ext", "ext", "width", "height", "date", "year", "month", "day", "make", "camera", "index",
+];
+
+enum Segment {
+    Literal(String),
+    Field(&'static str),
+}
+
+/// A parsed naming template; `/` separates directories, `{{` and `}}` are literal braces
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> PyResult<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                },
+                '{' => {
+                    let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                    let field = FIELDS.iter().find(|f| **f == name).ok_or_else(|| {
+                        PyValueError::new_err(format!(
+                            "Unsupported template field '{}', expected one of {}",
+                            name,
+                            FIELDS.join(", ")
+                        ))
+                    })?;
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(field));
+                },
+                '}' => return Err(PyValueError::new_err("Unmatched '}' in naming template")),
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        if segments.is_empty() {
+            return Err(PyValueError::new_err("Naming template is empty"));
+        }
+        let absolute = matches!(segments.first(), Some(Segment::Literal(text)) if text.starts_with('/'));
+        let backslash = segments.iter().any(|s| matches!(s, Segment::Literal(text) if text.contains('\\')));
+        if absolute || backslash {
+            return Err(PyValueError::new_err("Naming template must be a relative path using '/' separators"));
+        }
+        Ok(Template { segments })
+    }
+
+    pub fn uses(&self, field: &str) -> bool {
+        self.segments.iter().any(|s| matches!(s, Segment::Field(f) if *f == field))
+    }
+
+    /// Relative output path for one file; field values cannot add directories
+    /// and `..` components cannot leave the output directory
+    pub fn render(&self, values: &Values) -> PathBuf {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => rendered.push_str(text),
+                Segment::Field(field) => rendered.push_str(&sanitize(&values.get(field))),
+            }
+        }
+        rendered.split('/').filter(|c| !c.is_empty() && *c != ".").map(safe_component).collect()
+    }
+}
+
+/// What a template's fields expand to for one file
+pub struct Values {
+    pub source: String,
+    pub index: usize,
+    pub width: u32,
+    pub height: u32,
+    pub metadata: Metadata,
+}
+
+impl Values {
+    fn get(&self, field: &str) -> String {
+        let path = Path::new(&self.source);
+        let os = |s: Option<&std::ffi::OsStr>| s.map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let date = self.metadata.date;
+        match field {
+            "stem" => os(path.file_stem()),
+            "name" => os(path.file_name()),
+            "src_ext" => os(path.extension()).to_lowercase(),
+            "ext" => "jpg".to_string(),
+            "width" => self.width.to_string(),
+            "height" => self.height.to_string(),
+            "date" => date.map(|(y, m, d)| format!("{:04}-{:02}-{:02}", y, m, d)).unwrap_or_default(),
+            "year" => date.map(|(y, _, _)| format!("{:04}", y)).unwrap_or_default(),
+            "month" => date.map(|(_, m, _)| format!("{:02}", m)).unwrap_or_default(),
+            "day" => date.map(|(_, _, d)| format!("{:02}", d)).unwrap_or_default(),
+            "make" => self.metadata.make.clone().unwrap_or_default(),
+            "camera" => self.metadata.camera.clone().unwrap_or_default(),
+            "index" => self.index.to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Replace characters that are invalid in file names on any platform, and
+/// directory separators so a value stays within its path component
+fn sanitize(value: &str) -> String {
+    let cleaned: String = value
+        .chars()
+        .map(|c| if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') { '_' } else { c })
+        .collect();
+    match cleaned.trim() {
+        "" => "unknown".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Windows rejects these names (with any extension) and trailing dots or
+/// spaces; `..` ends up as `_` the same way
+fn safe_component(component: &str) -> String {
+    let component = component.trim_end_matches(['.', ' ']);
+    let stem = component.split('.').next().unwrap_or_default().to_ascii_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || (stem.len() == 4
+            && (stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.as_bytes()[3].is_ascii_digit());
+    match (reserved, component.is_empty()) {
+        (_, true) => "_".to_string(),
+        (true, _) => format!("_{}", component),
+        _ => component.to_string(),
+    }
+}
+
+/// Camera and capture date from IFD0 of a JPEG or TIFF-based file
+#[derive(Default)]
+pub struct Metadata {
+    pub make: Option<String>,
+    pub camera: Option<String>,
+    /// `(year, month, day)`; the file's modification date when the tag is missing
+    pub date: Option<(i32, u32, u32)>,
+}
+
+/// Civil date of a count of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i32, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = (yoe + era * 400 + (month <= 2) as i64) as i32;
+    (year, month, day)
+}
+
+/// `YYYY:MM:DD HH:MM:SS` as written by cameras
+fn parse_exif_date(text: &str) -> Option<(i32, u32, u32)> {
+    let mut parts = text.get(..10)?.split(':');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok().filter(|m| (1..=12).contains(m))?;
+    let day = parts.next()?.parse().ok().filter(|d| (1..=31).contains(d))?;
+    Some((year, month, day))
+}
+
+/// Count of days since 1970-01-01 of a civil date, the inverse of `civil_from_days`
+fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let year = year as i64 - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Seconds since the epoch of a `YYYY:MM:DD HH:MM:SS` camera time, read as UTC
+///
+/// Cameras record local time without a zone, so only differences between
+/// values are meaningful; a trailing zone or sub-second part is ignored.
+pub fn parse_exif_datetime(text: &str) -> Option<f64> {
+    let (year, month, day) = parse_exif_date(text)?;
+    let mut clock = text.get(11..19)?.split(':').map(|part| part.parse::<u32>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let seconds = days_from_civil(year, month, day) * 86_400 + (hour * 3600 + minute * 60 + second) as i64;
+    Some(seconds as f64)
+}
+
+/// Make, model and DateTime from IFD0 only, without the modification date fallback
+pub fn read_exif(path: &str) -> Metadata {
+    let mut metadata = Metadata::default();
+    let base = orientation::jpeg_exif_offset(path).unwrap_or(0);
+    if let Ok(mut file) = tiff::TiffFile::open_at(path, base) {
+        if let Some(ifd0) = file.ifds().ok().and_then(|ifds| ifds.into_iter().next()) {
+            let mut ascii = |tag| ifd0.find(tag).and_then(|entry| file.value_ascii(entry)).filter(|s| !s.is_empty());
+            metadata.make = ascii(tiff::TAG_MAKE);
+            metadata.camera = ascii(tiff::TAG_MODEL);
+            metadata.date = ascii(tiff::TAG_DATE_TIME).as_deref().and_then(parse_exif_date);
+        }
+    }
+    metadata
+}
+
+pub fn read_metadata(path: &str) -> Metadata {
+    let mut metadata = read_exif(path);
+    if metadata.date.is_none() {
+        metadata.date = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since| civil_from_days((since.as_secs() / 86_400) as i64));
+    }
+    metadata
+}
+
+/// What to do when a rendered name is already taken
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Collision {
+    /// Append `_1`, `_2`... before the extension
+    Suffix,
+    Overwrite,
+    Skip,
+}
+
+impl Collision {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "suffix" => Ok(Collision::Suffix),
+            "overwrite" => Ok(Collision::Overwrite),
+            "skip" => Ok(Collision::Skip),
+            _ => Err(PyValueError::new_err(format!(
+                "Unsupported collision policy '{}', expected 'suffix', 'overwrite' or 'skip'",
+                name
+            ))),
+        }
+    }
+}
+
+/// Names handed out in one batch, so parallel workers never pick the same file
+#[derive(Default)]
+pub struct Claims {
+    claimed: Mutex<HashSet<PathBuf>>,
+}
+
+impl Claims {
+    /// Claim `wanted` or a free variant of it; None when `Skip` finds it taken
+    pub fn claim(&self, wanted: PathBuf, collision: Collision) -> Option<PathBuf> {
+        let mut claimed = self.claimed.lock().unwrap_or_else(|e| e.into_inner());
+        let taken = |path: &Path| claimed.contains(path) || path.exists();
+        let path = match collision {
+            // Two files of one batch still never overwrite each other
+            Collision::Overwrite if !claimed.contains(&wanted) => wanted,
+            Collision::Skip if taken(&wanted) => return None,
+            _ if !taken(&wanted) => wanted,
+            _ => {
+                let stem = wanted.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+                let ext = wanted.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+                (1..)
+                    .map(|n| wanted.with_file_name(format!("{}_{}{}", stem, n, ext)))
+                    .find(|candidate| !taken(candidate))?
+            },
+        };
+        claimed.insert(path.clone());
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_round_trip() {
+        for days in [-719_468, -1, 0, 59, 11_016, 19_723, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn exif_datetime_is_epoch_seconds() {
+        assert_eq!(parse_exif_datetime("1970:01:01 00:00:00"), Some(0.0));
+        assert_eq!(parse_exif_datetime("2024:02:29 12:34:56"), Some(1_709_210_096.0));
+        assert_eq!(parse_exif_datetime("2024:02:29 12:34:56+02:00"), Some(1_709_210_096.0));
+        assert_eq!(parse_exif_datetime("2024:02:29"), None);
+        assert_eq!(parse_exif_datetime("0000:00:00 00:00:00"), None);
+    }
+}
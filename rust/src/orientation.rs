@@ -0,0 +1,148 @@
+// src/orientation.rs
+// EXIF orientation lookup and normalization of decoded pixels to display orientation
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+
+use image::DynamicImage;
+
+use crate::{provenance, tiff};
+
+// JPEG headers scanned for the EXIF segment before giving up
+const MAX_JPEG_SEGMENTS: usize = 64;
+
+/// Offset of the TIFF header inside the EXIF APP1 segment of a JPEG
+pub fn jpeg_exif_offset(path: &str) -> Option<u64> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let mut soi = [0u8; 2];
+    reader.read_exact(&mut soi).ok()?;
+    if soi != [0xff, 0xd8] {
+        return None;
+    }
+
+    for _ in 0..MAX_JPEG_SEGMENTS {
+        let mut marker = [0u8; 4];
+        reader.read_exact(&mut marker).ok()?;
+        // Start of scan or a stray byte: no more metadata segments
+        if marker[0] != 0xff || marker[1] == 0xda {
+            return None;
+        }
+        let length = u16::from_be_bytes([marker[2], marker[3]]) as i64;
+
+        if marker[1] == 0xe1 {
+            let mut signature = [0u8; 6];
+            reader.read_exact(&mut signature).ok()?;
+            if &signature == b"Exif\0\0" {
+                return reader.stream_position().ok();
+            }
+            reader.seek_relative(length - 2 - 6).ok()?;
+        } else {
+            reader.seek_relative(length - 2).ok()?;
+        }
+    }
+    None
+}
+
+/// EXIF orientation (1-8) of a JPEG or TIFF-based file, 1 when absent
+///
+/// RAW files are read from IFD0, so the value applies to their embedded
+/// previews, which are stored unrotated.
+pub fn read(path: &str) -> u16 {
+    let base = jpeg_exif_offset(path).unwrap_or(0);
+    let Ok(mut file) = tiff::TiffFile::open_at(path, base) else {
+        return 1;
+    };
+    let Ok(ifds) = file.ifds() else {
+        return 1;
+    };
+    ifds.first()
+        .and_then(|ifd0| ifd0.find(tiff::TAG_ORIENTATION))
+        .and_then(|entry| file.value_u32(entry))
+        .map(|value| value as u16)
+        .filter(|value| (1..=8).contains(value))
+        .unwrap_or(1)
+}
+
+/// Display orientation of pixels decoded from a file tagged `orientation`
+///
+/// `source` is the conversion that produced them (None for images read
+/// directly); decodes that already rotated are returned as they are.
+pub fn to_display(img: DynamicImage, orientation: u16, source: Option<provenance::Source>) -> DynamicImage {
+    if source.is_some_and(|source| source.oriented) {
+        img
+    } else {
+        apply(img, orientation)
+    }
+}
+
+/// Turn stored pixels into the orientation a viewer would display
+pub fn apply(img: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GenericImageView, Rgb, RgbImage};
+
+    use super::*;
+    use crate::tiff::fixtures::{temp_path, tiff_bytes, write_tiff};
+
+    /// 2x1 image, red on the left, blue on the right
+    fn landscape() -> DynamicImage {
+        let mut img = RgbImage::new(2, 1);
+        img.put_pixel(0, 0, Rgb([255, 0, 0]));
+        img.put_pixel(1, 0, Rgb([0, 0, 255]));
+        DynamicImage::ImageRgb8(img)
+    }
+
+    /// JPEG headers with an EXIF block holding `orientation`, cut off at the scan
+    fn write_jpeg(name: &str, orientation: u32) -> String {
+        let exif = tiff_bytes(&[&[(tiff::TAG_ORIENTATION, orientation)]]);
+        let mut data = vec![0xff, 0xd8, 0xff, 0xe1];
+        data.extend_from_slice(&((2 + 6 + exif.len()) as u16).to_be_bytes());
+        data.extend_from_slice(b"Exif\0\0");
+        data.extend_from_slice(&exif);
+        data.extend_from_slice(&[0xff, 0xda, 0x00, 0x02]);
+        let path = temp_path(name);
+        std::fs::write(&path, data).expect("write test JPEG");
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn orientation_is_read_from_jpeg_and_tiff() {
+        let jpeg = write_jpeg("rotated.jpg", 6);
+        let raw = write_tiff("rotated.dng", &[&[(tiff::TAG_ORIENTATION, 8)]]);
+        let plain = write_tiff("plain.dng", &[&[(tiff::TAG_MAKE, 0)]]);
+        let read_all = [read(&jpeg), read(&raw.to_string_lossy()), read(&plain.to_string_lossy())];
+        for path in [std::path::PathBuf::from(jpeg), raw, plain] {
+            let _ = std::fs::remove_file(path);
+        }
+        assert_eq!(read_all, [6, 8, 1]);
+    }
+
+    #[test]
+    fn stored_pixels_are_rotated_for_display() {
+        let rotated = to_display(landscape(), 6, None);
+        assert_eq!(rotated.dimensions(), (1, 2));
+        // 90 degrees clockwise: the left edge ends up on top
+        assert_eq!(rotated.get_pixel(0, 0).0[..3], [255, 0, 0]);
+        assert_eq!(rotated.get_pixel(0, 1).0[..3], [0, 0, 255]);
+    }
+
+    #[test]
+    fn decodes_already_rotated_are_left_alone() {
+        let libraw = provenance::Source { backend: "libraw", full_decode: true, oriented: true };
+        let preview = provenance::Source { backend: "embedded_preview", full_decode: false, oriented: false };
+        assert_eq!(to_display(landscape(), 6, Some(libraw)).dimensions(), (2, 1));
+        assert_eq!(to_display(landscape(), 6, Some(preview)).dimensions(), (1, 2));
+    }
+}
@@ -0,0 +1,82 @@
+// src/output.rs
+// Where a conversion step puts its result: the JPEG file the caller asked for, or memory
+
+use std::cell::{Cell, RefCell};
+
+use image::DynamicImage;
+
+use crate::tuning;
+
+/// Output path to hand the conversion steps while a `ScopedCapture` is active
+pub const IN_MEMORY: &str = "";
+
+/// A step's result before anyone needed it as a file
+enum Captured {
+    /// JPEG bytes of an embedded preview, decoded only when taken
+    Encoded(Vec<u8>),
+    Image(DynamicImage),
+}
+
+thread_local! {
+    static CAPTURING: Cell<bool> = const { Cell::new(false) };
+    static CAPTURED: RefCell<Option<Captured>> = const { RefCell::new(None) };
+}
+
+/// Keeps the result of the conversion on this thread in memory instead of
+/// writing it to the output path, until dropped
+///
+/// For callers that only want the pixels: previews go from the parser or a
+/// tool's stdout straight into the decoder and rendered images are never
+/// encoded, so no file is written anywhere, which read-only mounts and two
+/// workers on the same source both need.
+pub struct ScopedCapture {
+    previous: bool,
+}
+
+impl ScopedCapture {
+    pub fn start() -> Self {
+        let previous = CAPTURING.with(|cell| cell.replace(true));
+        CAPTURED.with(|captured| captured.borrow_mut().take());
+        ScopedCapture { previous }
+    }
+
+    /// The image delivered since `start`; None if no step delivered one
+    pub fn take(&self) -> Option<image::ImageResult<DynamicImage>> {
+        match CAPTURED.with(|captured| captured.borrow_mut().take())? {
+            Captured::Encoded(data) => Some(image::load_from_memory(&data)),
+            Captured::Image(img) => Some(Ok(img)),
+        }
+    }
+}
+
+impl Drop for ScopedCapture {
+    fn drop(&mut self) {
+        CAPTURING.with(|cell| cell.set(self.previous));
+        CAPTURED.with(|captured| captured.borrow_mut().take());
+    }
+}
+
+/// Whether results on this thread stay in memory
+pub fn capturing() -> bool {
+    CAPTURING.with(|cell| cell.get())
+}
+
+/// Hand over an extracted JPEG: kept in memory under a `ScopedCapture`,
+/// otherwise written to `jpg_path` as is. Returns whether it was delivered.
+pub fn deliver_jpeg(jpg_path: &str, data: Vec<u8>) -> bool {
+    if capturing() {
+        CAPTURED.with(|captured| *captured.borrow_mut() = Some(Captured::Encoded(data)));
+        return true;
+    }
+    std::fs::write(jpg_path, data).is_ok()
+}
+
+/// Hand over a rendered image: kept in memory under a `ScopedCapture`,
+/// otherwise saved to `jpg_path` as JPEG
+pub fn deliver_image(jpg_path: &str, img: DynamicImage) -> image::ImageResult<()> {
+    if capturing() {
+        CAPTURED.with(|captured| *captured.borrow_mut() = Some(Captured::Image(img)));
+        return Ok(());
+    }
+    tuning::save_jpeg(&img, jpg_path)
+}
@@ -0,0 +1,487 @@
+// src/paths.rs
+// Path normalization and file identity so index keys match across operating systems
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use unicode_normalization::UnicodeNormalization;
+
+/// True for `C:\...`, `C:/...` and `\\server\share` paths
+fn is_windows_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    path.starts_with("\\\\") || (bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && matches!(bytes[2], b'\\' | b'/'))
+}
+
+/// Canonical spelling of a path for use as an index key
+///
+/// - Unicode is composed to NFC: macOS reports decomposed (NFD) names, Linux
+///   and Windows keep whatever was written, usually NFC.
+/// - Trailing dots and spaces are dropped from each component, as Windows
+///   does when it creates the file.
+/// - Windows paths (drive letter or UNC) use `/` separators.
+/// - Repeated separators and `.` components are collapsed; `..` is kept,
+///   since resolving it without the filesystem could cross a symlink.
+///
+/// Spaces and emoji inside names are preserved as-is, and so are names made
+/// only of dots or spaces (`...`), which have nothing left to trim to.
+pub fn normalize(path: &str) -> String {
+    let composed: String = path.nfc().collect();
+    let composed = if is_windows_path(&composed) { composed.replace('\\', "/") } else { composed };
+
+    let absolute = composed.starts_with('/');
+    let unc = composed.starts_with("//");
+    let components: Vec<&str> = composed
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .map(|c| match c.trim_end_matches(['.', ' ']) {
+            "" => c,
+            trimmed => trimmed,
+        })
+        .collect();
+
+    let prefix = if unc {
+        "//"
+    } else if absolute {
+        "/"
+    } else {
+        ""
+    };
+    format!("{}{}", prefix, components.join("/"))
+}
+
+// Directory listings used to recover on-disk spelling; cleared wholesale when it grows past this
+const MAX_CACHED_LISTINGS: usize = 4096;
+
+fn case_cache() -> &'static Mutex<HashMap<u64, bool>> {
+    static CACHE: OnceLock<Mutex<HashMap<u64, bool>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn listing_cache() -> &'static Mutex<HashMap<PathBuf, HashMap<String, String>>> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, HashMap<String, String>>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn swap_case(name: &str) -> Option<String> {
+    let swapped: String = name
+        .chars()
+        .map(|c| if c.is_uppercase() { c.to_lowercase().next().unwrap_or(c) } else { c.to_uppercase().next().unwrap_or(c) })
+        .collect();
+    (swapped != name).then_some(swapped)
+}
+
+/// Filesystem types that fold case on Linux
+#[cfg(target_os = "linux")]
+const CASE_INSENSITIVE_FILESYSTEMS: &[&str] = &["vfat", "msdos", "exfat", "ntfs", "ntfs3", "fuseblk", "cifs", "smb3", "smbfs"];
+
+/// Ask the filesystem holding `dir` whether it folds case, without writing to it
+#[cfg(unix)]
+fn probe_case_insensitive(dir: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    // Any existing ancestor with a cased letter in its name answers the question
+    for candidate in dir.ancestors() {
+        let (Some(parent), Some(name)) = (candidate.parent(), candidate.file_name().and_then(|n| n.to_str())) else {
+            continue;
+        };
+        let Some(swapped) = swap_case(name) else {
+            continue;
+        };
+        let original = fs::metadata(candidate).ok()?;
+        return Some(
+            fs::metadata(parent.join(swapped))
+                .map(|m| m.dev() == original.dev() && m.ino() == original.ino())
+                .unwrap_or(false),
+        );
+    }
+    volume_case_insensitive(dir)
+}
+
+/// `pathconf(_PC_CASE_SENSITIVE)`: APFS and HFS+ volumes can be either
+#[cfg(target_os = "macos")]
+fn volume_case_insensitive(dir: &Path) -> Option<bool> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    // SAFETY: `dir` is a NUL-terminated path that outlives the call
+    match unsafe { libc::pathconf(dir.as_ptr(), libc::_PC_CASE_SENSITIVE) } {
+        -1 => None,
+        sensitive => Some(sensitive == 0),
+    }
+}
+
+/// From the mount table: FAT, exFAT, NTFS and SMB mounts fold case
+#[cfg(target_os = "linux")]
+fn volume_case_insensitive(dir: &Path) -> Option<bool> {
+    crate::storage::filesystem_type(dir).map(|fs_type| CASE_INSENSITIVE_FILESYSTEMS.contains(&fs_type.as_str()))
+}
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "linux"))))]
+fn volume_case_insensitive(_dir: &Path) -> Option<bool> {
+    None
+}
+
+/// True if the filesystem holding `path` treats names differing only in case as the same file
+///
+/// Probed once per device (macOS volumes can be either) and cached. The
+/// probe only reads: a cased ancestor looked up under its swapped case, else
+/// the volume's own answer (`pathconf` on macOS, the mount type on Linux).
+#[cfg(unix)]
+pub fn is_case_insensitive(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Some(dir) = path.ancestors().find(|p| p.is_dir()) else {
+        return false;
+    };
+    let Ok(device) = fs::metadata(dir).map(|m| m.dev()) else {
+        return false;
+    };
+
+    if let Some(&known) = case_cache().lock().unwrap_or_else(|e| e.into_inner()).get(&device) {
+        return known;
+    }
+    let insensitive = probe_case_insensitive(dir).unwrap_or(false);
+    case_cache().lock().unwrap_or_else(|e| e.into_inner()).insert(device, insensitive);
+    insensitive
+}
+
+/// True if the filesystem holding `path` treats names differing only in case as the same file
+#[cfg(not(unix))]
+pub fn is_case_insensitive(_path: &Path) -> bool {
+    cfg!(windows)
+}
+
+/// The on-disk spelling of `name` inside `dir`, re-reading the listing on a miss
+fn entry_case(dir: &Path, name: &str) -> Option<String> {
+    let key = name.to_lowercase();
+    let mut cache = listing_cache().lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(actual) = cache.get(dir).and_then(|listing| listing.get(&key)) {
+        return Some(actual.clone());
+    }
+
+    let listing: HashMap<String, String> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok()?.file_name().into_string().ok())
+        .map(|actual| (actual.nfc().collect::<String>().to_lowercase(), actual.nfc().collect()))
+        .collect();
+    let actual = listing.get(&key).cloned();
+
+    if cache.len() >= MAX_CACHED_LISTINGS {
+        cache.clear();
+    }
+    cache.insert(dir.to_path_buf(), listing);
+    actual
+}
+
+/// Identity of a file for index keys and duplicate reporting
+///
+/// The normalized path, and on case-insensitive filesystems with every
+/// component spelled as it is on disk, so `IMG_0001.CR2` and `img_0001.cr2`
+/// map to one identity. Components that do not exist keep the given spelling,
+/// so a file that is gone still maps to the identity it was indexed under as
+/// long as it was given in the same case.
+///
+/// Only for comparing paths: normalizing can turn the path of one file into
+/// that of a missing file or a sibling, so never open, walk or change the
+/// filesystem through an identity.
+pub fn identity(path: &str) -> String {
+    let normalized = normalize(path);
+    let as_path = Path::new(&normalized);
+    if !is_case_insensitive(as_path) {
+        return normalized;
+    }
+
+    let mut current = PathBuf::new();
+    for component in as_path.components() {
+        match component {
+            Component::Normal(name) => {
+                let name = name.to_string_lossy();
+                let actual = entry_case(if current.as_os_str().is_empty() { Path::new(".") } else { &current }, &name);
+                current.push(actual.as_deref().unwrap_or(&name));
+            },
+            other => current.push(other.as_os_str()),
+        }
+    }
+    current.to_string_lossy().into_owned()
+}
+
+/// `path` relative to the directory `base`, with `/` separators
+///
+/// Both are made absolute against the current directory first; `..` climbs
+/// out of `base`. Used for links in reports written to `base`.
+pub fn relative_to(path: &Path, base: &Path) -> std::io::Result<String> {
+    let path = std::path::absolute(path)?;
+    let base = std::path::absolute(base)?;
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+
+    let shared = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let parts: Vec<String> = std::iter::repeat_n("..".to_string(), base.len() - shared)
+        .chain(path[shared..].iter().map(|c| c.as_os_str().to_string_lossy().into_owned()))
+        .collect();
+    Ok(parts.join("/"))
+}
+
+/// Hash naming intermediate output of `source`: its path plus its size and
+/// modification time when it exists, so a changed file never maps to a temp
+/// name used for its earlier content
+fn source_hash(source: &str) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(source.as_bytes());
+    if let Ok(metadata) = fs::metadata(source) {
+        hasher.update(&metadata.len().to_le_bytes());
+        if let Ok(mtime) = metadata.modified().and_then(|t| t.duration_since(std::time::UNIX_EPOCH).map_err(std::io::Error::other)) {
+            hasher.update(&mtime.as_nanos().to_le_bytes());
+        }
+    }
+    hasher.finalize().to_hex()[..16].to_string()
+}
+
+fn next_temp_id() -> u64 {
+    static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+    TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Unique path in the system temp directory for intermediate output of `source`
+///
+/// The name is a hash of the source's path and content identity, the process
+/// id and a process-wide counter, and never reuses the source's file name, so
+/// 250+ character names or Windows device names (`CON`, `AUX`...) cannot
+/// produce over-long or reserved temp paths. Extensions that are not short
+/// and alphanumeric become `tmp`.
+pub fn temp_file(source: &str, ext: &str) -> PathBuf {
+    let ext = if (1..=8).contains(&ext.len()) && ext.bytes().all(|b| b.is_ascii_alphanumeric()) { ext } else { "tmp" };
+    std::env::temp_dir().join(format!("raw_processor_{}_{}_{}.{}", source_hash(source), std::process::id(), next_temp_id(), ext))
+}
+
+/// Private directory in the system temp directory, removed with its contents on drop
+///
+/// For tools that write their output next to their input: the input is
+/// linked in under a short name and the tool runs there.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// New empty directory named like `temp_file`
+    pub fn new(source: &str) -> std::io::Result<Self> {
+        let path = std::env::temp_dir().join(format!("raw_processor_{}_{}_{}", source_hash(source), std::process::id(), next_temp_id()));
+        fs::create_dir(&path)?;
+        Ok(TempDir { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Make `source` available inside the directory as `name`, without copying it
+    pub fn link(&self, source: &str, name: &str) -> std::io::Result<PathBuf> {
+        let target = self.path.join(name);
+        let source = std::path::absolute(source)?;
+        if fs::hard_link(&source, &target).is_err() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&source, &target)?;
+            #[cfg(windows)]
+            std::os::windows::fs::symlink_file(&source, &target)?;
+        }
+        Ok(target)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Short hidden name next to `path` for staging its replacement
+///
+/// Renaming the staged file over `path` stays on one filesystem. The name is
+/// a hash of `path`'s file name, so it fits NAME_MAX however long that is.
+pub fn sibling_temp(path: &Path) -> PathBuf {
+    let hash = blake3::hash(path.file_name().unwrap_or_default().as_encoded_bytes()).to_hex();
+    path.with_file_name(format!(".raw_processor_{}.tmp", &hash[..16]))
+}
+
+/// Rename `from` to `to`, failing with `AlreadyExists` rather than replacing
+/// a file at `to`, even one created while the rename runs
+#[cfg(target_os = "linux")]
+pub fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_from = std::ffi::CString::new(from.as_os_str().as_bytes())?;
+    let c_to = std::ffi::CString::new(to.as_os_str().as_bytes())?;
+    // SAFETY: both paths are NUL-terminated and outlive the call
+    let renamed = unsafe {
+        libc::renameat2(libc::AT_FDCWD, c_from.as_ptr(), libc::AT_FDCWD, c_to.as_ptr(), libc::RENAME_NOREPLACE)
+    };
+    if renamed == 0 {
+        return Ok(());
+    }
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+        // Filesystems without RENAME_NOREPLACE, and kernels before 3.15
+        Some(libc::EINVAL) | Some(libc::ENOSYS) => link_and_unlink(from, to),
+        _ => Err(error),
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    link_and_unlink(from, to)
+}
+
+/// `link` fails when `to` exists, so `from` only disappears once it is at `to`
+#[cfg(unix)]
+fn link_and_unlink(from: &Path, to: &Path) -> io::Result<()> {
+    fs::hard_link(from, to)?;
+    fs::remove_file(from)
+}
+
+/// `MoveFileExW` without `MOVEFILE_REPLACE_EXISTING` fails when `to` exists
+#[cfg(windows)]
+pub fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn MoveFileExW(existing_file_name: *const u16, new_file_name: *const u16, flags: u32) -> i32;
+    }
+    const MOVEFILE_COPY_ALLOWED: u32 = 2;
+
+    let from: Vec<u16> = from.as_os_str().encode_wide().chain([0]).collect();
+    let to: Vec<u16> = to.as_os_str().encode_wide().chain([0]).collect();
+    // SAFETY: both paths are NUL-terminated UTF-16 strings that outlive the call
+    if unsafe { MoveFileExW(from.as_ptr(), to.as_ptr(), MOVEFILE_COPY_ALLOWED) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decomposed_names_compose() {
+        let nfd = "/photos/Cafe\u{301}/Mu\u{308}nchen.jpg";
+        let nfc = "/photos/Caf\u{e9}/M\u{fc}nchen.jpg";
+        assert_eq!(normalize(nfd), nfc);
+        assert_eq!(normalize(nfd), normalize(nfc));
+    }
+
+    #[test]
+    fn emoji_and_spaces_are_kept() {
+        assert_eq!(normalize("/photos/\u{1f436} dog/my  photo \u{1f389}.jpg"), "/photos/\u{1f436} dog/my  photo \u{1f389}.jpg");
+        assert_eq!(normalize("/photos/ leading space.jpg"), "/photos/ leading space.jpg");
+    }
+
+    #[test]
+    fn trailing_dots_and_spaces_are_dropped() {
+        assert_eq!(normalize("/photos/2019. /IMG_0001.jpg. "), "/photos/2019/IMG_0001.jpg");
+        assert_eq!(normalize("C:\\Photos\\trip...\\a.jpg"), "C:/Photos/trip/a.jpg");
+    }
+
+    #[test]
+    fn dot_components() {
+        assert_eq!(normalize("/photos/./2019//a.jpg"), "/photos/2019/a.jpg");
+        assert_eq!(normalize("/photos/../2019/a.jpg"), "/photos/../2019/a.jpg");
+        assert_eq!(normalize("/photos/.../a.jpg"), "/photos/.../a.jpg");
+        assert_eq!(normalize("photos/a.jpg"), "photos/a.jpg");
+    }
+
+    #[test]
+    fn windows_and_unc_paths_use_forward_slashes() {
+        assert_eq!(normalize("C:\\Photos\\2019\\a.jpg"), "C:/Photos/2019/a.jpg");
+        assert_eq!(normalize("\\\\server\\share\\Photos\\a.jpg"), "//server/share/Photos/a.jpg");
+        assert_eq!(normalize("//server/share/Photos/a.jpg"), "//server/share/Photos/a.jpg");
+    }
+
+    #[test]
+    fn posix_backslashes_are_part_of_the_name() {
+        assert_eq!(normalize("/photos/a\\b.jpg"), "/photos/a\\b.jpg");
+    }
+
+    #[test]
+    fn missing_files_keep_their_normalized_identity() {
+        let missing = std::env::temp_dir().join("raw_processor_missing").join("Cafe\u{301}.jpg. ");
+        let identity = identity(&missing.to_string_lossy());
+        assert_eq!(identity, normalize(&missing.to_string_lossy()));
+        assert!(identity.ends_with("Caf\u{e9}.jpg"));
+    }
+
+    #[test]
+    fn case_is_swapped_per_character() {
+        assert_eq!(swap_case("IMG_0001.cr2").as_deref(), Some("img_0001.CR2"));
+        assert_eq!(swap_case("2019_01"), None);
+    }
+
+    #[test]
+    fn relative_paths_climb_out_of_base() {
+        assert_eq!(relative_to(Path::new("/a/b/c.jpg"), Path::new("/a/d")).unwrap(), "../b/c.jpg");
+        assert_eq!(relative_to(Path::new("/a/b/c.jpg"), Path::new("/a")).unwrap(), "b/c.jpg");
+    }
+
+    #[test]
+    fn temp_files_never_reuse_the_source_name() {
+        let long = format!("/photos/{}.CR2", "x".repeat(300));
+        let temp = temp_file(&long, "tiff");
+        assert!(temp.file_name().unwrap().len() < 100);
+        assert_eq!(temp.extension().unwrap(), "tiff");
+        assert_eq!(temp_file("/photos/CON", "a.b").extension().unwrap(), "tmp");
+        assert_ne!(temp_file("/photos/a.CR2", "tiff"), temp_file("/photos/a.CR2", "tiff"));
+    }
+
+    #[test]
+    fn temp_names_follow_content_changes() {
+        let source = crate::tiff::fixtures::temp_path("content.CR2");
+        let source_str = source.to_string_lossy();
+        fs::write(&source, b"short").unwrap();
+        let before = source_hash(&source_str);
+        assert_eq!(source_hash(&source_str), before);
+        fs::write(&source, b"longer content").unwrap();
+        assert_ne!(source_hash(&source_str), before);
+        assert!(temp_file(&source_str, "jpg").file_name().unwrap().to_string_lossy().contains(&format!("_{}_", std::process::id())));
+
+        let dir = TempDir::new(&source_str).unwrap();
+        let linked = dir.link(&source_str, "source.CR2").unwrap();
+        assert_eq!(fs::read(&linked).unwrap(), b"longer content");
+        let kept = dir.path().to_path_buf();
+        drop(dir);
+        assert!(!kept.exists());
+        assert!(source.exists());
+        fs::remove_file(&source).unwrap();
+    }
+
+    #[test]
+    fn sibling_temps_stay_short_and_in_place() {
+        let long = PathBuf::from(format!("/photos/{}.CR2", "x".repeat(250)));
+        let temp = sibling_temp(&long);
+        assert_eq!(temp.parent(), long.parent());
+        assert!(temp.file_name().unwrap().len() < 40);
+        assert_ne!(sibling_temp(Path::new("/photos/a.CR2")), sibling_temp(Path::new("/photos/b.CR2")));
+    }
+
+    #[test]
+    fn moves_never_replace_a_file() {
+        let dir = crate::tiff::fixtures::temp_path("paths_no_replace");
+        fs::create_dir_all(&dir).unwrap();
+        let (from, to) = (dir.join("from.jpg"), dir.join("to.jpg"));
+        fs::write(&from, b"from").unwrap();
+        fs::write(&to, b"to").unwrap();
+
+        let error = rename_no_replace(&from, &to).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!((fs::read(&from).unwrap(), fs::read(&to).unwrap()), (b"from".to_vec(), b"to".to_vec()));
+
+        fs::remove_file(&to).unwrap();
+        rename_no_replace(&from, &to).unwrap();
+        assert!(!from.exists());
+        assert_eq!(fs::read(&to).unwrap(), b"from");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,107 @@
+// src/placeholders.rs
+// Zero-byte files and online-only cloud placeholders, caught before any decoder runs
+
+use std::fs;
+use std::sync::Mutex;
+
+use pyo3::exceptions::PyIOError;
+use pyo3::prelude::*;
+
+/// A file with nothing to decode yet
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Zero bytes long: an interrupted copy or a sync client's stub
+    Empty,
+    /// Content kept in the cloud (OneDrive Files On-Demand, Dropbox and
+    /// iCloud online-only files); reading it would start a download
+    CloudOnly,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Empty => "empty",
+            Kind::CloudOnly => "cloud_only",
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Kind::Empty => "a zero-byte file",
+            Kind::CloudOnly => "an online-only cloud placeholder",
+        }
+    }
+}
+
+/// Whether `path` is a placeholder, from its metadata alone
+///
+/// Reading metadata never hydrates a cloud file, so this is safe to call on
+/// every file of a synced folder.
+pub fn detect(path: &str) -> Option<Kind> {
+    let metadata = fs::metadata(path).ok().filter(|m| m.is_file())?;
+    // Checked first: placeholders report either zero or the full cloud size
+    if is_cloud_only(&metadata) {
+        return Some(Kind::CloudOnly);
+    }
+    (metadata.len() == 0).then_some(Kind::Empty)
+}
+
+#[cfg(windows)]
+fn is_cloud_only(metadata: &fs::Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+    const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x40000;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x400000;
+    metadata.file_attributes()
+        & (FILE_ATTRIBUTE_OFFLINE | FILE_ATTRIBUTE_RECALL_ON_OPEN | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS)
+        != 0
+}
+
+#[cfg(target_os = "macos")]
+fn is_cloud_only(metadata: &fs::Metadata) -> bool {
+    use std::os::macos::fs::MetadataExt;
+    // File Provider files whose content has been evicted
+    const SF_DATALESS: u32 = 0x40000000;
+    metadata.st_flags() & SF_DATALESS != 0
+}
+
+/// Linux sync clients have no common placeholder marker; only empty stubs are caught
+#[cfg(not(any(windows, target_os = "macos")))]
+fn is_cloud_only(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+static HANDLER: Mutex<Option<PyObject>> = Mutex::new(None);
+
+pub fn set_handler(handler: Option<PyObject>) {
+    *HANDLER.lock().unwrap_or_else(|e| e.into_inner()) = handler;
+}
+
+/// Ask the hydration handler to fetch a placeholder; true when it says it did
+fn hydrate(path: &str, kind: Kind) -> PyResult<bool> {
+    Python::with_gil(|py| {
+        // Cloned out of the lock so the handler may replace itself
+        let handler = HANDLER.lock().unwrap_or_else(|e| e.into_inner()).as_ref().map(|h| h.clone_ref(py));
+        match handler {
+            Some(handler) => handler.call1(py, (path, kind.name()))?.is_true(py),
+            None => Ok(false),
+        }
+    })
+}
+
+/// Fail fast on a placeholder instead of running every backend and timeout on it
+///
+/// With a hydration handler set, it is offered the file first, and the file
+/// is decoded when the handler returns true and the file is no longer a
+/// placeholder.
+pub fn check(path: &str) -> PyResult<()> {
+    let Some(kind) = detect(path) else {
+        return Ok(());
+    };
+    let hydrated = hydrate(path, kind)
+        .map_err(|e| PyIOError::new_err(format!("Placeholder {} could not be hydrated: {}", path, e)))?;
+    if hydrated && detect(path).is_none() {
+        return Ok(());
+    }
+    Err(PyIOError::new_err(format!("Placeholder: {} is {}", path, kind.describe())))
+}
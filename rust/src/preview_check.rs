@@ -0,0 +1,118 @@
+// src/preview_check.rs
+// Distances between hashes of embedded previews and of full decodes, to judge whether preview-only scans can be trusted
+
+use std::collections::BTreeMap;
+
+use crate::hamming::{self, Packed};
+use crate::hashing;
+
+/// Both decodes of one sampled file
+pub struct FileCheck {
+    pub path: String,
+    pub camera: String,
+    /// Backend of each decode, or why it failed
+    pub preview: Result<&'static str, String>,
+    pub full: Result<&'static str, String>,
+    /// Distance per hash name, when both decodes succeeded
+    pub distances: Vec<(&'static str, u32)>,
+}
+
+impl FileCheck {
+    pub fn compared(&self) -> bool {
+        !self.distances.is_empty()
+    }
+}
+
+/// At most `count` of `paths`, evenly spaced so every part of the list is represented
+pub fn sample(paths: &[String], count: usize) -> Vec<String> {
+    if count == 0 || paths.len() <= count {
+        return paths.to_vec();
+    }
+    (0..count).map(|i| paths[i * paths.len() / count].clone()).collect()
+}
+
+/// Distance between the same-named hashes of two `side` x `side` thumbnails
+pub fn distances(preview: &[u8], full: &[u8], side: usize) -> Vec<(&'static str, u32)> {
+    hashing::thumbnail_hashes(preview, side)
+        .into_iter()
+        .zip(hashing::thumbnail_hashes(full, side))
+        .filter_map(|((name, a), (_, b))| {
+            let distance = hamming::distance(&Packed::from_str(&a)?, &Packed::from_str(&b)?)?;
+            Some((name, distance))
+        })
+        .collect()
+}
+
+/// Distances of one hash over a set of files
+#[derive(Default)]
+pub struct Distribution {
+    /// Sorted ascending
+    distances: Vec<u32>,
+}
+
+impl Distribution {
+    pub fn mean(&self) -> f64 {
+        self.distances.iter().map(|&d| d as f64).sum::<f64>() / self.distances.len().max(1) as f64
+    }
+
+    /// Distance at `fraction` (0-1) of the sorted list, 0 when empty
+    pub fn percentile(&self, fraction: f64) -> u32 {
+        if self.distances.is_empty() {
+            return 0;
+        }
+        let index = ((self.distances.len() - 1) as f64 * fraction).round() as usize;
+        self.distances[index]
+    }
+
+    pub fn max(&self) -> u32 {
+        self.distances.last().copied().unwrap_or(0)
+    }
+
+    /// Number of files at each distance that occurred
+    pub fn histogram(&self) -> BTreeMap<u32, usize> {
+        let mut histogram = BTreeMap::new();
+        for &distance in &self.distances {
+            *histogram.entry(distance).or_insert(0) += 1;
+        }
+        histogram
+    }
+}
+
+/// Files checked, files with a failed decode, and the distribution per hash name
+#[derive(Default)]
+pub struct Summary {
+    pub files: usize,
+    pub failed: usize,
+    pub hashes: BTreeMap<&'static str, Distribution>,
+}
+
+impl Summary {
+    fn add(&mut self, check: &FileCheck) {
+        self.files += 1;
+        if !check.compared() {
+            self.failed += 1;
+        }
+        for &(name, distance) in &check.distances {
+            self.hashes.entry(name).or_default().distances.push(distance);
+        }
+    }
+
+    fn finish(&mut self) {
+        for distribution in self.hashes.values_mut() {
+            distribution.distances.sort_unstable();
+        }
+    }
+}
+
+/// The summary over all checks, and one per camera
+pub fn summarize(checks: &[FileCheck]) -> (Summary, BTreeMap<String, Summary>) {
+    let mut overall = Summary::default();
+    let mut cameras: BTreeMap<String, Summary> = BTreeMap::new();
+    for check in checks {
+        overall.add(check);
+        cameras.entry(check.camera.clone()).or_default().add(check);
+    }
+    overall.finish();
+    cameras.values_mut().for_each(Summary::finish);
+    (overall, cameras)
+}
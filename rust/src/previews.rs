@@ -0,0 +1,150 @@
+// src/previews.rs
+// Enumeration of every embedded preview/thumbnail in a RAW file
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::process::Command;
+
+use crate::process::LimitedOutput;
+use crate::tiff;
+
+// Fujifilm RAF header: big-endian JPEG offset and length at fixed positions
+const RAF_MAGIC: &[u8] = b"FUJIFILMCCD-RAW";
+const RAF_JPEG_OFFSET_POS: u64 = 84;
+
+// Smaller embedded JPEGs are IFD thumbnails, not worth standing in for a decode
+const MIN_STAND_IN_BYTES: usize = 10_000;
+
+// Every preview-like tag exiftool knows across the supported makes
+const EXIFTOOL_PREVIEW_TAGS: [&str; 6] = [
+    "PreviewImage",
+    "JpgFromRaw",
+    "ThumbnailImage",
+    "OtherImage",
+    "EmbeddedImage",
+    "PreviewTIFF",
+];
+
+/// An embedded image and where it was found
+pub struct Preview {
+    pub source: String,
+    pub data: Vec<u8>,
+}
+
+fn is_jpeg(data: &[u8]) -> bool {
+    data.len() > 2 && data[0] == 0xFF && data[1] == 0xD8
+}
+
+/// JPEGs referenced directly from TIFF IFDs (thumbnail pointers and JPEG strips)
+fn tiff_previews(path: &str) -> Vec<Preview> {
+    let mut previews = Vec::new();
+    let Ok(mut file) = tiff::TiffFile::open(path) else {
+        return previews;
+    };
+    let Ok(ifds) = file.ifds() else {
+        return previews;
+    };
+
+    for (index, ifd) in ifds.iter().enumerate() {
+        let jpeg_offset = ifd.find(tiff::TAG_JPEG_OFFSET).and_then(|e| file.value_u32(e));
+        let jpeg_length = ifd.find(tiff::TAG_JPEG_LENGTH).and_then(|e| file.value_u32(e));
+        if let (Some(offset), Some(length)) = (jpeg_offset, jpeg_length) {
+            if let Ok(data) = file.read_bytes(offset, length) {
+                if is_jpeg(&data) {
+                    previews.push(Preview { source: format!("IFD{}:JPEGInterchangeFormat", index), data });
+                }
+            }
+        }
+
+        // Old-style JPEG (6) or JPEG (7) image data stored as a single strip
+        let compression = ifd.find(tiff::TAG_COMPRESSION).and_then(|e| file.value_u32(e));
+        let strip_offset = ifd.find(tiff::TAG_STRIP_OFFSETS).filter(|e| e.count == 1).and_then(|e| file.value_u32(e));
+        let strip_length = ifd.find(tiff::TAG_STRIP_BYTE_COUNTS).filter(|e| e.count == 1).and_then(|e| file.value_u32(e));
+        if let (Some(6 | 7), Some(offset), Some(length)) = (compression, strip_offset, strip_length) {
+            if let Ok(data) = file.read_bytes(offset, length) {
+                // Lossless JPEG raw data also uses compression 7 but is not viewable
+                if is_jpeg(&data) && image::load_from_memory(&data).is_ok() {
+                    previews.push(Preview { source: format!("IFD{}:StripJPEG", index), data });
+                }
+            }
+        }
+    }
+
+    previews
+}
+
+/// The JPEG referenced from the Fujifilm RAF header
+fn raf_preview(path: &str) -> Option<Preview> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0u8; 92];
+    file.read_exact(&mut header).ok()?;
+    if !header.starts_with(RAF_MAGIC) {
+        return None;
+    }
+
+    let pos = RAF_JPEG_OFFSET_POS as usize;
+    let offset = u32::from_be_bytes([header[pos], header[pos + 1], header[pos + 2], header[pos + 3]]);
+    let length = u32::from_be_bytes([header[pos + 4], header[pos + 5], header[pos + 6], header[pos + 7]]);
+    if length == 0 || length > 64 * 1024 * 1024 {
+        return None;
+    }
+
+    let mut data = vec![0u8; length as usize];
+    file.seek(SeekFrom::Start(offset as u64)).ok()?;
+    file.read_exact(&mut data).ok()?;
+    is_jpeg(&data).then(|| Preview { source: "RAF:Header".to_string(), data })
+}
+
+/// Previews exiftool can reach, including ones hidden in maker notes
+fn exiftool_previews(path: &str) -> Vec<Preview> {
+    EXIFTOOL_PREVIEW_TAGS
+        .iter()
+        .filter_map(|tag| {
+            let output = Command::new("exiftool")
+                .args(["-b", &format!("-{}", tag), path])
+                .limited_output()
+                .ok()?;
+            (output.status.success() && !output.stdout.is_empty()).then(|| Preview {
+                source: format!("exiftool:{}", tag),
+                data: output.stdout,
+            })
+        })
+        .collect()
+}
+
+/// Previews read without external tools (TIFF IFDs and the RAF header)
+pub fn native_previews(path: &str) -> Vec<Preview> {
+    let mut previews = tiff_previews(path);
+    previews.extend(raf_preview(path));
+    previews
+}
+
+/// Pixel size from a JPEG's header, without decoding it
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    image::io::Reader::with_format(Cursor::new(data), image::ImageFormat::Jpeg)
+        .into_dimensions()
+        .ok()
+}
+
+/// The largest native preview with a readable header, to use in place of a
+/// decode: the full-size JpgFromRaw of NEF/ARW/CR2/DNG, the RAF header JPEG
+pub fn best_native_preview(path: &str) -> Option<Preview> {
+    native_previews(path)
+        .into_iter()
+        .filter(|preview| preview.data.len() > MIN_STAND_IN_BYTES)
+        .filter_map(|preview| Some((jpeg_dimensions(&preview.data)?, preview)))
+        .max_by_key(|((width, height), preview)| (*width as u64 * *height as u64, preview.data.len()))
+        .map(|(_, preview)| preview)
+}
+
+/// Every distinct embedded preview, native sources first
+pub fn all_previews(path: &str) -> Vec<Preview> {
+    let mut previews = native_previews(path);
+    previews.extend(exiftool_previews(path));
+
+    // The same JPEG is usually reachable both natively and through exiftool
+    let mut seen = HashSet::new();
+    previews.retain(|p| seen.insert(blake3::hash(&p.data)));
+    previews
+}
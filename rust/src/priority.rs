@@ -0,0 +1,139 @@
+// src/priority.rs
+// Two tiers of work: interactive requests from a UI go ahead of queued background batch work
+
+use std::cell::Cell;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::lifecycle;
+
+// How often a held-back background item re-checks for shutdown
+const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    /// A request someone is waiting on, such as the file the user just clicked
+    Interactive,
+    /// Scans and batch work; the default for every thread
+    Background,
+}
+
+thread_local! {
+    static TIER: Cell<Tier> = const { Cell::new(Tier::Background) };
+    // Set while this thread runs a background item, so a queued item it
+    // steals while waiting on nested parallel work is not held back behind
+    // its own parent
+    static IN_TURN: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Interactive requests currently running
+static IN_FLIGHT: Mutex<usize> = Mutex::new(0);
+static INTERACTIVE_DONE: Condvar = Condvar::new();
+
+/// Tier of the work on this thread
+pub fn current() -> Tier {
+    TIER.with(|cell| cell.get())
+}
+
+/// Marks this thread's requests as interactive until dropped
+pub struct ScopedInteractive {
+    previous: Tier,
+}
+
+impl ScopedInteractive {
+    pub fn enter() -> Self {
+        let previous = current();
+        TIER.with(|cell| cell.set(Tier::Interactive));
+        ScopedInteractive { previous }
+    }
+}
+
+impl Drop for ScopedInteractive {
+    fn drop(&mut self) {
+        TIER.with(|cell| cell.set(self.previous));
+    }
+}
+
+/// Workers reserved for interactive requests
+///
+/// Parallel work inside a request (decoding both sides of a pair, the
+/// demosaic) runs here rather than on the global pool, whose workers may all
+/// be background items waiting for that very request to finish.
+fn interactive_pool() -> &'static rayon::ThreadPool {
+    static POOL: OnceLock<rayon::ThreadPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        rayon::ThreadPoolBuilder::new()
+            .thread_name(|i| format!("raw_processor-interactive-{}", i))
+            .start_handler(|_| TIER.with(|cell| cell.set(Tier::Interactive)))
+            .build()
+            .expect("interactive thread pool")
+    })
+}
+
+/// Run one request in this thread's tier
+///
+/// Interactive requests run on their own pool and hold back every background
+/// item that has not started yet until they finish; a background request
+/// first waits for interactive ones to drain. Call without the GIL.
+pub fn run<T: Send>(request: impl FnOnce() -> T + Send) -> T {
+    match current() {
+        Tier::Interactive => {
+            *IN_FLIGHT.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+            let _done = InFlight;
+            interactive_pool().install(request)
+        },
+        Tier::Background => {
+            let _turn = Turn::wait(Tier::Background);
+            request()
+        },
+    }
+}
+
+/// Drops one interactive request from the count, even when it panicked
+struct InFlight;
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut in_flight = IN_FLIGHT.lock().unwrap_or_else(|e| e.into_inner());
+        *in_flight = in_flight.saturating_sub(1);
+        if *in_flight == 0 {
+            INTERACTIVE_DONE.notify_all();
+        }
+    }
+}
+
+/// One item's go-ahead, held while it runs
+///
+/// Items already running are never interrupted; only those still queued give
+/// way to interactive requests.
+pub struct Turn {
+    outermost: bool,
+}
+
+impl Turn {
+    /// Wait until no interactive request is running before starting an item
+    /// of `tier` work; interactive items start at once, as does everything
+    /// once the engine shuts down
+    pub fn wait(tier: Tier) -> Self {
+        let outermost = !IN_TURN.with(|cell| cell.get());
+        if tier == Tier::Background && outermost {
+            let mut in_flight = IN_FLIGHT.lock().unwrap_or_else(|e| e.into_inner());
+            while *in_flight > 0 && !lifecycle::is_shut_down() {
+                in_flight = INTERACTIVE_DONE
+                    .wait_timeout(in_flight, SHUTDOWN_POLL)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+            }
+        }
+        IN_TURN.with(|cell| cell.set(true));
+        Turn { outermost }
+    }
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        if self.outermost {
+            IN_TURN.with(|cell| cell.set(false));
+        }
+    }
+}
@@ -0,0 +1,190 @@
+// src/process.rs
+// Global and per-storage limits on concurrently running external tools (exiftool, dcraw, dcraw_emu)
+
+use std::io::{self, Read};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::storage::{self, StorageKind};
+use crate::{deadline, lifecycle, priority};
+
+// How often a running tool is checked against the per-file deadline and shutdown
+const DEADLINE_POLL: Duration = Duration::from_millis(10);
+
+/// 0 means "use the number of CPUs"
+static MAX_PROCESSES: AtomicUsize = AtomicUsize::new(0);
+
+/// Running processes in total and per storage kind (local, network), and
+/// interactive requests waiting for a slot
+struct Active {
+    total: usize,
+    by_storage: [usize; 2],
+    interactive_waiting: usize,
+}
+
+static ACTIVE: Mutex<Active> = Mutex::new(Active { total: 0, by_storage: [0, 0], interactive_waiting: 0 });
+static SLOT_FREED: Condvar = Condvar::new();
+
+/// Set the maximum number of concurrent child processes (0 restores the default)
+pub fn set_max_processes(limit: usize) {
+    MAX_PROCESSES.store(limit, Ordering::Relaxed);
+    // A raised limit may let waiting threads through
+    SLOT_FREED.notify_all();
+}
+
+/// Current maximum number of concurrent child processes
+pub fn max_processes() -> usize {
+    match MAX_PROCESSES.load(Ordering::Relaxed) {
+        0 => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+        limit => limit,
+    }
+}
+
+/// Wake waiters after a storage policy changed its process limit or the engine shut down
+pub fn limits_changed() {
+    SLOT_FREED.notify_all();
+}
+
+/// A held process slot, released on drop
+///
+/// Conversions running under a storage scope also count against that
+/// storage's own limit, on top of the global one.
+struct Permit {
+    storage: Option<StorageKind>,
+}
+
+impl Permit {
+    /// Wait for a slot, giving up at this thread's per-file deadline
+    ///
+    /// Interactive requests take freed slots ahead of background work.
+    fn acquire() -> io::Result<Self> {
+        let kind = storage::current();
+        let deadline = deadline::current();
+        let interactive = priority::current() == priority::Tier::Interactive;
+
+        let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+        let mut waiting = false;
+        let acquired = loop {
+            if lifecycle::is_shut_down() {
+                break Err(shut_down());
+            }
+            let storage_full = kind.is_some_and(|k| {
+                let limit = storage::policy(k).max_processes;
+                limit > 0 && active.by_storage[k.slot()] >= limit
+            });
+            let outranked = !interactive && active.interactive_waiting > 0;
+            if active.total < max_processes() && !storage_full && !outranked {
+                break Ok(());
+            }
+            if interactive && !waiting {
+                active.interactive_waiting += 1;
+                waiting = true;
+            }
+            active = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        break Err(timed_out());
+                    }
+                    SLOT_FREED.wait_timeout(active, remaining).unwrap_or_else(|e| e.into_inner()).0
+                },
+                None => SLOT_FREED.wait(active).unwrap_or_else(|e| e.into_inner()),
+            };
+        };
+        if waiting {
+            active.interactive_waiting -= 1;
+            // Background waiters held back by this one may go now
+            SLOT_FREED.notify_all();
+        }
+        acquired?;
+        active.total += 1;
+        if let Some(k) = kind {
+            active.by_storage[k.slot()] += 1;
+        }
+        Ok(Permit { storage: kind })
+    }
+}
+
+fn timed_out() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, deadline::exceeded_message())
+}
+
+fn shut_down() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, lifecycle::SHUT_DOWN_MESSAGE)
+}
+
+/// Drain a child's pipe on its own thread so a chatty tool cannot block on a full pipe
+fn drain(pipe: Option<impl Read + Send + 'static>) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Run a spawned child to completion, killing it once `deadline` passes or
+/// the engine shuts down
+fn wait_until(mut child: Child, deadline: Option<Instant>) -> io::Result<Output> {
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let stop = match remaining {
+            Some(remaining) if remaining.is_zero() => Some(timed_out()),
+            _ if lifecycle::is_shut_down() => Some(shut_down()),
+            _ => None,
+        };
+        if let Some(error) = stop {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(error);
+        }
+        std::thread::sleep(remaining.map_or(DEADLINE_POLL, |r| r.min(DEADLINE_POLL)));
+    };
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+        active.total -= 1;
+        if let Some(k) = self.storage {
+            active.by_storage[k.slot()] -= 1;
+        }
+        // Waiters may be blocked on different limits, so wake them all
+        SLOT_FREED.notify_all();
+    }
+}
+
+/// `Command::output` that waits for a free slot under the global process limit
+///
+/// Under a per-file deadline both the wait and the tool itself are bounded:
+/// a tool still running at the deadline is killed and `TimedOut` returned.
+/// After shutdown no tool starts, and running ones are killed (`Interrupted`).
+pub trait LimitedOutput {
+    fn limited_output(&mut self) -> io::Result<Output>;
+}
+
+impl LimitedOutput for Command {
+    fn limited_output(&mut self) -> io::Result<Output> {
+        let _permit = Permit::acquire()?;
+        let child = self.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+        wait_until(child, deadline::current())
+    }
+}
+
+/// External tools running right now
+pub fn running() -> usize {
+    ACTIVE.lock().unwrap_or_else(|e| e.into_inner()).total
+}
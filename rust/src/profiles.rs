@@ -0,0 +1,139 @@
+// src/profiles.rs
+// Named scan profiles: conversion chain, thumbnail sizes, caches and limits chosen together for one kind of run
+
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+use crate::storage::{self, Backend, StorageKind};
+use crate::{memory, process, thumbnails, watermark};
+
+/// Settings a scan profile replaces when selected
+pub struct ScanProfile {
+    pub name: &'static str,
+    /// Conversion chain on every storage; None restores the default chains
+    pub backends: Option<&'static [Backend]>,
+    /// Upper bound on requested thumbnail long edges; 0 for none
+    pub max_thumbnail_edge: u32,
+    /// Encoded thumbnails kept in memory
+    pub thumbnail_cache_bytes: usize,
+    /// Memory budget for in-flight decodes; 0 for none
+    pub memory_budget: u64,
+    /// Concurrent external tools; 0 for one per core
+    pub max_processes: usize,
+    /// Never use embedded previews; demosaic at full size with the slow, high-quality interpolation
+    pub full_decode: bool,
+    /// Index hash that hash-type parameters default to
+    pub hash_type: &'static str,
+    /// SSIM of the thumbnails a hash match must also reach; 0 skips the check
+    pub min_ssim: f64,
+    /// Regions the `watermarked` matching profile leaves out, unless changed with `set_watermark_mask`
+    pub watermark_mask: watermark::Mask,
+}
+
+static PROFILES: &[ScanProfile] = &[
+    ScanProfile {
+        name: "default",
+        backends: None,
+        max_thumbnail_edge: 0,
+        thumbnail_cache_bytes: thumbnails::DEFAULT_BUDGET_BYTES,
+        memory_budget: 0,
+        max_processes: 0,
+        full_decode: false,
+        hash_type: "perceptual",
+        min_ssim: 0.0,
+        watermark_mask: watermark::DEFAULT,
+    },
+    // Small boards indexing a NAS: embedded previews only (never a full RAW
+    // decode), small thumbnails and caches, one exiftool/dcraw at a time
+    ScanProfile {
+        name: "previews_only",
+        backends: Some(&[Backend::EmbeddedPreview]),
+        max_thumbnail_edge: 512,
+        thumbnail_cache_bytes: 8 * 1024 * 1024,
+        memory_budget: 256 * 1024 * 1024,
+        max_processes: 1,
+        full_decode: false,
+        hash_type: "perceptual",
+        min_ssim: 0.0,
+        watermark_mask: watermark::DEFAULT,
+    },
+    // Archives where a wrong merge costs more than time: full decodes only,
+    // 256-bit hashes, and matches confirmed by structural similarity
+    ScanProfile {
+        name: "archival",
+        backends: Some(&[Backend::Libraw, Backend::FormatSpecific, Backend::Rawloader, Backend::Generic]),
+        max_thumbnail_edge: 0,
+        thumbnail_cache_bytes: thumbnails::DEFAULT_BUDGET_BYTES,
+        memory_budget: 0,
+        max_processes: 0,
+        full_decode: true,
+        hash_type: "fine",
+        min_ssim: 0.8,
+        watermark_mask: watermark::DEFAULT,
+    },
+];
+
+impl ScanProfile {
+    /// Policy for both storage kinds; None keeps the built-in chains
+    pub fn storage_policy(&self) -> Option<storage::StoragePolicy> {
+        self.backends.map(|backends| storage::StoragePolicy { backends: backends.to_vec(), max_processes: 0 })
+    }
+}
+
+static ACTIVE: RwLock<&'static ScanProfile> = RwLock::new(&PROFILES[0]);
+
+pub fn lookup(name: &str) -> Option<&'static ScanProfile> {
+    PROFILES.iter().find(|p| p.name == name)
+}
+
+/// Names of all built-in scan profiles
+pub fn profile_names() -> Vec<&'static str> {
+    PROFILES.iter().map(|p| p.name).collect()
+}
+
+/// The profile selected last
+pub fn active() -> &'static ScanProfile {
+    *ACTIVE.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Replace the storage policies, memory budget, process limit and thumbnail
+/// cache size with the profile's; settings changed afterwards override it
+pub fn select(profile: &'static ScanProfile) {
+    for kind in [StorageKind::Local, StorageKind::Network] {
+        storage::set_policy(kind, profile.storage_policy());
+    }
+    memory::set_budget(profile.memory_budget);
+    process::set_max_processes(profile.max_processes);
+    process::limits_changed();
+    thumbnails::set_budget(profile.thumbnail_cache_bytes);
+    *ACTIVE.write().unwrap_or_else(|e| e.into_inner()) = profile;
+}
+
+/// Watermark masks changed at runtime, by profile name
+fn mask_overrides() -> &'static RwLock<HashMap<&'static str, watermark::Mask>> {
+    static OVERRIDES: OnceLock<RwLock<HashMap<&'static str, watermark::Mask>>> = OnceLock::new();
+    OVERRIDES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Replace `profile`'s watermark mask; None restores its built-in one
+pub fn set_watermark_mask(profile: &'static ScanProfile, mask: Option<watermark::Mask>) {
+    let mut overrides = mask_overrides().write().unwrap_or_else(|e| e.into_inner());
+    match mask {
+        Some(mask) => overrides.insert(profile.name, mask),
+        None => overrides.remove(profile.name),
+    };
+}
+
+/// The watermark mask `profile` uses
+pub fn watermark_mask(profile: &ScanProfile) -> watermark::Mask {
+    let overrides = mask_overrides().read().unwrap_or_else(|e| e.into_inner());
+    overrides.get(profile.name).copied().unwrap_or(profile.watermark_mask)
+}
+
+/// A requested thumbnail long edge, capped by the active profile
+pub fn bound_edge(long_edge: u32) -> u32 {
+    match active().max_thumbnail_edge {
+        0 => long_edge,
+        max => long_edge.min(max),
+    }
+}
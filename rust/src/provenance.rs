@@ -0,0 +1,94 @@
+// src/provenance.rs
+// Which backend produced a decoded image, whether it was a full decode, and the source dimensions
+
+use std::cell::{Cell, RefCell};
+
+use crate::tiff;
+
+/// How the last conversion on this thread got its pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Source {
+    /// Conversion step that succeeded (`embedded_preview`, `rawloader`, `stream`...)
+    pub backend: &'static str,
+    /// False when the pixels came from a JPEG embedded in the file
+    pub full_decode: bool,
+    /// The decoder already turned the pixels to the EXIF display orientation
+    /// (dcraw and libraw do for full decodes; previews and rawloader do not)
+    pub oriented: bool,
+}
+
+thread_local! {
+    static PREVIEW_EXTRACTED: Cell<bool> = const { Cell::new(false) };
+    static ORIENTED: Cell<bool> = const { Cell::new(false) };
+    static LAST: Cell<Option<Source>> = const { Cell::new(None) };
+    static ATTEMPTED: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Forget the previous conversion before starting a new one
+pub fn clear() {
+    LAST.with(|cell| cell.set(None));
+    ATTEMPTED.with(|attempted| attempted.borrow_mut().clear());
+}
+
+/// Start a conversion step with `backend`
+pub fn begin_step(backend: &'static str) {
+    PREVIEW_EXTRACTED.with(|cell| cell.set(false));
+    ORIENTED.with(|cell| cell.set(false));
+    ATTEMPTED.with(|attempted| attempted.borrow_mut().push(backend));
+}
+
+/// Backends tried since the last `clear`, in order
+pub fn attempted() -> Vec<&'static str> {
+    ATTEMPTED.with(|attempted| attempted.borrow().clone())
+}
+
+/// Note that the current step produced its output from an embedded preview
+pub fn mark_preview() {
+    PREVIEW_EXTRACTED.with(|cell| cell.set(true));
+}
+
+/// Note that the current step's output is already in display orientation
+pub fn mark_oriented() {
+    ORIENTED.with(|cell| cell.set(true));
+}
+
+/// Record that `backend` produced the output of the current step
+pub fn finish(backend: &'static str) {
+    let full_decode = !PREVIEW_EXTRACTED.with(|cell| cell.get());
+    let oriented = ORIENTED.with(|cell| cell.get());
+    LAST.with(|cell| cell.set(Some(Source { backend, full_decode, oriented })));
+}
+
+/// The conversion recorded since the last `clear`, if any
+pub fn last() -> Option<Source> {
+    LAST.with(|cell| cell.get())
+}
+
+/// Full image dimensions from the file header, without decoding the pixels
+///
+/// For TIFF-based RAW files this is the largest image in the IFDs (the sensor
+/// data, not the previews); other formats use the image crate's header
+/// reader. None for containers neither understands (CR3, RAF...).
+pub fn original_dimensions(path: &str) -> Option<(u32, u32)> {
+    if let Ok(mut file) = tiff::TiffFile::open(path) {
+        let ifds = file.ifds().ok()?;
+        return ifds
+            .iter()
+            .filter_map(|ifd| {
+                let width = file.value_u32(ifd.find(tiff::TAG_IMAGE_WIDTH)?)?;
+                let height = file.value_u32(ifd.find(tiff::TAG_IMAGE_LENGTH)?)?;
+                Some((width, height))
+            })
+            .max_by_key(|(width, height)| *width as u64 * *height as u64);
+    }
+    image::image_dimensions(path).ok()
+}
+
+/// Where the pixels behind a grayscale thumbnail came from
+pub struct DecodeInfo {
+    /// Full image size from the file header, when it could be read
+    pub original: Option<(u32, u32)>,
+    /// Size of the image that was actually thumbnailed (preview or decode)
+    pub decoded: (u32, u32),
+    pub source: Source,
+}
@@ -0,0 +1,162 @@
+// src/raw_pairs.rs
+// RAW+JPEG pairs written by the camera for one shot, with their XMP sidecars, grouped as one logical image
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::exif;
+use crate::formats;
+
+/// Extensions of the images cameras write next to a RAW file
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "heic", "heif"];
+
+/// Default largest capture time difference, in seconds, between a RAW file and its JPEG
+pub const MAX_GAP: f64 = 2.0;
+
+/// One shot: the RAW file and the files that belong to it
+pub struct Capture {
+    /// The shot's representative RAW file (see `group`)
+    pub raw: String,
+    /// Other RAW files with the same basename, e.g. a DNG converted from the original
+    pub other_raws: Vec<String>,
+    /// Out-of-camera JPEGs (or HEIFs) with the same basename
+    pub images: Vec<String>,
+    /// XMP sidecars, either `IMG_0001.xmp` or `IMG_0001.ARW.xmp`
+    pub sidecars: Vec<String>,
+}
+
+impl Capture {
+    /// Every member, RAW file first
+    pub fn paths(&self) -> Vec<String> {
+        std::iter::once(&self.raw)
+            .chain(&self.other_raws)
+            .chain(&self.images)
+            .chain(&self.sidecars)
+            .cloned()
+            .collect()
+    }
+}
+
+enum Role {
+    Raw,
+    Image,
+    Sidecar,
+}
+
+/// What `path` is to a capture, and the (directory, lowercase basename) key tying the capture together
+fn classify(path: &str) -> Option<(Role, (String, String))> {
+    let path_ref = Path::new(path);
+    let directory = path_ref.parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = path_ref.file_name()?.to_str()?.to_lowercase();
+    let (stem, extension) = name.rsplit_once('.')?;
+
+    let (role, stem) = if extension == "xmp" {
+        // Darktable and others keep the RAW extension: `IMG_0001.ARW.xmp`
+        let stem = match stem.rsplit_once('.') {
+            Some((inner, ext)) if formats::is_raw(ext) || IMAGE_EXTENSIONS.contains(&ext) => inner,
+            _ => stem,
+        };
+        (Role::Sidecar, stem)
+    } else if formats::is_raw(&formats::extension(path)) {
+        (Role::Raw, stem)
+    } else if IMAGE_EXTENSIONS.contains(&extension) {
+        (Role::Image, stem)
+    } else {
+        return None;
+    };
+    (!stem.is_empty()).then(|| (role, (directory, stem.to_string())))
+}
+
+/// Sort key choosing a shot's representative RAW file: the camera's own
+/// format over DNG, which is usually a conversion, then the first path in
+/// sort order
+fn preference(path: &str) -> (bool, &str) {
+    (formats::extension(path) == "dng", path)
+}
+
+/// Group RAW files among `paths` with their sibling JPEGs and XMP sidecars
+///
+/// Siblings share the RAW file's directory and basename (case-insensitive).
+/// When both a RAW file and an image carry a capture time, they must be at
+/// most `max_gap` seconds apart, so a JPEG that only reuses the file number
+/// (counter rollover, another card) stays separate. Sidecars are matched by
+/// name alone. Only RAW files with at least one sibling form a capture;
+/// captures are ordered by the RAW file's position in `paths`. Several RAW
+/// files with one basename (`.CR3` and `.DNG`) are one capture: the camera's
+/// own format represents it (the first path when that still ties), and
+/// the others are listed in `other_raws`.
+pub fn group(paths: &[String], max_gap: f64) -> Vec<Capture> {
+    let mut raws: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    let mut siblings: HashMap<(String, String), (Vec<usize>, Vec<usize>)> = HashMap::new();
+    for (i, path) in paths.iter().enumerate() {
+        match classify(path) {
+            Some((Role::Raw, key)) => raws.entry(key).or_default().push(i),
+            Some((Role::Image, key)) => siblings.entry(key).or_default().0.push(i),
+            Some((Role::Sidecar, key)) => siblings.entry(key).or_default().1.push(i),
+            None => {},
+        }
+    }
+    // The representative first, then the other RAW files in path order
+    let mut shots: Vec<((String, String), Vec<usize>)> = raws
+        .into_iter()
+        .filter(|(key, raws)| raws.len() > 1 || siblings.contains_key(key))
+        .map(|(key, mut raws)| {
+            raws.sort_by(|&a, &b| preference(&paths[a]).cmp(&preference(&paths[b])).then(a.cmp(&b)));
+            (key, raws)
+        })
+        .collect();
+    shots.sort_by_key(|(_, raws)| raws[0]);
+
+    // Capture times only for files that could pair; NaN when unknown
+    let mut timed: Vec<usize> = shots.iter().map(|(_, raws)| raws[0]).collect();
+    timed.extend(shots.iter().flat_map(|(key, _)| siblings.get(key).into_iter().flat_map(|s| s.0.iter().copied())));
+    timed.sort_unstable();
+    timed.dedup();
+    let table = exif::read_batch(&timed.iter().map(|&i| paths[i].clone()).collect::<Vec<_>>());
+    let column = exif::COLUMNS.iter().position(|(name, _, _)| *name == "capture_time").expect("capture_time column");
+    let times: HashMap<usize, f64> = timed.iter().copied().zip(table.numbers[column].iter().copied()).collect();
+    let close = |a: usize, b: usize| {
+        let (a, b) = (times[&a], times[&b]);
+        a.is_nan() || b.is_nan() || (a - b).abs() <= max_gap
+    };
+
+    let none = (Vec::new(), Vec::new());
+    shots
+        .into_iter()
+        .filter_map(|(key, raws)| {
+            let (images, sidecars) = siblings.get(&key).unwrap_or(&none);
+            let raw = raws[0];
+            let images: Vec<String> =
+                images.iter().filter(|&&image| close(raw, image)).map(|&i| paths[i].clone()).collect();
+            let sidecars: Vec<String> = sidecars.iter().map(|&i| paths[i].clone()).collect();
+            let other_raws: Vec<String> = raws[1..].iter().map(|&i| paths[i].clone()).collect();
+            (!other_raws.is_empty() || !images.is_empty() || !sidecars.is_empty())
+                .then(|| Capture { raw: paths[raw].clone(), other_raws, images, sidecars })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| format!("/raw_pairs_test/{}", name)).collect()
+    }
+
+    #[test]
+    fn one_capture_per_shot_with_a_deterministic_raw() {
+        for order in [["IMG_1.DNG", "IMG_1.JPG", "IMG_1.CR2"], ["IMG_1.CR2", "IMG_1.DNG", "IMG_1.JPG"]] {
+            let captures = group(&paths(&order), MAX_GAP);
+            assert_eq!(captures.len(), 1);
+            assert_eq!(captures[0].raw, "/raw_pairs_test/IMG_1.CR2");
+            assert_eq!(captures[0].other_raws, paths(&["IMG_1.DNG"]));
+            assert_eq!(captures[0].paths(), paths(&["IMG_1.CR2", "IMG_1.DNG", "IMG_1.JPG"]));
+        }
+
+        // RAW files of one shot belong together without a JPEG; lone ones form no capture
+        let captures = group(&paths(&["b.NEF", "a.NEF", "b.NRW", "c.JPG"]), MAX_GAP);
+        assert_eq!(captures.len(), 1);
+        assert_eq!((captures[0].raw.as_str(), &captures[0].other_raws), ("/raw_pairs_test/b.NEF", &paths(&["b.NRW"])));
+    }
+}
@@ -0,0 +1,94 @@
+// src/remote.rs
+// Fetching of s3:// and http(s):// sources into local temporary copies
+
+use std::io;
+use std::path::PathBuf;
+
+use crate::formats;
+
+/// Leading bytes fetched when only the embedded preview is needed
+pub const PREVIEW_RANGE_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Check if a source path is a URL rather than a local file
+pub fn is_remote(path: &str) -> bool {
+    path.starts_with("s3://") || path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Lowercase file extension (or registered handler) of a local path or URL, ignoring any query string
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+pub fn source_extension(path: &str) -> String {
+    formats::extension(path.split(['?', '#']).next().unwrap_or(path))
+}
+
+/// A downloaded copy of a remote source, removed when dropped
+pub struct TempDownload {
+    pub path: PathBuf,
+    /// False when only a leading byte range was fetched
+    pub complete: bool,
+}
+
+impl TempDownload {
+    pub fn path_str(&self) -> &str {
+        self.path.to_str().unwrap_or_default()
+    }
+}
+
+impl Drop for TempDownload {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path); // Clean up
+    }
+}
+
+/// Map s3://bucket/key to a virtual-hosted HTTPS URL
+///
+/// Requests are unsigned, so the object must be public or the caller must pass
+/// a presigned https:// URL instead. `AWS_ENDPOINT_URL` overrides the endpoint
+/// (path-style, for MinIO and similar), otherwise `AWS_REGION` picks the region.
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+fn resolve_url(path: &str) -> String {
+    let Some(rest) = path.strip_prefix("s3://") else {
+        return path.to_string();
+    };
+    let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+
+    if let Ok(endpoint) = std::env::var("AWS_ENDPOINT_URL") {
+        return format!("{}/{}/{}", endpoint.trim_end_matches('/'), bucket, key);
+    }
+
+    match std::env::var("AWS_REGION") {
+        Ok(region) => format!("https://{}.s3.{}.amazonaws.com/{}", bucket, region, key),
+        Err(_) => format!("https://{}.s3.amazonaws.com/{}", bucket, key),
+    }
+}
+
+/// Download a remote source, optionally only its first `max_bytes`
+#[cfg(feature = "remote")]
+pub fn download(path: &str, max_bytes: Option<u64>) -> io::Result<TempDownload> {
+    let url = resolve_url(path);
+    let mut request = ureq::get(&url);
+    if let Some(max) = max_bytes {
+        request = request.set("Range", &format!("bytes=0-{}", max.saturating_sub(1)));
+    }
+
+    let response = request
+        .call()
+        .map_err(|e| io::Error::other(format!("Failed to fetch {}: {}", url, e)))?;
+
+    // Servers without range support answer 200 with the whole object
+    let complete = response.status() != 206;
+
+    let download = TempDownload { path: crate::paths::temp_file(path, &source_extension(path)), complete };
+    let mut file = std::fs::File::create(&download.path)?;
+    io::copy(&mut response.into_reader(), &mut file)?;
+
+    Ok(download)
+}
+
+/// Download a remote source, optionally only its first `max_bytes`
+#[cfg(not(feature = "remote"))]
+pub fn download(path: &str, _max_bytes: Option<u64>) -> io::Result<TempDownload> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("Cannot open {}: raw_processor was built without the `remote` feature", path),
+    ))
+}
@@ -0,0 +1,141 @@
+// src/saliency.rs
+// Region-of-interest detection so hashes follow the subject instead of the background
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+
+// Side of the map saliency is computed on; plenty for locating a subject
+const MAP_SIZE: usize = 64;
+// Box-blur radius (in map pixels) that removes texture but keeps the subject shape
+const BLUR_RADIUS: usize = 3;
+// Share of saliency mass left outside the region on each side
+const TAIL_SHARE: f64 = 0.1;
+// The region never shrinks below this share of the image side
+const MIN_ROI_SHARE: f64 = 0.4;
+
+/// How the region of interest is chosen
+#[derive(Clone, Copy, PartialEq)]
+pub enum RoiMode {
+    /// Whole image, i.e. plain hashing
+    Full,
+    /// Fixed center weighting, for subjects framed centrally
+    Center,
+    /// Contrast against the global mean, biased towards the center
+    Saliency,
+}
+
+impl RoiMode {
+    /// Parse the mode name passed from Python
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "none" => Ok(RoiMode::Full),
+            "center" => Ok(RoiMode::Center),
+            "saliency" => Ok(RoiMode::Saliency),
+            _ => Err(PyValueError::new_err(format!(
+                "Unsupported ROI mode '{}', expected 'none', 'center' or 'saliency'",
+                name
+            ))),
+        }
+    }
+}
+
+/// A square region of a square image, in pixels
+#[derive(Clone, Copy)]
+pub struct Roi {
+    pub y: usize,
+    pub x: usize,
+    pub side: usize,
+}
+
+/// Gaussian center prior, 1.0 at the center
+fn center_weight(y: usize, x: usize) -> f64 {
+    let half = MAP_SIZE as f64 / 2.0;
+    let sigma = MAP_SIZE as f64 * 0.35;
+    let (dy, dx) = (y as f64 + 0.5 - half, x as f64 + 0.5 - half);
+    (-(dy * dy + dx * dx) / (2.0 * sigma * sigma)).exp()
+}
+
+/// Frequency-tuned saliency: distance of the blurred image from its global mean
+fn saliency_map(small: &[u8]) -> Vec<f64> {
+    let mean = small.iter().map(|&p| p as f64).sum::<f64>() / small.len() as f64;
+
+    let mut map = vec![0.0; MAP_SIZE * MAP_SIZE];
+    for y in 0..MAP_SIZE {
+        for x in 0..MAP_SIZE {
+            let (y0, y1) = (y.saturating_sub(BLUR_RADIUS), (y + BLUR_RADIUS + 1).min(MAP_SIZE));
+            let (x0, x1) = (x.saturating_sub(BLUR_RADIUS), (x + BLUR_RADIUS + 1).min(MAP_SIZE));
+            let mut sum = 0.0;
+            for yy in y0..y1 {
+                for xx in x0..x1 {
+                    sum += small[yy * MAP_SIZE + xx] as f64;
+                }
+            }
+            let blurred = sum / ((y1 - y0) * (x1 - x0)) as f64;
+            map[y * MAP_SIZE + x] = (blurred - mean).abs() * center_weight(y, x);
+        }
+    }
+    map
+}
+
+/// Index range along one axis that holds all but `TAIL_SHARE` of the mass on each side
+fn mass_range(marginal: &[f64]) -> (usize, usize) {
+    let total: f64 = marginal.iter().sum();
+    if total <= 0.0 {
+        return (0, marginal.len());
+    }
+
+    let mut running = 0.0;
+    let mut start = 0;
+    let mut end = marginal.len();
+    for (i, &m) in marginal.iter().enumerate() {
+        if running < total * TAIL_SHARE {
+            start = i;
+        }
+        running += m;
+        if running >= total * (1.0 - TAIL_SHARE) {
+            end = i + 1;
+            break;
+        }
+    }
+    (start, end.max(start + 1))
+}
+
+/// Locate the subject of a square `side` x `side` grayscale image
+pub fn salient_region(pixels: &[u8], side: usize, mode: RoiMode) -> Roi {
+    let map = match mode {
+        RoiMode::Full => return Roi { y: 0, x: 0, side },
+        RoiMode::Center => (0..MAP_SIZE * MAP_SIZE)
+            .map(|i| center_weight(i / MAP_SIZE, i % MAP_SIZE))
+            .collect(),
+        RoiMode::Saliency => {
+            let small = crate::hashing::area_downsample(pixels, side, MAP_SIZE);
+            saliency_map(&small.into_raw_vec())
+        },
+    };
+
+    let rows: Vec<f64> = (0..MAP_SIZE).map(|y| map[y * MAP_SIZE..(y + 1) * MAP_SIZE].iter().sum()).collect();
+    let cols: Vec<f64> = (0..MAP_SIZE).map(|x| (0..MAP_SIZE).map(|y| map[y * MAP_SIZE + x]).sum()).collect();
+    let (y0, y1) = mass_range(&rows);
+    let (x0, x1) = mass_range(&cols);
+
+    // Square box around the mass, scaled back to image pixels
+    let scale = side as f64 / MAP_SIZE as f64;
+    let roi_side = (((y1 - y0).max(x1 - x0) as f64 * scale) as usize)
+        .max((side as f64 * MIN_ROI_SHARE) as usize)
+        .clamp(1, side);
+    let center_y = ((y0 + y1) as f64 / 2.0 * scale) as usize;
+    let center_x = ((x0 + x1) as f64 / 2.0 * scale) as usize;
+
+    Roi {
+        y: center_y.saturating_sub(roi_side / 2).min(side - roi_side),
+        x: center_x.saturating_sub(roi_side / 2).min(side - roi_side),
+        side: roi_side,
+    }
+}
+
+/// Copy a region out of a square image as a row-major square buffer
+pub fn crop(pixels: &[u8], side: usize, roi: Roi) -> Vec<u8> {
+    (roi.y..roi.y + roi.side)
+        .flat_map(|y| pixels[y * side + roi.x..y * side + roi.x + roi.side].iter().copied())
+        .collect()
+}
@@ -0,0 +1,235 @@
+// src/salvage.rs
+// Partial decoding of truncated or corrupted JPEGs, so damaged copies can still be hashed and matched
+
+use std::cell::Cell;
+use std::fs;
+
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+
+// Bytes dropped for the second decode that locates where real data ends;
+// rows decoded from them are given up along with the padding
+const PROBE_BYTES: usize = 1024;
+// Prefix lengths are narrowed down to this precision when looking for the
+// longest part of a corrupted file that still decodes
+const SEARCH_PRECISION: usize = 4096;
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    static SALVAGED: Cell<Option<f64>> = const { Cell::new(None) };
+}
+
+/// Whether decodes on this thread may fall back to `decode_jpeg`
+pub fn enabled() -> bool {
+    ENABLED.with(|cell| cell.get())
+}
+
+/// Enables salvaging on this thread, restoring the previous setting on drop
+pub struct ScopedSalvage {
+    previous: bool,
+}
+
+impl ScopedSalvage {
+    pub fn enable() -> Self {
+        SALVAGED.with(|cell| cell.set(None));
+        let previous = ENABLED.with(|cell| cell.replace(true));
+        ScopedSalvage { previous }
+    }
+}
+
+impl Drop for ScopedSalvage {
+    fn drop(&mut self) {
+        ENABLED.with(|cell| cell.set(self.previous));
+    }
+}
+
+/// Fraction of rows recovered by the last salvaged decode on this thread since
+/// the scope began; None when every decode was intact
+pub fn last() -> Option<f64> {
+    SALVAGED.with(|cell| cell.get())
+}
+
+/// `data` cut to `length` bytes and closed with an EOI marker
+///
+/// The decoder fills entropy-coded data that ends at a marker with zero
+/// bits, so the missing rest of the image decodes to filler instead of
+/// failing.
+fn closed(data: &[u8], length: usize) -> Vec<u8> {
+    let mut closed = data[..length].to_vec();
+    closed.extend_from_slice(&[0xFF, 0xD9]);
+    closed
+}
+
+fn decode(data: &[u8]) -> Option<RgbImage> {
+    image::load_from_memory_with_format(data, ImageFormat::Jpeg).ok().map(|img| img.to_rgb8())
+}
+
+/// The markers of a JPEG up to where it ends or stops making sense
+struct Layout {
+    /// Coded with SOF2, as a series of scans that each refine the whole image
+    progressive: bool,
+    /// Offset of each SOS marker, and whether its entropy-coded data ends at another marker
+    scans: Vec<(usize, bool)>,
+}
+
+fn layout(data: &[u8]) -> Layout {
+    let mut layout = Layout { progressive: false, scans: Vec::new() };
+    let mut at = 2;
+    while at + 4 <= data.len() && data[at] == 0xFF {
+        let marker = data[at + 1];
+        match marker {
+            // Fill bytes before a marker
+            0xFF => {
+                at += 1;
+                continue;
+            },
+            0xD9 => break,
+            0xC2 => layout.progressive = true,
+            _ => {},
+        }
+        let length = u16::from_be_bytes([data[at + 2], data[at + 3]]) as usize;
+        at += 2 + length;
+        if marker != 0xDA {
+            continue;
+        }
+        // Entropy-coded data runs to the next marker other than a stuffed 0xFF or a restart
+        let start = at - 2 - length;
+        let end = (at..data.len().saturating_sub(1))
+            .find(|&i| data[i] == 0xFF && data[i + 1] != 0x00 && !(0xD0..=0xD7).contains(&data[i + 1]));
+        layout.scans.push((start, end.is_some()));
+        match end {
+            Some(end) => at = end,
+            None => break,
+        }
+    }
+    layout
+}
+
+/// Whatever part of a damaged progressive JPEG decodes: its complete scans
+///
+/// Each scan of a progressive file covers every row, so row-by-row probing
+/// finds nothing; instead the file is cut before its first incomplete scan,
+/// or before ever earlier scans until it decodes when a scan is corrupt. The
+/// image then has every row at the detail the kept scans give. None when not
+/// even the first scan is complete.
+fn decode_progressive(data: &[u8], scans: &[(usize, bool)]) -> Option<RgbImage> {
+    let complete = scans.iter().position(|&(_, complete)| !complete).unwrap_or(scans.len());
+    // Cutting at the start of scan `kept` keeps the scans before it
+    (1..=complete).rev().find_map(|kept| {
+        let cut = scans.get(kept).map_or(data.len(), |&(start, _)| start);
+        decode(&closed(data, cut))
+    })
+}
+
+/// Whatever part of a damaged JPEG decodes, and the fraction of rows that did
+///
+/// A truncated file is closed where it ends; a file with corrupt data in
+/// the middle is cut before the corruption, found by decoding ever shorter
+/// prefixes (slow on large files, but only damaged files get here). The
+/// rows that came from real data are found by decoding the same file again
+/// `PROBE_BYTES` shorter: filler differs between the two, data does not.
+/// Rows below are painted with the mean color of the rows above, which
+/// disturbs hashes less than decoder filler. None when not even the first
+/// row decodes. Progressive files are salvaged from their complete scans
+/// instead (see `decode_progressive`) and recover every row. Records the
+/// fraction for `last`.
+pub fn decode_jpeg(path: &str) -> Option<DynamicImage> {
+    let data = fs::read(path).ok()?;
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    let layout = layout(&data);
+    if layout.progressive {
+        let img = decode_progressive(&data, &layout.scans)?;
+        SALVAGED.with(|cell| cell.set(Some(1.0)));
+        return Some(DynamicImage::ImageRgb8(img));
+    }
+
+    let length = if decode(&closed(&data, data.len())).is_some() {
+        data.len()
+    } else {
+        // Largest length known to decode, and smallest known not to
+        let (mut good, mut bad) = (0, data.len());
+        while bad - good > SEARCH_PRECISION {
+            let middle = good + (bad - good) / 2;
+            if decode(&closed(&data, middle)).is_some() {
+                good = middle;
+            } else {
+                bad = middle;
+            }
+        }
+        good
+    };
+    let mut img = decode(&closed(&data, length))?;
+    let probe = decode(&closed(&data, length.saturating_sub(PROBE_BYTES)));
+
+    let (width, height) = img.dimensions();
+    let row_bytes = width as usize * 3;
+    let valid_rows = match &probe {
+        Some(probe) if probe.dimensions() == img.dimensions() => img
+            .chunks(row_bytes)
+            .zip(probe.chunks(row_bytes))
+            .position(|(a, b)| a != b)
+            .unwrap_or(height as usize) as u32,
+        // Too short to probe: only the headers and a little data
+        _ => 0,
+    };
+    if valid_rows == 0 {
+        return None;
+    }
+
+    if valid_rows < height {
+        let valid = &img.as_raw()[..valid_rows as usize * row_bytes];
+        let mut sums = [0u64; 3];
+        for pixel in valid.chunks_exact(3) {
+            for (sum, &value) in sums.iter_mut().zip(pixel) {
+                *sum += value as u64;
+            }
+        }
+        let count = (valid.len() / 3) as u64;
+        let mean = Rgb(sums.map(|sum| (sum / count) as u8));
+        for y in valid_rows..height {
+            for x in 0..width {
+                img.put_pixel(x, y, mean);
+            }
+        }
+    }
+
+    SALVAGED.with(|cell| cell.set(Some(valid_rows as f64 / height as f64)));
+    Some(DynamicImage::ImageRgb8(img))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A marker segment with its length field
+    fn segment(marker: u8, body: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xFF, marker];
+        segment.extend_from_slice(&(body.len() as u16 + 2).to_be_bytes());
+        segment.extend_from_slice(body);
+        segment
+    }
+
+    #[test]
+    fn finds_complete_scans_of_progressive_files() {
+        let mut data = vec![0xFF, 0xD8];
+        data.extend(segment(0xC2, &[8, 0, 16, 0, 16, 1, 1, 0x11, 0]));
+        let first = data.len();
+        data.extend(segment(0xDA, &[1, 1, 0, 0, 0, 0]));
+        // Stuffed 0xFF and a restart marker belong to the scan's data
+        data.extend_from_slice(&[0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD0, 0x56]);
+        data.extend(segment(0xC4, &[0x10, 0]));
+        let second = data.len();
+        data.extend(segment(0xDA, &[1, 1, 0, 1, 63, 0]));
+        data.extend_from_slice(&[0x78, 0x9A]);
+
+        let layout = layout(&data);
+        assert!(layout.progressive);
+        assert_eq!(layout.scans, vec![(first, true), (second, false)]);
+
+        // The same file as baseline (SOF0)
+        data[3] = 0xC0;
+        assert!(!super::layout(&data).progressive);
+    }
+}
@@ -0,0 +1,120 @@
+// src/sampling.rs
+// File discovery and ordering for time-budgeted quick scans of large libraries
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::PyResult;
+
+use crate::formats;
+
+/// Which files a quick scan hashes first
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Largest first: big files are where duplicates cost the most space
+    Size,
+    /// Most recently modified first: new imports are the likeliest copies
+    Recency,
+    /// One file per directory in turn, so every folder is represented
+    Directory,
+}
+
+impl Priority {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "size" => Ok(Priority::Size),
+            "recency" => Ok(Priority::Recency),
+            "directory" => Ok(Priority::Directory),
+            _ => Err(PyValueError::new_err(format!(
+                "Unsupported priority '{}', expected 'size', 'recency' or 'directory'",
+                name
+            ))),
+        }
+    }
+}
+
+/// An image file found under the scanned roots
+pub struct Candidate {
+    pub path: String,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// Walk `roots` for image files until `stop`; returns what was found and
+/// whether the walk finished
+///
+/// Symlinked directories are not followed, so link loops cannot trap the
+/// walk. Unreadable directories are skipped.
+pub fn discover(roots: &[String], stop: Option<Instant>) -> (Vec<Candidate>, bool) {
+    let mut found = Vec::new();
+    let mut pending: Vec<PathBuf> = roots.iter().rev().map(PathBuf::from).collect();
+    while let Some(dir) = pending.pop() {
+        if stop.is_some_and(|stop| Instant::now() >= stop) {
+            return (found, false);
+        }
+        let Ok(entries) = fs::read_dir(&dir) else {
+            // A root may name a single file
+            add_file(&dir, &mut found);
+            continue;
+        };
+        let mut subdirs = Vec::new();
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else { continue };
+            if file_type.is_dir() {
+                subdirs.push(entry.path());
+            } else {
+                add_file(&entry.path(), &mut found);
+            }
+        }
+        // Depth first, in name order, so reruns see the library in the same order
+        subdirs.sort();
+        pending.extend(subdirs.into_iter().rev());
+    }
+    (found, true)
+}
+
+fn add_file(path: &Path, found: &mut Vec<Candidate>) {
+    let path = path.to_string_lossy().into_owned();
+    if !formats::is_image(&path) {
+        return;
+    }
+    if let Some(metadata) = fs::metadata(&path).ok().filter(|m| m.is_file()) {
+        found.push(Candidate { path, size: metadata.len(), modified: metadata.modified().ok() });
+    }
+}
+
+/// Put `candidates` in the order a quick scan hashes them; ties go by path
+pub fn prioritize(mut candidates: Vec<Candidate>, priority: Priority) -> Vec<Candidate> {
+    match priority {
+        Priority::Size => candidates.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path))),
+        Priority::Recency => candidates.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.path.cmp(&b.path))),
+        Priority::Directory => {
+            let mut by_directory: BTreeMap<String, Vec<Candidate>> = BTreeMap::new();
+            for candidate in candidates.drain(..) {
+                let dir = Path::new(&candidate.path).parent().map(|p| p.to_string_lossy().into_owned());
+                by_directory.entry(dir.unwrap_or_default()).or_default().push(candidate);
+            }
+            let mut queues: Vec<std::vec::IntoIter<Candidate>> = by_directory
+                .into_values()
+                .map(|mut files| {
+                    files.sort_by(|a, b| a.path.cmp(&b.path));
+                    files.into_iter()
+                })
+                .collect();
+            // Round robin until every directory is drained
+            while !queues.is_empty() {
+                queues.retain_mut(|queue| match queue.next() {
+                    Some(candidate) => {
+                        candidates.push(candidate);
+                        true
+                    },
+                    None => false,
+                });
+            }
+        },
+    }
+    candidates
+}
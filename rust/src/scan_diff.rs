@@ -0,0 +1,67 @@
+// src/scan_diff.rs
+// Differences between two scan sessions, for change-only reports
+
+use std::collections::HashSet;
+
+/// What one scan found: every file seen and the duplicate groups among them
+pub struct Session {
+    pub files: HashSet<String>,
+    pub groups: Vec<Vec<String>>,
+}
+
+/// Changes from an old session to a new one; lists are sorted for stable reports
+pub struct ScanDiff {
+    pub added_files: Vec<String>,
+    pub removed_files: Vec<String>,
+    /// New groups containing at least one pair that was not a duplicate before
+    pub new_duplicates: Vec<Vec<String>>,
+    /// Old groups none of whose pairs are duplicates anymore
+    pub resolved_groups: Vec<Vec<String>>,
+}
+
+/// Every unordered pair within a group, smaller path first
+fn group_pairs(group: &[String]) -> impl Iterator<Item = (&str, &str)> {
+    group.iter().enumerate().flat_map(move |(i, a)| {
+        group[i + 1..].iter().map(move |b| if a <= b { (a.as_str(), b.as_str()) } else { (b.as_str(), a.as_str()) })
+    })
+}
+
+fn pairs(groups: &[Vec<String>]) -> HashSet<(&str, &str)> {
+    groups.iter().flat_map(|g| group_pairs(g)).collect()
+}
+
+fn sorted_groups<'a>(groups: impl Iterator<Item = &'a Vec<String>>) -> Vec<Vec<String>> {
+    let mut groups: Vec<Vec<String>> = groups
+        .map(|g| {
+            let mut g = g.clone();
+            g.sort();
+            g
+        })
+        .collect();
+    groups.sort();
+    groups
+}
+
+/// Compare two sessions
+pub fn diff(old: &Session, new: &Session) -> ScanDiff {
+    let mut added_files: Vec<String> = new.files.difference(&old.files).cloned().collect();
+    let mut removed_files: Vec<String> = old.files.difference(&new.files).cloned().collect();
+    added_files.sort();
+    removed_files.sort();
+
+    let old_pairs = pairs(&old.groups);
+    let new_pairs = pairs(&new.groups);
+
+    let new_duplicates = sorted_groups(
+        new.groups
+            .iter()
+            .filter(|g| group_pairs(g).any(|pair| !old_pairs.contains(&pair))),
+    );
+    let resolved_groups = sorted_groups(
+        old.groups
+            .iter()
+            .filter(|g| g.len() > 1 && group_pairs(g).all(|pair| !new_pairs.contains(&pair))),
+    );
+
+    ScanDiff { added_files, removed_files, new_duplicates, resolved_groups }
+}
@@ -0,0 +1,372 @@
+// src/script.rs
+// Reviewable sh / PowerShell scripts for planned duplicate actions, instead of executing them
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::PyResult;
+
+use crate::index::DuplicateDecision;
+
+/// What happens to the file that loses a `keep_a`/`keep_b` decision
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Replace it with a hard link to the kept file
+    Hardlink,
+    Delete,
+    /// Move it into a holding directory
+    Move,
+}
+
+impl Action {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "hardlink" => Ok(Action::Hardlink),
+            "delete" => Ok(Action::Delete),
+            "move" => Ok(Action::Move),
+            _ => Err(PyValueError::new_err(format!(
+                "Unsupported action '{}', expected 'hardlink', 'delete' or 'move'",
+                name
+            ))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Hardlink => "hardlink",
+            Action::Delete => "delete",
+            Action::Move => "move",
+        }
+    }
+}
+
+/// What to do with duplicates when a call names no action
+#[derive(Clone, PartialEq, Eq)]
+pub struct Policy {
+    pub action: Action,
+    /// Holding directory, set exactly when the action is `move`
+    pub move_to: Option<String>,
+}
+
+static POLICY: RwLock<Option<Policy>> = RwLock::new(None);
+
+impl Policy {
+    pub fn parse(action: &str, move_to: Option<&str>) -> PyResult<Self> {
+        let action = Action::parse(action)?;
+        if (action == Action::Move) != move_to.is_some() {
+            return Err(PyValueError::new_err("move_to is required for action 'move' and only allowed with it"));
+        }
+        Ok(Policy { action, move_to: move_to.map(str::to_string) })
+    }
+
+    /// `action` and `move_to` as given, or the configured policy when no
+    /// action is given (`move_to` still overriding its directory)
+    pub fn resolve(action: Option<&str>, move_to: Option<&str>) -> PyResult<Self> {
+        match action {
+            Some(action) => Policy::parse(action, move_to),
+            None => {
+                let policy = policy();
+                Policy::parse(policy.action.name(), move_to.or(policy.move_to.as_deref()))
+            },
+        }
+    }
+}
+
+/// The configured policy; hard links unless set
+pub fn policy() -> Policy {
+    POLICY.read().unwrap_or_else(|e| e.into_inner()).clone().unwrap_or(Policy { action: Action::Hardlink, move_to: None })
+}
+
+/// None restores the default
+pub fn set_policy(policy: Option<Policy>) {
+    *POLICY.write().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Posix,
+    PowerShell,
+}
+
+impl Shell {
+    /// `sh` or `powershell`; None picks PowerShell on Windows and sh elsewhere
+    pub fn parse(name: Option<&str>) -> PyResult<Self> {
+        match name {
+            None if cfg!(windows) => Ok(Shell::PowerShell),
+            None | Some("sh") => Ok(Shell::Posix),
+            Some("powershell") => Ok(Shell::PowerShell),
+            Some(other) => Err(PyValueError::new_err(format!(
+                "Unsupported shell '{}', expected 'sh' or 'powershell'",
+                other
+            ))),
+        }
+    }
+}
+
+/// `(kept, duplicate)` pairs from the keep decisions, each duplicate once
+///
+/// Chains (a kept over b, b kept over c) resolve to the file that is kept in
+/// the end, so every duplicate points at a file the plan does not touch.
+/// Chains follow the identities; the pairs hold the paths the files were
+/// recorded under, which are what the steps act on.
+pub fn plan(decisions: &[DuplicateDecision]) -> Vec<(String, String)> {
+    let mut keeper_of: HashMap<&str, &str> = HashMap::new();
+    let mut file_of: HashMap<&str, &str> = HashMap::new();
+    let mut order = Vec::new();
+    for decision in decisions {
+        let (file_a, file_b) = decision.files();
+        let ((kept, kept_file), (duplicate, duplicate_file)) = match decision.decision.as_str() {
+            "keep_a" => ((decision.path_a.as_str(), file_a), (decision.path_b.as_str(), file_b)),
+            "keep_b" => ((decision.path_b.as_str(), file_b), (decision.path_a.as_str(), file_a)),
+            _ => continue,
+        };
+        file_of.entry(kept).or_insert(kept_file);
+        file_of.entry(duplicate).or_insert(duplicate_file);
+        if !keeper_of.contains_key(duplicate) {
+            keeper_of.insert(duplicate, kept);
+            order.push(duplicate);
+        }
+    }
+
+    order
+        .into_iter()
+        .filter_map(|duplicate| {
+            let mut kept = keeper_of[duplicate];
+            // Bounded walk so a cycle of keep decisions cannot loop forever
+            for _ in 0..keeper_of.len() {
+                match keeper_of.get(kept) {
+                    Some(next) => kept = next,
+                    None => break,
+                }
+            }
+            // A cycle has no file left to keep; leave its members alone
+            (!keeper_of.contains_key(kept) && kept != duplicate)
+                .then(|| (file_of[kept].to_string(), file_of[duplicate].to_string()))
+        })
+        .collect()
+}
+
+/// Single-quoted sh word; embedded quotes become `'\''`
+fn sh_quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', "'\\''"))
+}
+
+/// Single-quoted PowerShell string; embedded quotes (including typographic ones) are doubled
+fn ps_quote(path: &str) -> String {
+    let mut quoted = String::with_capacity(path.len() + 2);
+    quoted.push('\'');
+    for c in path.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Final path component of `path`, splitting on both separators since plans
+/// may hold paths from either platform
+fn basename(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// Where `duplicate` lands in the holding directory `dir`
+fn move_target(dir: &str, duplicate: &str, separator: char) -> String {
+    format!("{}{}{}", dir.trim_end_matches(['/', '\\']), separator, basename(duplicate))
+}
+
+// New-Item has no -LiteralPath and globs its -Path and -Target, so links are
+// made with kernel32 directly; MoveFileEx flag 1 is MOVEFILE_REPLACE_EXISTING
+const PS_NATIVE: &str = r#"Add-Type -Namespace RawProcessor -Name Native -MemberDefinition @'
+[DllImport("kernel32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+public static extern bool CreateHardLink(string link, string existing, IntPtr reserved);
+[DllImport("kernel32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+public static extern bool MoveFileEx(string existing, string replacement, int flags);
+'@
+"#;
+
+/// Script applying `action` to every duplicate in `plan`, each step guarded by
+/// a check that the kept file still exists
+///
+/// Links are made under a temporary name and renamed over the duplicate, so a
+/// failed link leaves the duplicate in place. Moves never overwrite a file
+/// already in the holding directory. Steps that could not be done are
+/// reported and make the script exit with status 1.
+pub fn render(plan: &[(String, String)], action: Action, shell: Shell, move_to: Option<&str>) -> String {
+    let mut script = String::new();
+    match shell {
+        Shell::Posix => script.push_str("#!/bin/sh\n"),
+        Shell::PowerShell => script.push_str("#Requires -Version 5.1\n"),
+    }
+    script.push_str(&format!("# Planned duplicate actions: {} file(s), action '{}'\n", plan.len(), action.name()));
+    script.push_str("# Nothing has been changed yet. Review every step, then run this script.\n");
+    match shell {
+        Shell::Posix => script.push_str("status=0\n"),
+        Shell::PowerShell => script.push_str("$failed = $false\n"),
+    }
+    if shell == Shell::PowerShell && action == Action::Hardlink {
+        script.push_str(PS_NATIVE);
+    }
+    if let Some(dir) = move_to {
+        script.push_str(&match shell {
+            Shell::Posix => format!("mkdir -p -- {}\n", sh_quote(dir)),
+            Shell::PowerShell => format!("New-Item -ItemType Directory -Force -Path {} | Out-Null\n", ps_quote(dir)),
+        });
+    }
+    let dir = move_to.unwrap_or(".");
+
+    for (kept, duplicate) in plan {
+        script.push('\n');
+        script.push_str(&format!("# keep {}\n", kept.escape_debug()));
+        let name = basename(duplicate);
+        let temp = format!(
+            "{}{}",
+            &duplicate[..duplicate.len() - name.len()],
+            crate::paths::sibling_temp(std::path::Path::new(name)).to_string_lossy()
+        );
+        match shell {
+            Shell::Posix => {
+                let (kept, temp, duplicate_q) = (sh_quote(kept), sh_quote(&temp), sh_quote(duplicate));
+                let step = match action {
+                    Action::Hardlink => format!(
+                        "rm -f -- {temp}; ln -- {kept} {temp} && mv -f -- {temp} {duplicate_q} || {{ rm -f -- {temp}; printf 'could not link: %s\\n' {duplicate_q} >&2; status=1; }}"
+                    ),
+                    Action::Delete => format!("rm -f -- {duplicate_q} || status=1"),
+                    Action::Move => {
+                        let target = sh_quote(&move_target(dir, duplicate, '/'));
+                        format!(
+                            "if [ -e {target} ] || [ -L {target} ]; then printf 'name already taken: %s\\n' {target} >&2; status=1; else mv -n -- {duplicate_q} {target}; if [ -e {duplicate_q} ]; then printf 'could not move: %s\\n' {duplicate_q} >&2; status=1; fi; fi"
+                        )
+                    },
+                };
+                script.push_str(&format!(
+                    "if [ -f {kept} ]; then {step}; else printf 'missing kept file: %s\\n' {kept} >&2; status=1; fi\n"
+                ));
+            },
+            Shell::PowerShell => {
+                let (kept, temp, duplicate_q) = (ps_quote(kept), ps_quote(&temp), ps_quote(duplicate));
+                let step = match action {
+                    Action::Hardlink => format!(
+                        "Remove-Item -LiteralPath {temp} -Force -ErrorAction SilentlyContinue; if (-not ([RawProcessor.Native]::CreateHardLink({temp}, {kept}, [IntPtr]::Zero) -and [RawProcessor.Native]::MoveFileEx({temp}, {duplicate_q}, 1))) {{ Remove-Item -LiteralPath {temp} -Force -ErrorAction SilentlyContinue; Write-Warning ('could not link: ' + {duplicate_q}); $failed = $true }}"
+                    ),
+                    Action::Delete => format!(
+                        "try {{ Remove-Item -LiteralPath {duplicate_q} -Force -ErrorAction Stop }} catch {{ Write-Warning $_; $failed = $true }}"
+                    ),
+                    Action::Move => {
+                        let target = ps_quote(&move_target(dir, duplicate, '\\'));
+                        format!(
+                            "if (Test-Path -LiteralPath {target}) {{ Write-Warning ('name already taken: ' + {target}); $failed = $true }} else {{ try {{ Move-Item -LiteralPath {duplicate_q} -Destination {target} -ErrorAction Stop }} catch {{ Write-Warning $_; $failed = $true }} }}"
+                        )
+                    },
+                };
+                script.push_str(&format!(
+                    "if (Test-Path -LiteralPath {kept} -PathType Leaf) {{ {step} }} else {{ Write-Warning ('missing kept file: ' + {kept}); $failed = $true }}\n"
+                ));
+            },
+        }
+    }
+
+    script.push('\n');
+    script.push_str(match shell {
+        Shell::Posix => "exit $status\n",
+        Shell::PowerShell => "if ($failed) { exit 1 }\n",
+    });
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn pair(kept: &str, duplicate: &str) -> (String, String) {
+        (kept.to_string(), duplicate.to_string())
+    }
+
+    #[test]
+    fn plans_act_on_recorded_paths() {
+        let decision = |(a, location_a): (&str, &str), (b, location_b): (&str, &str), verdict: &str| DuplicateDecision {
+            path_a: a.to_string(),
+            path_b: b.to_string(),
+            decision: verdict.to_string(),
+            location_a: location_a.to_string(),
+            location_b: location_b.to_string(),
+        };
+        // Identities normalize NFD and trailing dots away; the steps must not
+        let nfd = ("/p/caf\u{e9}.jpg", "/p/cafe\u{301}.jpg");
+        let dotted = ("/p/b.jpg", "/p/b.jpg.");
+        let decisions = [
+            decision(("/p/a.jpg", "/p/a.jpg"), nfd, "keep_a"),
+            decision(nfd, dotted, "keep_a"),
+            decision(("/p/c.jpg", ""), ("/p/d.jpg", ""), "keep_b"),
+        ];
+        assert_eq!(
+            plan(&decisions),
+            vec![pair("/p/a.jpg", "/p/cafe\u{301}.jpg"), pair("/p/a.jpg", "/p/b.jpg."), pair("/p/d.jpg", "/p/c.jpg")]
+        );
+    }
+
+    #[test]
+    fn powershell_steps_take_paths_literally() {
+        let plan = [pair("C:\\photos\\a [1].jpg", "C:\\photos\\b [1]'s.jpg")];
+        let script = render(&plan, Action::Hardlink, Shell::PowerShell, None);
+        assert!(!script.contains("New-Item -ItemType HardLink"));
+        assert!(!script.contains(" -Path "));
+        assert!(script.contains("CreateHardLink('C:\\photos\\.raw_processor_"));
+        assert!(script.contains("'C:\\photos\\b [1]''s.jpg'"));
+
+        let script = render(&plan, Action::Move, Shell::PowerShell, Some("D:\\held"));
+        assert!(script.contains("Test-Path -LiteralPath 'D:\\held\\b [1]''s.jpg'"));
+    }
+
+    #[cfg(unix)]
+    fn run(name: &str, plan: &[(String, String)], action: Action, move_to: Option<&str>) -> bool {
+        let script = crate::tiff::fixtures::temp_path(&format!("{}.sh", name));
+        fs::write(&script, render(plan, action, Shell::Posix, move_to)).unwrap();
+        let status = std::process::Command::new("sh").arg(&script).stderr(std::process::Stdio::null()).status().unwrap();
+        let _ = fs::remove_file(&script);
+        status.success()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_links_keep_the_duplicate() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = crate::tiff::fixtures::temp_path("script_link");
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        fs::write(path("kept.jpg"), b"kept").unwrap();
+        fs::write(path("dup.jpg"), b"dup").unwrap();
+
+        // Kept file gone: nothing changes and the script fails
+        assert!(!run("link", &[pair(&path("gone.jpg"), &path("dup.jpg"))], Action::Hardlink, None));
+        assert_eq!(fs::read(path("dup.jpg")).unwrap(), b"dup");
+
+        assert!(run("link", &[pair(&path("kept.jpg"), &path("dup.jpg"))], Action::Hardlink, None));
+        assert_eq!(fs::metadata(path("dup.jpg")).unwrap().ino(), fs::metadata(path("kept.jpg")).unwrap().ino());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn move_collisions_are_reported() {
+        let dir = crate::tiff::fixtures::temp_path("script_move");
+        let held = dir.join("held");
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::create_dir_all(dir.join("b")).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        for name in ["kept.jpg", "a/IMG_1.jpg", "b/IMG_1.jpg"] {
+            fs::write(path(name), name).unwrap();
+        }
+
+        let plan = [pair(&path("kept.jpg"), &path("a/IMG_1.jpg")), pair(&path("kept.jpg"), &path("b/IMG_1.jpg"))];
+        assert!(!run("move", &plan, Action::Move, Some(&held.to_string_lossy())));
+        assert_eq!(fs::read(held.join("IMG_1.jpg")).unwrap(), b"a/IMG_1.jpg");
+        assert_eq!(fs::read(path("b/IMG_1.jpg")).unwrap(), b"b/IMG_1.jpg");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+// src/search.rs
+// Ranked nearest-neighbour search over indexed hashes: a 64-bit hash prunes, a 256-bit hash confirms
+
+use rayon::prelude::*;
+
+use crate::hamming::{self, Packed};
+use crate::index::ImageRecord;
+
+/// An indexed image close to the query
+pub struct Match {
+    pub record: ImageRecord,
+    /// Hamming distance of the perceptual hashes, the ranking key
+    pub distance: u32,
+    /// Hamming distance of the average hashes, used to break ties
+    pub average_distance: u32,
+    /// Hamming distance of the 256-bit fine hashes, once confirmed
+    pub fine_distance: Option<u32>,
+}
+
+/// Hashes of the query image, as '0'/'1' strings
+pub struct QueryHashes {
+    pub average: String,
+    pub perceptual: String,
+    /// 256-bit hash for the confirmation stage
+    pub fine: String,
+}
+
+/// Medoid of a group of hashes: the position of the one with the smallest
+/// total Hamming distance to all the others, and that total
+///
+/// Incomparable pairs (different lengths or malformed) count as every bit
+/// differing; ties go to the earliest hash.
+pub fn medoid(hashes: &[&str]) -> Option<(usize, u64)> {
+    let packed: Vec<Option<Packed>> = hashes.par_iter().map(|h| Packed::from_str(h).filter(|p| p.bits() > 0)).collect();
+    packed
+        .par_iter()
+        .enumerate()
+        .map(|(i, a)| {
+            let total: u64 = packed
+                .iter()
+                .zip(hashes)
+                .map(|(b, b_str)| {
+                    let distance = a.as_ref().zip(b.as_ref()).and_then(|(a, b)| hamming::distance(a, b));
+                    distance.unwrap_or(hashes[i].len().max(b_str.len()) as u32) as u64
+                })
+                .sum();
+            (i, total)
+        })
+        .min_by_key(|&(i, total)| (total, i))
+}
+
+/// Every record within `max_distance` of the query perceptual hash, closest first
+fn candidates(records: Vec<ImageRecord>, average_hash: &str, perceptual_hash: &str, max_distance: u32) -> Vec<Match> {
+    let Some(perceptual_hash) = Packed::from_str(perceptual_hash).filter(|p| p.bits() > 0) else {
+        return Vec::new();
+    };
+    let mut matches: Vec<Match> = records
+        .into_iter()
+        .filter_map(|record| {
+            let distance = hamming::distance(&perceptual_hash, &Packed::from_str(&record.perceptual_hash)?)?;
+            let average_distance = hamming::str_distance(average_hash, &record.average_hash).unwrap_or(u32::MAX);
+            (distance <= max_distance).then_some(Match { record, distance, average_distance, fine_distance: None })
+        })
+        .collect();
+
+    matches.sort_by(|a, b| {
+        a.distance
+            .cmp(&b.distance)
+            .then(a.average_distance.cmp(&b.average_distance))
+            .then_with(|| a.record.path.cmp(&b.record.path))
+    });
+    matches
+}
+
+/// The `k` records closest to the query hashes, at most `max_distance` away
+pub fn nearest(
+    records: Vec<ImageRecord>,
+    average_hash: &str,
+    perceptual_hash: &str,
+    k: usize,
+    max_distance: u32,
+) -> Vec<Match> {
+    let mut matches = candidates(records, average_hash, perceptual_hash, max_distance);
+    matches.truncate(k);
+    matches
+}
+
+/// Two-stage search: prune with the 64-bit perceptual hash, confirm with the fine hash
+///
+/// Every candidate within `max_distance` is checked against the query's fine hash and
+/// kept if at most `max_fine_distance` of its 256 bits differ; the `k` best
+/// are returned, ranked by fine distance. Records indexed without a fine hash
+/// get one from `fine_hash_of` (typically by decoding the file); candidates
+/// for which none can be had are dropped.
+pub fn nearest_confirmed(
+    records: Vec<ImageRecord>,
+    query: &QueryHashes,
+    k: usize,
+    max_distance: u32,
+    max_fine_distance: u32,
+    fine_hash_of: impl Fn(&ImageRecord) -> Option<String> + Sync,
+) -> Vec<Match> {
+    let mut matches: Vec<Match> = candidates(records, &query.average, &query.perceptual, max_distance)
+        .into_par_iter()
+        .filter_map(|mut m| {
+            let fine_distance = if m.record.fine_hash.is_empty() {
+                hamming::str_distance(&query.fine, &fine_hash_of(&m.record)?)
+            } else {
+                hamming::str_distance(&query.fine, &m.record.fine_hash)
+            }?;
+            m.fine_distance = Some(fine_distance);
+            (fine_distance <= max_fine_distance).then_some(m)
+        })
+        .collect();
+
+    matches.sort_by(|a, b| {
+        a.fine_distance
+            .cmp(&b.fine_distance)
+            .then(a.distance.cmp(&b.distance))
+            .then_with(|| a.record.path.cmp(&b.record.path))
+    });
+    matches.truncate(k);
+    matches
+}
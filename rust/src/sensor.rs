@@ -0,0 +1,73 @@
+// src/sensor.rs
+// Grayscale thumbnails straight from the Bayer sensor data, without demosaicing
+
+use image::{imageops, ImageBuffer, Luma};
+use rawloader::{RawImage, RawImageData};
+
+use crate::grayscale::{GrayscaleBuffer, GrayscaleDtype};
+
+/// Luminance of every 2x2 Bayer tile inside the crop, scaled to `size` x `size`
+///
+/// Any 2x2 window of a Bayer sensor holds one red, two green and one blue
+/// site, so its mean after black/white level scaling is a luminance estimate
+/// that needs no demosaic and does not depend on the layout. Thumbnails made
+/// this way match across tools whatever interpolation they would use, but not
+/// thumbnails of decoded images. None for sensors without a 2x2 layout
+/// (X-Trans, Foveon, linear DNG), which need the regular decode. Also returns
+/// the size of the tile grid that was scaled.
+pub fn thumbnail(
+    raw_image: &RawImage,
+    size: u32,
+    dtype: GrayscaleDtype,
+    filter: imageops::FilterType,
+) -> Option<(GrayscaleBuffer, (u32, u32))> {
+    if raw_image.cpp != 1 || raw_image.cfa.width != 2 || raw_image.cfa.height != 2 {
+        return None;
+    }
+    let [top, right, bottom, left] = raw_image.crops;
+    let width = raw_image.width.checked_sub(left + right)?;
+    let height = raw_image.height.checked_sub(top + bottom)?;
+    let (tiles_x, tiles_y) = (width / 2, height / 2);
+    if tiles_x == 0 || tiles_y == 0 {
+        return None;
+    }
+
+    // Scale each site to 0-1 with the black and white level of its color
+    let level = |y: usize, x: usize, value: f32| -> f32 {
+        let color = raw_image.cfa.color_at(y, x);
+        let black = raw_image.blacklevels[color] as f32;
+        let white = raw_image.whitelevels[color] as f32;
+        if white <= black {
+            return 0.0;
+        }
+        ((value - black) / (white - black)).clamp(0.0, 1.0)
+    };
+    let site = |y: usize, x: usize| -> Option<f32> {
+        let idx = y * raw_image.width + x;
+        match &raw_image.data {
+            RawImageData::Integer(data) => data.get(idx).map(|&v| level(y, x, v as f32)),
+            RawImageData::Float(data) => data.get(idx).map(|&v| v.clamp(0.0, 1.0)),
+        }
+    };
+
+    let mut tiles = ImageBuffer::<Luma<f32>, Vec<f32>>::new(tiles_x as u32, tiles_y as u32);
+    for ty in 0..tiles_y {
+        let y = top + 2 * ty;
+        for tx in 0..tiles_x {
+            let x = left + 2 * tx;
+            let sum = site(y, x)? + site(y, x + 1)? + site(y + 1, x)? + site(y + 1, x + 1)?;
+            // Same display gamma as the rest of the pipeline
+            tiles.put_pixel(tx as u32, ty as u32, Luma([(sum / 4.0).powf(0.45)]));
+        }
+    }
+
+    let resized = imageops::resize(&tiles, size, size, filter).into_raw();
+    let grayscale = match dtype {
+        GrayscaleDtype::U8 => GrayscaleBuffer::U8(resized.iter().map(|&v| (v.clamp(0.0, 1.0) * 255.0).round() as u8).collect()),
+        GrayscaleDtype::U16 => {
+            GrayscaleBuffer::U16(resized.iter().map(|&v| (v.clamp(0.0, 1.0) * 65535.0).round() as u16).collect())
+        },
+        GrayscaleDtype::F32 => GrayscaleBuffer::F32(resized.into_iter().map(|v| v.clamp(0.0, 1.0)).collect()),
+    };
+    Some((grayscale, tiles.dimensions()))
+}
@@ -0,0 +1,219 @@
+// src/sessions.rs
+// Duplicate groups of a scan session kept in the index, read back one page at a time
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::PyResult;
+
+use crate::index::{self, IndexStore};
+
+// Groups per stored setting, so a page touches a few settings rather than all of them
+const GROUPS_PER_CHUNK: usize = 1000;
+// Group ids per stored setting of a sort order
+const IDS_PER_CHUNK: usize = 10_000;
+
+/// Order in which `page` returns the groups
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    /// Most files first
+    Files,
+    /// Largest total size first
+    Bytes,
+    /// By the group's first path in name order
+    Path,
+}
+
+impl SortBy {
+    const ALL: [SortBy; 3] = [SortBy::Files, SortBy::Bytes, SortBy::Path];
+
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "files" => Ok(SortBy::Files),
+            "bytes" => Ok(SortBy::Bytes),
+            "path" => Ok(SortBy::Path),
+            _ => Err(PyValueError::new_err(format!(
+                "Unsupported sort_by '{}', expected 'files', 'bytes' or 'path'",
+                name
+            ))),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SortBy::Files => "files",
+            SortBy::Bytes => "bytes",
+            SortBy::Path => "path",
+        }
+    }
+}
+
+/// One duplicate group with the total size of its files
+pub struct Group {
+    pub paths: Vec<String>,
+    pub bytes: u64,
+    /// From `group_ids::stable_ids`; None in sessions saved before it existed
+    pub stable_id: Option<String>,
+}
+
+impl Group {
+    fn first_path(&self) -> &str {
+        self.paths.iter().min().map(String::as_str).unwrap_or_default()
+    }
+
+    fn encode(&self) -> String {
+        let mut fields = vec![match &self.stable_id {
+            Some(stable_id) => format!("{}:{}", self.bytes, stable_id),
+            None => self.bytes.to_string(),
+        }];
+        fields.extend(self.paths.iter().map(|path| index::escape(path)));
+        fields.join("\t")
+    }
+
+    fn decode(line: &str) -> io::Result<Group> {
+        let mut fields = line.split('\t');
+        let head = fields.next().unwrap_or_default();
+        let (bytes, stable_id) = match head.split_once(':') {
+            Some((bytes, stable_id)) => (bytes, Some(stable_id.to_string())),
+            None => (head, None),
+        };
+        let bytes = bytes.parse().map_err(|_| invalid(format!("Corrupt session group: {}", line)))?;
+        Ok(Group { paths: fields.map(index::unescape).collect(), bytes, stable_id })
+    }
+}
+
+fn count_key(session: &str) -> String {
+    format!("session:{}:groups", session)
+}
+
+fn chunk_key(session: &str, chunk: usize) -> String {
+    format!("session:{}:groups:{}", session, chunk)
+}
+
+fn order_key(session: &str, sort_by: SortBy, chunk: usize) -> String {
+    format!("session:{}:order:{}:{}", session, sort_by.name(), chunk)
+}
+
+/// Whether a setting is a session chunk that older versions emptied instead
+/// of removing; it reads the same as a missing one
+pub fn is_emptied_chunk(key: &str, value: &str) -> bool {
+    key.starts_with("session:") && value.is_empty()
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Number of groups stored for `session`, or None when it was never saved
+pub fn count(store: &mut dyn IndexStore, session: &str) -> io::Result<Option<usize>> {
+    match store.get_setting(&count_key(session))? {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| invalid(format!("Corrupt group count of session '{}': {}", session, value))),
+        None => Ok(None),
+    }
+}
+
+/// Store `groups` as `session`, replacing what was stored under that name
+///
+/// Groups keep their position as id. Each sort order is computed once here,
+/// so reading any page later costs a few settings whatever the session size.
+pub fn save(store: &mut dyn IndexStore, session: &str, groups: &[Group]) -> io::Result<()> {
+    let previous = count(store, session)?.unwrap_or(0);
+    for (chunk, groups) in groups.chunks(GROUPS_PER_CHUNK).enumerate() {
+        let lines: Vec<String> = groups.iter().map(Group::encode).collect();
+        store.put_setting(&chunk_key(session, chunk), &lines.join("\n"))?;
+    }
+
+    for sort_by in SortBy::ALL {
+        let mut ids: Vec<usize> = (0..groups.len()).collect();
+        // Ties fall back to the stable id and then the first path, so a
+        // re-scan that finds the groups in another order pages them the same
+        let tie = |a: usize, b: usize| {
+            (&groups[a].stable_id, groups[a].first_path()).cmp(&(&groups[b].stable_id, groups[b].first_path()))
+        };
+        match sort_by {
+            SortBy::Files => ids.sort_by(|&a, &b| {
+                (groups[b].paths.len(), groups[b].bytes)
+                    .cmp(&(groups[a].paths.len(), groups[a].bytes))
+                    .then_with(|| tie(a, b))
+            }),
+            SortBy::Bytes => ids.sort_by(|&a, &b| {
+                (groups[b].bytes, groups[b].paths.len())
+                    .cmp(&(groups[a].bytes, groups[a].paths.len()))
+                    .then_with(|| tie(a, b))
+            }),
+            SortBy::Path => {
+                ids.sort_by(|&a, &b| groups[a].first_path().cmp(groups[b].first_path()).then_with(|| tie(a, b)))
+            },
+        }
+        for (chunk, ids) in ids.chunks(IDS_PER_CHUNK).enumerate() {
+            let ids: Vec<String> = ids.iter().map(usize::to_string).collect();
+            store.put_setting(&order_key(session, sort_by, chunk), &ids.join("\n"))?;
+        }
+        // Chunks a larger earlier save left behind are removed rather than kept stale
+        for chunk in groups.len().div_ceil(IDS_PER_CHUNK)..previous.div_ceil(IDS_PER_CHUNK) {
+            store.remove_setting(&order_key(session, sort_by, chunk))?;
+        }
+    }
+    for chunk in groups.len().div_ceil(GROUPS_PER_CHUNK)..previous.div_ceil(GROUPS_PER_CHUNK) {
+        store.remove_setting(&chunk_key(session, chunk))?;
+    }
+
+    // Written last: an interrupted save leaves the old count, which never
+    // reaches past the chunks it wrote
+    store.put_setting(&count_key(session), &groups.len().to_string())
+}
+
+/// Groups `offset..offset + limit` of `session` in `sort_by` order, with their ids
+///
+/// Only the settings holding the page are read. An unknown session or an
+/// offset past the end gives an empty page.
+pub fn page(
+    store: &mut dyn IndexStore,
+    session: &str,
+    offset: usize,
+    limit: usize,
+    sort_by: SortBy,
+) -> io::Result<Vec<(usize, Group)>> {
+    let total = count(store, session)?.unwrap_or(0);
+    let end = offset.saturating_add(limit).min(total);
+    if offset >= end {
+        return Ok(Vec::new());
+    }
+
+    let mut ids = Vec::with_capacity(end - offset);
+    for chunk in offset / IDS_PER_CHUNK..=(end - 1) / IDS_PER_CHUNK {
+        let value = store.get_setting(&order_key(session, sort_by, chunk))?.unwrap_or_default();
+        let start = chunk * IDS_PER_CHUNK;
+        for (position, id) in value.lines().enumerate().map(|(i, id)| (start + i, id)) {
+            if (offset..end).contains(&position) {
+                let id: usize = id
+                    .parse()
+                    .map_err(|_| invalid(format!("Corrupt group order of session '{}': {}", session, id)))?;
+                ids.push(id);
+            }
+        }
+    }
+
+    let mut chunks: HashMap<usize, Vec<String>> = HashMap::new();
+    let mut page = Vec::with_capacity(ids.len());
+    for id in ids {
+        let chunk = id / GROUPS_PER_CHUNK;
+        let lines = match chunks.entry(chunk) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = store.get_setting(&chunk_key(session, chunk))?.unwrap_or_default();
+                entry.insert(value.lines().map(str::to_string).collect())
+            },
+        };
+        let line = lines
+            .get(id % GROUPS_PER_CHUNK)
+            .ok_or_else(|| invalid(format!("Session '{}' is missing group {}", session, id)))?;
+        page.push((id, Group::decode(line)?));
+    }
+    Ok(page)
+}
@@ -0,0 +1,160 @@
+// src/sidecar.rs
+// Rules for derived files (sidecars, catalog previews, NAS thumbnails) that must not be hashed
+
+use std::path::{Component, Path};
+use std::sync::RwLock;
+
+/// How a rule matches a path; all comparisons are case-insensitive
+#[derive(Clone)]
+pub enum Rule {
+    /// File extension without the dot, e.g. `xmp`
+    Extension(String),
+    /// File name prefix, e.g. `._` for macOS AppleDouble files
+    Prefix(String),
+    /// Any directory component equal to this name, e.g. `@eaDir`
+    Directory(String),
+    /// Any directory component ending in this suffix, e.g. `.lrdata`
+    DirectorySuffix(String),
+}
+
+impl Rule {
+    /// Parse a rule from its kind name as used by the Python config
+    pub fn parse(kind: &str, pattern: &str) -> Option<Rule> {
+        let pattern = pattern.to_lowercase();
+        match kind {
+            "extension" => Some(Rule::Extension(pattern.trim_start_matches('.').to_string())),
+            "prefix" => Some(Rule::Prefix(pattern)),
+            "directory" => Some(Rule::Directory(pattern)),
+            "directory_suffix" => Some(Rule::DirectorySuffix(pattern)),
+            _ => None,
+        }
+    }
+
+    /// The (kind, pattern) pair this rule was parsed from
+    pub fn describe(&self) -> (&'static str, &str) {
+        match self {
+            Rule::Extension(p) => ("extension", p),
+            Rule::Prefix(p) => ("prefix", p),
+            Rule::Directory(p) => ("directory", p),
+            Rule::DirectorySuffix(p) => ("directory_suffix", p),
+        }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let lower = |s: &std::ffi::OsStr| s.to_string_lossy().to_lowercase();
+        let file_name = path.file_name().map(lower).unwrap_or_default();
+        let mut dirs = path
+            .parent()
+            .into_iter()
+            .flat_map(|p| p.components())
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(lower(name)),
+                _ => None,
+            });
+
+        match self {
+            Rule::Extension(ext) => path.extension().map(lower).is_some_and(|e| &e == ext),
+            Rule::Prefix(prefix) => file_name.starts_with(prefix.as_str()),
+            Rule::Directory(name) => dirs.any(|d| &d == name),
+            Rule::DirectorySuffix(suffix) => dirs.any(|d| d.ends_with(suffix.as_str())),
+        }
+    }
+}
+
+/// Built-in rules covering the common editors, catalogs and NAS indexers
+pub fn default_rules() -> Vec<Rule> {
+    vec![
+        // Editor sidecars: Lightroom/ACR, DxO, RawTherapee, ON1, Apple Photos, Capture One
+        Rule::Extension("xmp".to_string()),
+        Rule::Extension("dop".to_string()),
+        Rule::Extension("pp3".to_string()),
+        Rule::Extension("on1".to_string()),
+        Rule::Extension("aae".to_string()),
+        Rule::Extension("cos".to_string()),
+        // Camera-written video thumbnails
+        Rule::Extension("thm".to_string()),
+        // macOS AppleDouble resource forks
+        Rule::Prefix("._".to_string()),
+        // Synology and generic thumbnail directories
+        Rule::Directory("@eadir".to_string()),
+        Rule::Directory(".thumbnails".to_string()),
+        Rule::Directory("capture one".to_string()),
+        // Lightroom "<catalog> Previews.lrdata" / "Smart Previews.lrdata"
+        Rule::DirectorySuffix(".lrdata".to_string()),
+    ]
+}
+
+fn rules() -> &'static RwLock<Vec<Rule>> {
+    static RULES: std::sync::OnceLock<RwLock<Vec<Rule>>> = std::sync::OnceLock::new();
+    RULES.get_or_init(|| RwLock::new(default_rules()))
+}
+
+/// Add a rule to the active set
+pub fn add_rule(rule: Rule) {
+    rules().write().unwrap_or_else(|e| e.into_inner()).push(rule);
+}
+
+/// Replace the active rules (an empty list disables filtering)
+pub fn set_rules(new_rules: Vec<Rule>) {
+    *rules().write().unwrap_or_else(|e| e.into_inner()) = new_rules;
+}
+
+/// Snapshot of the active rules
+pub fn active_rules() -> Vec<Rule> {
+    rules().read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// True if `path` is a derived file that should be excluded from hashing
+pub fn is_derived(path: &str) -> bool {
+    let path = Path::new(path);
+    rules()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .any(|rule| rule.matches(path))
+}
+
+/// Best guess at the original a derived file belongs to
+///
+/// Handles `IMG_1.CR2.xmp` / `IMG_1.xmp` sidecars, `._IMG_1.JPG` AppleDouble
+/// files and Synology `@eaDir/IMG_1.JPG/SYNOPHOTO_THUMB_XL.jpg` thumbnails.
+/// Only returns a path that exists on disk.
+pub fn parent_of(path: &str) -> Option<String> {
+    let path = Path::new(path);
+    let dir = path.parent()?;
+    let file_name = path.file_name()?.to_str()?;
+
+    // @eaDir/<original name>/<thumbnail>
+    if let Some(original_dir) = dir.file_name().and_then(|n| n.to_str()) {
+        let ea_dir = dir.parent()?;
+        if ea_dir.file_name().is_some_and(|n| n.eq_ignore_ascii_case("@eaDir")) {
+            let candidate = ea_dir.parent()?.join(original_dir);
+            return candidate.exists().then(|| candidate.to_string_lossy().into_owned());
+        }
+    }
+
+    if let Some(original) = file_name.strip_prefix("._") {
+        let candidate = dir.join(original);
+        return candidate.exists().then(|| candidate.to_string_lossy().into_owned());
+    }
+
+    // IMG_1.CR2.xmp names the original directly
+    let stem = path.file_stem()?.to_str()?;
+    let direct = dir.join(stem);
+    if Path::new(stem).extension().is_some() && direct.is_file() {
+        return Some(direct.to_string_lossy().into_owned());
+    }
+
+    // IMG_1.xmp: any non-derived sibling with the same stem
+    let entries = std::fs::read_dir(dir).ok()?;
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| {
+            p.as_path() != path
+                && p.is_file()
+                && p.file_stem().and_then(|s| s.to_str()).is_some_and(|s| s.eq_ignore_ascii_case(stem))
+                && !is_derived(&p.to_string_lossy())
+        })
+        .map(|p| p.to_string_lossy().into_owned())
+}
@@ -0,0 +1,237 @@
+// src/simulation.rs
+// Dry runs of a duplicate policy over a saved scan session: what each policy would keep and free
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::time::SystemTime;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::PyResult;
+
+use crate::image_stats::ImageStats;
+use crate::provenance;
+
+/// Which member of a duplicate group a policy keeps
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum KeepRule {
+    /// Most bytes on disk
+    Largest,
+    /// Most pixels according to the file header
+    HighestResolution,
+    /// Earliest modification time
+    Oldest,
+    /// Latest modification time
+    Newest,
+    /// Fewest characters in the path, e.g. the copy outside nested backup folders
+    ShortestPath,
+    /// Highest measured sharpness
+    Sharpest,
+    /// Lowest measured noise
+    LeastNoisy,
+    /// Fewest clipped pixels, then mean brightness closest to mid-gray
+    BestExposed,
+}
+
+const KEEP_RULES: &[&str] = &[
+    "largest",
+    "highest_resolution",
+    "oldest",
+    "newest",
+    "shortest_path",
+    "sharpest",
+    "least_noisy",
+    "best_exposed",
+];
+
+impl KeepRule {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "largest" => Ok(KeepRule::Largest),
+            "highest_resolution" => Ok(KeepRule::HighestResolution),
+            "oldest" => Ok(KeepRule::Oldest),
+            "newest" => Ok(KeepRule::Newest),
+            "shortest_path" => Ok(KeepRule::ShortestPath),
+            "sharpest" => Ok(KeepRule::Sharpest),
+            "least_noisy" => Ok(KeepRule::LeastNoisy),
+            "best_exposed" => Ok(KeepRule::BestExposed),
+            _ => Err(PyValueError::new_err(format!(
+                "Unsupported keep rule '{}', expected one of {:?}",
+                name, KEEP_RULES
+            ))),
+        }
+    }
+
+    /// Whether the rule ranks by `ImageStats`, which take a decode to measure
+    pub fn needs_stats(self) -> bool {
+        matches!(self, KeepRule::Sharpest | KeepRule::LeastNoisy | KeepRule::BestExposed)
+    }
+}
+
+/// What a policy looks at when choosing; read once per file
+struct Facts {
+    size: u64,
+    modified: Option<SystemTime>,
+    pixels: Option<u64>,
+    /// (device, inode), to tell files that are already hard links of each other
+    identity: Option<(u64, u64)>,
+    stats: Option<ImageStats>,
+}
+
+impl Facts {
+    /// None when the file no longer exists
+    fn read(path: &str, keep: KeepRule, stats: Option<&ImageStats>) -> Option<Facts> {
+        let metadata = fs::metadata(path).ok()?;
+        let pixels = match stats.and_then(|stats| stats.width.zip(stats.height)) {
+            Some(dimensions) => Some(dimensions),
+            None => (keep == KeepRule::HighestResolution).then(|| provenance::original_dimensions(path)).flatten(),
+        }
+        .map(|(width, height)| width as u64 * height as u64);
+        Some(Facts {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            pixels,
+            identity: identity(&metadata),
+            stats: stats.copied(),
+        })
+    }
+}
+
+#[cfg(unix)]
+pub fn identity(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn identity(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// The outcome for one duplicate group
+pub struct GroupOutcome {
+    pub kept: String,
+    /// The other members, each acted on with the policy's action
+    pub duplicates: Vec<String>,
+    /// Bytes the group would stop taking up
+    pub reclaimed_bytes: u64,
+}
+
+pub struct Simulation {
+    pub groups: Vec<GroupOutcome>,
+    /// Session files that no longer exist; never kept or acted on
+    pub missing: Vec<String>,
+    /// Bytes taken by all existing files in the groups
+    pub total_bytes: u64,
+    pub reclaimed_bytes: u64,
+}
+
+/// Order by a measure where lower is better; files without stats sort last
+fn measured(a: &Facts, b: &Facts, measure: impl Fn(&ImageStats) -> f64) -> Ordering {
+    match (&a.stats, &b.stats) {
+        (Some(a), Some(b)) => measure(a).total_cmp(&measure(b)),
+        (a, b) => a.is_none().cmp(&b.is_none()),
+    }
+}
+
+/// Merge groups sharing a file, so each file is decided once; groups keep
+/// the order of their first appearance
+fn merge_groups(groups: &[Vec<String>]) -> Vec<Vec<String>> {
+    let mut group_of: HashMap<&str, usize> = HashMap::new();
+    let mut merged: Vec<Vec<&str>> = Vec::new();
+    for group in groups {
+        let mut targets: Vec<usize> = group.iter().filter_map(|path| group_of.get(path.as_str()).copied()).collect();
+        targets.sort_unstable();
+        targets.dedup();
+        let target = match targets.first() {
+            Some(&first) => first,
+            None => {
+                merged.push(Vec::new());
+                merged.len() - 1
+            },
+        };
+        // Fold the other groups this one bridges into the earliest
+        for &other in targets.iter().skip(1) {
+            for path in std::mem::take(&mut merged[other]) {
+                group_of.insert(path, target);
+                merged[target].push(path);
+            }
+        }
+        for path in group {
+            if group_of.insert(path, target).is_none() {
+                merged[target].push(path);
+            }
+        }
+    }
+    merged
+        .into_iter()
+        .filter(|group| group.len() > 1)
+        .map(|group| group.into_iter().map(str::to_string).collect())
+        .collect()
+}
+
+/// Which file of each of the session's duplicate groups `keep` would keep and
+/// what acting on the others would free, reading only file metadata and headers
+///
+/// The measured rules rank by `stats`, which the caller gathers beforehand;
+/// files without stats rank last. Dimensions in `stats` also spare reading
+/// headers. Ties are broken by path so the same session and policy always
+/// give the same answer. Files already hard-linked to the kept one free
+/// nothing, and moving frees the space on this volume only.
+pub fn simulate(groups: &[Vec<String>], keep: KeepRule, stats: &HashMap<String, ImageStats>) -> Simulation {
+    let mut simulation = Simulation { groups: Vec::new(), missing: Vec::new(), total_bytes: 0, reclaimed_bytes: 0 };
+
+    for group in merge_groups(groups) {
+        let mut members: Vec<(String, Facts)> = Vec::with_capacity(group.len());
+        for path in group {
+            match Facts::read(&path, keep, stats.get(&path)) {
+                Some(facts) => members.push((path, facts)),
+                None => simulation.missing.push(path),
+            }
+        }
+        // Count every existing file once, even when hard links share its blocks
+        let mut seen = HashSet::new();
+        simulation.total_bytes += members
+            .iter()
+            .filter(|(_, facts)| facts.identity.is_none_or(|id| seen.insert(id)))
+            .map(|(_, facts)| facts.size)
+            .sum::<u64>();
+        if members.len() < 2 {
+            continue;
+        }
+
+        members.sort_by(|(a_path, a), (b_path, b)| {
+            let preference = match keep {
+                KeepRule::Largest => b.size.cmp(&a.size),
+                KeepRule::HighestResolution => b.pixels.cmp(&a.pixels),
+                // Files without a time sort last either way
+                KeepRule::Oldest => a.modified.is_none().cmp(&b.modified.is_none()).then(a.modified.cmp(&b.modified)),
+                KeepRule::Newest => b.modified.cmp(&a.modified),
+                KeepRule::ShortestPath => a_path.chars().count().cmp(&b_path.chars().count()),
+                KeepRule::Sharpest => measured(a, b, |stats| -stats.sharpness),
+                KeepRule::LeastNoisy => measured(a, b, |stats| stats.noise),
+                KeepRule::BestExposed => measured(a, b, |stats| stats.clipped)
+                    .then_with(|| measured(a, b, |stats| (stats.exposure - 0.5).abs())),
+            };
+            preference.then_with(|| a_path.cmp(b_path))
+        });
+
+        let mut members = members.into_iter();
+        let (kept, kept_facts) = members.next().expect("group has two members");
+        let mut freed = HashSet::new();
+        let mut reclaimed_bytes = 0;
+        let mut duplicates = Vec::new();
+        for (path, facts) in members {
+            let shares_kept = facts.identity.is_some() && facts.identity == kept_facts.identity;
+            // Blocks shared by several duplicates are freed once, and never if the kept file uses them
+            if !shares_kept && facts.identity.is_none_or(|id| freed.insert(id)) {
+                reclaimed_bytes += facts.size;
+            }
+            duplicates.push(path);
+        }
+        simulation.reclaimed_bytes += reclaimed_bytes;
+        simulation.groups.push(GroupOutcome { kept, duplicates, reclaimed_bytes });
+    }
+    simulation.missing.sort();
+    simulation
+}
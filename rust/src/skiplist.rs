@@ -0,0 +1,309 @@
+// src/skiplist.rs
+// Persistent list of poison files (repeated crashes or timeouts) that batches bypass
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{OnceLock, RwLock};
+
+use crate::failures::Category;
+use crate::paths;
+
+/// Failure history of one file
+#[derive(Clone)]
+pub struct Entry {
+    pub failures: u32,
+    /// Category and message of the most recent failure
+    pub category: String,
+    pub reason: String,
+}
+
+struct SkipList {
+    file: PathBuf,
+    /// Failures at which a file is skipped
+    max_failures: u32,
+    entries: BTreeMap<String, Entry>,
+    dirty: bool,
+}
+
+fn skip_list() -> &'static RwLock<Option<SkipList>> {
+    static SKIP_LIST: OnceLock<RwLock<Option<SkipList>>> = OnceLock::new();
+    SKIP_LIST.get_or_init(|| RwLock::new(None))
+}
+
+/// Whether a failure of this category counts towards skipping the file
+///
+/// Only crashes and timeouts: they cost the most on every run and are not
+/// fixed by the user (unlike missing files or permissions).
+pub fn is_poison(category: Category) -> bool {
+    matches!(category, Category::Crashed | Category::Timeout)
+}
+
+/// One tab-separated line per file: failures, category, reason, path (last,
+/// so paths may contain tabs)
+fn parse(text: &str) -> BTreeMap<String, Entry> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            let failures = fields.next()?.parse().ok()?;
+            let category = fields.next()?.to_string();
+            let reason = fields.next()?.to_string();
+            let path = fields.next()?.to_string();
+            Some((path, Entry { failures, category, reason }))
+        })
+        .collect()
+}
+
+/// Reasons are single-line so the file stays one entry per line
+fn one_line(text: &str) -> String {
+    text.replace(['\t', '\r', '\n'], " ")
+}
+
+/// Directory of "decoding" markers next to the list file
+fn marker_dir(file: &Path) -> PathBuf {
+    file.with_extension("decoding")
+}
+
+/// Whether the process that wrote a marker is still running
+fn process_alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        // Signal 0 only checks the pid; EPERM means it exists under another user
+        match i32::try_from(pid) {
+            Ok(pid) if pid > 0 => {
+                (unsafe { libc::kill(pid, 0) } == 0) || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+            },
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+/// Count a crash for every file whose decode left its marker behind
+///
+/// Segfaults, aborts and OOM kills end the process before any failure is
+/// recorded; the marker written before the decode is all that survives.
+/// Markers of processes still running are decodes in progress and are left.
+fn collect_markers(list: &mut SkipList) {
+    let Ok(markers) = fs::read_dir(marker_dir(&list.file)) else { return };
+    for marker in markers.flatten() {
+        let name = marker.file_name();
+        let pid = name.to_str().and_then(|name| name.split_once('_')).and_then(|(pid, _)| pid.parse().ok());
+        if pid.is_some_and(process_alive) {
+            continue;
+        }
+        if let Ok(path) = fs::read_to_string(marker.path()) {
+            if !path.is_empty() {
+                list.count(path, Category::Crashed, "Process ended while decoding");
+            }
+        }
+        let _ = fs::remove_file(marker.path());
+    }
+}
+
+/// Keep the skip-list in `file`, loading any entries already there
+///
+/// A file is skipped once it has crashed or timed out `max_failures` times.
+/// `None` turns skipping off. Decodes that a previous process never finished
+/// count as crashes. Failures not yet written to the previous file are saved
+/// first.
+pub fn configure(file: Option<PathBuf>, max_failures: u32) -> io::Result<()> {
+    install(open(file, max_failures)?);
+    Ok(())
+}
+
+/// A skip-list read by `open`, not in use until `install`ed
+pub struct Opened(Option<SkipList>);
+
+impl Opened {
+    /// Installing it turns skipping off
+    pub fn none() -> Self {
+        Opened(None)
+    }
+}
+
+/// Read the list in `file` for `configure`, without switching to it
+pub fn open(file: Option<PathBuf>, max_failures: u32) -> io::Result<Opened> {
+    let Some(file) = file else { return Ok(Opened(None)) };
+    let entries = match fs::read_to_string(&file) {
+        Ok(text) => parse(&text),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => return Err(e),
+    };
+    let mut list = SkipList { file, max_failures: max_failures.max(1), entries, dirty: false };
+    collect_markers(&mut list);
+    list.persist()?;
+    Ok(Opened(Some(list)))
+}
+
+/// Switch to an opened list; failures not yet written to the previous file are saved first
+pub fn install(opened: Opened) {
+    let _ = save();
+    *skip_list().write().unwrap_or_else(|e| e.into_inner()) = opened.0;
+}
+
+/// Marks one file as being decoded until dropped
+///
+/// The marker is a file under the list's `.decoding` directory holding the
+/// path, so it survives a crash of this process and is counted at the next
+/// `configure`.
+pub struct Decoding {
+    marker: Option<PathBuf>,
+}
+
+impl Decoding {
+    /// Start decoding `path`; does nothing when no skip-list is configured
+    pub fn start(path: &str) -> Self {
+        let guard = skip_list().read().unwrap_or_else(|e| e.into_inner());
+        let marker = guard.as_ref().and_then(|list| {
+            static COUNTER: AtomicU64 = AtomicU64::new(0);
+            let dir = marker_dir(&list.file);
+            let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+            let marker = dir.join(format!("{}_{}", std::process::id(), n));
+            let identity = paths::identity(path);
+            // Best effort: a marker that cannot be written only loses crash detection
+            fs::create_dir_all(&dir).and_then(|_| fs::write(&marker, identity)).ok().map(|_| marker)
+        });
+        Decoding { marker }
+    }
+}
+
+impl Drop for Decoding {
+    fn drop(&mut self) {
+        if let Some(marker) = &self.marker {
+            let _ = fs::remove_file(marker);
+        }
+    }
+}
+
+/// The entry for `path` if it has failed often enough to be skipped
+pub fn skipped(path: &str) -> Option<Entry> {
+    let guard = skip_list().read().unwrap_or_else(|e| e.into_inner());
+    let list = guard.as_ref()?;
+    list.entries.get(&paths::identity(path)).filter(|entry| entry.failures >= list.max_failures).cloned()
+}
+
+impl SkipList {
+    fn count(&mut self, identity: String, category: Category, message: &str) {
+        let entry = self.entries.entry(identity).or_insert_with(|| Entry {
+            failures: 0,
+            category: String::new(),
+            reason: String::new(),
+        });
+        entry.failures += 1;
+        entry.category = category.name().to_string();
+        entry.reason = one_line(message);
+        self.dirty = true;
+    }
+
+    /// Write the list back if it changed, through a temp file so a crash
+    /// mid-write keeps the previous list
+    fn persist(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let text: String = self
+            .entries
+            .iter()
+            .map(|(path, entry)| format!("{}\t{}\t{}\t{}\n", entry.failures, entry.category, entry.reason, path))
+            .collect();
+        if let Some(parent) = self.file.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = self.file.with_extension("tmp");
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, &self.file)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// Count a crash or timeout of `path`, written out right away
+pub fn record_failure(path: &str, category: Category, message: &str) {
+    let mut guard = skip_list().write().unwrap_or_else(|e| e.into_inner());
+    let Some(list) = guard.as_mut() else { return };
+    list.count(paths::identity(path), category, message);
+    // Best effort: failing to persist the list must not fail the decode; it
+    // stays dirty and is retried with the next change
+    let _ = list.persist();
+}
+
+/// Forget the history of a file that decoded after all
+pub fn record_success(path: &str) {
+    let mut guard = skip_list().write().unwrap_or_else(|e| e.into_inner());
+    if let Some(list) = guard.as_mut() {
+        if list.entries.remove(&paths::identity(path)).is_some() {
+            list.dirty = true;
+            let _ = list.persist();
+        }
+    }
+}
+
+/// All recorded files with their history, skipped or not yet
+pub fn entries() -> Vec<(String, Entry)> {
+    let guard = skip_list().read().unwrap_or_else(|e| e.into_inner());
+    guard.as_ref().map(|list| list.entries.iter().map(|(p, e)| (p.clone(), e.clone())).collect()).unwrap_or_default()
+}
+
+/// Remove `path` from the list, or every entry when None; returns how many were removed
+pub fn clear(path: Option<&str>) -> usize {
+    let mut guard = skip_list().write().unwrap_or_else(|e| e.into_inner());
+    let Some(list) = guard.as_mut() else { return 0 };
+    let removed = match path {
+        Some(path) => list.entries.remove(&paths::identity(path)).is_some() as usize,
+        None => std::mem::take(&mut list.entries).len(),
+    };
+    list.dirty |= removed > 0;
+    removed
+}
+
+/// Write the list back if it changed
+pub fn save() -> io::Result<()> {
+    let mut guard = skip_list().write().unwrap_or_else(|e| e.into_inner());
+    guard.as_mut().map_or(Ok(()), SkipList::persist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unfinished_decodes_count_as_crashes() {
+        let file = crate::tiff::fixtures::temp_path("skiplist.tsv");
+        let markers = marker_dir(&file);
+        let _ = fs::remove_file(&file);
+        let _ = fs::remove_dir_all(&markers);
+
+        // A marker left by a process that is gone
+        fs::create_dir_all(&markers).unwrap();
+        fs::write(markers.join("2147483600_0"), "/photos/bad.CR2").unwrap();
+        configure(Some(file.clone()), 2).unwrap();
+        assert_eq!(fs::read_dir(&markers).unwrap().count(), 0);
+        assert!(fs::read_to_string(&file).unwrap().starts_with("1\tcrashed\t"));
+        assert!(skipped("/photos/bad.CR2").is_none());
+
+        // Failures are on disk before the batch ends
+        record_failure("/photos/bad.CR2", Category::Timeout, "took\ttoo long");
+        assert!(fs::read_to_string(&file).unwrap().starts_with("2\ttimeout\ttook too long\t"));
+        assert!(skipped("/photos/bad.CR2").is_some());
+
+        let decoding = Decoding::start("/photos/other.CR2");
+        assert_eq!(fs::read_dir(&markers).unwrap().count(), 1);
+        drop(decoding);
+        assert_eq!(fs::read_dir(&markers).unwrap().count(), 0);
+
+        record_success("/photos/bad.CR2");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "");
+        configure(None, 2).unwrap();
+        let _ = fs::remove_file(&file);
+        let _ = fs::remove_dir_all(&markers);
+    }
+}
@@ -0,0 +1,415 @@
+// src/storage.rs
+// Local vs network storage detection and the per-storage conversion policy
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, RwLock};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::PyResult;
+
+use crate::remote;
+
+/// Where a source file lives, as far as IO cost is concerned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageKind {
+    Local,
+    Network,
+}
+
+impl StorageKind {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name.to_lowercase().as_str() {
+            "local" => Ok(StorageKind::Local),
+            "network" => Ok(StorageKind::Network),
+            _ => Err(PyValueError::new_err(format!(
+                "Unsupported storage '{}', expected 'local' or 'network'",
+                name
+            ))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StorageKind::Local => "local",
+            StorageKind::Network => "network",
+        }
+    }
+
+    /// Position in per-storage tables
+    pub fn slot(self) -> usize {
+        match self {
+            StorageKind::Local => 0,
+            StorageKind::Network => 1,
+        }
+    }
+}
+
+/// One step of the RAW conversion chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// Tuned handling from the camera profile database (exiftool, rawloader or libraw)
+    CameraProfile,
+    /// Embedded JPEG via exiftool, then `dcraw -e`
+    EmbeddedPreview,
+    /// libraw for sub-variants (sRAW, pixel shift...) dcraw and rawloader cannot read
+    Libraw,
+    /// Per-format dcraw/dcraw_emu settings (Sony, Canon, Nikon, Fuji...)
+    FormatSpecific,
+    /// In-process rawloader decode, no external tools
+    Rawloader,
+    /// Generic dcraw, then dcraw_emu
+    Generic,
+}
+
+const BACKENDS: [(&str, Backend); 6] = [
+    ("camera_profile", Backend::CameraProfile),
+    ("embedded_preview", Backend::EmbeddedPreview),
+    ("libraw", Backend::Libraw),
+    ("format_specific", Backend::FormatSpecific),
+    ("rawloader", Backend::Rawloader),
+    ("generic", Backend::Generic),
+];
+
+impl Backend {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        let lower = name.to_lowercase();
+        BACKENDS
+            .iter()
+            .find(|(n, _)| *n == lower)
+            .map(|(_, backend)| *backend)
+            .ok_or_else(|| {
+                let names: Vec<&str> = BACKENDS.iter().map(|(n, _)| *n).collect();
+                PyValueError::new_err(format!("Unsupported backend '{}', expected one of {}", name, names.join(", ")))
+            })
+    }
+
+    pub fn name(self) -> &'static str {
+        BACKENDS.iter().find(|(_, b)| *b == self).map(|(n, _)| *n).unwrap_or("")
+    }
+}
+
+/// Conversion chain and external process limit for one kind of storage
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoragePolicy {
+    pub backends: Vec<Backend>,
+    /// Concurrent external tools for files on this storage; 0 leaves only the global limit
+    pub max_processes: usize,
+}
+
+impl Default for StoragePolicy {
+    /// The full chain in its historical order
+    fn default() -> Self {
+        StoragePolicy { backends: BACKENDS.iter().map(|(_, b)| *b).collect(), max_processes: 0 }
+    }
+}
+
+fn policies() -> &'static RwLock<[Option<StoragePolicy>; 2]> {
+    static POLICIES: RwLock<[Option<StoragePolicy>; 2]> = RwLock::new([None, None]);
+    &POLICIES
+}
+
+/// Replace the policy for `kind` (None restores the default)
+pub fn set_policy(kind: StorageKind, policy: Option<StoragePolicy>) {
+    policies().write().unwrap_or_else(|e| e.into_inner())[kind.slot()] = policy;
+}
+
+pub fn policy(kind: StorageKind) -> StoragePolicy {
+    policies().read().unwrap_or_else(|e| e.into_inner())[kind.slot()].clone().unwrap_or_default()
+}
+
+/// Preferred step order per lowercase extension, on top of the storage policy
+fn extension_orders() -> &'static RwLock<HashMap<String, Vec<Backend>>> {
+    static ORDERS: OnceLock<RwLock<HashMap<String, Vec<Backend>>>> = OnceLock::new();
+    ORDERS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Prefer `order` for files with `extension` (None drops the preference)
+pub fn set_extension_order(extension: &str, order: Option<Vec<Backend>>) {
+    let mut orders = extension_orders().write().unwrap_or_else(|e| e.into_inner());
+    match order {
+        Some(order) => orders.insert(extension.to_lowercase(), order),
+        None => orders.remove(&extension.to_lowercase()),
+    };
+}
+
+/// Every extension with a preferred order
+pub fn extension_order_list() -> Vec<(String, Vec<Backend>)> {
+    let orders = extension_orders().read().unwrap_or_else(|e| e.into_inner());
+    let mut list: Vec<_> = orders.iter().map(|(ext, order)| (ext.clone(), order.clone())).collect();
+    list.sort_by(|a, b| a.0.cmp(&b.0));
+    list
+}
+
+/// Extensions with tuned dcraw/dcraw_emu settings in the `format_specific` step
+pub const FORMAT_SPECIFIC_EXTENSIONS: &[&str] = &["arw", "cr2", "cr3", "nef", "orf", "rw2", "pef", "srw", "dcr", "kdc", "raf"];
+
+/// Steps to try for a file with `extension` on `kind` storage
+///
+/// The storage policy decides which steps run; a preferred order for the
+/// extension moves the steps it names to the front, in its order, and the
+/// rest follow in policy order. rawloader stands in for the tuned dcraw
+/// settings, so formats that have them skip it unless their preferred order
+/// names it. A `ScopedRestriction` on this thread drops the steps it rules out.
+pub fn chain(kind: StorageKind, extension: &str) -> Vec<Backend> {
+    let restriction = restriction();
+    let orders = extension_orders().read().unwrap_or_else(|e| e.into_inner());
+    let order = orders.get(extension);
+    let skip_rawloader = FORMAT_SPECIFIC_EXTENSIONS.contains(&extension)
+        && !order.is_some_and(|order| order.contains(&Backend::Rawloader));
+    let backends: Vec<Backend> = policy(kind)
+        .backends
+        .into_iter()
+        .filter(|&backend| restriction.allows(backend) && !(skip_rawloader && backend == Backend::Rawloader))
+        .collect();
+    let Some(order) = order else {
+        return backends;
+    };
+    let mut chain: Vec<Backend> = order.iter().copied().filter(|backend| backends.contains(backend)).collect();
+    chain.extend(backends.into_iter().filter(|backend| !order.contains(backend)));
+    chain
+}
+
+/// Filesystem types that go over the wire
+#[cfg(not(windows))]
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "ncpfs", "afs", "9p", "afpfs", "webdav", "davfs", "ceph", "glusterfs",
+    "lustre", "fuse.sshfs", "fuse.rclone", "fuse.davfs2", "fuse.glusterfs", "fuse.s3fs", "fuse.gcsfuse",
+];
+
+/// Undo the octal escapes (`\040` for a space) used in mountinfo
+#[cfg(target_os = "linux")]
+fn unescape_mount_path(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|d| std::str::from_utf8(d).ok()).and_then(|d| u8::from_str_radix(d, 8).ok());
+        match (bytes[i], octal) {
+            (b'\\', Some(byte)) => {
+                out.push(byte);
+                i += 4;
+            },
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Mount points and filesystem types from /proc/self/mountinfo
+#[cfg(target_os = "linux")]
+fn mount_table() -> Vec<(PathBuf, String)> {
+    let Ok(mountinfo) = std::fs::read_to_string("/proc/self/mountinfo") else {
+        return Vec::new();
+    };
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            // Optional fields end at "-", after which come the type and source
+            let (before, after) = line.split_once(" - ")?;
+            let mount_point = before.split(' ').nth(4)?;
+            let fs_type = after.split(' ').next()?;
+            Some((PathBuf::from(unescape_mount_path(mount_point)), fs_type.to_string()))
+        })
+        .collect()
+}
+
+/// Type of the filesystem mounted deepest above `path`
+#[cfg(target_os = "linux")]
+pub fn filesystem_type(path: &Path) -> Option<String> {
+    mount_table()
+        .into_iter()
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.components().count())
+        .map(|(_, fs_type)| fs_type)
+}
+
+/// Type of the filesystem holding `path`, from `statfs` (`nfs`, `smbfs`, `apfs`...)
+#[cfg(target_os = "macos")]
+pub fn filesystem_type(path: &Path) -> Option<String> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is written in full on success
+    if unsafe { libc::statfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: statfs succeeded
+    let stat = unsafe { stat.assume_init() };
+    let name: Vec<u8> = stat.f_fstypename.iter().take_while(|&&c| c != 0).map(|&c| c as u8).collect();
+    Some(String::from_utf8_lossy(&name).into_owned())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn filesystem_type(_path: &Path) -> Option<String> {
+    None
+}
+
+/// Whether the drive holding `path` is a mapped network drive or a share
+#[cfg(windows)]
+fn is_network_directory(path: &Path) -> bool {
+    use std::path::{Component, Prefix};
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetDriveTypeW(root_path_name: *const u16) -> u32;
+    }
+    const DRIVE_REMOTE: u32 = 4;
+
+    // Canonical paths carry a verbatim prefix (`\\?\C:\`, `\\?\UNC\server\share`)
+    let Some(Component::Prefix(prefix)) = path.components().next() else {
+        return false;
+    };
+    let drive = match prefix.kind() {
+        Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => letter,
+        Prefix::UNC(..) | Prefix::VerbatimUNC(..) => return true,
+        _ => return false,
+    };
+    let root: Vec<u16> = format!("{}:\\", drive as char).encode_utf16().chain([0]).collect();
+    // SAFETY: `root` is a NUL-terminated UTF-16 string that outlives the call
+    unsafe { GetDriveTypeW(root.as_ptr()) == DRIVE_REMOTE }
+}
+
+#[cfg(not(windows))]
+fn is_network_directory(path: &Path) -> bool {
+    filesystem_type(path).is_some_and(|fs_type| NETWORK_FILESYSTEMS.contains(&fs_type.as_str()))
+}
+
+// Detected directories; cleared wholesale when it grows past this
+const MAX_CACHED_DIRECTORIES: usize = 4096;
+
+fn directory_cache() -> &'static Mutex<HashMap<PathBuf, StorageKind>> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, StorageKind>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Whether `path` is on local disk or a network mount
+///
+/// URLs and UNC paths are always network. Otherwise the filesystem type
+/// decides (NFS, SMB/CIFS, sshfs and friends), from the mount table on Linux
+/// and `statfs` on macOS; on Windows mapped drives are network. Results are
+/// cached per directory.
+pub fn detect(path: &str) -> StorageKind {
+    if remote::is_remote(path) || path.starts_with("\\\\") || path.starts_with("//") {
+        return StorageKind::Network;
+    }
+
+    let absolute = std::fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| PathBuf::from(path));
+    let directory = absolute.parent().map(Path::to_path_buf).unwrap_or(absolute);
+
+    let mut cache = directory_cache().lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(kind) = cache.get(&directory) {
+        return *kind;
+    }
+
+    let kind = if is_network_directory(&directory) { StorageKind::Network } else { StorageKind::Local };
+    if cache.len() >= MAX_CACHED_DIRECTORIES {
+        cache.clear();
+    }
+    cache.insert(directory, kind);
+    kind
+}
+
+thread_local! {
+    static CURRENT: Cell<Option<StorageKind>> = const { Cell::new(None) };
+}
+
+/// Storage of the file being converted on this thread, if any
+pub fn current() -> Option<StorageKind> {
+    CURRENT.with(|cell| cell.get())
+}
+
+/// Marks conversions on this thread as reading from `kind` until dropped
+pub struct ScopedStorage {
+    previous: Option<StorageKind>,
+}
+
+impl ScopedStorage {
+    pub fn new(kind: StorageKind) -> Self {
+        let previous = CURRENT.with(|cell| cell.replace(Some(kind)));
+        ScopedStorage { previous }
+    }
+}
+
+impl Drop for ScopedStorage {
+    fn drop(&mut self) {
+        CURRENT.with(|cell| cell.set(self.previous));
+    }
+}
+
+/// Which conversion steps may produce the pixels on this thread
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Restriction {
+    Unrestricted,
+    /// Only the embedded preview step
+    PreviewsOnly,
+    /// Every step but the embedded previews, and no step may fall back to one
+    NoPreviews,
+}
+
+impl Restriction {
+    pub fn allows(self, backend: Backend) -> bool {
+        match self {
+            Restriction::Unrestricted => true,
+            Restriction::PreviewsOnly => backend == Backend::EmbeddedPreview,
+            Restriction::NoPreviews => backend != Backend::EmbeddedPreview,
+        }
+    }
+}
+
+thread_local! {
+    static RESTRICTION: Cell<Restriction> = const { Cell::new(Restriction::Unrestricted) };
+}
+
+pub fn restriction() -> Restriction {
+    RESTRICTION.with(|cell| cell.get())
+}
+
+/// Restricts conversions on this thread until dropped
+pub struct ScopedRestriction {
+    previous: Restriction,
+}
+
+impl ScopedRestriction {
+    pub fn new(restriction: Restriction) -> Self {
+        let previous = RESTRICTION.with(|cell| cell.replace(restriction));
+        ScopedRestriction { previous }
+    }
+}
+
+impl Drop for ScopedRestriction {
+    fn drop(&mut self) {
+        RESTRICTION.with(|cell| cell.set(self.previous));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rawloader_only_stands_in_for_missing_tuned_settings() {
+        assert!(chain(StorageKind::Local, "dng").contains(&Backend::Rawloader));
+        assert!(!chain(StorageKind::Local, "nef").contains(&Backend::Rawloader));
+
+        set_extension_order("kdc", Some(vec![Backend::Rawloader]));
+        let chain_with_order = chain(StorageKind::Local, "kdc");
+        set_extension_order("kdc", None);
+        assert_eq!(chain_with_order[0], Backend::Rawloader);
+        assert_eq!(chain_with_order.len(), BACKENDS.len());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn mount_paths_are_unescaped() {
+        assert_eq!(unescape_mount_path("/mnt/My\\040Photos"), "/mnt/My Photos");
+        assert_eq!(unescape_mount_path("/mnt/a\\b"), "/mnt/a\\b");
+    }
+}
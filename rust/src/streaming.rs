@@ -0,0 +1,762 @@
+// src/streaming.rs
+// Bounded-memory grayscale thumbnails of huge TIFF/PSB scans, downsampled strip by strip
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+
+use ::tiff::decoder::{ChunkType, Decoder, DecodingResult};
+use ::tiff::tags::Tag;
+use ::tiff::ColorType;
+
+use crate::grayscale::LumaMode;
+use crate::{formats, throttle};
+
+// TIFFs above this size are streamed instead of decoded whole
+const STREAM_THRESHOLD_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Width and height of the full-resolution image
+pub type Dimensions = (u32, u32);
+
+/// Whether `path` should go through the streaming decoder
+///
+/// PSD/PSB always do (the image crate cannot read them); TIFFs only when
+/// they are too large to decode in memory.
+pub fn is_streamable(path: &str) -> bool {
+    let ext = formats::extension(path);
+    match ext.as_str() {
+        "psd" | "psb" => true,
+        "tif" | "tiff" => std::fs::metadata(path).map(|m| m.len() > STREAM_THRESHOLD_BYTES).unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn unsupported(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Area-averages full-resolution samples into a `size` x `size` grid as they arrive
+struct AreaAccumulator {
+    size: usize,
+    channels: usize,
+    /// Output column of every source column
+    column_cells: Vec<u32>,
+    height: usize,
+    sums: Vec<f64>,
+    counts: Vec<u32>,
+}
+
+impl AreaAccumulator {
+    fn new(width: usize, height: usize, size: usize, channels: usize) -> Self {
+        AreaAccumulator {
+            size,
+            channels,
+            column_cells: (0..width).map(|x| (x * size / width) as u32).collect(),
+            height,
+            sums: vec![0.0; size * size * channels],
+            counts: vec![0; size * size * channels],
+        }
+    }
+
+    /// Add one channel of source row `y`, starting at column `x0`, values normalized to 0-1
+    fn add_row(&mut self, channel: usize, y: usize, x0: usize, values: impl Iterator<Item = f64>) {
+        let row = y * self.size / self.height;
+        for (x, value) in (x0..).zip(values) {
+            let cell = ((row * self.size + self.column_cells[x] as usize) * self.channels) + channel;
+            self.sums[cell] += value;
+            self.counts[cell] += 1;
+        }
+    }
+
+    /// Row-major 8-bit luma of the grid, reducing color like the decoded thumbnails do
+    fn finish(self, luma: LumaMode) -> Vec<u8> {
+        let average = |cell: usize, channel: usize| {
+            let i = cell * self.channels + channel;
+            self.sums[i] / self.counts[i].max(1) as f64
+        };
+        (0..self.size * self.size)
+            .map(|cell| {
+                let level = if self.channels >= 3 {
+                    luma.luma([average(cell, 0), average(cell, 1), average(cell, 2)])
+                } else {
+                    average(cell, 0)
+                };
+                (level.clamp(0.0, 1.0) * 255.0).round() as u8
+            })
+            .collect()
+    }
+}
+
+/// One TIFF sample type, kept as stored until it is averaged into the grid
+trait Sample: Copy {
+    const BYTES: usize;
+    fn read(bytes: &[u8], big_endian: bool) -> Self;
+    /// Undo horizontal differencing (Predictor 2)
+    fn wrapping_add(self, other: Self) -> Self;
+    /// The value normalized to 0-1
+    fn unit(self) -> f64;
+}
+
+macro_rules! integer_sample {
+    ($t:ty) => {
+        impl Sample for $t {
+            const BYTES: usize = std::mem::size_of::<$t>();
+            fn read(bytes: &[u8], big_endian: bool) -> Self {
+                let bytes = bytes.try_into().expect("sample width");
+                if big_endian { <$t>::from_be_bytes(bytes) } else { <$t>::from_le_bytes(bytes) }
+            }
+            fn wrapping_add(self, other: Self) -> Self {
+                <$t>::wrapping_add(self, other)
+            }
+            fn unit(self) -> f64 {
+                self as f64 / <$t>::MAX as f64
+            }
+        }
+    };
+}
+
+macro_rules! float_sample {
+    ($t:ty) => {
+        impl Sample for $t {
+            const BYTES: usize = std::mem::size_of::<$t>();
+            fn read(bytes: &[u8], big_endian: bool) -> Self {
+                let bytes = bytes.try_into().expect("sample width");
+                if big_endian { <$t>::from_be_bytes(bytes) } else { <$t>::from_le_bytes(bytes) }
+            }
+            fn wrapping_add(self, other: Self) -> Self {
+                self + other
+            }
+            fn unit(self) -> f64 {
+                self as f64
+            }
+        }
+    };
+}
+
+integer_sample!(u8);
+integer_sample!(u16);
+integer_sample!(u32);
+float_sample!(f32);
+float_sample!(f64);
+
+/// Streaming PackBits (TIFF compression 32773) decoder
+struct PackBits<R> {
+    inner: R,
+    /// Literal bytes still to copy through
+    literal: usize,
+    /// A byte still to repeat, and how often
+    run: (u8, usize),
+}
+
+impl<R: Read> Read for PackBits<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let mut byte = [0u8; 1];
+        while self.literal == 0 && self.run.1 == 0 {
+            if self.inner.read(&mut byte)? == 0 {
+                return Ok(0);
+            }
+            match byte[0] as i8 {
+                header @ 0.. => self.literal = header as usize + 1,
+                -128 => {},
+                header => {
+                    self.inner.read_exact(&mut byte)?;
+                    self.run = (byte[0], (1 - header as isize) as usize);
+                },
+            }
+        }
+        if self.literal > 0 {
+            let n = self.literal.min(out.len());
+            let n = self.inner.read(&mut out[..n])?;
+            if n == 0 {
+                return Err(unsupported("Truncated PackBits data"));
+            }
+            self.literal -= n;
+            Ok(n)
+        } else {
+            let n = self.run.1.min(out.len());
+            out[..n].fill(self.run.0);
+            self.run.1 -= n;
+            Ok(n)
+        }
+    }
+}
+
+/// Streaming LZW (TIFF compression 5) decoder
+struct Lzw<R> {
+    inner: R,
+    decoder: weezl::decode::Decoder,
+    done: bool,
+}
+
+impl<R: BufRead> Read for Lzw<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while !self.done && !out.is_empty() {
+            let input = self.inner.fill_buf()?;
+            let at_end = input.is_empty();
+            let result = self.decoder.decode_bytes(input, out);
+            self.inner.consume(result.consumed_in);
+            match result.status {
+                Ok(weezl::LzwStatus::Done) => self.done = true,
+                Ok(weezl::LzwStatus::NoProgress) if at_end => self.done = true,
+                Ok(_) => {},
+                Err(e) => return Err(unsupported(format!("Corrupt LZW data: {}", e))),
+            }
+            if result.consumed_out > 0 {
+                return Ok(result.consumed_out);
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Reader over the decompressed bytes of one strip or tile; None for compressions only the tiff crate reads
+fn chunk_reader<'a>(
+    file: &'a mut BufReader<File>,
+    compression: u16,
+    (offset, length): (u64, u64),
+) -> io::Result<Option<Box<dyn Read + 'a>>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let compressed = file.take(length);
+    Ok(Some(match compression {
+        1 => Box::new(compressed),
+        5 => Box::new(Lzw {
+            inner: compressed,
+            decoder: weezl::decode::Decoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8),
+            done: false,
+        }),
+        8 | 32946 => Box::new(flate2::read::ZlibDecoder::new(compressed)),
+        32773 => Box::new(PackBits { inner: compressed, literal: 0, run: (0, 0) }),
+        _ => return Ok(None),
+    }))
+}
+
+/// How the samples of one image are laid out in its strips or tiles
+struct Layout {
+    width: usize,
+    height: usize,
+    chunk_width: usize,
+    chunk_height: usize,
+    /// Samples per pixel in the file, and how many of them carry color
+    samples: usize,
+    color: usize,
+    /// PlanarConfiguration 2: one plane per sample, each split into its own chunks
+    planar: bool,
+    chunks_per_plane: usize,
+    big_endian: bool,
+    predictor: u16,
+}
+
+/// Where one chunk's samples land: its top-left pixel, size, the channel of
+/// its first sample and the samples per pixel it holds
+struct Placement {
+    x0: usize,
+    y0: usize,
+    data_width: usize,
+    data_height: usize,
+    channel: usize,
+    stride: usize,
+}
+
+impl Layout {
+    /// None for the alpha plane of a planar image, which is never read
+    fn placement(&self, chunk: usize) -> Option<Placement> {
+        let (plane, index) = if self.planar { (chunk / self.chunks_per_plane, chunk % self.chunks_per_plane) } else { (0, chunk) };
+        if plane >= self.color {
+            return None;
+        }
+        let chunks_across = self.width.div_ceil(self.chunk_width);
+        let (x0, y0) = (index % chunks_across * self.chunk_width, index / chunks_across * self.chunk_height);
+        Some(Placement {
+            x0,
+            y0,
+            data_width: self.chunk_width.min(self.width.saturating_sub(x0)),
+            data_height: self.chunk_height.min(self.height.saturating_sub(y0)),
+            channel: plane,
+            stride: if self.planar { 1 } else { self.samples },
+        })
+    }
+}
+
+/// Average one decoded row of a chunk (`data_width` pixels at the start of `row`) into the grid
+fn add_samples<T: Sample>(accumulator: &mut AreaAccumulator, placement: &Placement, y: usize, row: &[T]) {
+    let row = &row[..placement.data_width * placement.stride];
+    let channels = if placement.stride == 1 { 1 } else { accumulator.channels };
+    for channel in 0..channels {
+        let values = row.iter().skip(channel).step_by(placement.stride).map(|s| s.unit());
+        accumulator.add_row(placement.channel + channel, placement.y0 + y, placement.x0, values);
+    }
+}
+
+/// Decode and average one chunk row by row, so memory stays at one row however tall the strip
+fn stream_chunk<T: Sample>(
+    layout: &Layout,
+    placement: &Placement,
+    reader: &mut dyn Read,
+    accumulator: &mut AreaAccumulator,
+) -> io::Result<()> {
+    // Tiles are stored padded to the full tile width
+    let row_samples = layout.chunk_width * placement.stride;
+    let mut bytes = vec![0u8; row_samples * T::BYTES];
+    let mut row: Vec<T> = Vec::with_capacity(row_samples);
+    for y in 0..placement.data_height {
+        reader.read_exact(&mut bytes)?;
+        throttle::acquire(bytes.len() as u64, 0);
+
+        row.clear();
+        match layout.predictor {
+            3 => {
+                // Floating point predictor: byte-wise differencing over planes of
+                // same-significance bytes, most significant plane first
+                for i in placement.stride..bytes.len() {
+                    bytes[i] = bytes[i].wrapping_add(bytes[i - placement.stride]);
+                }
+                let mut sample = vec![0u8; T::BYTES];
+                for k in 0..row_samples {
+                    for (b, byte) in sample.iter_mut().enumerate() {
+                        *byte = bytes[b * row_samples + k];
+                    }
+                    row.push(T::read(&sample, true));
+                }
+            },
+            _ => row.extend(bytes.chunks_exact(T::BYTES).map(|b| T::read(b, layout.big_endian))),
+        }
+        if layout.predictor == 2 {
+            for i in placement.stride..row.len() {
+                row[i] = row[i].wrapping_add(row[i - placement.stride]);
+            }
+        }
+        add_samples(accumulator, placement, y, &row);
+    }
+    Ok(())
+}
+
+/// Average a chunk the tiff crate decoded whole (JPEG and other block compressions)
+fn add_decoded_chunk(accumulator: &mut AreaAccumulator, placement: &Placement, data: DecodingResult) -> io::Result<()> {
+    fn rows<T: Sample>(accumulator: &mut AreaAccumulator, placement: &Placement, data: &[T]) {
+        let row_samples = placement.data_width * placement.stride;
+        for (y, row) in data.chunks_exact(row_samples).take(placement.data_height).enumerate() {
+            add_samples(accumulator, placement, y, row);
+        }
+    }
+    match data {
+        DecodingResult::U8(v) => rows(accumulator, placement, &v),
+        DecodingResult::U16(v) => rows(accumulator, placement, &v),
+        DecodingResult::U32(v) => rows(accumulator, placement, &v),
+        DecodingResult::F32(v) => rows(accumulator, placement, &v),
+        DecodingResult::F64(v) => rows(accumulator, placement, &v),
+        _ => return Err(unsupported("Unsupported TIFF sample format")),
+    }
+    Ok(())
+}
+
+/// Grayscale thumbnail of a TIFF/BigTIFF, decoding one row of one strip or tile at a time
+///
+/// Uncompressed, LZW, Deflate and PackBits chunks are decompressed as a
+/// stream; other compressions (JPEG) are left to the tiff crate one chunk at
+/// a time, which bounds memory for tiled files only.
+fn tiff_thumbnail(path: &str, size: usize, luma: LumaMode) -> io::Result<(Vec<u8>, Dimensions)> {
+    let tiff_error = |e: ::tiff::TiffError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+    let mut decoder = Decoder::new(BufReader::new(File::open(path)?)).map_err(tiff_error)?;
+
+    let (width, height) = decoder.dimensions().map_err(tiff_error)?;
+    let (width, height) = (width as usize, height as usize);
+    // Samples per pixel, how many of them carry color, and bits per sample
+    let (samples, color, bits) = match decoder.colortype().map_err(tiff_error)? {
+        ColorType::Gray(bits) => (1, 1, bits),
+        ColorType::GrayA(bits) => (2, 1, bits),
+        ColorType::RGB(bits) => (3, 3, bits),
+        ColorType::RGBA(bits) => (4, 3, bits),
+        other => return Err(unsupported(format!("Unsupported TIFF color type {:?}", other))),
+    };
+    let tag = |decoder: &mut Decoder<_>, tag: Tag, default: u16| -> io::Result<u16> {
+        Ok(decoder.find_tag_unsigned::<u16>(tag).map_err(tiff_error)?.unwrap_or(default))
+    };
+    let compression = tag(&mut decoder, Tag::Compression, 1)?;
+    let planar = tag(&mut decoder, Tag::PlanarConfiguration, 1)? == 2;
+    let predictor = tag(&mut decoder, Tag::Predictor, 1)?;
+    let float = tag(&mut decoder, Tag::SampleFormat, 1)? == 3;
+
+    let chunk_type = decoder.get_chunk_type();
+    let (offsets_tag, counts_tag) = match chunk_type {
+        ChunkType::Strip => (Tag::StripOffsets, Tag::StripByteCounts),
+        ChunkType::Tile => (Tag::TileOffsets, Tag::TileByteCounts),
+    };
+    let offsets = decoder.get_tag_u64_vec(offsets_tag).map_err(tiff_error)?;
+    let counts = decoder.get_tag_u64_vec(counts_tag).map_err(tiff_error)?;
+    let (chunk_width, chunk_height) = decoder.chunk_dimensions();
+
+    // The tiff crate keeps its byte order to itself; "MM" opens big-endian files
+    let mut file = BufReader::new(File::open(path)?);
+    let mut order = [0u8; 2];
+    file.read_exact(&mut order)?;
+    let layout = Layout {
+        width,
+        height,
+        chunk_width: chunk_width as usize,
+        chunk_height: chunk_height as usize,
+        samples,
+        color,
+        planar,
+        chunks_per_plane: if planar { offsets.len() / samples } else { offsets.len() },
+        big_endian: &order == b"MM",
+        predictor,
+    };
+    if offsets.len() != counts.len() || layout.chunks_per_plane == 0 {
+        return Err(unsupported("Inconsistent TIFF chunk offsets"));
+    }
+
+    let mut accumulator = AreaAccumulator::new(width, height, size, color);
+    for (chunk, range) in offsets.into_iter().zip(counts).enumerate() {
+        let Some(placement) = layout.placement(chunk) else {
+            continue;
+        };
+        let Some(mut reader) = chunk_reader(&mut file, compression, range)? else {
+            // The tiff crate indexes planar chunks wrongly past the first plane
+            if planar {
+                return Err(unsupported(format!("Unsupported compression {} for planar TIFF", compression)));
+            }
+            let data = decoder.read_chunk(chunk as u32).map_err(tiff_error)?;
+            throttle::acquire(range.1, 0);
+            add_decoded_chunk(&mut accumulator, &placement, data)?;
+            continue;
+        };
+        match (bits, float) {
+            (8, false) => stream_chunk::<u8>(&layout, &placement, &mut reader, &mut accumulator)?,
+            (16, false) => stream_chunk::<u16>(&layout, &placement, &mut reader, &mut accumulator)?,
+            (32, false) => stream_chunk::<u32>(&layout, &placement, &mut reader, &mut accumulator)?,
+            (32, true) => stream_chunk::<f32>(&layout, &placement, &mut reader, &mut accumulator)?,
+            (64, true) => stream_chunk::<f64>(&layout, &placement, &mut reader, &mut accumulator)?,
+            _ => return Err(unsupported(format!("Unsupported TIFF sample format ({} bits)", bits))),
+        }
+    }
+    throttle::acquire(0, 1);
+
+    Ok((accumulator.finish(luma), (width as u32, height as u32)))
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+/// Skip a section prefixed with its big-endian length
+fn skip_section(reader: &mut BufReader<File>, wide: bool) -> io::Result<()> {
+    let length = if wide { read_u64(reader)? } else { read_u32(reader)? as u64 };
+    reader.seek_relative(length as i64)
+}
+
+/// Expand one PackBits-compressed row into `out`
+fn unpack_bits(packed: &[u8], out: &mut [u8]) -> io::Result<()> {
+    let (mut i, mut o) = (0, 0);
+    while i < packed.len() && o < out.len() {
+        let header = packed[i] as i8;
+        i += 1;
+        if header >= 0 {
+            let n = (header as usize + 1).min(out.len() - o);
+            let literal = packed.get(i..i + n).ok_or_else(|| unsupported("Truncated PSD row"))?;
+            out[o..o + n].copy_from_slice(literal);
+            i += n;
+            o += n;
+        } else if header != -128 {
+            let n = ((1 - header as isize) as usize).min(out.len() - o);
+            let value = *packed.get(i).ok_or_else(|| unsupported("Truncated PSD row"))?;
+            out[o..o + n].fill(value);
+            i += 1;
+            o += n;
+        }
+    }
+    Ok(())
+}
+
+/// Grayscale thumbnail of the merged image of a PSD/PSB, one row at a time
+fn psd_thumbnail(path: &str, size: usize, luma: LumaMode) -> io::Result<(Vec<u8>, Dimensions)> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut signature = [0u8; 4];
+    reader.read_exact(&mut signature)?;
+    if &signature != b"8BPS" {
+        return Err(unsupported("Not a Photoshop document"));
+    }
+    // Version 2 (PSB) widens the section lengths and row byte counts
+    let large = match read_u16(&mut reader)? {
+        1 => false,
+        2 => true,
+        version => return Err(unsupported(format!("Unsupported Photoshop version {}", version))),
+    };
+    reader.seek_relative(6)?;
+    let channels = read_u16(&mut reader)? as usize;
+    let height = read_u32(&mut reader)? as usize;
+    let width = read_u32(&mut reader)? as usize;
+    let depth = read_u16(&mut reader)? as usize;
+    let color = match read_u16(&mut reader)? {
+        1 => 1,
+        3 => 3,
+        mode => return Err(unsupported(format!("Unsupported Photoshop color mode {}", mode))),
+    };
+    if depth != 8 && depth != 16 {
+        return Err(unsupported(format!("Unsupported Photoshop bit depth {}", depth)));
+    }
+    if channels < color || width == 0 || height == 0 {
+        return Err(unsupported("Corrupt Photoshop header"));
+    }
+
+    // Color mode data, image resources, then layer and mask information
+    skip_section(&mut reader, false)?;
+    skip_section(&mut reader, false)?;
+    skip_section(&mut reader, large)?;
+
+    let compressed = match read_u16(&mut reader)? {
+        0 => false,
+        1 => true,
+        compression => return Err(unsupported(format!("Unsupported Photoshop compression {}", compression))),
+    };
+
+    // Only the color channels are read; byte counts cover every channel
+    let row_lengths: Vec<usize> = if compressed {
+        (0..color * height)
+            .map(|_| Ok(if large { read_u32(&mut reader)? as usize } else { read_u16(&mut reader)? as usize }))
+            .collect::<io::Result<_>>()?
+    } else {
+        Vec::new()
+    };
+    if compressed {
+        let skipped = (channels - color) * height * if large { 4 } else { 2 };
+        reader.seek(SeekFrom::Current(skipped as i64))?;
+    }
+
+    let row_bytes = width * depth / 8;
+    let mut row = vec![0u8; row_bytes];
+    let mut packed = Vec::new();
+    let mut accumulator = AreaAccumulator::new(width, height, size, color);
+    for channel in 0..color {
+        for y in 0..height {
+            if compressed {
+                packed.resize(row_lengths[channel * height + y], 0);
+                reader.read_exact(&mut packed)?;
+                unpack_bits(&packed, &mut row)?;
+                throttle::acquire(packed.len() as u64, 0);
+            } else {
+                reader.read_exact(&mut row)?;
+                throttle::acquire(row.len() as u64, 0);
+            }
+
+            if depth == 8 {
+                accumulator.add_row(channel, y, 0, row.iter().map(|&s| s as f64 / 255.0));
+            } else {
+                let samples = row.chunks_exact(2).map(|s| u16::from_be_bytes([s[0], s[1]]) as f64 / 65535.0);
+                accumulator.add_row(channel, y, 0, samples);
+            }
+        }
+    }
+    throttle::acquire(0, 1);
+
+    Ok((accumulator.finish(luma), (width as u32, height as u32)))
+}
+
+/// Row-major `size` x `size` grayscale thumbnail of a TIFF or PSD/PSB, in bounded memory
+///
+/// Samples are area-averaged into the grid as they are decoded, so memory is
+/// one row (of a TIFF strip or tile, or of a PSD channel) plus the grid,
+/// whatever the image size. The result is stretched to a square like the decoded thumbnails.
+pub fn grayscale_thumbnail(path: &str, size: usize, luma: LumaMode) -> io::Result<Vec<u8>> {
+    grayscale_thumbnail_with_dimensions(path, size, luma).map(|(pixels, _)| pixels)
+}
+
+/// `grayscale_thumbnail` together with the full image width and height
+pub fn grayscale_thumbnail_with_dimensions(
+    path: &str,
+    size: usize,
+    luma: LumaMode,
+) -> io::Result<(Vec<u8>, Dimensions)> {
+    let ext = formats::extension(path);
+    match ext.as_str() {
+        "psd" | "psb" => psd_thumbnail(path, size, luma),
+        _ => tiff_thumbnail(path, size, luma),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::tiff::fixtures::temp_path;
+
+    const TYPE_LONG: u16 = 4;
+
+    /// Little-endian TIFF of `chunks` (already encoded) with every tag stored
+    /// as LONG; offsets and byte counts are filled in from the chunks
+    fn write_tiff(name: &str, tags: &[(u16, Vec<u32>)], chunks: &[Vec<u8>], tiled: bool) -> String {
+        let mut data = b"II*\0\0\0\0\0".to_vec();
+        let mut offsets = Vec::new();
+        for chunk in chunks {
+            offsets.push(data.len() as u32);
+            data.extend_from_slice(chunk);
+        }
+        let (offsets_tag, counts_tag) = if tiled { (324, 325) } else { (273, 279) };
+        let mut tags = tags.to_vec();
+        tags.push((offsets_tag, offsets));
+        tags.push((counts_tag, chunks.iter().map(|c| c.len() as u32).collect()));
+        tags.sort_by_key(|(tag, _)| *tag);
+
+        let ifd = data.len() as u32 + data.len() as u32 % 2;
+        data.resize(ifd as usize, 0);
+        data[4..8].copy_from_slice(&ifd.to_le_bytes());
+        let mut extra = ifd as usize + 2 + tags.len() * 12 + 4;
+        let mut arrays = Vec::new();
+        data.extend_from_slice(&(tags.len() as u16).to_le_bytes());
+        for (tag, values) in &tags {
+            data.extend_from_slice(&tag.to_le_bytes());
+            data.extend_from_slice(&TYPE_LONG.to_le_bytes());
+            data.extend_from_slice(&(values.len() as u32).to_le_bytes());
+            if values.len() == 1 {
+                data.extend_from_slice(&values[0].to_le_bytes());
+            } else {
+                data.extend_from_slice(&(extra as u32).to_le_bytes());
+                extra += values.len() * 4;
+                arrays.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+            }
+        }
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&arrays);
+
+        let path = temp_path(name);
+        std::fs::write(&path, data).expect("write test TIFF");
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Base tags of a `width` x `height` image with `samples` samples of `bits` bits
+    fn tags(width: u32, height: u32, samples: u32, bits: u32, compression: u32) -> Vec<(u16, Vec<u32>)> {
+        vec![
+            (256, vec![width]),
+            (257, vec![height]),
+            (258, vec![bits; samples as usize]),
+            (259, vec![compression]),
+            (262, vec![if samples >= 3 { 2 } else { 1 }]),
+            (277, vec![samples]),
+        ]
+    }
+
+    fn thumbnail(path: &str) -> Vec<u8> {
+        let thumbnail = tiff_thumbnail(path, 4, LumaMode::Bt709).expect("thumbnail");
+        let _ = std::fs::remove_file(path);
+        thumbnail.0
+    }
+
+    /// Deterministic test pattern, one value per pixel and sample
+    fn pattern(pixels: usize, samples: usize) -> Vec<u16> {
+        (0..pixels * samples).map(|i| ((i * 7919) % 65536) as u16).collect()
+    }
+
+    fn bytes_u16(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn planar_matches_chunky() {
+        let (width, height) = (8, 6);
+        let pixels = pattern(width * height, 3);
+        let chunky = write_tiff("chunky.tif", &tags(8, 6, 3, 16, 1), &[bytes_u16(&pixels)], false);
+        let planes: Vec<Vec<u8>> = (0..3)
+            .map(|plane| bytes_u16(&pixels.iter().skip(plane).step_by(3).copied().collect::<Vec<_>>()))
+            .collect();
+        let mut planar_tags = tags(8, 6, 3, 16, 1);
+        planar_tags.push((284, vec![2]));
+        let planar = write_tiff("planar.tif", &planar_tags, &planes, false);
+
+        let expected = thumbnail(&chunky);
+        assert!(expected.iter().any(|&v| v != expected[0]));
+        assert_eq!(thumbnail(&planar), expected);
+    }
+
+    #[test]
+    fn compressed_strips_match_uncompressed() {
+        let (width, height) = (8, 6);
+        let pixels = pattern(width * height, 1);
+        // Two strips, the second one short
+        let strips: Vec<&[u16]> = pixels.chunks(width * 4).collect();
+        let strip_tags = |compression| {
+            let mut tags = tags(8, 6, 1, 16, compression);
+            tags.push((278, vec![4]));
+            tags
+        };
+        let plain = write_tiff("plain.tif", &strip_tags(1), &strips.iter().map(|s| bytes_u16(s)).collect::<Vec<_>>(), false);
+        let expected = thumbnail(&plain);
+
+        // Deflate with horizontal differencing
+        let deflated: Vec<Vec<u8>> = strips
+            .iter()
+            .map(|strip| {
+                let differenced: Vec<u16> = strip
+                    .chunks(width)
+                    .flat_map(|row| (0..row.len()).map(|x| if x == 0 { row[0] } else { row[x].wrapping_sub(row[x - 1]) }).collect::<Vec<_>>())
+                    .collect();
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&bytes_u16(&differenced)).unwrap();
+                encoder.finish().unwrap()
+            })
+            .collect();
+        let mut deflate_tags = strip_tags(8);
+        deflate_tags.push((317, vec![2]));
+        assert_eq!(thumbnail(&write_tiff("deflate.tif", &deflate_tags, &deflated, false)), expected);
+
+        let lzw: Vec<Vec<u8>> = strips
+            .iter()
+            .map(|strip| {
+                let mut encoder = weezl::encode::Encoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8);
+                encoder.encode(&bytes_u16(strip)).unwrap()
+            })
+            .collect();
+        assert_eq!(thumbnail(&write_tiff("lzw.tif", &strip_tags(5), &lzw, false)), expected);
+
+        // PackBits as literal runs of at most 128 bytes
+        let packbits: Vec<Vec<u8>> = strips
+            .iter()
+            .map(|strip| bytes_u16(strip).chunks(128).flat_map(|run| std::iter::once(run.len() as u8 - 1).chain(run.iter().copied())).collect())
+            .collect();
+        assert_eq!(thumbnail(&write_tiff("packbits.tif", &strip_tags(32773), &packbits, false)), expected);
+    }
+
+    #[test]
+    fn padded_tiles_match_strips() {
+        let (width, height) = (20, 18);
+        let pixels: Vec<u8> = pattern(width * height, 1).into_iter().map(|v| v as u8).collect();
+        let strip = write_tiff("strip8.tif", &tags(20, 18, 1, 8, 1), std::slice::from_ref(&pixels), false);
+
+        // Four 16x16 tiles, padded past the right and bottom edges
+        let tiles: Vec<Vec<u8>> = (0..4)
+            .map(|tile| {
+                let (x0, y0) = (tile % 2 * 16, tile / 2 * 16);
+                (0..16 * 16)
+                    .map(|i| {
+                        let (x, y) = (x0 + i % 16, y0 + i / 16);
+                        if x < width && y < height { pixels[y * width + x] } else { 255 }
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut tile_tags = tags(20, 18, 1, 8, 1);
+        tile_tags.extend([(322, vec![16]), (323, vec![16])]);
+        let tiled = write_tiff("tiled.tif", &tile_tags, &tiles, true);
+
+        assert_eq!(thumbnail(&tiled), thumbnail(&strip));
+    }
+
+    #[test]
+    fn uniform_image_averages_to_its_level() {
+        let path = write_tiff("uniform.tif", &tags(4, 4, 1, 16, 1), &[bytes_u16(&[32896; 16])], false);
+        assert_eq!(thumbnail(&path), vec![128; 16]);
+    }
+}
@@ -0,0 +1,391 @@
+// src/summaries.rs
+// Per-directory aggregate signatures, so re-scans can skip unchanged subtrees without stat-ing every file
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::index::{self, ImageRecord};
+use crate::paths;
+
+/// Index setting holding the summaries of the last `summarize`
+pub const SETTING_KEY: &str = "directory_summaries";
+/// Index setting holding the directory stamps taken before the last scan
+pub const STAMPS_KEY: &str = "directory_stamps";
+
+/// Aggregates over every indexed file under one directory, subdirectories included
+#[derive(Clone, Default)]
+pub struct DirectorySummary {
+    pub directory: String,
+    pub files: u64,
+    pub total_bytes: u64,
+    /// Order-independent digest of the files' sizes and content hashes, as hex
+    pub signature: String,
+    /// The directory's own modification time stamped before the scan, in
+    /// nanoseconds since the epoch; None when it was not stamped
+    pub modified: Option<u128>,
+    /// `listing` of the directory stamped before the scan; empty when not stamped
+    pub listing: String,
+}
+
+/// A directory as it was before a scan, see `stamp`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Stamp {
+    pub modified: Option<u128>,
+    pub listing: String,
+}
+
+/// Lane-wise wrapping sum of per-file digests
+///
+/// A sum rather than a XOR: two identical files would cancel out of a XOR,
+/// so adding a duplicate copy would leave the signature unchanged.
+#[derive(Clone, Copy, Default)]
+struct Digest([u64; 4]);
+
+impl Digest {
+    fn of(record: &ImageRecord) -> Digest {
+        Digest::of_fields(&[&record.size.to_string(), &record.average_hash, &record.perceptual_hash, &record.fine_hash])
+    }
+
+    fn of_fields(fields: &[&str]) -> Digest {
+        let mut hasher = blake3::Hasher::new();
+        for field in fields {
+            hasher.update(field.as_bytes());
+            hasher.update(b"\0");
+        }
+        let bytes = hasher.finalize();
+        let mut lanes = [0u64; 4];
+        for (lane, chunk) in lanes.iter_mut().zip(bytes.as_bytes().chunks_exact(8)) {
+            *lane = u64::from_le_bytes(chunk.try_into().expect("8-byte chunk"));
+        }
+        Digest(lanes)
+    }
+
+    fn add(&mut self, other: Digest) {
+        for (lane, other) in self.0.iter_mut().zip(other.0) {
+            *lane = lane.wrapping_add(other);
+        }
+    }
+
+    fn hex(&self) -> String {
+        self.0.iter().map(|lane| format!("{:016x}", lane)).collect()
+    }
+}
+
+/// Modification time of a directory in nanoseconds since the epoch
+pub fn modified(directory: &str) -> Option<u128> {
+    let modified = fs::metadata(directory).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
+
+/// Signature of the files directly in `directory`: their count, and a digest
+/// of each one's name, size and modification time
+///
+/// Catches files rewritten in place, which leave the directory's own
+/// modification time alone. Costs one stat per file.
+pub fn listing(directory: &str) -> Option<String> {
+    let mut files = 0u64;
+    let mut digest = Digest::default();
+    for entry in fs::read_dir(directory).ok()? {
+        let entry = entry.ok()?;
+        if !entry.file_type().ok()?.is_file() {
+            continue;
+        }
+        let metadata = entry.metadata().ok()?;
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+        let name = entry.file_name();
+        digest.add(Digest::of_fields(&[&name.to_string_lossy(), &metadata.len().to_string(), &mtime.to_string()]));
+        files += 1;
+    }
+    Some(format!("{}:{}", files, digest.hex()))
+}
+
+/// Stamps of `root` and every directory below it, keyed like `summarize`
+///
+/// Taken before a scan, so anything that changes while the scan runs makes
+/// the directory differ from its stamp. Symlinked directories are not entered.
+pub fn stamp(root: &Path, stamps: &mut HashMap<String, Stamp>) {
+    let key = root.to_string_lossy().into_owned();
+    stamps.insert(key.clone(), Stamp { modified: modified(&key), listing: listing(&key).unwrap_or_default() });
+    let Ok(entries) = fs::read_dir(root) else { return };
+    for entry in entries.flatten() {
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            stamp(&entry.path(), stamps);
+        }
+    }
+}
+
+pub fn encode_stamps(stamps: &HashMap<String, Stamp>) -> String {
+    let mut lines: Vec<String> = stamps
+        .iter()
+        .map(|(directory, stamp)| {
+            let modified = stamp.modified.map(|m| m.to_string()).unwrap_or_default();
+            [index::escape(directory), modified, stamp.listing.clone()].join("\t")
+        })
+        .collect();
+    lines.sort();
+    lines.join("\n")
+}
+
+pub fn decode_stamps(value: &str) -> io::Result<HashMap<String, Stamp>> {
+    let mut stamps = HashMap::new();
+    for line in value.lines().filter(|line| !line.is_empty()) {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Corrupt directory stamp: {}", line));
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [directory, modified, listing] = <[&str; 3]>::try_from(fields).map_err(|_| invalid())?;
+        let modified = match modified {
+            "" => None,
+            m => Some(m.parse().map_err(|_| invalid())?),
+        };
+        stamps.insert(index::unescape(directory), Stamp { modified, listing: listing.to_string() });
+    }
+    Ok(stamps)
+}
+
+/// Summaries of every directory holding indexed files, directly or below,
+/// with each directory's stamp from before the scan
+///
+/// Directories are named by the paths the files were indexed under, and
+/// matched to stamps by that name, or by identity when it was stamped under
+/// another spelling. Directories without a stamp get none, so
+/// `verify` never finds them unchanged. Files are not touched.
+pub fn summarize(records: &[ImageRecord], stamps: &HashMap<String, Stamp>) -> Vec<DirectorySummary> {
+    let by_identity: HashMap<String, &Stamp> =
+        stamps.iter().map(|(directory, stamp)| (paths::identity(directory), stamp)).collect();
+    let mut totals: BTreeMap<String, (u64, u64, Digest)> = BTreeMap::new();
+    for record in records {
+        let digest = Digest::of(record);
+        let mut dir = Path::new(record.file()).parent();
+        while let Some(d) = dir.filter(|d| !d.as_os_str().is_empty()) {
+            let (files, bytes, signature) = totals.entry(d.to_string_lossy().into_owned()).or_default();
+            *files += 1;
+            *bytes += record.size;
+            signature.add(digest);
+            dir = d.parent();
+        }
+    }
+    totals
+        .into_iter()
+        .map(|(directory, (files, total_bytes, signature))| {
+            let stamp = stamps.get(&directory).or_else(|| by_identity.get(&paths::identity(&directory)).copied());
+            DirectorySummary {
+                modified: stamp.and_then(|stamp| stamp.modified),
+                listing: stamp.map(|stamp| stamp.listing.clone()).unwrap_or_default(),
+                directory,
+                files,
+                total_bytes,
+                signature: signature.hex(),
+            }
+        })
+        .collect()
+}
+
+/// One line per summary, for the index setting
+pub fn encode(summaries: &[DirectorySummary]) -> String {
+    summaries
+        .iter()
+        .map(|s| {
+            let modified = s.modified.map(|m| m.to_string()).unwrap_or_default();
+            [
+                index::escape(&s.directory),
+                s.files.to_string(),
+                s.total_bytes.to_string(),
+                s.signature.clone(),
+                modified,
+                s.listing.clone(),
+            ]
+            .join("\t")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn decode(value: &str) -> io::Result<HashMap<String, DirectorySummary>> {
+    let mut summaries = HashMap::new();
+    for line in value.lines().filter(|line| !line.is_empty()) {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Corrupt directory summary: {}", line));
+        let mut fields: Vec<&str> = line.split('\t').collect();
+        // Summaries written before listings existed have none and never verify
+        if fields.len() == 5 {
+            fields.push("");
+        }
+        let [directory, files, total_bytes, signature, modified, listing] =
+            <[&str; 6]>::try_from(fields).map_err(|_| invalid())?;
+        let summary = DirectorySummary {
+            directory: index::unescape(directory),
+            files: files.parse().map_err(|_| invalid())?,
+            total_bytes: total_bytes.parse().map_err(|_| invalid())?,
+            signature: signature.to_string(),
+            modified: match modified {
+                "" => None,
+                m => Some(m.parse().map_err(|_| invalid())?),
+            },
+            listing: listing.to_string(),
+        };
+        summaries.insert(summary.directory.clone(), summary);
+    }
+    Ok(summaries)
+}
+
+/// What a re-scan of some roots has to look at again
+#[derive(Default)]
+pub struct Verification {
+    /// Largest subtrees whose directories are all as summarized; skip them
+    pub unchanged: Vec<DirectorySummary>,
+    /// Directories whose entries changed since the summary: list them again
+    /// and re-check their files. Their summarized subdirectories are judged on
+    /// their own, so a new or removed subdirectory shows up here as its parent;
+    /// summarized directories that no longer exist are listed too.
+    pub changed: Vec<String>,
+    /// Directories with no summary, to be scanned in full
+    pub unknown: Vec<String>,
+}
+
+/// Compare `roots` against stored summaries without decoding or hashing
+///
+/// A directory is unchanged when its modification time and its `listing`
+/// (file count, sizes and modification times) both match the stamp taken
+/// before the summarized scan. Adding, removing or renaming an entry updates
+/// the directory's time; rewriting a file in place changes the listing.
+/// Roots are matched to summaries by identity.
+pub fn verify(summaries: &HashMap<String, DirectorySummary>, roots: &[String]) -> Verification {
+    let mut children: HashMap<&str, Vec<&DirectorySummary>> = HashMap::new();
+    for summary in summaries.values() {
+        if let Some(parent) = Path::new(&summary.directory).parent() {
+            if let Some(parent) = summaries.get(parent.to_string_lossy().as_ref()) {
+                children.entry(parent.directory.as_str()).or_default().push(summary);
+            }
+        }
+    }
+    for list in children.values_mut() {
+        list.sort_by(|a, b| a.directory.cmp(&b.directory));
+    }
+
+    let mut verification = Verification::default();
+    for root in roots {
+        match find(summaries, root) {
+            Some(summary) => {
+                if visit(summary, &children, &mut verification) {
+                    verification.unchanged.push(summary.clone());
+                }
+            },
+            None => verification.unknown.push(root.clone()),
+        }
+    }
+    verification
+}
+
+/// The summary of `directory`, spelled as given or any other way with the same identity
+pub fn find<'a>(summaries: &'a HashMap<String, DirectorySummary>, directory: &str) -> Option<&'a DirectorySummary> {
+    summaries.get(directory).or_else(|| {
+        let identity = paths::identity(directory);
+        summaries.values().find(|summary| paths::identity(&summary.directory) == identity)
+    })
+}
+
+/// Whether the whole subtree is unchanged; otherwise its unchanged parts are
+/// recorded in `verification`
+fn visit(
+    summary: &DirectorySummary,
+    children: &HashMap<&str, Vec<&DirectorySummary>>,
+    verification: &mut Verification,
+) -> bool {
+    let own = summary.modified.is_some()
+        && modified(&summary.directory) == summary.modified
+        && !summary.listing.is_empty()
+        && listing(&summary.directory).as_ref() == Some(&summary.listing);
+    if !own {
+        verification.changed.push(summary.directory.clone());
+    }
+    let subdirectories = children.get(summary.directory.as_str()).map(Vec::as_slice).unwrap_or_default();
+    let unchanged: Vec<bool> = subdirectories.iter().map(|child| visit(child, children, verification)).collect();
+    if own && unchanged.iter().all(|&u| u) {
+        return true;
+    }
+    for (child, unchanged) in subdirectories.iter().zip(unchanged) {
+        if unchanged {
+            verification.unchanged.push((*child).clone());
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(path: &Path) -> ImageRecord {
+        ImageRecord {
+            path: path.to_string_lossy().into_owned(),
+            size: fs::metadata(path).unwrap().len(),
+            average_hash: "00".to_string(),
+            ..ImageRecord::default()
+        }
+    }
+
+    #[test]
+    fn changes_after_the_stamp_are_never_fresh() {
+        let root = crate::tiff::fixtures::temp_path("summaries");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.jpg"), b"aaaa").unwrap();
+        fs::write(root.join("sub/b.jpg"), b"bbbb").unwrap();
+        let records = [record(&root.join("a.jpg")), record(&root.join("sub/b.jpg"))];
+        let key = root.to_string_lossy().into_owned();
+        let sub = root.join("sub").to_string_lossy().into_owned();
+
+        let mut stamps = HashMap::new();
+        stamp(&root, &mut stamps);
+        let encoded = encode_stamps(&stamps);
+        let stamps = decode_stamps(&encoded).unwrap();
+        let summaries: HashMap<String, DirectorySummary> =
+            decode(&encode(&summarize(&records, &stamps))).unwrap().into_iter().collect();
+        let verification = verify(&summaries, std::slice::from_ref(&key));
+        assert_eq!(verification.unchanged.iter().map(|s| &s.directory).collect::<Vec<_>>(), [&key]);
+        assert!(verification.changed.is_empty());
+
+        // Rewritten in place: same directory time, different size
+        fs::write(root.join("sub/b.jpg"), b"bbbbbbbb").unwrap();
+        let verification = verify(&summaries, std::slice::from_ref(&key));
+        assert_eq!(verification.changed, [sub]);
+
+        // Without a stamp from before the scan, nothing verifies
+        let unstamped = decode(&encode(&summarize(&records, &HashMap::new()))).unwrap();
+        let verification = verify(&unstamped, std::slice::from_ref(&key));
+        assert!(verification.unchanged.is_empty());
+        assert_eq!(verification.changed.len(), 2);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directories_are_walked_as_named() {
+        // `sub.` normalizes to the identity of its sibling `sub`
+        let root = crate::tiff::fixtures::temp_path("summaries_named");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub.")).unwrap();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub./a.jpg"), b"aaaa").unwrap();
+        fs::write(root.join("sub/b.jpg"), b"bbbb").unwrap();
+        let indexed = record(&root.join("sub./a.jpg"));
+        let indexed = ImageRecord { path: paths::identity(&indexed.path), location: indexed.path, ..indexed };
+        let key = root.to_string_lossy().into_owned();
+        let dotted = root.join("sub.").to_string_lossy().into_owned();
+
+        let mut stamps = HashMap::new();
+        stamp(&root, &mut stamps);
+        let summaries: HashMap<String, DirectorySummary> =
+            summarize(&[indexed], &stamps).into_iter().map(|s| (s.directory.clone(), s)).collect();
+        assert_eq!(find(&summaries, &dotted).map(|s| s.files), Some(1));
+        assert!(verify(&summaries, std::slice::from_ref(&key)).changed.is_empty());
+
+        // Only the directory the file is in counts, not the one its identity names
+        fs::write(root.join("sub/b.jpg"), b"bbbbbbbb").unwrap();
+        assert!(verify(&summaries, std::slice::from_ref(&key)).changed.is_empty());
+        fs::write(root.join("sub./a.jpg"), b"aaaaaaaa").unwrap();
+        assert_eq!(verify(&summaries, std::slice::from_ref(&key)).changed, [dotted]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
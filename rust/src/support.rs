@@ -0,0 +1,201 @@
+// src/support.rs
+// Up-front report of how a file would be decoded: container, camera, previews, decoders and tools
+
+use std::fs::File;
+use std::io::{self, Read};
+
+use image::ImageFormat;
+
+use crate::storage::{self, Backend};
+use crate::{camera_profiles, formats, naming, previews, profiles, streaming};
+
+/// Tools the conversion chain runs, by executable name
+const TOOLS: [&str; 3] = ["exiftool", "dcraw", "dcraw_emu"];
+
+/// One embedded preview
+pub struct PreviewInfo {
+    pub source: String,
+    pub bytes: usize,
+    /// None when the image crate cannot read its header
+    pub dimensions: Option<(u32, u32)>,
+}
+
+pub struct Report {
+    pub container: &'static str,
+    /// Extension the file is routed by: its own, or a registered handler
+    pub extension: String,
+    pub raw: bool,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub dimensions: Option<(u32, u32)>,
+    pub previews: Vec<PreviewInfo>,
+    /// In-process decoder that reads the file: `image`, `streaming` or `rawloader`
+    pub native_decoder: Option<&'static str>,
+    /// External tools the conversion chain would try, with whether each is on PATH
+    pub tools: Vec<(&'static str, bool)>,
+    pub warnings: Vec<String>,
+}
+
+/// Container format from the file's magic bytes, whatever its extension says
+fn container(header: &[u8]) -> &'static str {
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+    match () {
+        _ if at(0, &[0xFF, 0xD8]) => "jpeg",
+        _ if at(0, b"\x89PNG") => "png",
+        _ if at(0, b"FUJIFILMCCD-RAW") => "raf",
+        _ if at(0, b"IIRO") || at(0, b"IIRS") || at(0, b"MMOR") => "orf",
+        _ if at(0, b"IIU\0") => "rw2",
+        _ if at(0, b"II\x1a\0\0\0HEAPCCDR") => "crw",
+        _ if at(0, b"II*\0") || at(0, b"MM\0*") => "tiff",
+        _ if at(0, b"\0MRM") => "mrw",
+        _ if at(0, b"FOVb") => "x3f",
+        _ if at(0, b"8BPS") => "psd",
+        _ if at(4, b"ftypcrx ") => "cr3",
+        _ if at(4, b"ftypavif") => "avif",
+        _ if at(4, b"ftyp") => "heif",
+        _ if at(0, b"RIFF") && at(8, b"WEBP") => "webp",
+        _ if at(0, b"GIF8") => "gif",
+        _ if at(0, b"BM") => "bmp",
+        _ if header.len() > 1 && header[0] == b'P' && (b'1'..=b'7').contains(&header[1]) => "pnm",
+        _ => "unknown",
+    }
+}
+
+/// Whether an executable named `tool` is on PATH
+fn on_path(tool: &str) -> bool {
+    let Some(path) = std::env::var_os("PATH") else {
+        return false;
+    };
+    std::env::split_paths(&path).any(|dir| {
+        let candidate = dir.join(tool);
+        candidate.is_file() || (cfg!(windows) && candidate.with_extension("exe").is_file())
+    })
+}
+
+/// Tools a step of the conversion chain runs for this file
+fn backend_tools(backend: Backend, path: &str, ext: &str, full_decode: bool) -> Vec<&'static str> {
+    match backend {
+        Backend::CameraProfile => match camera_profiles::lookup_for_file(path) {
+            Some(profile) => {
+                let mut tools = Vec::new();
+                if !full_decode && !profile.has_quirk(camera_profiles::Quirk::PreviewTooSmall) {
+                    tools.push("exiftool");
+                }
+                if profile.has_quirk(camera_profiles::Quirk::PreferLibraw) {
+                    tools.push("dcraw_emu");
+                }
+                tools
+            },
+            None => Vec::new(),
+        },
+        Backend::EmbeddedPreview if full_decode => Vec::new(),
+        Backend::EmbeddedPreview if ext == "raf" => vec!["exiftool"],
+        Backend::EmbeddedPreview => vec!["exiftool", "dcraw"],
+        Backend::Libraw if crate::detect_raw_variant(path, ext) != crate::RawVariant::Standard => vec!["dcraw_emu"],
+        Backend::Libraw | Backend::Rawloader => Vec::new(),
+        Backend::FormatSpecific => match ext {
+            "raf" => vec!["dcraw", "dcraw_emu", "exiftool"],
+            ext if storage::FORMAT_SPECIFIC_EXTENSIONS.contains(&ext) => vec!["dcraw"],
+            _ => Vec::new(),
+        },
+        Backend::Generic => vec!["dcraw", "dcraw_emu"],
+    }
+}
+
+/// Inspect `path` without decoding its pixels
+///
+/// `exiftool` also lists the previews only exiftool can reach (maker notes),
+/// at the cost of a few exiftool runs.
+pub fn probe(path: &str, exiftool: bool) -> io::Result<Report> {
+    let mut header = Vec::with_capacity(32);
+    File::open(path)?.take(32).read_to_end(&mut header)?;
+    let container = container(&header);
+    let extension = formats::extension(path);
+    let raw = formats::is_raw(&extension);
+
+    let found = if exiftool { previews::all_previews(path) } else { previews::native_previews(path) };
+    let previews: Vec<PreviewInfo> = found
+        .into_iter()
+        .map(|preview| PreviewInfo {
+            dimensions: image::io::Reader::new(io::Cursor::new(&preview.data))
+                .with_guessed_format()
+                .ok()
+                .and_then(|reader| reader.into_dimensions().ok()),
+            bytes: preview.data.len(),
+            source: preview.source,
+        })
+        .collect();
+
+    let (mut make, mut model) = match camera_profiles::read_make_model(path) {
+        Some((make, model)) => (Some(make), Some(model).filter(|m| !m.is_empty())),
+        None => {
+            let exif = naming::read_exif(path);
+            (exif.make, exif.camera)
+        },
+    };
+
+    let mut dimensions = None;
+    let native_decoder = if raw {
+        // rawloader parses the whole container and checks its camera list without decoding
+        let parsed = File::open(path).ok().and_then(|mut file| rawloader::decode_dummy(&mut file).ok());
+        parsed.map(|image| {
+            dimensions = Some((image.width as u32, image.height as u32));
+            make = make.take().or(Some(image.clean_make));
+            model = model.take().or(Some(image.clean_model));
+            "rawloader"
+        })
+    } else if streaming::is_streamable(path) || matches!(extension.as_str(), "psd" | "psb") {
+        Some("streaming")
+    } else {
+        let format = ImageFormat::from_extension(&extension).filter(|format| format.can_read());
+        format.map(|format| {
+            dimensions = image::io::Reader::open(path).ok().and_then(|mut reader| {
+                reader.set_format(format);
+                reader.into_dimensions().ok()
+            });
+            "image"
+        })
+    };
+
+    // Steps in chain order up to the first one that decodes natively
+    let mut wanted: Vec<&'static str> = Vec::new();
+    if raw {
+        let full_decode = profiles::active().full_decode;
+        for backend in storage::chain(storage::detect(path), &extension) {
+            if extension == "raf" && matches!(backend, Backend::Libraw | Backend::Rawloader | Backend::Generic) {
+                continue;
+            }
+            if backend == Backend::Rawloader && native_decoder.is_some() {
+                break;
+            }
+            wanted.extend(backend_tools(backend, path, &extension, full_decode));
+        }
+    }
+    let tools: Vec<(&'static str, bool)> =
+        TOOLS.iter().filter(|tool| wanted.contains(tool)).map(|tool| (*tool, on_path(tool))).collect();
+
+    let mut warnings = Vec::new();
+    if !raw && native_decoder.is_none() {
+        warnings.push(format!(
+            "No decoder for extension '{}' (content looks like {}); see register_extension",
+            extension, container
+        ));
+    }
+    if raw && native_decoder.is_none() {
+        let missing: Vec<&str> = tools.iter().filter(|(_, available)| !available).map(|(tool, _)| *tool).collect();
+        if tools.is_empty() {
+            warnings.push("No conversion step can decode this file".to_string());
+        } else if missing.len() == tools.len() {
+            warnings.push(format!("Needs {} and none is installed; the file will fail", missing.join(" or ")));
+        } else {
+            if previews.is_empty() {
+                warnings.push("No embedded preview found; expect a slow full decode through external tools".to_string());
+            }
+            for tool in missing {
+                warnings.push(format!("{} is not on PATH; its conversion steps will be skipped", tool));
+            }
+        }
+    }
+
+    Ok(Report { container, extension, raw, make, model, dimensions, previews, native_decoder, tools, warnings })
+}
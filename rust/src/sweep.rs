@@ -0,0 +1,142 @@
+// src/sweep.rs
+// How duplicate groups change as the Hamming threshold grows, from one pass over the pairs
+
+use rayon::prelude::*;
+
+use crate::hamming::{self, Packed};
+
+/// Grouping statistics at one threshold
+pub struct SweepPoint {
+    pub threshold: u32,
+    /// Pairs of images within the threshold
+    pub pairs: usize,
+    /// Groups of two or more images (connected components of the pair graph)
+    pub groups: usize,
+    /// Images that belong to some group
+    pub grouped_images: usize,
+    pub largest_group: usize,
+}
+
+/// Hashes packed for comparing; None for empty or malformed ones, which never match
+fn pack_all(hashes: &[&str]) -> Vec<Option<Packed>> {
+    hashes.par_iter().map(|h| Packed::from_str(h).filter(|p| p.bits() > 0)).collect()
+}
+
+struct DisjointSets {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(n: usize) -> Self {
+        DisjointSets { parent: (0..n).collect(), size: vec![1; n] }
+    }
+
+    fn root(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+}
+
+/// Group counts and sizes at each of `thresholds`, grouping images whose hashes
+/// are within the threshold (single linkage, like transitive duplicate groups)
+///
+/// Pairwise distances are computed once up to the largest threshold, then
+/// merged in order of distance, so the whole curve costs one O(n^2) pass.
+/// Hashes of different lengths or empty hashes never match.
+pub fn threshold_sweep(hashes: &[&str], thresholds: &[u32]) -> Vec<SweepPoint> {
+    let max_threshold = thresholds.iter().copied().max().unwrap_or(0);
+    let packed = pack_all(hashes);
+
+    let mut edges: Vec<(u32, usize, usize)> = (0..packed.len())
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            let packed = &packed;
+            (i + 1..packed.len()).filter_map(move |j| {
+                let distance = hamming::distance(packed[i].as_ref()?, packed[j].as_ref()?)?;
+                (distance <= max_threshold).then_some((distance, i, j))
+            })
+        })
+        .collect();
+    edges.par_sort_unstable();
+
+    let mut order: Vec<u32> = thresholds.to_vec();
+    order.sort_unstable();
+    order.dedup();
+
+    let mut sets = DisjointSets::new(hashes.len());
+    let (mut groups, mut grouped_images, mut largest_group) = (0usize, 0usize, 0usize);
+    let mut next_edge = 0;
+    let mut points = Vec::with_capacity(order.len());
+    for threshold in order {
+        while next_edge < edges.len() && edges[next_edge].0 <= threshold {
+            let (_, i, j) = edges[next_edge];
+            next_edge += 1;
+            let (a, b) = (sets.root(i), sets.root(j));
+            if a == b {
+                continue;
+            }
+            let (size_a, size_b) = (sets.size[a], sets.size[b]);
+            // Singletons join the grouped images; two groups merging become one
+            groups = groups + 1 - (size_a >= 2) as usize - (size_b >= 2) as usize;
+            grouped_images += (size_a == 1) as usize + (size_b == 1) as usize;
+            largest_group = largest_group.max(size_a + size_b);
+
+            let (big, small) = if size_a >= size_b { (a, b) } else { (b, a) };
+            sets.parent[small] = big;
+            sets.size[big] = size_a + size_b;
+        }
+        points.push(SweepPoint { threshold, pairs: next_edge, groups, grouped_images, largest_group });
+    }
+    points
+}
+
+/// Duplicate groups at one threshold: positions of the hashes in each group
+/// of two or more, in the order of their first member
+pub fn groups(hashes: &[&str], threshold: u32) -> Vec<Vec<usize>> {
+    let packed = pack_all(hashes);
+    let edges: Vec<(usize, usize)> = (0..packed.len())
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            let packed = &packed;
+            (i + 1..packed.len()).filter_map(move |j| {
+                let distance = hamming::distance(packed[i].as_ref()?, packed[j].as_ref()?)?;
+                (distance <= threshold).then_some((i, j))
+            })
+        })
+        .collect();
+
+    components(hashes.len(), edges)
+}
+
+/// Connected components of two or more among `count` items joined by `edges`,
+/// in the order of their first member
+pub fn components(count: usize, edges: impl IntoIterator<Item = (usize, usize)>) -> Vec<Vec<usize>> {
+    let mut sets = DisjointSets::new(count);
+    for (i, j) in edges {
+        let (a, b) = (sets.root(i), sets.root(j));
+        if a != b {
+            let (big, small) = if sets.size[a] >= sets.size[b] { (a, b) } else { (b, a) };
+            sets.parent[small] = big;
+            sets.size[big] += sets.size[small];
+        }
+    }
+
+    let mut position_of_root = std::collections::HashMap::new();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for i in 0..count {
+        let root = sets.root(i);
+        if sets.size[root] < 2 {
+            continue;
+        }
+        let position = *position_of_root.entry(root).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[position].push(i);
+    }
+    groups
+}
@@ -0,0 +1,85 @@
+// src/throttle.rs
+// Shared token bucket limiting read bandwidth and IOPS for network storage
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Refills at `rate` tokens per second; a rate of 0 disables the limit
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    /// Take `amount` tokens and return how long the caller should wait
+    ///
+    /// The bucket may go into debt so a single large file is not starved
+    /// forever; the debt is paid back by later callers waiting.
+    fn take(&mut self, amount: f64, now: Instant) -> Duration {
+        if self.rate <= 0.0 {
+            return Duration::ZERO;
+        }
+
+        // Burst capacity is one second worth of tokens
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+        self.tokens -= amount;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+struct Throttle {
+    bytes: Bucket,
+    ops: Bucket,
+}
+
+static THROTTLE: Mutex<Option<Throttle>> = Mutex::new(None);
+
+/// Configure the shared limits; 0 disables the corresponding limit
+pub fn set_limits(bytes_per_second: u64, ops_per_second: u64) {
+    let now = Instant::now();
+    let mut throttle = THROTTLE.lock().unwrap_or_else(|e| e.into_inner());
+
+    *throttle = if bytes_per_second == 0 && ops_per_second == 0 {
+        None
+    } else {
+        Some(Throttle {
+            bytes: Bucket { rate: bytes_per_second as f64, tokens: bytes_per_second as f64, last: now },
+            ops: Bucket { rate: ops_per_second as f64, tokens: ops_per_second as f64, last: now },
+        })
+    };
+}
+
+/// Current limits as (bytes per second, operations per second)
+pub fn limits() -> (u64, u64) {
+    let throttle = THROTTLE.lock().unwrap_or_else(|e| e.into_inner());
+    match throttle.as_ref() {
+        Some(t) => (t.bytes.rate as u64, t.ops.rate as u64),
+        None => (0, 0),
+    }
+}
+
+/// Block until `bytes` and `ops` fit within the configured limits
+pub fn acquire(bytes: u64, ops: u64) {
+    let wait = {
+        let mut throttle = THROTTLE.lock().unwrap_or_else(|e| e.into_inner());
+        match throttle.as_mut() {
+            Some(t) => {
+                let now = Instant::now();
+                t.bytes.take(bytes as f64, now).max(t.ops.take(ops as f64, now))
+            },
+            None => return,
+        }
+    };
+
+    if !wait.is_zero() {
+        std::thread::sleep(wait);
+    }
+}
@@ -0,0 +1,201 @@
+// src/thumbnails.rs
+// In-memory cache of encoded thumbnails served to Python, optionally backed by a
+// directory shared between worker processes
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::locking::{self, FileLock};
+
+/// Default total encoded bytes kept before the least recently used entries are dropped
+pub const DEFAULT_BUDGET_BYTES: usize = 64 * 1024 * 1024;
+
+static BUDGET_BYTES: AtomicUsize = AtomicUsize::new(DEFAULT_BUDGET_BYTES);
+
+/// Identifies one rendition of one file version
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ThumbnailKey {
+    path: String,
+    modified: Option<SystemTime>,
+    size: u64,
+    long_edge: u32,
+    format: String,
+}
+
+impl ThumbnailKey {
+    /// Build a key, picking up the file's mtime and size so edits invalidate it
+    pub fn new(path: &str, long_edge: u32, format: &str) -> Self {
+        let metadata = std::fs::metadata(path).ok();
+        ThumbnailKey {
+            path: path.to_string(),
+            modified: metadata.as_ref().and_then(|m| m.modified().ok()),
+            size: metadata.map(|m| m.len()).unwrap_or(0),
+            long_edge,
+            format: format.to_string(),
+        }
+    }
+
+    /// Stable file name for the shared cache directory
+    fn file_name(&self) -> String {
+        let modified = self
+            .modified
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let id = format!("{}\0{}\0{}\0{}\0{}", self.path, modified, self.size, self.long_edge, self.format);
+        format!("{}.{}", blake3::hash(id.as_bytes()).to_hex(), self.format)
+    }
+}
+
+struct Entry {
+    bytes: Vec<u8>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct ThumbnailCache {
+    entries: HashMap<ThumbnailKey, Entry>,
+    total_bytes: usize,
+    clock: u64,
+}
+
+fn cache() -> &'static Mutex<ThumbnailCache> {
+    static CACHE: OnceLock<Mutex<ThumbnailCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(ThumbnailCache::default()))
+}
+
+/// Look up a cached thumbnail
+pub fn get(key: &ThumbnailKey) -> Option<Vec<u8>> {
+    let mut cache = cache().lock().ok()?;
+    cache.clock += 1;
+    let clock = cache.clock;
+
+    cache.entries.get_mut(key).map(|entry| {
+        entry.last_used = clock;
+        entry.bytes.clone()
+    })
+}
+
+/// Store a thumbnail, evicting least recently used entries over the budget
+pub fn put(key: ThumbnailKey, bytes: Vec<u8>) {
+    let Ok(mut cache) = cache().lock() else {
+        return;
+    };
+    let budget = budget();
+    if bytes.len() > budget {
+        return;
+    }
+
+    cache.clock += 1;
+    let entry = Entry { bytes, last_used: cache.clock };
+    cache.total_bytes += entry.bytes.len();
+    if let Some(old) = cache.entries.insert(key, entry) {
+        cache.total_bytes -= old.bytes.len();
+    }
+
+    evict(&mut cache, budget);
+}
+
+fn evict(cache: &mut ThumbnailCache, budget: usize) {
+    while cache.total_bytes > budget {
+        let oldest = cache
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone());
+
+        match oldest.and_then(|key| cache.entries.remove(&key)) {
+            Some(entry) => cache.total_bytes -= entry.bytes.len(),
+            None => break,
+        }
+    }
+}
+
+/// Bytes of encoded thumbnails kept in memory
+pub fn budget() -> usize {
+    BUDGET_BYTES.load(Ordering::Relaxed)
+}
+
+/// Resize the in-memory cache, dropping least recently used entries over the new budget
+pub fn set_budget(bytes: usize) {
+    BUDGET_BYTES.store(bytes, Ordering::Relaxed);
+    if let Ok(mut cache) = cache().lock() {
+        evict(&mut cache, bytes);
+    }
+}
+
+/// Drop every thumbnail cached in memory; the shared directory is left alone
+pub fn clear() {
+    if let Ok(mut cache) = cache().lock() {
+        cache.entries.clear();
+        cache.total_bytes = 0;
+    }
+}
+
+fn disk_dir() -> &'static RwLock<Option<PathBuf>> {
+    static DIR: OnceLock<RwLock<Option<PathBuf>>> = OnceLock::new();
+    DIR.get_or_init(|| RwLock::new(None))
+}
+
+/// Share thumbnails with other processes through `dir`; None keeps them in memory only
+pub fn set_disk_dir(dir: Option<PathBuf>) -> io::Result<()> {
+    if let Some(dir) = &dir {
+        prepare_disk_dir(dir)?;
+    }
+    use_disk_dir(dir);
+    Ok(())
+}
+
+/// Create the directory layout `use_disk_dir` expects
+pub fn prepare_disk_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir.join("locks"))
+}
+
+/// Switch to a directory already set up by `prepare_disk_dir`
+pub fn use_disk_dir(dir: Option<PathBuf>) {
+    *disk_dir().write().unwrap_or_else(|e| e.into_inner()) = dir;
+}
+
+/// The shared cache directory, if one is configured
+pub fn disk_dir_path() -> Option<PathBuf> {
+    disk_dir().read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Where `key` is (or will be) stored in the shared directory, if one is configured
+pub fn disk_path(key: &ThumbnailKey) -> Option<PathBuf> {
+    disk_dir_path().map(|dir| dir.join(key.file_name()))
+}
+
+/// Read a thumbnail from the shared directory, rendering and storing it on a miss
+///
+/// An advisory lock (striped over 256 lock files) is held while checking and
+/// rendering, so concurrent processes asking for the same thumbnail render it
+/// once and the others read the result. Files are written to a temp name and
+/// renamed, so readers never see a partial thumbnail. Without a shared
+/// directory this just renders.
+pub fn load_or_render<E>(key: &ThumbnailKey, render: impl FnOnce() -> Result<Vec<u8>, E>) -> Result<Vec<u8>, E> {
+    let Some(dir) = disk_dir_path() else {
+        return render();
+    };
+
+    let name = key.file_name();
+    let entry = dir.join(&name);
+    // Rendering twice is the worst case if locking is unavailable (e.g. some network filesystems)
+    let _lock = FileLock::exclusive(&dir.join("locks").join(format!("{}.lock", &name[..2]))).ok();
+
+    if let Ok(bytes) = fs::read(&entry) {
+        return Ok(bytes);
+    }
+
+    let bytes = render()?;
+    let temp = locking::unique_temp(&entry);
+    if fs::write(&temp, &bytes).and_then(|_| fs::rename(&temp, &entry)).is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    Ok(bytes)
+}
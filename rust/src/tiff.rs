@@ -0,0 +1,255 @@
+// src/tiff.rs
+// Minimal TIFF IFD reader used to sniff RAW container layouts and EXIF blocks
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+// Tags we care about when classifying RAW files
+pub const TAG_IMAGE_WIDTH: u16 = 0x0100;
+pub const TAG_IMAGE_LENGTH: u16 = 0x0101;
+pub const TAG_COMPRESSION: u16 = 0x0103;
+pub const TAG_MAKE: u16 = 0x010f;
+pub const TAG_MODEL: u16 = 0x0110;
+pub const TAG_STRIP_OFFSETS: u16 = 0x0111;
+pub const TAG_ORIENTATION: u16 = 0x0112;
+pub const TAG_SAMPLES_PER_PIXEL: u16 = 0x0115;
+pub const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+pub const TAG_DATE_TIME: u16 = 0x0132;
+pub const TAG_SUB_IFDS: u16 = 0x014a;
+pub const TAG_JPEG_OFFSET: u16 = 0x0201;
+pub const TAG_JPEG_LENGTH: u16 = 0x0202;
+pub const TAG_EXIF_IFD: u16 = 0x8769;
+pub const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+pub const TAG_BODY_SERIAL_NUMBER: u16 = 0xa431;
+pub const TAG_CAMERA_SERIAL_NUMBER: u16 = 0xc62f;
+
+// Field types
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+
+// Guards against corrupt files with looping or huge IFD chains
+const MAX_IFDS: usize = 32;
+const MAX_ENTRIES: u16 = 1024;
+const MAX_BLOB_BYTES: u32 = 64 * 1024 * 1024;
+
+/// A single IFD entry, with the value field kept raw until it is interpreted
+#[derive(Clone, Copy)]
+pub struct IfdEntry {
+    pub tag: u16,
+    pub field_type: u16,
+    pub count: u32,
+    value: [u8; 4],
+}
+
+/// One image file directory
+pub struct Ifd {
+    pub entries: Vec<IfdEntry>,
+}
+
+/// A TIFF-based file opened for IFD inspection
+pub struct TiffFile {
+    file: File,
+    little_endian: bool,
+    /// Position of the TIFF header in the file; offsets are relative to it
+    base: u64,
+}
+
+impl TiffFile {
+    /// Open a file and check the TIFF byte-order header
+    pub fn open(path: &str) -> io::Result<Self> {
+        Self::open_at(path, 0)
+    }
+
+    /// Open a TIFF structure embedded at `base`, e.g. the EXIF block of a JPEG
+    pub fn open_at(path: &str, base: u64) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(base))?;
+        let mut header = [0u8; 2];
+        file.read_exact(&mut header)?;
+
+        let little_endian = match &header {
+            b"II" => true,
+            b"MM" => false,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a TIFF container")),
+        };
+
+        Ok(TiffFile { file, little_endian, base })
+    }
+
+    fn u16_from(&self, bytes: [u8; 2]) -> u16 {
+        if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) }
+    }
+
+    fn u32_from(&self, bytes: [u8; 4]) -> u32 {
+        if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+    }
+
+    fn read_u16_at(&mut self, offset: u64) -> io::Result<u16> {
+        let mut buf = [0u8; 2];
+        self.file.seek(SeekFrom::Start(self.base + offset))?;
+        self.file.read_exact(&mut buf)?;
+        Ok(self.u16_from(buf))
+    }
+
+    fn read_u32_at(&mut self, offset: u64) -> io::Result<u32> {
+        let mut buf = [0u8; 4];
+        self.file.seek(SeekFrom::Start(self.base + offset))?;
+        self.file.read_exact(&mut buf)?;
+        Ok(self.u32_from(buf))
+    }
+
+    /// Read a single IFD and return it together with the offset of the next one
+    fn read_ifd(&mut self, offset: u32) -> io::Result<(Ifd, u32)> {
+        let count = self.read_u16_at(offset as u64)?.min(MAX_ENTRIES);
+        let mut raw = vec![0u8; count as usize * 12];
+        self.file.read_exact(&mut raw)?;
+
+        let entries = raw
+            .chunks_exact(12)
+            .map(|chunk| IfdEntry {
+                tag: self.u16_from([chunk[0], chunk[1]]),
+                field_type: self.u16_from([chunk[2], chunk[3]]),
+                count: self.u32_from([chunk[4], chunk[5], chunk[6], chunk[7]]),
+                value: [chunk[8], chunk[9], chunk[10], chunk[11]],
+            })
+            .collect();
+
+        let next = self.read_u32_at(offset as u64 + 2 + count as u64 * 12)?;
+        Ok((Ifd { entries }, next))
+    }
+
+    /// Interpret an entry as a single SHORT or LONG value
+    pub fn value_u32(&self, entry: &IfdEntry) -> Option<u32> {
+        if entry.count != 1 {
+            return None;
+        }
+
+        match entry.field_type {
+            TYPE_SHORT => Some(self.u16_from([entry.value[0], entry.value[1]]) as u32),
+            TYPE_LONG => Some(self.u32_from(entry.value)),
+            _ => None,
+        }
+    }
+
+    /// Interpret an entry as a list of LONG values (offsets for SubIFDs and similar)
+    pub fn values_u32(&mut self, entry: &IfdEntry) -> io::Result<Vec<u32>> {
+        if entry.count <= 1 {
+            return Ok(self.value_u32(entry).into_iter().collect());
+        }
+
+        let offset = self.u32_from(entry.value) as u64;
+        (0..entry.count.min(MAX_IFDS as u32) as u64)
+            .map(|i| self.read_u32_at(offset + i * 4))
+            .collect()
+    }
+
+    /// Interpret an entry as an ASCII string, without trailing NULs and padding
+    pub fn value_ascii(&mut self, entry: &IfdEntry) -> Option<String> {
+        if entry.field_type != TYPE_ASCII {
+            return None;
+        }
+
+        let bytes = if entry.count <= 4 {
+            entry.value[..entry.count as usize].to_vec()
+        } else {
+            self.read_bytes(self.u32_from(entry.value), entry.count).ok()?
+        };
+
+        let text = String::from_utf8_lossy(&bytes);
+        Some(text.trim_end_matches('\0').trim().to_string())
+    }
+
+    /// Read `len` bytes at `offset`, e.g. an embedded JPEG blob
+    pub fn read_bytes(&mut self, offset: u32, len: u32) -> io::Result<Vec<u8>> {
+        if len > MAX_BLOB_BYTES {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Embedded blob too large"));
+        }
+
+        let mut buf = vec![0u8; len as usize];
+        self.file.seek(SeekFrom::Start(self.base + offset as u64))?;
+        self.file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Read the IFD an entry points to, such as the EXIF IFD
+    pub fn sub_ifd(&mut self, entry: &IfdEntry) -> io::Result<Ifd> {
+        let offset = self.value_u32(entry).filter(|&offset| offset != 0);
+        let offset = offset.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid IFD pointer"))?;
+        self.read_ifd(offset).map(|(ifd, _)| ifd)
+    }
+
+    /// Walk the IFD0 chain and any SubIFDs it references
+    pub fn ifds(&mut self) -> io::Result<Vec<Ifd>> {
+        let mut pending = vec![self.read_u32_at(4)?];
+        let mut ifds = Vec::new();
+
+        while let Some(offset) = pending.pop() {
+            if offset == 0 || ifds.len() >= MAX_IFDS {
+                continue;
+            }
+
+            let (ifd, next) = self.read_ifd(offset)?;
+            for entry in ifd.entries.iter().filter(|e| e.tag == TAG_SUB_IFDS) {
+                pending.extend(self.values_u32(entry)?);
+            }
+            pending.push(next);
+            ifds.push(ifd);
+        }
+
+        Ok(ifds)
+    }
+}
+
+impl Ifd {
+    /// Find the first entry with the given tag
+    pub fn find(&self, tag: u16) -> Option<&IfdEntry> {
+        self.entries.iter().find(|e| e.tag == tag)
+    }
+}
+
+/// Minimal TIFF files for tests
+#[cfg(test)]
+pub mod fixtures {
+    use std::path::PathBuf;
+
+    /// A fresh path in the temp directory, unique per test and process
+    pub fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("raw_processor_test_{}_{}", std::process::id(), name))
+    }
+
+    /// Little-endian TIFF with one IFD per entry list, chained in order;
+    /// values are stored as SHORT when they fit, LONG otherwise
+    pub fn tiff_bytes(ifds: &[&[(u16, u32)]]) -> Vec<u8> {
+        let mut data = b"II*\0".to_vec();
+        data.extend_from_slice(&8u32.to_le_bytes());
+        for (i, entries) in ifds.iter().enumerate() {
+            let mut entries = entries.to_vec();
+            entries.sort_by_key(|&(tag, _)| tag);
+            data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+            for (tag, value) in entries {
+                data.extend_from_slice(&tag.to_le_bytes());
+                if value <= u16::MAX as u32 {
+                    data.extend_from_slice(&super::TYPE_SHORT.to_le_bytes());
+                    data.extend_from_slice(&1u32.to_le_bytes());
+                    data.extend_from_slice(&(value as u16).to_le_bytes());
+                    data.extend_from_slice(&[0, 0]);
+                } else {
+                    data.extend_from_slice(&super::TYPE_LONG.to_le_bytes());
+                    data.extend_from_slice(&1u32.to_le_bytes());
+                    data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            let next = if i + 1 < ifds.len() { data.len() as u32 + 4 } else { 0 };
+            data.extend_from_slice(&next.to_le_bytes());
+        }
+        data
+    }
+
+    /// Write `tiff_bytes(ifds)` to a temp file named `name`
+    pub fn write_tiff(name: &str, ifds: &[&[(u16, u32)]]) -> PathBuf {
+        let path = temp_path(name);
+        std::fs::write(&path, tiff_bytes(ifds)).expect("write test TIFF");
+        path
+    }
+}
@@ -0,0 +1,80 @@
+// src/tuning.rs
+// Process-wide defaults for thumbnail size, conversion step timeout, JPEG quality and match thresholds
+
+use std::io::BufWriter;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use image::{DynamicImage, ImageOutputFormat, ImageResult};
+
+/// Side of the square grayscale thumbnails hashes are computed from
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 512;
+
+/// Time after which a conversion chain stops trying further backends
+pub const DEFAULT_STEP_TIMEOUT: Duration = Duration::from_millis(4000);
+pub const DEFAULT_JPEG_QUALITY: u8 = 85;
+
+/// Largest hash distances, in bits, used when a call does not give its own
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Thresholds {
+    /// `find_duplicate_groups`
+    pub duplicate: u32,
+    /// 64-bit perceptual hash in `find_similar`
+    pub similar: u32,
+    /// 256-bit fine hash confirming a `find_similar` candidate
+    pub fine: u32,
+}
+
+pub const DEFAULT_THRESHOLDS: Thresholds = Thresholds { duplicate: 5, similar: 10, fine: 40 };
+
+static THUMBNAIL_SIZE: AtomicU32 = AtomicU32::new(DEFAULT_THUMBNAIL_SIZE);
+static STEP_TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_STEP_TIMEOUT.as_millis() as u64);
+static JPEG_QUALITY: AtomicU8 = AtomicU8::new(DEFAULT_JPEG_QUALITY);
+static THRESHOLDS: RwLock<Thresholds> = RwLock::new(DEFAULT_THRESHOLDS);
+
+/// Default thumbnail side for functions that return pixels or previews
+pub fn thumbnail_size() -> u32 {
+    THUMBNAIL_SIZE.load(Ordering::Relaxed)
+}
+
+/// Set the default thumbnail side; the caller checks it is not zero
+pub fn set_thumbnail_size(size: u32) {
+    THUMBNAIL_SIZE.store(size, Ordering::Relaxed);
+}
+
+pub fn step_timeout() -> Duration {
+    Duration::from_millis(STEP_TIMEOUT_MS.load(Ordering::Relaxed))
+}
+
+pub fn set_step_timeout(timeout: Duration) {
+    STEP_TIMEOUT_MS.store((timeout.as_millis() as u64).max(1), Ordering::Relaxed);
+}
+
+/// Quality (1-100) of every JPEG this module encodes
+pub fn jpeg_quality() -> u8 {
+    JPEG_QUALITY.load(Ordering::Relaxed)
+}
+
+/// Set the JPEG quality; the caller checks it is within 1-100
+pub fn set_jpeg_quality(quality: u8) {
+    JPEG_QUALITY.store(quality, Ordering::Relaxed);
+}
+
+pub fn thresholds() -> Thresholds {
+    *THRESHOLDS.read().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn set_thresholds(thresholds: Thresholds) {
+    *THRESHOLDS.write().unwrap_or_else(|e| e.into_inner()) = thresholds;
+}
+
+pub fn jpeg_format() -> ImageOutputFormat {
+    ImageOutputFormat::Jpeg(jpeg_quality())
+}
+
+/// Save `img` to `path` as JPEG at the configured quality
+pub fn save_jpeg(img: &DynamicImage, path: &str) -> ImageResult<()> {
+    let mut file = BufWriter::new(std::fs::File::create(path)?);
+    img.write_to(&mut file, jpeg_format())
+}
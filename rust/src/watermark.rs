@@ -0,0 +1,58 @@
+// src/watermark.rs
+// Edge and corner regions left out of matching, where watermarks, timestamps and added borders live
+
+use image::{DynamicImage, Rgb, RgbImage};
+use pyo3::exceptions::PyValueError;
+use pyo3::PyResult;
+
+/// Regions masked before hashing, as fractions of the image size
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mask {
+    /// Strip cut from every edge (added frames, thin borders)
+    pub border: f64,
+    /// Size of the rectangle blacked out in each corner, after the border
+    /// (logos, signatures, camera date stamps)
+    pub corner_width: f64,
+    pub corner_height: f64,
+}
+
+/// Stock mask: 3% borders and corners a quarter wide and an eighth tall
+pub const DEFAULT: Mask = Mask { border: 0.03, corner_width: 0.25, corner_height: 0.125 };
+
+impl Mask {
+    pub fn new(border: f64, corner_width: f64, corner_height: f64) -> PyResult<Self> {
+        if !(0.0..0.5).contains(&border) {
+            return Err(PyValueError::new_err("border must be at least 0 and below 0.5"));
+        }
+        if !(0.0..=0.5).contains(&corner_width) || !(0.0..=0.5).contains(&corner_height) {
+            return Err(PyValueError::new_err("corner_width and corner_height must be between 0 and 0.5"));
+        }
+        Ok(Mask { border, corner_width, corner_height })
+    }
+
+    /// `img` without its border strips and with black corners
+    ///
+    /// Both images of a pair get the same mask, so the corners hash the same
+    /// whatever was stamped there; black keeps their hash bits at 0 instead
+    /// of flipping around the image mean.
+    pub fn apply(&self, img: &DynamicImage) -> DynamicImage {
+        let (width, height) = (img.width(), img.height());
+        let (dx, dy) = ((width as f64 * self.border) as u32, (height as f64 * self.border) as u32);
+        let mut rgb: RgbImage =
+            img.crop_imm(dx, dy, width.saturating_sub(2 * dx).max(1), height.saturating_sub(2 * dy).max(1)).to_rgb8();
+
+        let (width, height) = rgb.dimensions();
+        let (cw, ch) = ((width as f64 * self.corner_width) as u32, (height as f64 * self.corner_height) as u32);
+        if cw == 0 || ch == 0 {
+            return DynamicImage::ImageRgb8(rgb);
+        }
+        for (x, y, pixel) in rgb.enumerate_pixels_mut() {
+            let in_corner_column = x < cw || x >= width - cw;
+            let in_corner_row = y < ch || y >= height - ch;
+            if in_corner_column && in_corner_row {
+                *pixel = Rgb([0, 0, 0]);
+            }
+        }
+        DynamicImage::ImageRgb8(rgb)
+    }
+}
@@ -78,8 +78,10 @@ class RawImageLoader(ImageLoader):
     
     def can_load(self, path: str) -> bool:
         ext = Path(path).suffix.lower()
-        # Explicitly include all requested formats: DNG, RAF, ARW, NEF, CR2, CR3
-        raw_formats = ['.dng', '.raf', '.arw', '.nef', '.cr2', '.cr3', '.nrw', '.srf']
+        # Explicitly include all requested formats: DNG, RAF, ARW, NEF, CR2, CR3,
+        # plus ORF, RW2, PEF, SRW and legacy Kodak DCR/KDC
+        raw_formats = ['.dng', '.raf', '.arw', '.nef', '.cr2', '.cr3', '.nrw', '.srf',
+                   '.orf', '.rw2', '.pef', '.srw', '.dcr', '.kdc']
         if ext in raw_formats:
             return os.path.isfile(path)
         return False
@@ -452,7 +454,8 @@ def are_filenames_related(path1: str, path2: str) -> bool:
 def is_raw_format(path: str) -> bool:
     """Check if a file is in RAW format"""
     ext = Path(path).suffix.lower()
-    raw_formats = ['.dng', '.raf', '.arw', '.nef', '.cr2', '.cr3', '.nrw', '.srf']
+    raw_formats = ['.dng', '.raf', '.arw', '.nef', '.cr2', '.cr3', '.nrw', '.srf',
+                   '.orf', '.rw2', '.pef', '.srw', '.dcr', '.kdc']
     return ext in raw_formats
 
 
@@ -21,6 +21,13 @@ from imagefinder.database import check_image_exists, store_image_info
 from imagefinder.imageprocessor import load_image, ImageLoaderRegistry
 from imagefinder.image_types import ImageInfo
 
+# Path identities come from the Rust module when it is built
+try:
+    import raw_processor
+    RUST_ENABLED = True
+except ImportError:
+    RUST_ENABLED = False
+
 
 @dataclass
 class ScanOptions:
@@ -431,6 +438,27 @@ def convert_cr3_with_exiftool(path: str, output_path: str) -> bool:
     except Exception:
         return False
 
+def path_identity(path: str) -> str:
+    """Identity of a file, so one file reached under several spellings is indexed once"""
+    if RUST_ENABLED:
+        return raw_processor.path_identity(path)
+    return os.path.normcase(os.path.normpath(path))
+
+def collect_image_paths(folder_path: str, registry: ImageLoaderRegistry) -> List[str]:
+    """Loadable images under a folder, each listed once under its identity"""
+    seen = set()
+    paths = []
+    for root, _, files in os.walk(folder_path):
+        for file in files:
+            path = os.path.join(root, file)
+            if not registry.can_load_file(path):
+                continue
+            identity = path_identity(path)
+            if identity not in seen:
+                seen.add(identity)
+                paths.append(identity)
+    return paths
+
 def scan_and_store_folder(db_conn: sqlite3.Connection, options: ScanOptions) -> None:
     """Scan a folder and store image information in the database"""
     # Prepare registry for file type checking
@@ -444,17 +472,13 @@ def scan_and_store_folder(db_conn: sqlite3.Connection, options: ScanOptions) ->
         logging.debug(f"Starting image scan on folder: {options.folder_path}")
         logging.debug(f"Force rewrite: {options.force_rewrite}, Source prefix: {options.source_prefix}")
     
-    # First pass to count files
-    for root, _, files in os.walk(options.folder_path):
-        for file in files:
-            path = os.path.join(root, file)
-            
-            # Check if any loader can handle this file
-            if registry.can_load_file(path):
-                total_files += 1
-                # Count RAW images separately
-                if is_raw_format(path):
-                    raw_files += 1
+    # Collect paths to process and count them
+    paths_to_process = collect_image_paths(options.folder_path, registry)
+    for path in paths_to_process:
+        total_files += 1
+        # Count RAW images separately
+        if is_raw_format(path):
+            raw_files += 1
     
     print(f"Starting image indexing...\nTotal image files to process: {total_files} (including {raw_files} RAW files)")
     print(f"Force rewrite mode: {options.force_rewrite}")
@@ -515,14 +539,6 @@ def scan_and_store_folder(db_conn: sqlite3.Connection, options: ScanOptions) ->
         finally:
             thread_db_conn.close()
                 
-    # Collect paths to process
-    paths_to_process = []
-    for root, _, files in os.walk(options.folder_path):
-        for file in files:
-            path = os.path.join(root, file)
-            if registry.can_load_file(path):
-                paths_to_process.append(path)
-    
     # Process in parallel
     with ThreadPoolExecutor(max_workers=8) as executor:
         executor.map(process_file, paths_to_process)